@@ -0,0 +1,71 @@
+//! Allocation of floats in float heavy code. Layout widths are computed once
+//! with positive floats, which are mostly stored in the tagged pointer, and
+//! once with negative floats, which are always heap allocated. The number of
+//! heap floats comes from the statistics returned by `garbage-collect`. Run
+//! with `cargo bench --bench floats`.
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 7;
+const ITERATIONS: usize = 1_000_000;
+/// `float`, `/` and `*` each make a new float.
+const FLOATS_PER_ITERATION: usize = 3;
+
+struct Case {
+    name: &'static str,
+    /// The column width in each iteration, as a float.
+    column: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case { name: "compact widths", column: "(float (1+ (mod i 32)))" },
+    Case { name: "heap widths", column: "(float (- -1 (mod i 32)))" },
+];
+
+/// Run `case` and return how long it took and how many floats were allocated
+/// on the heap.
+fn run(case: &Case) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rune"))
+        .arg("--repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("rune should start");
+    let mut stdin = child.stdin.take().unwrap();
+    let form = format!(
+        "(progn (garbage-collect)
+                (let ((i 0) (widths nil))
+                  (while (< i {ITERATIONS})
+                    (setq widths (cons (* (/ {} 4) 7.5) widths))
+                    (setq i (1+ i))))
+                (assq 'floats (garbage-collect)))",
+        case.column
+    );
+    writeln!(stdin, "{}\nexit", form.replace('\n', " ")).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let elapsed = start.elapsed();
+    assert!(output.status.success(), "{} failed", case.name);
+    // The result is printed as (floats SIZE LIVE FREED)
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.lines().find_map(|x| x.split_once("(floats ")).expect("float stats").1;
+    let counts: Vec<usize> =
+        line.trim_end_matches(')').split(' ').map(|x| x.parse().unwrap()).collect();
+    (elapsed, counts[1] + counts[2])
+}
+
+fn main() {
+    let total = ITERATIONS * FLOATS_PER_ITERATION;
+    for case in CASES {
+        let runs: Vec<_> = (0..RUNS).map(|_| run(case)).collect();
+        let best = runs.iter().map(|(time, _)| *time).min().unwrap();
+        let heap_floats = runs[0].1;
+        println!(
+            "{}: {}ms, {heap_floats} of {total} floats allocated",
+            case.name,
+            best.as_millis()
+        );
+    }
+}
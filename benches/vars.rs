@@ -0,0 +1,55 @@
+//! Timings of variable access. Each case is run in a new `rune` process, and
+//! the best of several runs is reported, because the interpreter has no way to
+//! time code from lisp yet. Run with `cargo bench --bench vars`.
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 7;
+
+struct Case {
+    name: &'static str,
+    args: &'static [&'static str],
+    /// Forms to evaluate in the repl.
+    input: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "bytecode varref/varset",
+        args: &["--repl"],
+        // (while (< bench-x bench-n) (setq bench-x (1+ bench-x))) bench-x
+        input: "(progn (defvar bench-x 0) (defvar bench-n 20000000)
+                  (funcall (make-byte-code 0 (unibyte-string 8 9 87 131 12 0 8 84 16 130 0 0 8 135)
+                                           (vector 'bench-x 'bench-n) 2)))",
+    },
+    Case {
+        name: "interpreted setq",
+        args: &["--repl"],
+        input: "(progn (defvar bench-x 0)
+                  (while (< bench-x 3000000) (setq bench-x (1+ bench-x))) bench-x)",
+    },
+    Case { name: "load bootstrap", args: &["--load"], input: "" },
+];
+
+fn run(case: &Case) -> Duration {
+    let start = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rune"))
+        .args(case.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("rune should start");
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "{}\nexit", case.input.replace('\n', " ")).unwrap();
+    drop(stdin);
+    assert!(child.wait().unwrap().success(), "{} failed", case.name);
+    start.elapsed()
+}
+
+fn main() {
+    for case in CASES {
+        let best = (0..RUNS).map(|_| run(case)).min().unwrap();
+        println!("{}: {}ms", case.name, best.as_millis());
+    }
+}
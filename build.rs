@@ -254,6 +254,7 @@ use crate::core::object::Object;
     .unwrap();
 
     // write out the value of each defvar
+    let mut bool_vars = Vec::new();
     for (ident, _, value, ty) in all_defvar {
         let nil = "Object::NIL";
         let mut value = match value {
@@ -267,19 +268,22 @@ use crate::core::object::Object;
             value.to_mut().insert_str(len - 1, "; cx");
         }
         writeln!(f, "env.vars.insert(sym::{ident}, cx.add({value}));").unwrap();
-        match ty {
-            DefvarType::Bool => {
-                writeln!(
-                    f,
-                    "{{
+        if let DefvarType::Bool = ty {
+            bool_vars.push(ident);
+        }
+    }
+
+    // byte-boolean-vars may be defined after the variables it lists, so only
+    // add them once every variable has been initialized
+    for ident in bool_vars {
+        writeln!(
+            f,
+            "{{
     let bool_vars = env.vars.get_mut(sym::BYTE_BOOLEAN_VARS).unwrap();
     bool_vars.set(crate::cons!(sym::{ident}, bool_vars.bind(cx); cx));
 }}"
-                )
-                .unwrap();
-            }
-            DefvarType::Other => {}
-        }
+        )
+        .unwrap();
     }
 
     writeln!(f, "}}").unwrap();
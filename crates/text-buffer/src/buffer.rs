@@ -411,6 +411,31 @@ impl Buffer {
         self.cursor = Metric { bytes: byte_pos, chars: pos };
     }
 
+    /// The position of the cursor in chars.
+    pub const fn cursor(&self) -> usize {
+        self.cursor.chars
+    }
+
+    /// The character after char position `pos`, if there is one.
+    pub fn char_after(&self, pos: usize) -> Option<char> {
+        if pos >= self.total.chars {
+            return None;
+        }
+        let end = pos + 1;
+        let start = self.to_abs_pos(Metric { bytes: self.char_to_byte(pos), chars: pos });
+        let end = self.to_abs_pos(Metric { bytes: self.char_to_byte(end), chars: end });
+        self.read(start.bytes..end.bytes).chars().next()
+    }
+
+    /// The character before char position `pos`, if there is one.
+    pub fn char_before(&self, pos: usize) -> Option<char> {
+        let end = pos.min(self.total.chars);
+        let beg = end.checked_sub(1)?;
+        let start = self.to_abs_pos(Metric { bytes: self.char_to_byte(beg), chars: beg });
+        let end = self.to_abs_pos(Metric { bytes: self.char_to_byte(end), chars: end });
+        self.read(start.bytes..end.bytes).chars().next()
+    }
+
     fn to_abs_pos(&self, pos: Metric) -> Metric {
         let chars = pos.chars;
         let bytes = if pos.bytes < self.gap_start {
@@ -801,6 +826,24 @@ mod test {
         buffer.delete_range(247, 45);
     }
 
+    #[test]
+    fn test_char_after() {
+        let mut buffer = Buffer::from("world");
+        buffer.insert("héllo ");
+        assert_eq!(buffer.cursor(), 6);
+        assert_eq!(buffer.char_after(0), Some('h'));
+        assert_eq!(buffer.char_after(1), Some('é'));
+        assert_eq!(buffer.char_after(5), Some(' '));
+        assert_eq!(buffer.char_after(6), Some('w'));
+        assert_eq!(buffer.char_after(10), Some('d'));
+        assert_eq!(buffer.char_after(11), None);
+        assert_eq!(buffer.char_after(20), None);
+        assert_eq!(buffer.char_before(0), None);
+        assert_eq!(buffer.char_before(2), Some('é'));
+        assert_eq!(buffer.char_before(6), Some(' '));
+        assert_eq!(buffer.char_before(11), Some('d'));
+    }
+
     #[test]
     fn test_pos() {
         let mut buffer = Buffer::new();
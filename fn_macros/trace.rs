@@ -44,7 +44,7 @@ pub(crate) fn expand(orig: &syn::DeriveInput) -> TokenStream {
                         } else {
                             new_fields.extend(quote! {#vis #ident: #rt<#ty>,});
                             mark_fields.extend(
-                                quote! {crate::core::gc::Trace::trace(&self.#ident, stack);},
+                                quote! {crate::core::gc::Trace::trace(&mut self.#ident, state);},
                             );
                         }
                     }
@@ -65,7 +65,7 @@ pub(crate) fn expand(orig: &syn::DeriveInput) -> TokenStream {
                         } else {
                             new_fields.extend(quote! {#vis #rt<#ty>,});
                             mark_fields
-                                .extend(quote! {crate::core::gc::Trace::trace(&self.#idx, stack);});
+                                .extend(quote! {crate::core::gc::Trace::trace(&mut self.#idx, state);});
                         }
                     }
                     new_fields = quote! {(#new_fields);};
@@ -75,7 +75,7 @@ pub(crate) fn expand(orig: &syn::DeriveInput) -> TokenStream {
             let test_mod = format_ident!("derive_trace_{orig_name}");
             quote! {
                 impl crate::core::gc::Trace for #orig_name #static_generics {
-                    fn trace(&self, stack: &mut Vec<crate::core::object::RawObj>) {
+                    fn trace(&mut self, state: &mut crate::core::gc::GcState) {
                         #mark_fields
                     }
                 }
@@ -12,3 +12,7 @@
 (defun substitute-command-keys (string &optional no-face)
   "stub of function for bootstrapping"
   string)
+
+(defun help--docstring-quote (string)
+  "stub of function for bootstrapping"
+  string)
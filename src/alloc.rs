@@ -1,10 +1,14 @@
-use crate::core::env::{Symbol, SymbolCell};
-use crate::core::gc::Context;
+use crate::core::env::{sym, Env, Symbol, SymbolCell};
+use crate::core::error::{Type, TypeError};
+use crate::core::gc::{Context, ObjKind, Rt};
 use crate::core::object::{
-    nil, ByteFn, FnArgs, Gc, GcObj, IntoObject, LispString, LispVec, RecordBuilder,
+    nil, ByteFn, FnArgs, Function, Gc, GcObj, IntoObject, LispBoolVec, LispFinalizer, LispString,
+    LispVec, Object, RecordBuilder,
 };
-use anyhow::{ensure, Result};
+use crate::root;
+use anyhow::{bail, ensure, Result};
 use fn_macros::defun;
+use std::cell::Cell;
 
 #[defun]
 pub(crate) fn list<'ob>(objects: &[GcObj<'ob>], cx: &'ob Context) -> GcObj<'ob> {
@@ -35,14 +39,20 @@ pub(crate) fn make_closure<'ob>(
 
     // TODO: returning an owned type is not safe here
     Ok(unsafe {
-        ByteFn::new(prototype.codes(), new_constants.untag(), prototype.args, prototype.depth)
+        ByteFn::new(
+            prototype.codes(),
+            new_constants.untag(),
+            prototype.args,
+            prototype.arglist(),
+            prototype.depth,
+        )
     })
 }
 
 #[defun]
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn make_byte_code<'ob>(
-    arglist: u64,
+    arglist: GcObj<'ob>,
     byte_code: &'ob LispString,
     constants: &'ob LispVec,
     depth: usize,
@@ -51,8 +61,25 @@ pub(crate) fn make_byte_code<'ob>(
     _elements: &[GcObj],
     cx: &'ob Context,
 ) -> Result<&'ob ByteFn> {
+    // A list of symbols is the argument list of a dynamically bound function,
+    // otherwise it is the integer spec of a lexical one.
+    let (args, arglist) = match arglist.untag() {
+        Object::Int(spec) => (FnArgs::from_arg_spec(u64::try_from(spec)?)?, None),
+        Object::NIL => (FnArgs::default(), None),
+        Object::Cons(list) => {
+            let (required, optional, rest) = crate::interpreter::parse_arg_list(list.into())?;
+            let args = FnArgs {
+                required: required.len() as u16,
+                optional: optional.len() as u16,
+                rest: rest.is_some(),
+                advice: false,
+            };
+            (args, Some(list))
+        }
+        x => bail!(TypeError::new(Type::List, x)),
+    };
     unsafe {
-        let bytefn = ByteFn::new(byte_code, constants, FnArgs::from_arg_spec(arglist)?, depth);
+        let bytefn = ByteFn::new(byte_code, constants, args, arglist, depth);
         Ok(bytefn.into_obj(cx).untag())
     }
 }
@@ -67,6 +94,18 @@ fn vector<'ob>(objects: &[GcObj<'ob>]) -> Vec<GcObj<'ob>> {
     objects.into()
 }
 
+/// Return a new bool-vector of LENGTH, with every element set to INIT.
+#[defun]
+fn make_bool_vector(length: usize, init: GcObj) -> LispBoolVec {
+    LispBoolVec::new(length, !init.nil())
+}
+
+/// Return a new bool-vector with OBJECTS as its elements.
+#[defun]
+fn bool_vector(objects: &[GcObj]) -> LispBoolVec {
+    LispBoolVec::from_bits(objects.iter().map(|x| !x.nil()))
+}
+
 #[defun]
 fn record<'ob>(type_: GcObj<'ob>, slots: &[GcObj<'ob>]) -> RecordBuilder<'ob> {
     let mut record = vec![type_];
@@ -74,6 +113,14 @@ fn record<'ob>(type_: GcObj<'ob>, slots: &[GcObj<'ob>]) -> RecordBuilder<'ob> {
     RecordBuilder(record)
 }
 
+/// Create a new record of type TYPE with SLOTS slots, each set to INIT.
+#[defun]
+fn make_record<'ob>(type_: GcObj<'ob>, slots: usize, init: GcObj<'ob>) -> RecordBuilder<'ob> {
+    let mut record = vec![init; slots + 1];
+    record[0] = type_;
+    RecordBuilder(record)
+}
+
 #[defun]
 fn purecopy(obj: GcObj) -> GcObj {
     obj
@@ -84,3 +131,205 @@ fn make_symbol<'ob>(name: &str, cx: &'ob Context) -> Gc<Symbol<'ob>> {
     let sym = SymbolCell::new_uninterned(name);
     sym.into_obj(cx)
 }
+
+thread_local! {
+    static IN_POST_GC_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Copy the values of `gc-cons-threshold` and `gc-cons-percentage` into the
+/// collector. They are only read again once one of them has been set.
+fn read_gc_settings(env: &mut Rt<Env>, cx: &mut Context) {
+    if !env.vars.take_gc_settings_changed() {
+        return;
+    }
+    let threshold = match env.vars.get(sym::GC_CONS_THRESHOLD).map(|x| x.get(cx)) {
+        Some(Object::Int(x)) => Some(usize::try_from(x).unwrap_or(0)),
+        _ => None,
+    };
+    let percentage = match env.vars.get(sym::GC_CONS_PERCENTAGE).map(|x| x.get(cx)) {
+        Some(Object::Float(x)) => Some(x.get()),
+        _ => None,
+    };
+    if let Some(threshold) = threshold {
+        cx.gc_threshold = threshold;
+    }
+    if let Some(percentage) = percentage {
+        cx.gc_percentage = percentage;
+    }
+}
+
+/// Update `gcs-done` and `gc-elapsed`, run the finalizers that were found
+/// unreachable, and run `post-gc-hook`. Like Emacs, errors in finalizers and
+/// the hook are ignored. These are not run again if they trigger a
+/// collection themselves.
+fn post_gc(env: &mut Rt<Env>, cx: &mut Context) {
+    let stats = cx.gc_stats();
+    let gcs_done = stats.gcs_done as i64;
+    let elapsed = stats.elapsed.as_secs_f64();
+    env.vars.insert(sym::GCS_DONE, cx.add(gcs_done));
+    env.vars.insert(sym::GC_ELAPSED, cx.add(elapsed));
+
+    if IN_POST_GC_HOOK.with(|x| x.replace(true)) {
+        return;
+    }
+    let finalizers = cx.take_pending_finalizers();
+    root!(finalizers, cx);
+    for finalizer in finalizers.iter() {
+        let function = finalizer.bind(cx).untag().take();
+        if let Ok(function) = Gc::<Function>::try_from(function) {
+            root!(function, cx);
+            root!(args, Vec::new(), cx);
+            let _ = function.call(args, env, cx, None);
+        }
+    }
+    root!(hooks, vec![sym::POST_GC_HOOK.into()], cx);
+    let _ = crate::eval::run_hooks(hooks, env, cx);
+    IN_POST_GC_HOOK.with(|x| x.set(false));
+}
+
+/// Collect garbage if enough has been allocated since the last collection.
+/// This is called at the safepoints of the interpreters.
+pub(crate) fn maybe_garbage_collect(env: &mut Rt<Env>, cx: &mut Context) {
+    read_gc_settings(env, cx);
+    let gcs_done = cx.gc_stats().gcs_done;
+    cx.garbage_collect(false);
+    if cx.gc_stats().gcs_done != gcs_done {
+        // The global block only grows when definitions change, so only check
+        // it when the local heap needed collecting.
+        if cx.should_collect_global() {
+            cx.collect_global();
+        }
+        post_gc(env, cx);
+    }
+}
+
+/// Reclaim storage for Lisp objects that are no longer needed. This includes
+/// the objects shared between threads, such as old function definitions.
+/// Returns a list of `(NAME SIZE USED FREE)` entries, where SIZE is the number
+/// of bytes taken by one object, USED is the number of live objects, and FREE
+/// is the number of objects freed by this collection. The last entry is `(heap
+/// 1024 TOTAL FREE)`, where TOTAL and FREE are the size of the heap and the
+/// space not yet allocated in units of 1024 bytes.
+#[defun]
+pub(crate) fn garbage_collect<'ob>(env: &mut Rt<Env>, cx: &'ob mut Context) -> GcObj<'ob> {
+    read_gc_settings(env, cx);
+    cx.garbage_collect(true);
+    cx.collect_global();
+    post_gc(env, cx);
+    let stats = cx.gc_stats();
+    let kinds = [
+        (sym::CONSES, ObjKind::Cons),
+        (sym::SYMBOLS, ObjKind::Symbol),
+        (sym::STRINGS, ObjKind::String),
+        (sym::VECTORS, ObjKind::Vec),
+        (sym::FLOATS, ObjKind::Float),
+        (sym::BYTE_FUNCTIONS, ObjKind::ByteFn),
+        (sym::HASH_TABLES, ObjKind::HashTable),
+        (sym::BUFFERS, ObjKind::Buffer),
+    ];
+    let mut entries: Vec<GcObj> = kinds
+        .into_iter()
+        .map(|(name, kind)| {
+            let size = kind.alloc_size();
+            list![name, size, stats.live.get(kind), stats.freed.get(kind); cx]
+        })
+        .collect();
+    let total = (stats.live.bytes() + stats.free_bytes) / 1024;
+    let free = stats.free_bytes / 1024;
+    entries.push(list![sym::HEAP, 1024, total, free; cx]);
+    list(&entries, cx)
+}
+
+/// Make a finalizer that will run FUNCTION. FUNCTION is called with no
+/// arguments after the finalizer becomes unreachable.
+#[defun]
+fn make_finalizer<'ob>(function: Gc<Function>, cx: &'ob Context) -> Gc<&'ob LispFinalizer> {
+    unsafe { LispFinalizer::new(function.into()) }.into_obj(cx)
+}
+
+defsym!(CONSES);
+defsym!(SYMBOLS);
+defsym!(STRINGS);
+defsym!(VECTORS);
+defsym!(FLOATS);
+defsym!(BYTE_FUNCTIONS);
+defsym!(HASH_TABLES);
+defsym!(BUFFERS);
+defsym!(HEAP);
+
+defvar!(GC_CONS_THRESHOLD, 800_000);
+defvar!(GC_CONS_PERCENTAGE, 0.1);
+defvar!(GCS_DONE, 0);
+defvar!(GC_ELAPSED, 0.0);
+defvar!(POST_GC_HOOK);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_garbage_collect() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let _live = list![1, 2, 3; cx];
+        root!(_live, cx);
+        let _garbage = cx.add("garbage");
+        let stats = rebind!(garbage_collect(env, cx));
+        let conses = list![sym::CONSES, ObjKind::Cons.alloc_size(), 3, 0; cx];
+        let entries: Vec<_> = stats.as_list().unwrap().map(|x| x.unwrap()).collect();
+        assert_eq!(entries[0], conses);
+        let strings: Vec<_> = entries[2].as_list().unwrap().map(|x| x.unwrap()).collect();
+        assert_eq!(strings[0], sym::STRINGS);
+        assert_eq!(strings[3], 1);
+        assert_eq!(env.vars.get(sym::GCS_DONE).unwrap().bind(cx), 1);
+        let elapsed = env.vars.get(sym::GC_ELAPSED).unwrap().bind(cx);
+        assert!(matches!(elapsed.untag(), Object::Float(_)));
+    }
+
+    #[test]
+    #[cfg(not(feature = "gc_stress"))]
+    fn test_gc_cons_threshold() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        env.vars.insert(sym::GC_CONS_THRESHOLD, cx.add(10_000));
+        maybe_garbage_collect(env, cx);
+        assert_eq!(cx.gc_stats().gcs_done, 0);
+        // strings are counted along with their contents
+        cx.add("x".repeat(6_000));
+        maybe_garbage_collect(env, cx);
+        assert_eq!(cx.gc_stats().gcs_done, 0);
+        cx.add("x".repeat(6_000));
+        maybe_garbage_collect(env, cx);
+        assert_eq!(cx.gc_stats().gcs_done, 1);
+        assert_eq!(env.vars.get(sym::GCS_DONE).unwrap().bind(cx), 1);
+        // a new threshold is used once it is set
+        env.vars.insert(sym::GC_CONS_THRESHOLD, cx.add(100_000));
+        cx.add("x".repeat(20_000));
+        maybe_garbage_collect(env, cx);
+        assert_eq!(cx.gc_stats().gcs_done, 1);
+    }
+
+    #[test]
+    fn test_finalizer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let form = "(progn (defvar finalized nil)
+                           (make-finalizer #'(lambda () (setq finalized t)))
+                           nil)";
+        let obj = crate::reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        crate::interpreter::eval(obj, None, env, cx).unwrap();
+        let finalized = crate::core::env::intern("finalized", cx);
+        root!(finalized, cx);
+        garbage_collect(env, cx);
+        assert_eq!(env.vars.get(finalized.bind(cx)).unwrap().bind(cx), sym::TRUE);
+        // finalizers are only run once
+        env.vars.insert(finalized.bind(cx), nil());
+        garbage_collect(env, cx);
+        assert_eq!(env.vars.get(finalized.bind(cx)).unwrap().bind(cx), sym::NIL);
+    }
+}
@@ -1,20 +1,70 @@
-use crate::core::object::{Gc, IntoObject, Number, Object};
+use crate::core::{
+    env::{sym, Env},
+    error::{EvalError, Type, TypeError},
+    gc::Rt,
+    object::{nil, Gc, IntoObject, Number, Object},
+};
+use anyhow::{bail, Result};
 use float_cmp::ApproxEq;
 use fn_macros::defun;
-use std::cmp::{PartialEq, PartialOrd};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+use std::cmp::{Ordering, PartialEq, PartialOrd};
 use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+/// The value of a number. Integer results that overflow an `i64` become
+/// `Big`, and are only normalized back to a fixnum when they are converted
+/// to an object. This keeps the common case of fixnum arithmetic to a single
+/// overflow check.
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum NumberValue {
     Int(i64),
     Float(f64),
+    Big(BigInt),
 }
 
 impl<'ob> Gc<Number<'ob>> {
     pub(crate) fn val(self) -> NumberValue {
         match self.untag() {
             Number::Int(x) => NumberValue::Int(x),
-            Number::Float(x) => NumberValue::Float(**x),
+            Number::Float(x) => NumberValue::Float(x.get()),
+            Number::BigInt(x) => NumberValue::Big(x.get().clone()),
+        }
+    }
+
+    /// The value of an integer, or a type error if this is a float.
+    pub(crate) fn int_val(self) -> Result<NumberValue, TypeError> {
+        match self.val() {
+            NumberValue::Float(_) => Err(TypeError::new(Type::Int, self)),
+            x => Ok(x),
+        }
+    }
+}
+
+impl NumberValue {
+    fn to_f64(&self) -> f64 {
+        match self {
+            NumberValue::Int(x) => *x as f64,
+            NumberValue::Float(x) => *x,
+            NumberValue::Big(x) => x.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Convert an integer to a bignum. Floats are truncated.
+    pub(crate) fn into_big(self) -> BigInt {
+        match self {
+            NumberValue::Int(x) => x.into(),
+            NumberValue::Big(x) => x,
+            NumberValue::Float(x) => BigInt::from_f64(x).unwrap_or_default(),
+        }
+    }
+
+    /// Convert a float that has no fractional part to an integer.
+    pub(crate) fn from_integral_float(x: f64) -> Self {
+        if (i64::MIN as f64..i64::MAX as f64).contains(&x) {
+            NumberValue::Int(x as i64)
+        } else {
+            NumberValue::Big(BigInt::from_f64(x).unwrap_or_default())
         }
     }
 }
@@ -24,8 +74,10 @@ impl IntoObject for NumberValue {
 
     fn into_obj<const C: bool>(self, block: &crate::core::gc::Block<C>) -> Gc<Self::Out<'_>> {
         match self {
-            NumberValue::Int(x) => x.into(),
+            NumberValue::Int(x) if crate::core::object::is_fixnum(x) => x.into(),
+            NumberValue::Int(x) => BigInt::from(x).into_obj(block),
             NumberValue::Float(x) => block.add(x),
+            NumberValue::Big(x) => x.into_obj(block),
         }
     }
 }
@@ -33,18 +85,19 @@ impl IntoObject for NumberValue {
 fn arith(
     cur: NumberValue,
     next: NumberValue,
-    int_fn: fn(i64, i64) -> i64,
+    int_fn: fn(i64, i64) -> Option<i64>,
+    big_fn: fn(BigInt, BigInt) -> BigInt,
     float_fn: fn(f64, f64) -> f64,
 ) -> NumberValue {
-    match cur {
-        NumberValue::Float(cur) => match next {
-            NumberValue::Float(next) => NumberValue::Float(float_fn(cur, next)),
-            NumberValue::Int(next) => NumberValue::Float(float_fn(cur, next as f64)),
-        },
-        NumberValue::Int(cur) => match next {
-            NumberValue::Float(next) => NumberValue::Float(float_fn(cur as f64, next)),
-            NumberValue::Int(next) => NumberValue::Int(int_fn(cur, next)),
+    match (cur, next) {
+        (NumberValue::Int(cur), NumberValue::Int(next)) => match int_fn(cur, next) {
+            Some(x) => NumberValue::Int(x),
+            None => NumberValue::Big(big_fn(cur.into(), next.into())),
         },
+        (cur @ NumberValue::Float(_), next) | (cur, next @ NumberValue::Float(_)) => {
+            NumberValue::Float(float_fn(cur.to_f64(), next.to_f64()))
+        }
+        (cur, next) => NumberValue::Big(big_fn(cur.into_big(), next.into_big())),
     }
 }
 
@@ -56,8 +109,12 @@ impl Neg for NumberValue {
     type Output = Self;
     fn neg(self) -> Self::Output {
         match self {
-            NumberValue::Int(x) => NumberValue::Int(-x),
+            NumberValue::Int(x) => match x.checked_neg() {
+                Some(x) => NumberValue::Int(x),
+                None => NumberValue::Big(-BigInt::from(x)),
+            },
             NumberValue::Float(x) => NumberValue::Float(-x),
+            NumberValue::Big(x) => NumberValue::Big(-x),
         }
     }
 }
@@ -65,43 +122,65 @@ impl Neg for NumberValue {
 impl Add for NumberValue {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Add::add, Add::add)
+        arith(self, rhs, i64::checked_add, Add::add, Add::add)
     }
 }
 
 impl Sub for NumberValue {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Sub::sub, Sub::sub)
+        arith(self, rhs, i64::checked_sub, Sub::sub, Sub::sub)
     }
 }
 
 impl Mul for NumberValue {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Mul::mul, Mul::mul)
+        arith(self, rhs, i64::checked_mul, Mul::mul, Mul::mul)
     }
 }
 
-impl Div for NumberValue {
-    type Output = Self;
-    fn div(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Div::div, Div::div)
+impl NumberValue {
+    /// True if dividing `self` by `rhs` is an integer division by zero. Float
+    /// division by zero follows IEEE instead.
+    fn is_int_div_by_zero(&self, rhs: &Self) -> bool {
+        match (self, rhs) {
+            (NumberValue::Float(_), _) | (_, NumberValue::Float(_)) => false,
+            (_, NumberValue::Int(x)) => *x == 0,
+            (_, NumberValue::Big(x)) => x.is_zero(),
+        }
     }
-}
 
-impl Rem for NumberValue {
-    type Output = Self;
-    fn rem(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Rem::rem, Rem::rem)
+    /// Divide by `rhs`, or `None` for an integer division by zero. The only
+    /// fixnum division that overflows is `i64::MIN / -1`, which becomes a
+    /// bignum.
+    pub(crate) fn checked_div(self, rhs: Self) -> Option<Self> {
+        if self.is_int_div_by_zero(&rhs) {
+            return None;
+        }
+        Some(arith(self, rhs, i64::checked_div, Div::div, Div::div))
+    }
+
+    /// The remainder of dividing by `rhs`, or `None` for an integer division
+    /// by zero.
+    pub(crate) fn checked_rem(self, rhs: Self) -> Option<Self> {
+        if self.is_int_div_by_zero(&rhs) {
+            return None;
+        }
+        Some(arith(self, rhs, i64::checked_rem, Rem::rem, Rem::rem))
     }
 }
 
+pub(crate) fn arith_error(env: &mut Rt<Env>) -> anyhow::Error {
+    EvalError::signal(sym::ARITH_ERROR.into(), nil(), env).into()
+}
+
 impl<'ob> PartialEq<i64> for Gc<Number<'ob>> {
     fn eq(&self, other: &i64) -> bool {
         match self.val() {
             NumberValue::Int(num) => num == *other,
             NumberValue::Float(num) => num == *other as f64,
+            NumberValue::Big(num) => num == BigInt::from(*other),
         }
     }
 }
@@ -111,21 +190,19 @@ impl<'ob> PartialEq<f64> for Gc<Number<'ob>> {
         match self.val() {
             NumberValue::Int(num) => num as f64 == *other,
             NumberValue::Float(num) => num.approx_eq(*other, (f64::EPSILON, 2)),
+            NumberValue::Big(num) => num.to_f64() == Some(*other),
         }
     }
 }
 
 impl PartialOrd for NumberValue {
-    fn partial_cmp(&self, other: &NumberValue) -> Option<std::cmp::Ordering> {
-        match self {
-            NumberValue::Int(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(rhs),
-                NumberValue::Float(rhs) => (*lhs as f64).partial_cmp(rhs),
-            },
-            NumberValue::Float(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(&(*rhs as f64)),
-                NumberValue::Float(rhs) => lhs.partial_cmp(rhs),
-            },
+    fn partial_cmp(&self, other: &NumberValue) -> Option<Ordering> {
+        match (self, other) {
+            (NumberValue::Int(lhs), NumberValue::Int(rhs)) => lhs.partial_cmp(rhs),
+            (NumberValue::Float(_), _) | (_, NumberValue::Float(_)) => {
+                self.to_f64().partial_cmp(&other.to_f64())
+            }
+            (lhs, rhs) => lhs.clone().into_big().partial_cmp(&rhs.clone().into_big()),
         }
     }
 }
@@ -156,8 +233,16 @@ pub(crate) fn mul(numbers: &[Gc<Number>]) -> NumberValue {
 }
 
 #[defun(name = "/")]
-pub(crate) fn div(number: Gc<Number>, divisors: &[Gc<Number>]) -> NumberValue {
-    divisors.iter().fold(number.val(), |acc, x| acc / x.val())
+pub(crate) fn div(
+    number: Gc<Number>,
+    divisors: &[Gc<Number>],
+    env: &mut Rt<Env>,
+) -> Result<NumberValue> {
+    let mut acc = number.val();
+    for x in divisors {
+        acc = acc.checked_div(x.val()).ok_or_else(|| arith_error(env))?;
+    }
+    Ok(acc)
 }
 
 #[defun(name = "1+")]
@@ -175,6 +260,7 @@ pub(crate) fn num_eq(number: Gc<Number>, numbers: &[Gc<Number>]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x == num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x == num),
+        num @ NumberValue::Big(_) => numbers.iter().all(|&x| x.val() == num),
     }
 }
 
@@ -184,6 +270,7 @@ pub(crate) fn num_ne(number: Gc<Number>, numbers: &[Gc<Number>]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x != num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x != num),
+        num @ NumberValue::Big(_) => numbers.iter().all(|&x| x.val() != num),
     }
 }
 
@@ -192,10 +279,15 @@ fn cmp(
     numbers: &[Gc<Number>],
     cmp: fn(&NumberValue, &NumberValue) -> bool,
 ) -> bool {
-    numbers
-        .iter()
-        .try_fold(number.val(), |acc, &x| cmp(&acc, &x.val()).then_some(NumberValue::Int(0)))
-        .is_some()
+    let mut prev = number.val();
+    for x in numbers {
+        let next = x.val();
+        if !cmp(&prev, &next) {
+            return false;
+        }
+        prev = next;
+    }
+    true
 }
 
 #[defun(name = "<")]
@@ -218,19 +310,76 @@ pub(crate) fn greater_than_or_eq(number: Gc<Number>, numbers: &[Gc<Number>]) ->
     cmp(number, numbers, NumberValue::ge)
 }
 
+/// Combine integers bitwise, using bignums only if one of the arguments is
+/// a bignum.
+fn bitwise(
+    init: i64,
+    ints: &[Gc<Number>],
+    int_fn: fn(i64, i64) -> i64,
+    big_fn: fn(&BigInt, &BigInt) -> BigInt,
+) -> Result<NumberValue> {
+    let mut acc = NumberValue::Int(init);
+    for x in ints {
+        acc = match (acc, x.int_val()?) {
+            (NumberValue::Int(acc), NumberValue::Int(x)) => NumberValue::Int(int_fn(acc, x)),
+            (acc, x) => NumberValue::Big(big_fn(&acc.into_big(), &x.into_big())),
+        };
+    }
+    Ok(acc)
+}
+
+#[defun]
+pub(crate) fn logior(ints_or_markers: &[Gc<Number>]) -> Result<NumberValue> {
+    bitwise(0, ints_or_markers, |x, y| x | y, |x, y| x | y)
+}
+
 #[defun]
-pub(crate) fn logior(ints_or_markers: &[Gc<i64>]) -> i64 {
-    ints_or_markers.iter().fold(0, |acc, x| acc | x.untag())
+fn logand(int_or_markers: &[Gc<Number>]) -> Result<NumberValue> {
+    bitwise(-1, int_or_markers, |x, y| x & y, |x, y| x & y)
 }
 
 #[defun]
-fn logand(int_or_markers: &[Gc<i64>]) -> i64 {
-    int_or_markers.iter().fold(-1, |accum, x| accum & x.untag())
+fn logxor(ints_or_markers: &[Gc<Number>]) -> Result<NumberValue> {
+    bitwise(0, ints_or_markers, |x, y| x ^ y, |x, y| x ^ y)
+}
+
+#[defun]
+fn lognot(number: Gc<Number>) -> Result<NumberValue> {
+    Ok(match number.int_val()? {
+        NumberValue::Int(x) => NumberValue::Int(!x),
+        x => NumberValue::Big(!x.into_big()),
+    })
+}
+
+/// Return BASE raised to POWER. The result is an integer if both arguments
+/// are integers and POWER is not negative.
+#[defun]
+fn expt(base: Gc<Number>, power: Gc<Number>) -> Result<NumberValue> {
+    let (base, power) = (base.val(), power.val());
+    let is_float = |x: &NumberValue| matches!(x, NumberValue::Float(_));
+    if is_float(&base) || is_float(&power) || power < NumberValue::Int(0) {
+        return Ok(NumberValue::Float(base.to_f64().powf(power.to_f64())));
+    }
+    if let (NumberValue::Int(x), NumberValue::Int(y)) = (&base, &power) {
+        if let Some(x) = u32::try_from(*y).ok().and_then(|y| x.checked_pow(y)) {
+            return Ok(NumberValue::Int(x));
+        }
+    }
+    let (base, power) = (base.into_big(), power.into_big());
+    match power.to_u32() {
+        Some(power) => Ok(NumberValue::Big(base.pow(power))),
+        // Only 0, 1 and -1 can be raised to a power this large
+        None if base.abs() <= BigInt::from(1) => {
+            let odd = power.bit(0);
+            Ok(NumberValue::Big(if base.is_negative() && !odd { base.abs() } else { base }))
+        }
+        None => bail!("Overflow error: {base} to the power {power}"),
+    }
 }
 
 #[defun(name = "mod")]
-pub(crate) fn modulo(x: Gc<Number>, y: Gc<Number>) -> NumberValue {
-    x.val() % y.val()
+pub(crate) fn modulo(x: Gc<Number>, y: Gc<Number>, env: &mut Rt<Env>) -> Result<NumberValue> {
+    x.val().checked_rem(y.val()).ok_or_else(|| arith_error(env))
 }
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -260,7 +409,9 @@ pub(crate) fn min(number_or_marker: Gc<Number>, number_or_markers: &[Gc<Number>]
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::core::error::ErrorType;
     use crate::core::gc::{Context, RootSet};
+    use crate::root;
 
     #[test]
     fn test_add() {
@@ -290,10 +441,49 @@ mod test {
     #[test]
     fn test_div() {
         let roots = &RootSet::default();
-        let cx = &Context::new(roots);
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+
+        assert_eq!(div(cx.add_as(12.0), &[], env).unwrap(), NumberValue::Float(12.0));
+        assert_eq!(div(12.into(), &[5.into(), 2.into()], env).unwrap(), NumberValue::Int(1));
+        let min = cx.add(NumberValue::Int(i64::MIN)).try_into().unwrap();
+        let quotient = div(min, &[(-1).into()], env).unwrap();
+        assert_eq!(quotient, NumberValue::Big(-BigInt::from(i64::MIN)));
+    }
 
-        assert_eq!(div(cx.add_as(12.0), &[]), NumberValue::Float(12.0));
-        assert_eq!(div(12.into(), &[5.into(), 2.into()]), NumberValue::Int(1));
+    #[test]
+    fn test_div_by_zero() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let assert_arith_error = |err: anyhow::Error, env: &Rt<Env>| {
+            let Ok(EvalError { error: ErrorType::Signal(id), .. }) = err.downcast::<EvalError>()
+            else {
+                panic!("expected a signal")
+            };
+            let (symbol, _) = env.get_exception(id).unwrap();
+            assert_eq!(*symbol, sym::ARITH_ERROR);
+        };
+        let big: Gc<Number> = cx.add(BigInt::from(1) << 70_usize).try_into().unwrap();
+        let zero = 0.into();
+
+        let err = div(7.into(), &[zero], env).unwrap_err();
+        assert_arith_error(err, env);
+        let err = div(big, &[zero], env).unwrap_err();
+        assert_arith_error(err, env);
+        let err = div(big, &[2.into(), zero], env).unwrap_err();
+        assert_arith_error(err, env);
+        let err = modulo(7.into(), zero, env).unwrap_err();
+        assert_arith_error(err, env);
+        let err = modulo(big, zero, env).unwrap_err();
+        assert_arith_error(err, env);
+
+        // Float division by zero follows IEEE
+        let inf = div(cx.add_as(1.0), &[zero], env).unwrap();
+        assert_eq!(inf, NumberValue::Float(f64::INFINITY));
+        let inf = div(big, &[cx.add_as(0.0)], env).unwrap();
+        assert_eq!(inf, NumberValue::Float(f64::INFINITY));
+        assert_eq!(modulo(7.into(), 2.into(), env).unwrap(), NumberValue::Int(1));
     }
 
     #[test]
@@ -338,6 +528,47 @@ mod test {
     fn test_other() {
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
-        assert_eq!(logand(&[258.into_obj(cx), 255.into_obj(cx)]), 2);
+        assert_eq!(logand(&[258.into(), 255.into()]).unwrap(), NumberValue::Int(2));
+        assert!(logand(&[cx.add_as(1.0)]).is_err());
+    }
+
+    #[test]
+    fn test_bignum() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let num = |x: NumberValue| -> Gc<Number> { cx.add(x).try_into().unwrap() };
+        let big = |x: BigInt| num(NumberValue::Big(x));
+        let fixnum = crate::core::object::MOST_POSITIVE_FIXNUM;
+        let max = num(NumberValue::Int(fixnum));
+        let overflow = BigInt::from(i64::MAX) + 1_i64;
+
+        // Overflowing an i64 promotes to a bignum
+        let product = mul(&[max, max, 2.into()]);
+        assert_eq!(product, NumberValue::Big(BigInt::from(fixnum) * fixnum * 2));
+        let negated = sub(Some(big(BigInt::from(i64::MIN))), &[]);
+        assert_eq!(negated, NumberValue::Big(overflow.clone()));
+        let power = expt(2.into(), 64.into()).unwrap();
+        assert_eq!(power, NumberValue::Big(BigInt::from(1) << 64_usize));
+        assert_eq!(expt(2.into(), (-1).into()).unwrap(), NumberValue::Float(0.5));
+        let power = expt((-1).into(), big(overflow.clone())).unwrap();
+        assert_eq!(power, NumberValue::Big(1.into()));
+
+        // Results are normalized to fixnums when they fit
+        let sum = cx.add(add(&[big(overflow.clone()), (-1).into(), big(-overflow.clone())]));
+        assert_eq!(sum, -1);
+        assert_eq!(cx.add(mul(&[big(overflow.clone()), 0.into()])), 0);
+        assert!(matches!(cx.add(add_one(max)).untag(), Object::BigInt(_)));
+        assert_eq!(cx.add(sub_one(num(NumberValue::Int(fixnum + 1)))), fixnum);
+
+        assert!(less_than(1.into(), &[big(overflow.clone())]));
+        assert!(greater_than(big(overflow.clone()), &[max, cx.add_as(1.0)]));
+        assert!(num_eq(big(overflow.clone()), &[big(overflow.clone())]));
+        let (x, y) = (big(overflow.clone()), big(overflow.clone()));
+        assert!(!x.ptr_eq(y) && crate::fns::eql(x.into(), y.into()));
+        assert!(num_ne(big(overflow.clone()), &[max]));
+        let not = lognot(big(overflow.clone())).unwrap();
+        assert_eq!(not, NumberValue::Big(-overflow.clone() - 1));
+        let or = logior(&[big(overflow.clone()), 1.into()]).unwrap();
+        assert_eq!(or, NumberValue::Big(overflow + 1));
     }
 }
@@ -3,7 +3,7 @@ use crate::{
         env::{Env, INTERNED_SYMBOLS},
         error::{Type, TypeError},
         gc::{Context, Rt},
-        object::{GcObj, LispBuffer, Object},
+        object::{Gc, GcObj, LispBuffer, Object},
     },
     hashmap::HashMap,
 };
@@ -54,6 +54,39 @@ fn buffer_live_p(buffer: GcObj, env: &mut Rt<Env>) -> bool {
     }
 }
 
+/// Kill the buffer BUFFER-OR-NAME, which defaults to the current buffer.
+/// Returns t if the buffer was killed.
+#[defun]
+pub(crate) fn kill_buffer(
+    buffer_or_name: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let buffer: &LispBuffer = match buffer_or_name.map(Gc::untag) {
+        None => match env.buffer_list.bind_mut(cx).front() {
+            Some(buffer) => buffer,
+            None => return Ok(false),
+        },
+        Some(Object::Buffer(b)) => b,
+        Some(Object::String(s)) => {
+            let name: &str = s.try_into()?;
+            let buffer_list = BUFFERS.lock().unwrap();
+            let Some(buffer) = buffer_list.get(name) else {
+                bail!("No such buffer {name}");
+            };
+            cx.bind(*buffer)
+        }
+        Some(x) => bail!(TypeError::new(Type::String, x)),
+    };
+    match env.kill_buffer(buffer) {
+        Some(name) => {
+            BUFFERS.lock().unwrap().remove(&name);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
 #[defun]
 pub(crate) fn get_buffer_create<'ob>(
     buffer_or_name: GcObj<'ob>,
@@ -90,6 +123,7 @@ pub(crate) fn get_buffer_create<'ob>(
 mod test {
     use crate::core::env::sym;
     use crate::core::gc::RootSet;
+    use crate::root;
 
     use super::*;
 
@@ -100,4 +134,23 @@ mod test {
         let buffer = get_buffer_create(cx.add("test_create_buffer"), sym::NIL.into(), cx).unwrap();
         assert!(matches!(buffer.untag(), Object::Buffer(_)));
     }
+
+    #[test]
+    fn test_kill_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let name = cx.add("test_kill_buffer");
+        let buffer = get_buffer_create(name, sym::NIL.into(), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        assert!(buffer_live_p(buffer, env));
+        assert!(kill_buffer(None, env, cx).unwrap());
+        assert!(!buffer_live_p(buffer, env));
+        assert!(env.current_buffer.is_none());
+        assert!(!kill_buffer(Some(buffer), env, cx).unwrap());
+        // the name can be used by a new buffer
+        let new = get_buffer_create(name, sym::NIL.into(), cx).unwrap();
+        assert!(!new.ptr_eq(buffer));
+        assert!(kill_buffer(Some(name), env, cx).unwrap());
+    }
 }
@@ -1,8 +1,11 @@
 //! The main bytecode interpeter.
-use crate::core::env::{sym, Env, Symbol};
+use crate::core::env::{sym, Env, Symbol, TrappedWrite};
 use crate::core::error::{ErrorType, EvalError, EvalResult};
 use crate::core::gc::{Context, IntoRoot, Rt, Trace};
-use crate::core::object::{nil, ByteFn, Gc, GcObj, LispString, LispVec, Object, WithLifetime};
+use crate::core::object::{
+    nil, ByteFn, Gc, GcObj, LispString, LispVec, Object, TagType, WithLifetime,
+};
+use crate::fns::signal_circular_list;
 use crate::root;
 use anyhow::{bail, Result};
 use bstr::ByteSlice;
@@ -72,7 +75,7 @@ impl ProgramCounter {
 #[derive(Clone)]
 struct CallFrame<'brw> {
     pc: ProgramCounter,
-    consts: &'brw Rt<&'static LispVec>,
+    func: &'brw Rt<&'static ByteFn>,
     /// The index where this call frame starts on the stack. The interpreter
     /// should not access elements beyond this index.
     start: usize,
@@ -81,14 +84,16 @@ struct CallFrame<'brw> {
 impl<'brw> CallFrame<'brw> {
     fn new(func: &'brw Rt<&'static ByteFn>, frame_start: usize, cx: &Context) -> CallFrame<'brw> {
         CallFrame {
-            pc: ProgramCounter::new(func.code().bind(cx).as_bytes()),
-            consts: func.consts(),
+            pc: ProgramCounter::new(func.bind(cx).codes().as_bytes()),
+            func,
             start: frame_start,
         }
     }
 
     fn get_const<'ob>(&self, i: usize, cx: &'ob Context) -> GcObj<'ob> {
-        self.consts.bind(cx).get(i).expect("constant had invalid index").get()
+        // The function can be moved by the collector, so always go through the
+        // root to get the constants.
+        self.func.bind(cx).constants().get(i).expect("constant had invalid index").get()
     }
 }
 
@@ -153,8 +158,8 @@ impl LispStack {
 }
 
 impl Trace for LispStack {
-    fn trace(&self, stack: &mut Vec<crate::core::object::RawObj>) {
-        self.0.trace(stack);
+    fn trace(&mut self, state: &mut crate::core::gc::GcState) {
+        self.0.trace(state);
     }
 }
 
@@ -238,7 +243,7 @@ impl<'brw, 'ob> Routine<'brw> {
     fn varref(&mut self, idx: u16, env: &Rt<Env>, cx: &'ob Context) -> Result<()> {
         let symbol = self.frame.get_const(idx as usize, cx);
         if let Object::Symbol(sym) = symbol.untag() {
-            let Some(var) = env.vars.get(sym) else { bail!("Void Variable: {sym}") };
+            let Some(var) = env.var(sym) else { bail!("Void Variable: {sym}") };
             self.stack.push(var.bind(cx));
             Ok(())
         } else {
@@ -246,25 +251,41 @@ impl<'brw, 'ob> Routine<'brw> {
         }
     }
 
-    fn varset(&mut self, idx: usize, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
-        let obj = self.frame.get_const(idx, cx);
-        let symbol: Symbol = obj.try_into()?;
+    fn varset(&mut self, idx: usize, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+        let symbol: Gc<Symbol> = self.frame.get_const(idx, cx).try_into()?;
         let value = self.stack.pop(cx);
-        crate::data::set(symbol, value, env)?;
+        if !Self::has_watchers(symbol.untag()) {
+            return env.set_var(symbol.untag(), value, cx);
+        }
+        root!(symbol, cx);
+        root!(value, cx);
+        crate::data::set(symbol, value, env, cx)?;
         Ok(())
     }
 
-    fn varbind(&mut self, idx: u16, env: &mut Rt<Env>, cx: &'ob Context) {
+    fn varbind(&mut self, idx: u16, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
         let value = self.stack.pop(cx);
         let symbol = self.frame.get_const(idx as usize, cx);
-        let Object::Symbol(sym) = symbol.untag() else {
+        let Ok(symbol) = Gc::<Symbol>::try_from(symbol) else {
             unreachable!("Varbind was not a symbol: {:?}", symbol)
         };
-        env.varbind(sym, value, cx);
+        if !Self::has_watchers(symbol.untag()) {
+            return env.varbind(symbol.untag(), value, cx);
+        }
+        root!(symbol, cx);
+        root!(value, cx);
+        crate::data::notify_variable_watchers(symbol, value, sym::LET, env, cx)?;
+        env.varbind(symbol.bind(cx).untag(), value.bind(cx), cx)
+    }
+
+    /// Variables without watchers can be set without rooting anything, since
+    /// no lisp code will run.
+    fn has_watchers(symbol: Symbol) -> bool {
+        symbol.indirect_variable().trapped_write() == TrappedWrite::Trapped
     }
 
-    fn unbind(&self, idx: u16, env: &mut Rt<Env>, cx: &'ob Context) {
-        env.unbind(idx, cx);
+    fn unbind(&self, idx: u16, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+        crate::data::unbind(idx, env, cx)
     }
 
     #[inline(always)]
@@ -300,6 +321,35 @@ impl<'brw, 'ob> Routine<'brw> {
         }
     }
 
+    /// Bind the arguments of a dynamically bound function to the variables in
+    /// its argument list. The arguments are removed from the stack, and the
+    /// number of bindings made is returned.
+    fn bind_dynamic_args(
+        &mut self,
+        func: &Rt<&'static ByteFn>,
+        env: &mut Rt<Env>,
+        cx: &mut Context,
+    ) -> Result<u16> {
+        let Some(arglist) = func.bind(cx).arglist() else { return Ok(0) };
+        let (required, optional, rest) = crate::interpreter::parse_arg_list(arglist.into())?;
+        let vars = required.into_iter().chain(optional).chain(rest);
+        // the stack only holds the arguments, in the order they were passed
+        let args: Vec<_> = vars
+            .zip(Rt::bind_slice(self.stack, cx))
+            .map(|(var, val)| (var.tag(), *val))
+            .collect();
+        root!(args, move(args), cx);
+        self.stack.clear();
+        for i in 0..args.len() {
+            let (var, val) = &*args[i];
+            if Self::has_watchers(var.bind(cx).untag()) {
+                crate::data::notify_variable_watchers(var, val, sym::LET, env, cx)?;
+            }
+            env.varbind(var.bind(cx).untag(), val.bind(cx), cx)?;
+        }
+        Ok(args.len() as u16)
+    }
+
     fn call(
         &mut self,
         arg_cnt: u16,
@@ -321,7 +371,7 @@ impl<'brw, 'ob> Routine<'brw> {
         let result = func.call(args, env, cx, Some(&name))?;
         self.stack.remove_top(arg_cnt);
         self.stack[0].set(result);
-        cx.garbage_collect(false);
+        crate::alloc::maybe_garbage_collect(env, cx);
         Ok(())
     }
 
@@ -332,33 +382,24 @@ impl<'brw, 'ob> Routine<'brw> {
                 Err(e) => e,
             };
 
-            // we will fix this once we can handle different error types
-            #[allow(clippy::never_loop)]
+            if matches!(err.error, ErrorType::Throw(_)) {
+                return Err(err);
+            }
+            let error = if let EvalError { error: ErrorType::Signal(id), .. } = err {
+                let Some((sym, data)) = env.get_exception(id) else {
+                    unreachable!("Exception not found")
+                };
+                cons!(sym, data; cx)
+            } else {
+                // TODO: Need to remove the anyhow branch once
+                // full errors are implemented
+                cons!(sym::ERROR, format!("{err}"); cx)
+            };
+            let error_symbol = error.as_cons().car();
             while let Some(handler) = self.handlers.bind_mut(cx).pop() {
-                match handler.condition.untag() {
-                    Object::Symbol(sym::ERROR) => {}
-                    Object::Cons(cons) => {
-                        for x in cons.elements() {
-                            let x = x?;
-                            // TODO: Handle different error symbols
-                            if x != sym::DEBUG && x != sym::ERROR {
-                                bail_err!("non-error conditions {x} not yet supported")
-                            }
-                        }
-                    }
-                    x => bail_err!("Invalid condition handler: {x}"),
+                if !crate::eval::handles_error(handler.condition, error_symbol, env, cx)? {
+                    continue;
                 }
-
-                let error = if let EvalError { error: ErrorType::Signal(id), .. } = err {
-                    let Some((sym, data)) = env.get_exception(id) else {
-                        unreachable!("Exception not found")
-                    };
-                    cons!(sym, data; cx)
-                } else {
-                    // TODO: Need to remove the anyhow branch once
-                    // full errors are implemented
-                    cons!(sym::ERROR, format!("{err}"); cx)
-                };
                 self.stack.truncate(handler.stack_size);
                 self.stack.push(error);
                 self.frame.pc.goto(handler.jump_code);
@@ -374,6 +415,7 @@ impl<'brw, 'ob> Routine<'brw> {
         use crate::{alloc, arith, data, fns};
         use opcode::OpCode as op;
         loop {
+            cx.stress_collect();
             let op = match self.frame.pc.next().try_into() {
                 Ok(x) => x,
                 Err(e) => panic!("Invalid Bytecode: {e}"),
@@ -439,19 +481,19 @@ impl<'brw, 'ob> Routine<'brw> {
                     let idx = self.frame.pc.arg2();
                     self.varset(idx.into(), env, cx)?;
                 }
-                op::VarBind0 => self.varbind(0, env, cx),
-                op::VarBind1 => self.varbind(1, env, cx),
-                op::VarBind2 => self.varbind(2, env, cx),
-                op::VarBind3 => self.varbind(3, env, cx),
-                op::VarBind4 => self.varbind(4, env, cx),
-                op::VarBind5 => self.varbind(5, env, cx),
+                op::VarBind0 => self.varbind(0, env, cx)?,
+                op::VarBind1 => self.varbind(1, env, cx)?,
+                op::VarBind2 => self.varbind(2, env, cx)?,
+                op::VarBind3 => self.varbind(3, env, cx)?,
+                op::VarBind4 => self.varbind(4, env, cx)?,
+                op::VarBind5 => self.varbind(5, env, cx)?,
                 op::VarBindN => {
                     let idx = self.frame.pc.arg1();
-                    self.varbind(idx, env, cx);
+                    self.varbind(idx, env, cx)?;
                 }
                 op::VarBindN2 => {
                     let idx = self.frame.pc.arg2();
-                    self.varbind(idx, env, cx);
+                    self.varbind(idx, env, cx)?;
                 }
                 op::Call0 => self.call(0, env, cx)?,
                 op::Call1 => self.call(1, env, cx)?,
@@ -467,19 +509,19 @@ impl<'brw, 'ob> Routine<'brw> {
                     let idx = self.frame.pc.arg2();
                     self.call(idx, env, cx)?;
                 }
-                op::Unbind0 => self.unbind(0, env, cx),
-                op::Unbind1 => self.unbind(1, env, cx),
-                op::Unbind2 => self.unbind(2, env, cx),
-                op::Unbind3 => self.unbind(3, env, cx),
-                op::Unbind4 => self.unbind(4, env, cx),
-                op::Unbind5 => self.unbind(5, env, cx),
+                op::Unbind0 => self.unbind(0, env, cx)?,
+                op::Unbind1 => self.unbind(1, env, cx)?,
+                op::Unbind2 => self.unbind(2, env, cx)?,
+                op::Unbind3 => self.unbind(3, env, cx)?,
+                op::Unbind4 => self.unbind(4, env, cx)?,
+                op::Unbind5 => self.unbind(5, env, cx)?,
                 op::UnbindN => {
                     let idx = self.frame.pc.arg1();
-                    self.unbind(idx, env, cx);
+                    self.unbind(idx, env, cx)?;
                 }
                 op::UnbindN2 => {
                     let idx = self.frame.pc.arg2();
-                    self.unbind(idx, env, cx);
+                    self.unbind(idx, env, cx)?;
                 }
                 op::PopHandler => {
                     self.handlers.pop();
@@ -590,8 +632,11 @@ impl<'brw, 'ob> Routine<'brw> {
                 }
                 op::Set => {
                     let newlet = self.stack.pop(cx);
-                    let top = self.stack.top();
-                    top.set(data::set(top.bind_as(cx)?, newlet, env)?);
+                    root!(newlet, cx);
+                    let place: Gc<Symbol> = self.stack.top().bind_as(cx)?;
+                    root!(place, cx);
+                    let value = data::set(place, newlet, env, cx)?;
+                    self.stack.top().set(value);
                 }
                 op::Fset => {
                     let def = self.stack.pop(cx);
@@ -778,7 +823,7 @@ impl<'brw, 'ob> Routine<'brw> {
                 op::Equal => {
                     let rhs = self.stack.pop(cx);
                     let top = self.stack.top();
-                    top.set(fns::equal(top.bind(cx), rhs));
+                    top.set(fns::equal(top.bind(cx), rhs, env, cx)?);
                 }
                 op::Nthcdr => {
                     let list = self.stack.pop(cx);
@@ -793,7 +838,7 @@ impl<'brw, 'ob> Routine<'brw> {
                 op::Member => {
                     let list = self.stack.pop(cx);
                     let top = self.stack.top();
-                    top.set(fns::member(top.bind(cx), list.try_into()?)?);
+                    top.set(fns::member(top.bind(cx), list.try_into()?, env, cx)?);
                 }
                 op::Assq => {
                     let alist = self.stack.pop(cx);
@@ -852,8 +897,9 @@ impl<'brw, 'ob> Routine<'brw> {
                         unreachable!("switch table was not a hash table")
                     };
                     let cond = self.stack.pop(cx);
-                    if let Some(offset) = table.borrow().get(&cond) {
-                        let Object::Int(offset) = offset.get().untag() else {
+                    let offset = table.get(cond);
+                    if let Some(offset) = offset.or_else(|e| signal_circular_list(e, env, cx))? {
+                        let Object::Int(offset) = offset.untag() else {
                             unreachable!("switch value was not a int")
                         };
                         self.frame.pc.goto(offset as u16);
@@ -940,7 +986,7 @@ fn byte_code<'ob>(
     cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>> {
     let fun = crate::alloc::make_byte_code(
-        0,
+        0.into(),
         bytestr.get(cx),
         vector.get(cx),
         maxdepth,
@@ -967,7 +1013,14 @@ pub(crate) fn call<'ob>(
     let mut rout =
         Routine { stack, call_frames: vec![], frame: CallFrame::new(func, 0, cx), handlers };
     rout.prepare_lisp_args(func.bind(cx), arg_cnt, name, cx)?;
-    rout.run(env, cx)
+    let bindings = rout.bind_dynamic_args(func, env, cx)?;
+    if bindings == 0 {
+        return rout.run(env, cx);
+    }
+    let result = rebind!(rout.run(env, cx)?);
+    root!(result, cx);
+    crate::data::unbind(bindings, env, cx)?;
+    Ok(result.bind(cx))
 }
 
 #[allow(clippy::enum_glob_use)]
@@ -1000,7 +1053,7 @@ mod test {
             println!("Test seq: {opcodes:?}");
             opcodes.into_obj(cx1).untag()
         };
-        let bytecode = crate::alloc::make_byte_code($arglist, &opcodes, constants, 0, None, None, &[], cx1).unwrap();
+        let bytecode = crate::alloc::make_byte_code($arglist.into(), &opcodes, constants, 0, None, None, &[], cx1).unwrap();
         root!(bytecode, cx1);
         let $name = bytecode;
         )
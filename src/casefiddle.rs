@@ -0,0 +1,70 @@
+use crate::core::{
+    error::{Type, TypeError},
+    gc::Context,
+    object::{GcObj, Object},
+};
+use anyhow::{bail, Result};
+use fn_macros::defun;
+
+/// Convert a character code to the single character produced by `convert`. If
+/// the conversion would produce more than one character, the character is
+/// unchanged.
+fn convert_char<I>(chr: i64, convert: impl Fn(char) -> I) -> i64
+where
+    I: Iterator<Item = char>,
+{
+    let Some(c) = u32::try_from(chr).ok().and_then(char::from_u32) else { return chr };
+    let mut converted = convert(c);
+    match (converted.next(), converted.next()) {
+        (Some(x), None) => i64::from(u32::from(x)),
+        _ => chr,
+    }
+}
+
+fn convert_case<'ob, I>(
+    obj: GcObj<'ob>,
+    convert: impl Fn(char) -> I,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>>
+where
+    I: Iterator<Item = char>,
+{
+    match obj.untag() {
+        Object::Int(chr) => Ok(convert_char(chr, convert).into()),
+        Object::String(string) => {
+            let s: &str = string.try_into()?;
+            let converted: String = s.chars().flat_map(convert).collect();
+            Ok(cx.add(converted))
+        }
+        _ => bail!(TypeError::new(Type::String, obj)),
+    }
+}
+
+/// Convert OBJ to upper case. OBJ can be a string or a character.
+#[defun]
+fn upcase<'ob>(obj: GcObj<'ob>, cx: &'ob Context) -> Result<GcObj<'ob>> {
+    convert_case(obj, char::to_uppercase, cx)
+}
+
+/// Convert OBJ to lower case. OBJ can be a string or a character.
+#[defun]
+fn downcase<'ob>(obj: GcObj<'ob>, cx: &'ob Context) -> Result<GcObj<'ob>> {
+    convert_case(obj, char::to_lowercase, cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_case() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(upcase(cx.add("foo-Bar"), cx).unwrap(), "FOO-BAR");
+        assert_eq!(downcase(cx.add("FOO-Bar"), cx).unwrap(), "foo-bar");
+        assert_eq!(upcase(cx.add('a' as i64), cx).unwrap(), 'A' as i64);
+        // ß upcases to two characters, so the character is left unchanged
+        assert_eq!(upcase(cx.add('ß' as i64), cx).unwrap(), 'ß' as i64);
+    }
+}
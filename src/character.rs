@@ -2,6 +2,60 @@ use crate::core::object::Gc;
 use anyhow::Result;
 use fn_macros::defun;
 
+/// The modifier bits that can be added to a character code, such as by the
+/// `\M-` escape in a character literal.
+pub(crate) const CHAR_ALT: u32 = 1 << 22;
+pub(crate) const CHAR_SUPER: u32 = 1 << 23;
+pub(crate) const CHAR_HYPER: u32 = 1 << 24;
+pub(crate) const CHAR_SHIFT: u32 = 1 << 25;
+pub(crate) const CHAR_CTL: u32 = 1 << 26;
+pub(crate) const CHAR_META: u32 = 1 << 27;
+
+pub(crate) const CHAR_MODIFIER_MASK: u32 =
+    CHAR_ALT | CHAR_SUPER | CHAR_HYPER | CHAR_SHIFT | CHAR_CTL | CHAR_META;
+
+/// Raw bytes in the range 0x80..=0xFF are represented by the character codes
+/// at the top of the character space, so they can't be confused with the
+/// Unicode characters of the same value.
+pub(crate) const fn byte8_to_char(byte: u8) -> u32 {
+    byte as u32 + 0x3F_FF00
+}
+
+/// The raw byte represented by `code`, if it is a raw-byte character.
+pub(crate) fn char_to_byte8(code: u32) -> Option<u8> {
+    (0x3F_FF80..=0x3F_FFFF).contains(&code).then(|| (code - 0x3F_FF00) as u8)
+}
+
+/// Apply the control modifier to `chr`. ASCII letters and the characters
+/// `@[\]^_` become control characters, `?` becomes DEL, and anything else
+/// gets the control modifier bit.
+pub(crate) fn ctrlify(chr: u32) -> u32 {
+    let base = chr & !CHAR_MODIFIER_MASK;
+    if base == u32::from('?') {
+        0o177 | (chr & CHAR_MODIFIER_MASK)
+    } else if base >= 0x80 {
+        chr | CHAR_CTL
+    } else if (0o101..=0o132).contains(&(chr & 0o137)) || (0o100..=0o137).contains(&(chr & 0o177)) {
+        chr & (0o37 | !0o177)
+    } else {
+        chr | CHAR_CTL
+    }
+}
+
+/// Return the code of the character named `name`, ignoring case and extra
+/// whitespace. The name can be any Unicode name or alias, or `U+` followed by
+/// the code in hex.
+pub(crate) fn char_from_name(name: &str) -> Option<u32> {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some(hex) = name.strip_prefix("U+").or_else(|| name.strip_prefix("u+")) {
+        if hex.is_empty() || !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+            return None;
+        }
+        return u32::from_str_radix(hex, 16).ok().filter(|x| *x <= 0x10_FFFF);
+    }
+    unicode_names2::character(&name).map(u32::from)
+}
+
 #[defun]
 fn unibyte_string(bytes: &[Gc<i64>]) -> Result<Vec<u8>> {
     let unibyte: Result<Vec<u8>, _> = bytes.iter().map(|x| u8::try_from(x.untag())).collect();
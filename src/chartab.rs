@@ -0,0 +1,218 @@
+//! Char-tables, which map every character to a value.
+use crate::core::{
+    env::{sym, Env, Symbol},
+    error::{Type, TypeError},
+    gc::{Context, Rt},
+    object::{CharTable, Function, Gc, GcObj, Object, MAX_CHAR},
+};
+use crate::root;
+use anyhow::{bail, ensure, Result};
+use fn_macros::defun;
+
+/// The most extra slots a char-table can have.
+const MAX_EXTRA_SLOTS: i64 = 10;
+
+/// Make a char-table with PURPOSE as its subtype and every character set to
+/// INIT. The number of extra slots comes from the `char-table-extra-slots`
+/// property of PURPOSE.
+#[defun]
+fn make_char_table(
+    purpose: Symbol,
+    init: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<CharTable> {
+    let slots = crate::data::get(purpose, sym::CHAR_TABLE_EXTRA_SLOTS, env, cx);
+    let slots = match slots.untag() {
+        Object::NIL => 0,
+        Object::Int(x) if (0..=MAX_EXTRA_SLOTS).contains(&x) => x as usize,
+        _ => bail!("Invalid char-table-extra-slots for {purpose}: {slots}"),
+    };
+    Ok(unsafe { CharTable::new(purpose.into(), init.unwrap_or_default(), slots) })
+}
+
+#[defun]
+fn char_table_p(object: GcObj) -> bool {
+    matches!(object.untag(), Object::CharTable(_))
+}
+
+#[defun]
+fn char_table_subtype(char_table: &CharTable) -> GcObj<'_> {
+    char_table.purpose()
+}
+
+#[defun]
+fn char_table_parent(char_table: &CharTable) -> GcObj<'_> {
+    char_table.parent()
+}
+
+#[defun]
+fn set_char_table_parent<'ob>(char_table: &CharTable, parent: GcObj<'ob>) -> Result<GcObj<'ob>> {
+    ensure!(
+        matches!(parent.untag(), Object::NIL | Object::CharTable(_)),
+        TypeError::new(Type::CharTable, parent)
+    );
+    char_table.set_parent(parent)?;
+    Ok(parent)
+}
+
+#[defun]
+fn char_table_extra_slot(char_table: &CharTable, n: usize) -> Result<GcObj<'_>> {
+    let len = char_table.extra_slots();
+    match char_table.extra_slot(n) {
+        Some(value) => Ok(value),
+        None => bail!("index {n} is out of bounds. Length was {len}"),
+    }
+}
+
+#[defun]
+fn set_char_table_extra_slot<'ob>(
+    char_table: &CharTable,
+    n: usize,
+    value: GcObj<'ob>,
+) -> Result<GcObj<'ob>> {
+    char_table.set_extra_slot(n, value)?;
+    Ok(value)
+}
+
+/// Convert a character to an index in a char-table.
+fn char_code(obj: GcObj) -> Result<u32> {
+    match obj.untag() {
+        Object::Int(x) if (0..=MAX_CHAR.into()).contains(&x) => Ok(x as u32),
+        _ => Err(TypeError::new(Type::Int, obj).into()),
+    }
+}
+
+/// Parse a range of characters of the form `(FROM . TO)`.
+fn char_range(obj: GcObj) -> Result<Option<(u32, u32)>> {
+    let Object::Cons(cons) = obj.untag() else { return Ok(None) };
+    let (from, to) = (char_code(cons.car())?, char_code(cons.cdr())?);
+    ensure!(from <= to, "Invalid character range: {obj}");
+    Ok(Some((from, to)))
+}
+
+/// Return the value in CHAR-TABLE for RANGE. RANGE is nil for the default
+/// value, a character, or a cons `(FROM . TO)`, in which case the value for
+/// FROM is returned.
+#[defun]
+fn char_table_range<'ob>(char_table: &'ob CharTable, range: GcObj) -> Result<GcObj<'ob>> {
+    match range.untag() {
+        Object::NIL => Ok(char_table.default_value()),
+        Object::Cons(_) => {
+            let (from, _) = char_range(range)?.unwrap();
+            Ok(char_table.get(from))
+        }
+        _ => Ok(char_table.get(char_code(range)?)),
+    }
+}
+
+/// Set the value in CHAR-TABLE for RANGE to VALUE. RANGE is t for every
+/// character, nil for the default value, a character, or a cons `(FROM . TO)`.
+#[defun]
+fn set_char_table_range<'ob>(
+    char_table: &CharTable,
+    range: GcObj,
+    value: GcObj<'ob>,
+) -> Result<GcObj<'ob>> {
+    match range.untag() {
+        Object::NIL => char_table.set_default_value(value),
+        Object::TRUE => char_table.set_range(0, MAX_CHAR, value),
+        Object::Cons(_) => {
+            let (from, to) = char_range(range)?.unwrap();
+            char_table.set_range(from, to, value);
+        }
+        _ => {
+            let chr = char_code(range)?;
+            char_table.set_range(chr, chr, value);
+        }
+    }
+    Ok(value)
+}
+
+/// Call FUNCTION for each character in CHAR-TABLE that has a non-nil value.
+/// FUNCTION is called with a key and the value, where the key is either a
+/// character or a cons `(FROM . TO)` for a range of characters that share the
+/// same value.
+#[defun]
+fn map_char_table(
+    function: &Rt<Gc<Function>>,
+    char_table: &Rt<Gc<&'static CharTable>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let mut entries = Vec::new();
+    for (start, end, value) in char_table.bind(cx).untag().effective_ranges() {
+        let (start, end) = (i64::from(start), i64::from(end));
+        let key = if start == end { start.into() } else { cons!(start, end; cx) };
+        entries.push(key);
+        entries.push(value);
+    }
+    root!(entries, cx);
+    root!(args, Vec::new(), cx);
+    for entry in entries.chunks(2) {
+        args.push(&entry[0]);
+        args.push(&entry[1]);
+        function.call(args, env, cx, None)?;
+        args.clear();
+    }
+    Ok(false)
+}
+
+defsym!(CHAR_TABLE_EXTRA_SLOTS);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::core::object::{nil, IntoObject};
+
+    #[test]
+    fn test_char_table() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        crate::root!(env, Env::default(), cx);
+        let purpose = crate::core::env::intern("syntax-table", cx);
+        crate::data::put(purpose, sym::CHAR_TABLE_EXTRA_SLOTS, 1.into(), env, cx).unwrap();
+        let table = make_char_table(purpose, None, env, cx).unwrap().into_obj(cx).untag();
+        assert_eq!(char_table_subtype(table), purpose);
+        assert_eq!(set_char_table_extra_slot(table, 0, 7.into()).unwrap(), 7);
+        assert_eq!(char_table_extra_slot(table, 0).unwrap(), 7);
+        assert!(char_table_extra_slot(table, 1).is_err());
+
+        set_char_table_range(table, cons!('a' as i64, 'z' as i64; cx), 1.into()).unwrap();
+        set_char_table_range(table, ('q' as i64).into(), 2.into()).unwrap();
+        assert_eq!(char_table_range(table, ('b' as i64).into()).unwrap(), 1);
+        assert_eq!(char_table_range(table, ('q' as i64).into()).unwrap(), 2);
+        assert_eq!(char_table_range(table, ('A' as i64).into()).unwrap(), nil());
+        assert_eq!(char_table_range(table, cons!('q' as i64, 'z' as i64; cx)).unwrap(), 2);
+        assert!(char_table_range(table, (i64::from(MAX_CHAR) + 1).into()).is_err());
+
+        set_char_table_range(table, nil(), 3.into()).unwrap();
+        assert_eq!(char_table_range(table, ('A' as i64).into()).unwrap(), 3);
+        assert_eq!(char_table_range(table, nil()).unwrap(), 3);
+        assert!(set_char_table_parent(table, 1.into()).is_err());
+    }
+
+    #[test]
+    fn test_map_char_table() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let form = "(let ((parent (make-char-table 'test))
+                          (table (make-char-table 'test))
+                          (result nil))
+                      (set-char-table-range parent '(?a . ?z) 'lower)
+                      (set-char-table-range table ?m 'middle)
+                      (set-char-table-parent table parent)
+                      (map-char-table #'(lambda (k v) (setq result (cons (cons k v) result)))
+                                      table)
+                      (nreverse result))";
+        let obj = crate::reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let result = crate::interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(
+            result.to_string(),
+            "(((97 . 108) . lower) (109 . middle) ((110 . 122) . lower))"
+        );
+    }
+}
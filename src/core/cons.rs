@@ -1,4 +1,4 @@
-use super::gc::{Block, GcManaged, GcMark, Trace};
+use super::gc::{Block, GcManaged, GcState, Trace};
 use super::object::{CloneIn, Gc, GcObj, IntoObject, Object, RawObj};
 use anyhow::{anyhow, Result};
 use std::cell::Cell;
@@ -10,8 +10,11 @@ pub(crate) use iter::*;
 
 #[derive(Eq)]
 pub(crate) struct Cons {
-    marked: GcMark,
     mutable: bool,
+    /// Where the cons was read from, as an id in the table of source
+    /// positions kept by [`crate::lread`]. Zero if unknown. This fits in the
+    /// padding after `mutable`, so it does not make conses larger.
+    source: Cell<u32>,
     car: Cell<RawObj>,
     cdr: Cell<RawObj>,
 }
@@ -28,8 +31,8 @@ impl Cons {
     // lifetimes.
     pub(crate) unsafe fn new(car: GcObj, cdr: GcObj) -> Self {
         Self {
-            marked: GcMark::default(),
             mutable: true,
+            source: Cell::new(0),
             car: Cell::new(car.into_raw()),
             cdr: Cell::new(cdr.into_raw()),
         }
@@ -64,31 +67,32 @@ impl Cons {
             Err(anyhow!("Attempt to call set-cdr on immutable cons cell"))
         }
     }
+
+    pub(crate) fn source(&self) -> u32 {
+        self.source.get()
+    }
+
+    pub(crate) fn set_source(&self, source: u32) {
+        self.source.set(source);
+    }
 }
 
 impl<'new> CloneIn<'new, &'new Cons> for Cons {
     fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Cons> {
-        unsafe { Cons::new(self.car().clone_in(bk), self.cdr().clone_in(bk)).into_obj(bk) }
+        let cons = unsafe { Cons::new(self.car().clone_in(bk), self.cdr().clone_in(bk)) };
+        cons.set_source(self.source());
+        cons.into_obj(bk)
     }
 }
 
-impl GcManaged for Cons {
-    fn get_mark(&self) -> &GcMark {
-        &self.marked
-    }
-}
+impl GcManaged for Cons {}
 
 impl Trace for Cons {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        let cdr = self.cdr();
-        if cdr.is_markable() {
-            stack.push(cdr.into_raw());
-        }
-        let car = self.car();
-        if car.is_markable() {
-            stack.push(car.into_raw());
-        }
-        self.mark();
+    fn trace(&mut self, state: &mut GcState) {
+        let car = self.car().forward(state);
+        self.car.set(car.into_raw());
+        let cdr = self.cdr().forward(state);
+        self.cdr.set(cdr.into_raw());
     }
 }
 
@@ -96,11 +100,26 @@ impl Display for Cons {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_char('(')?;
         let mut cons = self;
-
+        // A cycle in the tail is found with Brent's algorithm, like the
+        // printer, and printed as the index of the element it loops back to.
+        let (mut tortoise, mut tortoise_idx, mut steps, mut power) = (self, 0, 2, 2);
+        let mut idx = 0;
         loop {
             write!(f, "{}", cons.car())?;
+            idx += 1;
             match cons.cdr().untag() {
+                Object::Cons(tail) if std::ptr::eq(tail, tortoise) => {
+                    write!(f, " . #{tortoise_idx}")?;
+                    break;
+                }
                 Object::Cons(tail) => {
+                    steps -= 1;
+                    if steps == 0 {
+                        power *= 2;
+                        steps = power;
+                        tortoise = tail;
+                        tortoise_idx = idx;
+                    }
                     cons = tail;
                     f.write_char(' ')?;
                 }
@@ -203,4 +222,23 @@ mod test {
         assert_eq!(lhs, list![5, 1, 1.5, "foo"; cx]);
         assert_ne!(lhs, list![5, 1, 1.5, "bar"; cx]);
     }
+
+    #[test]
+    fn display_circular() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let list = list![1, 2; cx];
+        assert_eq!(list.to_string(), "(1 2)");
+        list.as_cons().cdr().as_cons().set_cdr(list).unwrap();
+        assert_eq!(list.to_string(), "(1 2 . #0)");
+        let list = list![1, 2, 3; cx];
+        list.as_cons()
+            .cdr()
+            .as_cons()
+            .cdr()
+            .as_cons()
+            .set_cdr(list.as_cons().cdr())
+            .unwrap();
+        assert_eq!(list.to_string(), "(1 2 3 2 . #2)");
+    }
 }
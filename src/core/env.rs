@@ -1,19 +1,25 @@
 #![allow(unstable_name_collisions)]
-use super::gc::{Block, Context, Rt};
+use super::error::EvalError;
+use super::gc::{Block, Context, GcState, Rt};
 use super::object::{Buffer, CloneIn, Function, Gc, GcObj, LispBuffer, WithLifetime};
 use crate::hashmap::HashMap;
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use fn_macros::Trace;
 use std::collections::VecDeque;
 use std::sync::Mutex;
 
 mod symbol;
+mod vars;
 pub(crate) use symbol::*;
+use vars::SymbolValues;
 
 #[derive(Debug, Default, Trace)]
 pub(crate) struct Env {
-    pub(crate) vars: HashMap<Symbol<'static>, GcObj<'static>>,
-    pub(crate) props: HashMap<Symbol<'static>, Vec<(Symbol<'static>, GcObj<'static>)>>,
+    pub(crate) vars: SymbolValues,
+    /// Property lists set by this thread. The symbol holds a read-only copy
+    /// that other threads see, but this thread keeps using the original so
+    /// that it can modify the values in place.
+    props: HashMap<Symbol<'static>, GcObj<'static>>,
     pub(crate) catch_stack: Vec<GcObj<'static>>,
     exception: (GcObj<'static>, GcObj<'static>),
     #[no_trace]
@@ -26,27 +32,39 @@ pub(crate) struct Env {
 }
 
 impl Rt<Env> {
-    pub(crate) fn set_var(&mut self, sym: Symbol, value: GcObj) -> Result<()> {
-        if sym.is_const() {
-            Err(anyhow!("Attempt to set a constant symbol: {sym}"))
-        } else {
-            self.vars.insert(sym, value);
-            Ok(())
+    /// The value of the variable `sym`, following variable aliases.
+    pub(crate) fn var(&self, sym: Symbol) -> Option<&Rt<GcObj<'static>>> {
+        self.vars.get(sym.indirect_variable())
+    }
+
+    pub(crate) fn set_var(&mut self, sym: Symbol, value: GcObj, cx: &Context) -> Result<()> {
+        let sym = sym.indirect_variable();
+        if sym.trapped_write() == TrappedWrite::NoWrite {
+            return Err(self.setting_constant(sym, cx).into());
         }
+        self.vars.insert(sym, value);
+        Ok(())
     }
 
-    pub(crate) fn set_prop(&mut self, symbol: Symbol, propname: Symbol, value: GcObj) {
-        match self.props.get_mut(symbol) {
-            Some(plist) => match plist.iter_mut().find(|x| x.0 == propname) {
-                Some(x) => x.1.set(value),
-                None => plist.push((propname, value)),
-            },
-            None => {
-                self.props.insert(symbol, vec![(propname, value)]);
-            }
+    /// The `setting-constant` error for `sym`.
+    pub(crate) fn setting_constant(&mut self, sym: Symbol, cx: &Context) -> EvalError {
+        let data = crate::cons!(sym; cx);
+        EvalError::signal(sym::SETTING_CONSTANT.into(), data, self)
+    }
+
+    /// The property list of `symbol` as seen by this thread.
+    pub(crate) fn plist<'ob>(&self, symbol: Symbol, cx: &'ob Context) -> GcObj<'ob> {
+        match self.props.get(symbol) {
+            Some(plist) => plist.bind(cx),
+            None => symbol.plist(cx),
         }
     }
 
+    pub(crate) fn set_plist(&mut self, symbol: Symbol, plist: GcObj) {
+        self.props.insert(symbol, plist);
+        INTERNED_SYMBOLS.lock().unwrap().set_plist(symbol, plist);
+    }
+
     pub(in crate::core) fn set_exception(&mut self, tag: GcObj, data: GcObj) -> u32 {
         self.exception.0.set(tag);
         self.exception.1.set(data);
@@ -61,10 +79,24 @@ impl Rt<Env> {
         (id == self.exception_id).then_some((&self.exception.0, &self.exception.1))
     }
 
-    pub(crate) fn varbind(&mut self, var: Symbol, value: GcObj, cx: &Context) {
+    pub(crate) fn varbind(&mut self, var: Symbol, value: GcObj, cx: &Context) -> Result<()> {
+        let var = var.indirect_variable();
+        if var.trapped_write() == TrappedWrite::NoWrite {
+            return Err(self.setting_constant(var, cx).into());
+        }
         let prev_value = self.vars.get(var).map(|x| x.bind(cx));
         self.binding_stack.push((var, prev_value));
         self.vars.insert(var, value);
+        Ok(())
+    }
+
+    /// The variable and previous value of the innermost dynamic binding.
+    pub(crate) fn last_binding<'ob>(
+        &self,
+        cx: &'ob Context,
+    ) -> Option<(Symbol<'ob>, Option<GcObj<'ob>>)> {
+        let (sym, value) = self.binding_stack.last()?.bind_ref(cx);
+        Some((*sym, *value))
     }
 
     pub(crate) fn unbind(&mut self, count: u16, cx: &Context) {
@@ -79,8 +111,26 @@ impl Rt<Env> {
         }
     }
 
-    pub(crate) fn defvar(&mut self, var: Symbol, value: GcObj) -> Result<()> {
-        self.set_var(var, value)?;
+    pub(crate) fn defvar(&mut self, var: Symbol, value: GcObj, cx: &Context) -> Result<()> {
+        self.set_var(var, value, cx)?;
+        self.declare_special(var.indirect_variable(), value);
+        Ok(())
+    }
+
+    /// Define `var` as a constant. Unlike `set_var`, this can redefine a
+    /// constant.
+    pub(crate) fn defconst(&mut self, var: Symbol, value: GcObj, cx: &Context) -> Result<()> {
+        let var = var.indirect_variable();
+        if var.is_const() {
+            return Err(self.setting_constant(var, cx).into());
+        }
+        self.vars.insert(var, value);
+        var.set_trapped_write(TrappedWrite::NoWrite);
+        self.declare_special(var, value);
+        Ok(())
+    }
+
+    fn declare_special(&mut self, var: Symbol, value: GcObj) {
         var.make_special();
         // If this variable was unbound previously in the binding stack,
         // we will bind it to the new value
@@ -89,7 +139,6 @@ impl Rt<Env> {
                 binding.1.set(value);
             }
         }
-        Ok(())
     }
 
     pub(crate) fn set_buffer(&mut self, buffer: &LispBuffer, cx: &Context) -> Result<()> {
@@ -115,6 +164,19 @@ impl Rt<Env> {
         let mut buffer = buffer.lock().ok();
         func(buffer.as_mut())
     }
+
+    /// Kill `buffer`, releasing its text. Returns the name the buffer had, or
+    /// `None` if it was already killed.
+    pub(crate) fn kill_buffer(&mut self, buffer: &LispBuffer) -> Option<String> {
+        let front = unsafe { self.buffer_list.bind_mut_unchecked().front().copied() };
+        let lock = match front {
+            Some(current) if current == buffer && self.current_buffer.is_some() => {
+                self.current_buffer.take()
+            }
+            _ => buffer.lock().ok(),
+        };
+        lock.map(Buffer::kill)
+    }
 }
 
 pub(crate) struct ObjectMap {
@@ -157,12 +219,16 @@ impl Drop for SymbolBox {
 
 struct SymbolMap {
     map: HashMap<&'static str, SymbolBox>,
+    /// Symbols that have been removed with `unintern`. They can still be
+    /// referenced, so they are kept alive and their functions are traced.
+    removed: Vec<SymbolBox>,
 }
 
 impl SymbolMap {
     fn with_capacity(cap: usize) -> Self {
         Self {
             map: HashMap::with_capacity_and_hasher(cap, std::hash::BuildHasherDefault::default()),
+            removed: Vec::new(),
         }
     }
 
@@ -192,13 +258,23 @@ impl SymbolMap {
                 ptr
             }
         };
-        // SAFETY: We can guarantee that the reference is static because we have
-        // no methods to remove items from SymbolMap and SymbolMap has a private
-        // constructor, so the only one that exists is the one we create in this
-        // module, which is static.
+        // SAFETY: We can guarantee that the reference is static because
+        // symbols removed from the SymbolMap are never freed and SymbolMap has
+        // a private constructor, so the only one that exists is the one we
+        // create in this module, which is static.
         unsafe { Symbol::new(&*sym) }
     }
 
+    fn remove(&mut self, name: &str) -> bool {
+        match self.map.remove(name) {
+            Some(sym) => {
+                self.removed.push(sym);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn pre_init(&mut self, sym: Symbol<'static>) {
         use std::collections::hash_map::Entry;
         let name = sym.get().name();
@@ -224,6 +300,63 @@ impl ObjectMap {
         unsafe { symbol.set_func(new_func) }
     }
 
+    /// Set the property list of `symbol`. Like functions, property lists are
+    /// shared between threads, so the list is cloned into the global block and
+    /// is read-only.
+    pub(crate) fn set_plist(&self, symbol: Symbol, plist: GcObj) {
+        let new_plist = plist.clone_in(&self.block);
+        self.block.uninterned_symbol_map.clear();
+        // SAFETY: The plist is read-only and has been cloned into the map's
+        // context.
+        unsafe { symbol.set_plist(new_plist) }
+    }
+
+    /// Make `symbol` an alias for the variable `base`. Fails if this would
+    /// create a cycle of aliases.
+    pub(crate) fn set_var_alias(&self, symbol: Symbol, base: Symbol) -> Result<()> {
+        if base.indirect_variable() == symbol {
+            bail!("Cannot make {symbol} an alias for {base}, since it would create a loop");
+        }
+        let base = base.clone_in(&self.block).untag();
+        self.block.uninterned_symbol_map.clear();
+        // SAFETY: The symbol has been cloned into the map's context.
+        unsafe { symbol.set_var_alias(base) };
+        Ok(())
+    }
+
+    /// Clone `obj` into the global block so that it can be shared with other
+    /// threads. The copy is read-only.
+    pub(crate) fn share<'ob>(&self, obj: GcObj, cx: &'ob Context) -> GcObj<'ob> {
+        let new = obj.clone_in(&self.block);
+        self.block.uninterned_symbol_map.clear();
+        cx.bind(new)
+    }
+
+    /// The number of bytes used by objects in the global block.
+    pub(in crate::core) fn heap_bytes(&self) -> usize {
+        self.block.heap.borrow().bytes()
+    }
+
+    /// Collect the global block. The functions of interned symbols are roots,
+    /// and `trace_threads` should trace every reference into the block held
+    /// by the running threads. Returns the number of bytes that survived.
+    pub(in crate::core) fn garbage_collect(
+        &mut self,
+        trace_threads: impl FnOnce(&mut GcState),
+    ) -> usize {
+        let from_space = std::mem::take(&mut *self.block.heap.borrow_mut());
+        let mut state = GcState::new(from_space);
+        for symbol in self.map.map.values().chain(&self.map.removed) {
+            symbol.as_ref().trace_func(&mut state);
+        }
+        trace_threads(&mut state);
+        state.scan();
+        let heap = state.finish();
+        let bytes = heap.bytes();
+        *self.block.heap.borrow_mut() = heap;
+        bytes
+    }
+
     pub(crate) fn create_buffer(&self, name: &str) -> &LispBuffer {
         LispBuffer::create(name.to_owned(), &self.block)
     }
@@ -231,6 +364,20 @@ impl ObjectMap {
     pub(crate) fn get(&self, name: &str) -> Option<Symbol> {
         self.map.get(name)
     }
+
+    /// Remove the symbol named `name`, so that it is no longer interned.
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.map.remove(name)
+    }
+
+    /// All of the interned symbols.
+    pub(crate) fn symbols(&self) -> Vec<Symbol<'static>> {
+        self.map.map.values().map(|x| unsafe { Symbol::new(&*x.0) }).collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.map.map.len()
+    }
 }
 
 // This file includes all symbol definitions. Generated by build.rs
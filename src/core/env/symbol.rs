@@ -1,35 +1,64 @@
 #![allow(unstable_name_collisions)]
 use super::super::gc::Trace;
-use super::super::object::{Function, Gc, WithLifetime};
+use super::super::object::{Function, Gc, GcObj, WithLifetime};
 use crate::core::env::sym::BUILTIN_SYMBOLS;
-use crate::core::gc::GcManaged;
-use crate::core::object::{CloneIn, IntoObject, TagType};
-use crate::core::{gc::Context, object::RawObj};
+use crate::core::gc::Context;
+use crate::core::gc::{GcManaged, GcState};
+use crate::core::object::{CloneIn, IntoObject, Object, TagType};
 use anyhow::{bail, Result};
-use sptr::Strict;
+use std::cell::RefCell;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
-
-/// The allocation of a global symbol. This is shared
-/// between threads, so the interned value of a symbol
-/// will be the same location no matter which thread
-/// interned it. Functions are safe to share between
-/// threads because they are marked immutable by
-/// [`ObjectMap::set_func`] and they can only be replaced atomically.
-/// In order to garbage collect the function we need to
-/// halt all running threads. This has not been implemented
-/// yet.
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU8, Ordering};
+
+/// The allocation of a global symbol. This is shared between threads, so the
+/// interned value of a symbol will be the same location no matter which thread
+/// interned it. Functions and property lists are safe to share between threads
+/// because they are copied into the global block and marked immutable by
+/// [`ObjectMap::set_func`](super::ObjectMap::set_func), and they can only be
+/// replaced atomically. They are collected by [`Context::collect_global`],
+/// which stops every other thread at a safepoint first. The value of a symbol
+/// is not stored here; each thread keeps its own values in a table indexed by
+/// `var_id`.
 pub(crate) struct SymbolCell {
     name: SymbolName,
-    // Global symbols are always immutable, so we mark them so they are not
-    // traced by the GC.
-    marked: AtomicBool,
     // We can't use AtomicCell due to this issue:
     // https://github.com/crossbeam-rs/crossbeam/issues/748
     func: Option<AtomicPtr<u8>>,
+    // The property list is replaced atomically like the function, and a null
+    // pointer is nil.
+    plist: AtomicPtr<u8>,
+    // The variable this symbol is an alias for, stored like the property
+    // list. A null pointer means it is not an alias.
+    alias: AtomicPtr<u8>,
     special: AtomicBool,
+    trapped_write: AtomicU8,
+    // The index of this symbol's value in each thread's variable table. It is
+    // assigned the first time the symbol is given a value, and zero means no
+    // index has been assigned yet.
+    var_id: AtomicU32,
+}
+
+/// The next index to hand out in the variable tables.
+static NEXT_VAR_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    /// Indexes of symbols that were collected on this thread. A symbol can only
+    /// be collected once no thread holds a value for it, so its slot is empty
+    /// in every variable table and can be given to a new symbol.
+    static FREE_VAR_IDS: RefCell<Vec<u32>> = const { RefCell::new(Vec::new()) };
+}
+
+/// How writes to the value of a symbol are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrappedWrite {
+    /// The value can be set normally.
+    Untrapped,
+    /// The variable is constant.
+    NoWrite,
+    /// The variable has watchers that need to be notified before it changes.
+    Trapped,
 }
 
 #[derive(Debug)]
@@ -100,6 +129,28 @@ impl<'a> Symbol<'a> {
     pub(crate) fn is_special(self) -> bool {
         self.special.load(Ordering::Acquire)
     }
+
+    /// The variable this symbol is an alias for, if any.
+    pub(crate) fn var_alias(self) -> Option<Symbol<'a>> {
+        let ptr = self.get().alias.load(Ordering::Acquire);
+        if ptr.is_null() {
+            return None;
+        }
+        match unsafe { GcObj::from_raw_ptr(ptr) }.untag() {
+            Object::Symbol(sym) => Some(unsafe { sym.with_lifetime() }),
+            _ => unreachable!("variable alias was not a symbol"),
+        }
+    }
+
+    /// Follow the chain of variable aliases to the symbol that holds the
+    /// value. [`ObjectMap::set_var_alias`](super::ObjectMap::set_var_alias) ensures the chain has no cycles.
+    pub(crate) fn indirect_variable(self) -> Symbol<'a> {
+        let mut sym = self;
+        while let Some(alias) = sym.var_alias() {
+            sym = alias;
+        }
+        sym
+    }
 }
 
 unsafe impl Send for Symbol<'_> {}
@@ -113,13 +164,23 @@ impl<'old, 'new> WithLifetime<'new> for Symbol<'old> {
     }
 }
 
+impl Drop for SymbolCell {
+    fn drop(&mut self) {
+        if let Some(id) = self.var_id() {
+            // The free list is already gone if this is dropped while the thread
+            // is exiting.
+            let _ = FREE_VAR_IDS.try_with(|ids| ids.borrow_mut().push(id as u32));
+        }
+    }
+}
+
 impl Trace for Symbol<'_> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
+    fn trace(&mut self, state: &mut GcState) {
         // interned symbols are not collected yet
-        if matches!(self.name, SymbolName::Uninterned(_)) {
-            self.mark();
-            if let Some(func) = self.get().get() {
-                func.as_obj().trace_mark(stack);
+        if !self.interned() {
+            let ptr: *const SymbolCell = self.get();
+            if let Some(new) = state.forward(ptr.cast()) {
+                *self = unsafe { Symbol::from_ptr(new.cast()) };
             }
         }
     }
@@ -159,6 +220,14 @@ impl<'old, 'new> Symbol<'old> {
                             sym.set_func(new_func).unwrap();
                         }
                     }
+                    let plist = self.get().get_plist();
+                    if !plist.nil() {
+                        unsafe { sym.set_plist(plist.clone_in(bk)) };
+                    }
+                    if let Some(alias) = self.var_alias() {
+                        unsafe { sym.set_var_alias(alias.clone_in(bk).untag()) };
+                    }
+                    sym.set_trapped_write(self.trapped_write());
                     let new = sym.into_obj(bk);
                     bk.uninterned_symbol_map.insert(self, new.untag());
                     new
@@ -192,14 +261,17 @@ impl SymbolCell {
 
     pub(super) const fn new(name: &'static str) -> Self {
         // We have to do this workaround because starts_with is not const
-        if name.as_bytes()[0] == b':' {
+        if !name.is_empty() && name.as_bytes()[0] == b':' {
             Self::new_const(name)
         } else {
             Self {
                 name: SymbolName::Interned(name),
                 func: Some(Self::EMTPTY),
-                marked: AtomicBool::new(true),
+                plist: Self::EMTPTY,
+                alias: Self::EMTPTY,
                 special: AtomicBool::new(false),
+                trapped_write: AtomicU8::new(TrappedWrite::Untrapped as u8),
+                var_id: AtomicU32::new(0),
             }
         }
     }
@@ -208,8 +280,11 @@ impl SymbolCell {
         Self {
             name: SymbolName::Interned(name),
             func: Some(Self::EMTPTY),
-            marked: AtomicBool::new(true),
+            plist: Self::EMTPTY,
+            alias: Self::EMTPTY,
             special: AtomicBool::new(true),
+            trapped_write: AtomicU8::new(TrappedWrite::Untrapped as u8),
+            var_id: AtomicU32::new(0),
         }
     }
 
@@ -217,8 +292,11 @@ impl SymbolCell {
         Self {
             name: SymbolName::Interned(name),
             func: None,
-            marked: AtomicBool::new(true),
+            plist: Self::EMTPTY,
+            alias: Self::EMTPTY,
             special: AtomicBool::new(true),
+            trapped_write: AtomicU8::new(TrappedWrite::Untrapped as u8),
+            var_id: AtomicU32::new(0),
         }
     }
 
@@ -226,8 +304,11 @@ impl SymbolCell {
         Self {
             name: SymbolName::Uninterned(name.to_owned().into_boxed_str()),
             func: Some(Self::EMTPTY),
-            marked: AtomicBool::new(false),
+            plist: Self::EMTPTY,
+            alias: Self::EMTPTY,
             special: AtomicBool::new(false),
+            trapped_write: AtomicU8::new(TrappedWrite::Untrapped as u8),
+            var_id: AtomicU32::new(0),
         }
     }
 
@@ -248,6 +329,32 @@ impl SymbolCell {
         self.func.is_none()
     }
 
+    /// The index of this symbol's value in a variable table, if it has ever
+    /// been given a value.
+    pub(crate) fn var_id(&self) -> Option<usize> {
+        match self.var_id.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(id as usize),
+        }
+    }
+
+    /// Like `var_id`, but assign a new index if the symbol does not have one.
+    pub(crate) fn var_id_or_init(&self) -> usize {
+        if let Some(id) = self.var_id() {
+            return id;
+        }
+        let free = FREE_VAR_IDS.with_borrow_mut(Vec::pop);
+        let new = free.unwrap_or_else(|| NEXT_VAR_ID.fetch_add(1, Ordering::Relaxed));
+        match self.var_id.compare_exchange(0, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => new as usize,
+            // another thread assigned an index first
+            Err(id) => {
+                FREE_VAR_IDS.with_borrow_mut(|ids| ids.push(new));
+                id as usize
+            }
+        }
+    }
+
     pub(crate) fn has_func(&self) -> bool {
         match &self.func {
             Some(func) => !func.load(Ordering::Acquire).is_null(),
@@ -299,42 +406,77 @@ impl SymbolCell {
             func.store(Self::NULL, Ordering::Release);
         }
     }
-}
 
-impl GcManaged for SymbolCell {
-    fn get_mark(&self) -> &crate::core::gc::GcMark {
-        panic!("Symbol does not use GcMark")
+    fn get_plist(&self) -> GcObj<'static> {
+        unsafe { Gc::from_raw_ptr(self.plist.load(Ordering::Acquire)) }
     }
 
-    fn mark(&self) {
-        if matches!(self.name, SymbolName::Uninterned(_)) {
-            self.marked.store(true, Ordering::Release);
-        }
+    pub(crate) fn plist<'a>(&self, _cx: &'a Context) -> GcObj<'a> {
+        self.get_plist()
     }
 
-    fn unmark(&self) {
-        if matches!(self.name, SymbolName::Uninterned(_)) {
-            self.marked.store(false, Ordering::Release);
-        }
+    /// Set the property list of this symbol. This has the same requirements
+    /// as [`SymbolCell::set_func`], except that constant symbols can have a
+    /// property list.
+    pub(super) unsafe fn set_plist(&self, plist: GcObj) {
+        self.plist.store(plist.into_ptr().cast_mut(), Ordering::Release);
     }
 
-    fn is_marked(&self) -> bool {
-        match self.name {
-            SymbolName::Uninterned(_) => self.marked.load(Ordering::Acquire),
-            SymbolName::Interned(_) => true,
+    /// Make this symbol an alias for the variable `alias`. This has the same
+    /// requirements as [`SymbolCell::set_plist`].
+    pub(super) unsafe fn set_var_alias(&self, alias: Symbol) {
+        let obj: GcObj = alias.into();
+        self.alias.store(obj.into_ptr().cast_mut(), Ordering::Release);
+    }
+
+    pub(crate) fn trapped_write(&self) -> TrappedWrite {
+        if self.is_const() {
+            return TrappedWrite::NoWrite;
+        }
+        match self.trapped_write.load(Ordering::Acquire) {
+            0 => TrappedWrite::Untrapped,
+            1 => TrappedWrite::NoWrite,
+            _ => TrappedWrite::Trapped,
         }
     }
+
+    pub(crate) fn set_trapped_write(&self, trapped: TrappedWrite) {
+        self.trapped_write.store(trapped as u8, Ordering::Release);
+    }
 }
 
-impl Trace for SymbolCell {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        // interned symbols are not collected yet
-        if matches!(self.name, SymbolName::Uninterned(_)) {
-            self.mark();
-            if let Some(func) = self.get() {
-                func.as_obj().trace_mark(stack);
+impl GcManaged for SymbolCell {}
+
+impl SymbolCell {
+    /// Update the function of this symbol if it was moved by the collector.
+    /// This is also used for interned symbols, which are shared between
+    /// threads, when the global block is collected.
+    pub(in crate::core) fn trace_func(&self, state: &mut GcState) {
+        if let Some(func) = self.get() {
+            let new = func.forward(state);
+            if !new.ptr_eq(func) {
+                unsafe { self.set_func(new).expect("symbol should not be constant") };
             }
         }
+        let plist = self.get_plist();
+        let new = plist.forward(state);
+        if !new.ptr_eq(plist) {
+            unsafe { self.set_plist(new) };
+        }
+        let alias = self.alias.load(Ordering::Acquire);
+        if !alias.is_null() {
+            let alias = unsafe { GcObj::from_raw_ptr(alias) };
+            let new = alias.forward(state);
+            if !new.ptr_eq(alias) {
+                self.alias.store(new.into_ptr().cast_mut(), Ordering::Release);
+            }
+        }
+    }
+}
+
+impl Trace for SymbolCell {
+    fn trace(&mut self, state: &mut GcState) {
+        self.trace_func(state);
     }
 }
 
@@ -355,7 +497,7 @@ impl fmt::Debug for SymbolCell {
 /// the same address if they did originally. This keeps a mapping from old
 /// symbols to new.
 pub(in crate::core) struct UninternedSymbolMap {
-    map: std::cell::RefCell<Vec<(Symbol<'static>, Symbol<'static>)>>,
+    map: RefCell<Vec<(Symbol<'static>, Symbol<'static>)>>,
 }
 
 impl UninternedSymbolMap {
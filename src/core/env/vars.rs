@@ -0,0 +1,110 @@
+use super::{sym, Symbol};
+use crate::core::gc::{IntoRoot, Rt};
+use crate::core::object::GcObj;
+use fn_macros::Trace;
+
+/// The values of the special variables of one thread. Every symbol that is
+/// used as a variable has an index into this table (see
+/// [`SymbolCell::var_id`](super::SymbolCell::var_id)), so looking up a
+/// variable does not need to hash the symbol. Dynamic bindings swap the value
+/// in the slot and restore it when they are unbound.
+#[derive(Debug, Default, Trace)]
+pub(crate) struct SymbolValues {
+    // The symbol is kept in the slot so that uninterned symbols that have a
+    // value stay alive.
+    slots: Vec<Option<(Symbol<'static>, GcObj<'static>)>>,
+    /// Set when `gc-cons-threshold` or `gc-cons-percentage` change, so that the
+    /// collector only has to read them again after they are set.
+    #[no_trace]
+    gc_settings_changed: bool,
+}
+
+impl Rt<SymbolValues> {
+    pub(crate) fn get(&self, sym: Symbol) -> Option<&Rt<GcObj<'static>>> {
+        let slot = self.slots.get(sym.var_id()?)?;
+        slot.as_ref().map(|x| &x.1)
+    }
+
+    pub(crate) fn get_mut(&mut self, sym: Symbol) -> Option<&mut Rt<GcObj<'static>>> {
+        self.note_change(sym);
+        let slot = self.slots.get_mut(sym.var_id()?)?;
+        slot.as_mut().map(|x| &mut x.1)
+    }
+
+    pub(crate) fn insert<T: IntoRoot<GcObj<'static>>>(&mut self, sym: Symbol, value: T) {
+        self.note_change(sym);
+        let id = sym.var_id_or_init();
+        while self.slots.len() <= id {
+            self.slots.push(None::<(Symbol, GcObj)>);
+        }
+        self.slots[id].set((sym, value));
+    }
+
+    pub(crate) fn remove(&mut self, sym: Symbol) {
+        self.note_change(sym);
+        if let Some(slot) = sym.var_id().and_then(|id| self.slots.get_mut(id)) {
+            slot.clear();
+        }
+    }
+
+    fn note_change(&mut self, sym: Symbol) {
+        if sym == sym::GC_CONS_THRESHOLD || sym == sym::GC_CONS_PERCENTAGE {
+            self.gc_settings_changed = true;
+        }
+    }
+
+    /// Whether the GC settings were changed since the last time this was
+    /// called.
+    pub(crate) fn take_gc_settings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.gc_settings_changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Env, SymbolCell};
+    use crate::core::gc::{Context, RootSet};
+    use crate::core::object::IntoObject;
+    use crate::root;
+
+    #[test]
+    fn test_symbol_values() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        root!(other, Env::default(), cx);
+        let sym = SymbolCell::new_uninterned("symbol-values-test").into_obj(cx).untag();
+        root!(sym, cx);
+        assert!(env.vars.get(sym.bind(cx)).is_none());
+        env.vars.insert(sym.bind(cx), cx.add(1));
+        // each thread has its own values
+        assert!(other.vars.get(sym.bind(cx)).is_none());
+        other.vars.insert(sym.bind(cx), cx.add(2));
+        assert_eq!(env.vars.get(sym.bind(cx)).unwrap().bind(cx), 1);
+        // the symbol keeps its slot when it is moved by the collector
+        cx.garbage_collect(true);
+        assert_eq!(env.vars.get(sym.bind(cx)).unwrap().bind(cx), 1);
+        assert_eq!(other.vars.get(sym.bind(cx)).unwrap().bind(cx), 2);
+        env.vars.remove(sym.bind(cx));
+        assert!(env.vars.get(sym.bind(cx)).is_none());
+    }
+
+    #[test]
+    fn test_reuse_var_id() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let id = {
+            let sym = SymbolCell::new_uninterned("dead-var").into_obj(cx).untag();
+            env.vars.insert(sym, cx.add(1));
+            env.vars.remove(sym);
+            sym.var_id().unwrap()
+        };
+        // the index of a collected symbol is given to the next variable
+        cx.garbage_collect(true);
+        let sym = SymbolCell::new_uninterned("live-var").into_obj(cx).untag();
+        env.vars.insert(sym, cx.add(2));
+        assert_eq!(sym.var_id(), Some(id));
+        assert_eq!(env.vars.get(sym).unwrap().bind(cx), 2);
+    }
+}
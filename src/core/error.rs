@@ -9,6 +9,9 @@ use super::{
 #[derive(Debug)]
 pub(crate) struct EvalError {
     backtrace: Vec<String>,
+    /// The source position of the innermost form with a known position that
+    /// was being evaluated when the error occurred.
+    location: Option<String>,
     pub(crate) error: ErrorType,
 }
 
@@ -28,6 +31,9 @@ impl Display for EvalError {
             ErrorType::Throw(_) => writeln!(f, "No catch for throw")?,
             ErrorType::Signal(_) => writeln!(f, "Signal")?,
         }
+        if let Some(location) = &self.location {
+            writeln!(f, "at {location}")?;
+        }
         for x in &self.backtrace {
             writeln!(f, "{x}")?;
         }
@@ -38,18 +44,20 @@ impl Display for EvalError {
 
 impl EvalError {
     pub(crate) fn new_error(error: anyhow::Error) -> Self {
-        Self { backtrace: Vec::new(), error: ErrorType::Err(error) }
+        Self { backtrace: Vec::new(), location: None, error: ErrorType::Err(error) }
     }
 
     pub(crate) fn signal(error_symbol: GcObj, data: GcObj, env: &mut Rt<Env>) -> Self {
         Self {
             backtrace: Vec::new(),
+            location: None,
             error: ErrorType::Signal(env.set_exception(error_symbol, data)),
         }
     }
 
     pub(crate) fn throw(tag: GcObj, data: GcObj, env: &mut Rt<Env>) -> Self {
-        Self { backtrace: Vec::new(), error: ErrorType::Throw(env.set_exception(tag, data)) }
+        let error = ErrorType::Throw(env.set_exception(tag, data));
+        Self { backtrace: Vec::new(), location: None, error }
     }
 
     pub(crate) fn new(error: impl Into<Self>) -> Self {
@@ -58,7 +66,8 @@ impl EvalError {
 
     pub(crate) fn with_trace(error: anyhow::Error, name: &str, args: &[Rt<GcObj>]) -> Self {
         let display = display_slice(args);
-        Self { backtrace: vec![format!("{name} {display}")], error: ErrorType::Err(error) }
+        let backtrace = vec![format!("{name} {display}")];
+        Self { backtrace, location: None, error: ErrorType::Err(error) }
     }
 
     pub(crate) fn add_trace(mut self, name: &str, args: &[Rt<GcObj>]) -> Self {
@@ -66,11 +75,26 @@ impl EvalError {
         self.backtrace.push(format!("{name} {display}"));
         self
     }
+
+    /// Set the source location of the error, unless it is a `throw` or an
+    /// inner form already set it. `location` is only called when it is
+    /// needed.
+    pub(crate) fn add_location(mut self, location: impl FnOnce() -> Option<String>) -> Self {
+        if self.location.is_none() && !matches!(self.error, ErrorType::Throw(_)) {
+            self.location = location();
+        }
+        self
+    }
 }
 
 impl From<anyhow::Error> for EvalError {
     fn from(e: anyhow::Error) -> Self {
-        Self::new_error(e)
+        // Signals raised by functions that return `anyhow::Result` keep their
+        // error symbol
+        match e.downcast::<EvalError>() {
+            Ok(err) => err,
+            Err(e) => Self::new_error(e),
+        }
     }
 }
 
@@ -155,6 +179,10 @@ pub(crate) enum Type {
     Number,
     List,
     Buffer,
+    Finalizer,
+    BoolVec,
+    CharTable,
+    Obarray,
 }
 
 /// Error provided if object was the wrong type
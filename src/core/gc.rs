@@ -3,7 +3,9 @@ mod trace;
 #[macro_use]
 mod context;
 mod alloc;
+mod safepoint;
 pub(in crate::core) use alloc::*;
+pub(crate) use alloc::{GcState, ObjCounts, ObjKind};
 pub(crate) use context::*;
 pub(crate) use root::*;
 pub(crate) use trace::*;
@@ -1,21 +1,496 @@
-use super::Block;
+#![allow(unstable_name_collisions)]
+use super::{Block, Trace};
 use crate::core::cons::Cons;
 use crate::core::env::SymbolCell;
-use crate::core::object::{ByteFn, LispBuffer, LispFloat, LispHashTable, LispString, LispVec};
-use std::fmt::Debug;
-
-/// The owner of an object allocation. No references to
-/// the object can outlive this.
-#[derive(Debug)]
-pub(super) enum OwnedObject {
-    Float(Box<LispFloat>),
-    Cons(Box<Cons>),
-    Vec(Box<LispVec>),
-    HashTable(Box<LispHashTable>),
-    String(Box<LispString>),
-    Symbol(Box<SymbolCell>),
-    ByteFn(Box<ByteFn>),
-    Buffer(Box<LispBuffer>),
+use crate::core::object::{
+    ByteFn, CharTable, FloatCell, LispBigInt, LispBoolVec, LispBuffer, LispFinalizer,
+    LispHashTable, LispObarray, LispString, LispVec,
+};
+use std::alloc::Layout;
+use std::cell::Cell;
+use std::mem::{align_of, size_of, size_of_val};
+use std::ptr::NonNull;
+
+/// The type of an object allocated in a [`Heap`]. This is stored in the header
+/// of every allocation so that the collector can find the size of an object,
+/// trace it, and drop it once it is no longer reachable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum ObjKind {
+    Float,
+    Cons,
+    Vec,
+    HashTable,
+    String,
+    Symbol,
+    ByteFn,
+    Buffer,
+    Finalizer,
+    BoolVec,
+    CharTable,
+    BigInt,
+    Obarray,
+}
+
+impl ObjKind {
+    pub(crate) const ALL: [ObjKind; 13] = [
+        ObjKind::Float,
+        ObjKind::Cons,
+        ObjKind::Vec,
+        ObjKind::HashTable,
+        ObjKind::String,
+        ObjKind::Symbol,
+        ObjKind::ByteFn,
+        ObjKind::Buffer,
+        ObjKind::Finalizer,
+        ObjKind::BoolVec,
+        ObjKind::CharTable,
+        ObjKind::BigInt,
+        ObjKind::Obarray,
+    ];
+
+    /// Size of an allocation of this kind, including the header.
+    pub(crate) fn alloc_size(self) -> usize {
+        match self {
+            ObjKind::Float => alloc_size::<FloatCell>(),
+            ObjKind::Cons => alloc_size::<Cons>(),
+            ObjKind::Vec => alloc_size::<LispVec>(),
+            ObjKind::HashTable => alloc_size::<LispHashTable>(),
+            ObjKind::String => alloc_size::<LispString>(),
+            ObjKind::Symbol => alloc_size::<SymbolCell>(),
+            ObjKind::ByteFn => alloc_size::<ByteFn>(),
+            ObjKind::Buffer => alloc_size::<LispBuffer>(),
+            ObjKind::Finalizer => alloc_size::<LispFinalizer>(),
+            ObjKind::BoolVec => alloc_size::<LispBoolVec>(),
+            ObjKind::CharTable => alloc_size::<CharTable>(),
+            ObjKind::BigInt => alloc_size::<LispBigInt>(),
+            ObjKind::Obarray => alloc_size::<LispObarray>(),
+        }
+    }
+
+    /// Bytes owned by the object at `ptr` outside of its allocation, such as
+    /// the contents of a string or vector.
+    ///
+    /// SAFETY: `ptr` must point to a live object of this kind.
+    unsafe fn payload_size(self, ptr: *const u8) -> usize {
+        match self {
+            ObjKind::String => size_of_val(&**ptr.cast::<LispString>()),
+            ObjKind::Vec => size_of_val(&**ptr.cast::<LispVec>()),
+            ObjKind::BoolVec => (*ptr.cast::<LispBoolVec>()).len().div_ceil(8),
+            ObjKind::BigInt => (*ptr.cast::<LispBigInt>()).get().bits().div_ceil(8) as usize,
+            ObjKind::Float
+            | ObjKind::Cons
+            | ObjKind::HashTable
+            | ObjKind::Symbol
+            | ObjKind::ByteFn
+            | ObjKind::Buffer
+            | ObjKind::Finalizer
+            | ObjKind::CharTable
+            | ObjKind::Obarray => 0,
+        }
+    }
+
+    /// Drop the object stored at `ptr`.
+    ///
+    /// SAFETY: `ptr` must point to a live object of this kind that will not be
+    /// accessed again.
+    unsafe fn drop_object(self, ptr: *mut u8) {
+        match self {
+            ObjKind::Float => std::ptr::drop_in_place(ptr.cast::<FloatCell>()),
+            ObjKind::Cons => std::ptr::drop_in_place(ptr.cast::<Cons>()),
+            ObjKind::Vec => std::ptr::drop_in_place(ptr.cast::<LispVec>()),
+            ObjKind::HashTable => std::ptr::drop_in_place(ptr.cast::<LispHashTable>()),
+            ObjKind::String => std::ptr::drop_in_place(ptr.cast::<LispString>()),
+            ObjKind::Symbol => std::ptr::drop_in_place(ptr.cast::<SymbolCell>()),
+            ObjKind::ByteFn => std::ptr::drop_in_place(ptr.cast::<ByteFn>()),
+            ObjKind::Buffer => std::ptr::drop_in_place(ptr.cast::<LispBuffer>()),
+            ObjKind::Finalizer => std::ptr::drop_in_place(ptr.cast::<LispFinalizer>()),
+            ObjKind::BoolVec => std::ptr::drop_in_place(ptr.cast::<LispBoolVec>()),
+            ObjKind::CharTable => std::ptr::drop_in_place(ptr.cast::<CharTable>()),
+            ObjKind::BigInt => std::ptr::drop_in_place(ptr.cast::<LispBigInt>()),
+            ObjKind::Obarray => std::ptr::drop_in_place(ptr.cast::<LispObarray>()),
+        }
+    }
+
+    /// Update all the references held by the object stored at `ptr`.
+    ///
+    /// SAFETY: `ptr` must point to a live object of this kind that has no other
+    /// references to it.
+    unsafe fn trace_object(self, ptr: *mut u8, state: &mut GcState) {
+        match self {
+            ObjKind::Float
+            | ObjKind::String
+            | ObjKind::Buffer
+            | ObjKind::BoolVec
+            | ObjKind::BigInt => {}
+            ObjKind::Cons => (*ptr.cast::<Cons>()).trace(state),
+            ObjKind::Vec => (*ptr.cast::<LispVec>()).trace(state),
+            ObjKind::HashTable => {
+                let table = &mut *ptr.cast::<LispHashTable>();
+                // Weak tables are traced once everything else has been found
+                if table.weakness().is_some() {
+                    table.trace_test(state);
+                    state.weak_tables.push(table);
+                } else {
+                    table.trace(state);
+                }
+            }
+            ObjKind::Finalizer => (*ptr.cast::<LispFinalizer>()).trace(state),
+            ObjKind::CharTable => (*ptr.cast::<CharTable>()).trace(state),
+            ObjKind::Obarray => (*ptr.cast::<LispObarray>()).trace(state),
+            ObjKind::Symbol => (*ptr.cast::<SymbolCell>()).trace(state),
+            ObjKind::ByteFn => (*ptr.cast::<ByteFn>()).trace(state),
+        }
+    }
+}
+
+const fn alloc_size<T>() -> usize {
+    assert!(align_of::<T>() <= ALIGN);
+    (HEADER_SIZE + size_of::<T>() + (ALIGN - 1)) & !(ALIGN - 1)
+}
+
+const ALIGN: usize = 8;
+const HEADER_SIZE: usize = size_of::<Header>();
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The header that precedes every object in a [`Heap`]. The low byte holds the
+/// [`ObjKind`] of the object, and the rest holds the new address of the object
+/// once it has been copied by the collector. This uses the same encoding as
+/// tagged pointers.
+#[repr(transparent)]
+struct Header(Cell<*const u8>);
+
+impl Header {
+    fn new(kind: ObjKind) -> Self {
+        Self(Cell::new(sptr::invalid(kind as usize)))
+    }
+
+    fn kind(&self) -> ObjKind {
+        ObjKind::ALL[self.0.get().addr() & 0xFF]
+    }
+
+    fn forwarded(&self) -> Option<*const u8> {
+        let ptr = self.0.get();
+        (ptr.addr() >> 8 != 0).then(|| ptr.map_addr(|x| x >> 8))
+    }
+
+    fn set_forward(&self, ptr: *const u8) {
+        let kind = self.kind() as usize;
+        self.0.set(ptr.map_addr(|x| (x << 8) | kind));
+    }
+}
+
+/// A contiguous region of memory that objects are bump allocated into.
+struct Chunk {
+    start: NonNull<u8>,
+    capacity: usize,
+    used: usize,
+}
+
+impl Chunk {
+    fn new(capacity: usize) -> Self {
+        let layout = Self::layout(capacity);
+        // SAFETY: capacity is never zero
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        let Some(start) = NonNull::new(ptr) else { std::alloc::handle_alloc_error(layout) };
+        Self { start, capacity, used: 0 }
+    }
+
+    fn layout(capacity: usize) -> Layout {
+        Layout::from_size_align(capacity, ALIGN).expect("invalid chunk size")
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        let start = self.start.as_ptr().addr();
+        start <= addr && addr < start + self.used
+    }
+
+    /// Iterate over every allocation in this chunk, yielding the header and a
+    /// pointer to the object.
+    fn objects(&self) -> impl Iterator<Item = (&Header, *mut u8)> + '_ {
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            (offset < self.used).then(|| unsafe {
+                let ptr = self.start.as_ptr().add(offset);
+                let header = &*ptr.cast::<Header>();
+                offset += header.kind().alloc_size();
+                (header, ptr.add(HEADER_SIZE))
+            })
+        })
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        // Poison the memory so that any reference that survived a collection
+        // is caught as quickly as possible.
+        if cfg!(debug_assertions) {
+            unsafe { std::ptr::write_bytes(self.start.as_ptr(), 0xA5, self.used) };
+        }
+        unsafe { std::alloc::dealloc(self.start.as_ptr(), Self::layout(self.capacity)) };
+    }
+}
+
+/// The number of objects of each [`ObjKind`] in a heap.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct ObjCounts([usize; ObjKind::ALL.len()]);
+
+impl ObjCounts {
+    pub(crate) fn get(&self, kind: ObjKind) -> usize {
+        self.0[kind as usize]
+    }
+
+    pub(crate) fn total(&self) -> usize {
+        self.0.iter().sum()
+    }
+
+    /// The number of bytes used by all the objects, including their headers.
+    pub(crate) fn bytes(&self) -> usize {
+        ObjKind::ALL.iter().map(|&kind| self.get(kind) * kind.alloc_size()).sum()
+    }
+
+    /// The number of objects in `self` that are not in `other`.
+    pub(crate) fn difference(&self, other: &Self) -> Self {
+        Self(std::array::from_fn(|i| self.0[i].saturating_sub(other.0[i])))
+    }
+}
+
+/// A bump allocated heap of objects. Objects are stored contiguously in chunks
+/// with a one word header. The collector copies live objects into a new heap
+/// and then frees the old one.
+#[derive(Default)]
+pub(in crate::core) struct Heap {
+    chunks: Vec<Chunk>,
+    counts: ObjCounts,
+    /// Bytes owned by the objects outside of the heap, measured when they
+    /// were allocated or last copied.
+    payload_bytes: usize,
+}
+
+// SAFETY: The heap owns all of the memory in its chunks. The objects are only
+// accessed through a block, which controls sharing between threads.
+unsafe impl Send for Heap {}
+
+impl Heap {
+    /// The number of objects allocated in this heap.
+    pub(in crate::core) fn len(&self) -> usize {
+        self.counts.total()
+    }
+
+    pub(in crate::core) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(in crate::core) fn counts(&self) -> ObjCounts {
+        self.counts
+    }
+
+    /// The number of bytes used by the objects in this heap, including their
+    /// headers and the contents they own, like the text of a string.
+    pub(in crate::core) fn bytes(&self) -> usize {
+        self.counts.bytes() + self.payload_bytes
+    }
+
+    /// The number of bytes reserved by this heap but not yet allocated.
+    pub(in crate::core) fn free_bytes(&self) -> usize {
+        self.chunks.iter().map(|x| x.capacity - x.used).sum()
+    }
+
+    fn alloc_raw(&mut self, kind: ObjKind) -> *mut u8 {
+        let size = kind.alloc_size();
+        let needs_chunk = match self.chunks.last() {
+            Some(chunk) => chunk.capacity - chunk.used < size,
+            None => true,
+        };
+        if needs_chunk {
+            self.chunks.push(Chunk::new(CHUNK_SIZE.max(size)));
+        }
+        let chunk = self.chunks.last_mut().unwrap();
+        // SAFETY: We checked above that there is room in the chunk
+        let ptr = unsafe { chunk.start.as_ptr().add(chunk.used) };
+        chunk.used += size;
+        self.counts.0[kind as usize] += 1;
+        ptr
+    }
+
+    fn alloc<T>(&mut self, kind: ObjKind, obj: T) -> *const T {
+        debug_assert_eq!(kind.alloc_size(), alloc_size::<T>());
+        let ptr = self.alloc_raw(kind);
+        unsafe {
+            ptr.cast::<Header>().write(Header::new(kind));
+            let obj_ptr = ptr.add(HEADER_SIZE).cast::<T>();
+            obj_ptr.write(obj);
+            self.payload_bytes += kind.payload_size(obj_ptr.cast());
+            obj_ptr
+        }
+    }
+
+    /// Trace every object in this heap. This is used to update the references
+    /// a thread's heap holds into the global block when it is collected.
+    pub(in crate::core) fn trace_objects(&self, state: &mut GcState) {
+        for chunk in &self.chunks {
+            for (header, ptr) in chunk.objects() {
+                // SAFETY: The owner of this heap is stopped at a safepoint and
+                // does not hold any references into it.
+                unsafe { header.kind().trace_object(ptr, state) };
+            }
+        }
+    }
+
+    fn find_chunk(&self, addr: usize) -> Option<&Chunk> {
+        // Chunks are sorted by address before a collection starts
+        let idx = self.chunks.partition_point(|x| x.start.as_ptr().addr() <= addr);
+        let chunk = self.chunks.get(idx.checked_sub(1)?)?;
+        chunk.contains(addr).then_some(chunk)
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        for chunk in &self.chunks {
+            for (header, ptr) in chunk.objects() {
+                // Objects that were copied are now owned by the new heap
+                if header.forwarded().is_none() {
+                    unsafe { header.kind().drop_object(ptr) };
+                }
+            }
+        }
+    }
+}
+
+/// The state of an in-progress collection. Live objects are evacuated from the
+/// old heap into a new one, and every traced reference is updated to point to
+/// the new location.
+pub(crate) struct GcState {
+    from_space: Heap,
+    to_space: Heap,
+    /// The position in `to_space` of the next object to be scanned.
+    scan_pos: (usize, usize),
+    /// Weak hash tables that have been copied. Their entries are handled
+    /// after all strongly reachable objects have been found.
+    weak_tables: Vec<*mut LispHashTable>,
+}
+
+impl GcState {
+    pub(in crate::core) fn new(mut from_space: Heap) -> Self {
+        from_space.chunks.sort_unstable_by_key(|x| x.start.as_ptr().addr());
+        Self { from_space, to_space: Heap::default(), scan_pos: (0, 0), weak_tables: Vec::new() }
+    }
+
+    /// Get the new location of the object at `ptr`, copying it if it has not
+    /// been moved yet. Returns `None` if the object is not part of the heap
+    /// being collected, such as static symbols or objects in the global block.
+    pub(in crate::core) fn forward(&mut self, ptr: *const u8) -> Option<*const u8> {
+        let addr = ptr.addr();
+        self.from_space.find_chunk(addr)?;
+        let header = unsafe { &*ptr.sub(HEADER_SIZE).cast::<Header>() };
+        if let Some(new) = header.forwarded() {
+            return Some(new);
+        }
+        let kind = header.kind();
+        let new = self.to_space.alloc_raw(kind);
+        unsafe {
+            std::ptr::copy_nonoverlapping(ptr.sub(HEADER_SIZE), new, kind.alloc_size());
+            let new = new.add(HEADER_SIZE).cast_const();
+            header.set_forward(new);
+            self.to_space.payload_bytes += kind.payload_size(new);
+            Some(new)
+        }
+    }
+
+    /// Whether the object at `ptr` has been found reachable so far. Objects
+    /// that are not part of the heap being collected are always live.
+    pub(in crate::core) fn is_live(&self, ptr: *const u8) -> bool {
+        if self.from_space.find_chunk(ptr.addr()).is_none() {
+            return true;
+        }
+        let header = unsafe { &*ptr.sub(HEADER_SIZE).cast::<Header>() };
+        header.forwarded().is_some()
+    }
+
+    /// Trace every object that has been copied into the new heap. Tracing an
+    /// object can copy more objects, so this runs until no new objects are
+    /// added. Entries of weak tables are only kept alive once their key (or
+    /// value, depending on the weakness) has been found reachable, which gives
+    /// key weak tables ephemeron semantics.
+    pub(in crate::core) fn scan(&mut self) {
+        loop {
+            self.scan_copied();
+            let mut marked = false;
+            let mut idx = 0;
+            while let Some(&table) = self.weak_tables.get(idx) {
+                // SAFETY: weak tables live in the new heap, which does not move
+                // or drop objects until the collection is done.
+                marked |= unsafe { (*table).mark_weak(self) };
+                idx += 1;
+            }
+            if !marked {
+                break;
+            }
+        }
+    }
+
+    fn scan_copied(&mut self) {
+        let (mut chunk_idx, mut offset) = self.scan_pos;
+        while let Some(chunk) = self.to_space.chunks.get(chunk_idx) {
+            if offset >= chunk.used {
+                // Stay on the last chunk, since later objects may be copied
+                // into the space left in it.
+                if chunk_idx + 1 == self.to_space.chunks.len() {
+                    break;
+                }
+                chunk_idx += 1;
+                offset = 0;
+                continue;
+            }
+            unsafe {
+                let ptr = chunk.start.as_ptr().add(offset);
+                let kind = (*ptr.cast::<Header>()).kind();
+                offset += kind.alloc_size();
+                kind.trace_object(ptr.add(HEADER_SIZE), self);
+            }
+        }
+        self.scan_pos = (chunk_idx, offset);
+    }
+
+    /// Keep every finalizer that is no longer reachable alive so that its
+    /// function can be run, and return them. This should be called once all
+    /// reachable objects have been scanned.
+    pub(super) fn resurrect_finalizers(&mut self) -> Vec<*const LispFinalizer> {
+        let doomed: Vec<_> = self
+            .from_space
+            .chunks
+            .iter()
+            .flat_map(Chunk::objects)
+            .filter(|(header, _)| {
+                header.kind() == ObjKind::Finalizer && header.forwarded().is_none()
+            })
+            .map(|(_, ptr)| ptr.cast_const())
+            .filter(|&ptr| unsafe { !(*ptr.cast::<LispFinalizer>()).function().nil() })
+            .collect();
+        let resurrected = doomed
+            .into_iter()
+            .filter_map(|ptr| self.forward(ptr))
+            .map(<*const u8>::cast::<LispFinalizer>)
+            .collect();
+        self.scan();
+        resurrected
+    }
+
+    /// The number of objects in the heap being collected.
+    pub(super) fn old_counts(&self) -> ObjCounts {
+        self.from_space.counts()
+    }
+
+    /// Finish the collection and return the new heap. Entries of weak tables
+    /// that were not found reachable are removed, and objects that were not
+    /// copied are dropped along with the old heap.
+    pub(in crate::core) fn finish(mut self) -> Heap {
+        for table in std::mem::take(&mut self.weak_tables) {
+            unsafe { (*table).sweep_weak(&mut self) };
+        }
+        self.to_space
+    }
 }
 
 pub(in crate::core) trait AllocObject
@@ -27,35 +502,26 @@ where
 }
 
 impl AllocObject for f64 {
-    type Output = LispFloat;
+    type Output = FloatCell;
     fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
-        Block::<C>::register(&mut objects, OwnedObject::Float(Box::new(LispFloat::new(self))));
-        let Some(OwnedObject::Float(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::Float, FloatCell::new(self))
     }
 }
 
 impl AllocObject for Cons {
     type Output = Cons;
     fn alloc_obj<const CONST: bool>(mut self, block: &Block<CONST>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
         if CONST {
             self.mark_const();
         }
-        Block::<CONST>::register(&mut objects, OwnedObject::Cons(Box::new(self)));
-        let Some(OwnedObject::Cons(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::Cons, self)
     }
 }
 
 impl AllocObject for SymbolCell {
     type Output = SymbolCell;
     fn alloc_obj<const CONST: bool>(self, block: &Block<CONST>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
-        Block::<CONST>::register(&mut objects, OwnedObject::Symbol(Box::new(self)));
-        let Some(OwnedObject::Symbol(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::Symbol, self)
     }
 }
 
@@ -63,21 +529,14 @@ impl AllocObject for LispString {
     type Output = Self;
 
     fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
-        Block::<C>::register(&mut objects, OwnedObject::String(Box::new(self)));
-        let Some(OwnedObject::String(x)) = objects.last_mut() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::String, self)
     }
 }
 
 impl AllocObject for ByteFn {
     type Output = ByteFn;
     fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
-        let boxed = Box::new(self);
-        Block::<C>::register(&mut objects, OwnedObject::ByteFn(boxed));
-        let Some(OwnedObject::ByteFn(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::ByteFn, self)
     }
 }
 
@@ -85,13 +544,10 @@ impl AllocObject for LispVec {
     type Output = LispVec;
 
     fn alloc_obj<const CONST: bool>(mut self, block: &Block<CONST>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
         if CONST {
             self.make_const();
         }
-        Block::<CONST>::register(&mut objects, OwnedObject::Vec(Box::new(self)));
-        let Some(OwnedObject::Vec(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::Vec, self)
     }
 }
 
@@ -99,13 +555,10 @@ impl AllocObject for LispHashTable {
     type Output = Self;
 
     fn alloc_obj<const CONST: bool>(mut self, block: &Block<CONST>) -> *const Self::Output {
-        let mut objects = block.objects.borrow_mut();
         if CONST {
             self.make_const();
         }
-        Block::<CONST>::register(&mut objects, OwnedObject::HashTable(Box::new(self)));
-        let Some(OwnedObject::HashTable(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.heap.borrow_mut().alloc(ObjKind::HashTable, self)
     }
 }
 
@@ -114,9 +567,46 @@ impl AllocObject for LispBuffer {
 
     fn alloc_obj<const CONST: bool>(self, block: &Block<CONST>) -> *const Self::Output {
         assert!(CONST, "Buffers must only be created in the shared block");
-        let mut objects = block.objects.borrow_mut();
-        Block::<CONST>::register(&mut objects, OwnedObject::Buffer(Box::new(self)));
-        let Some(OwnedObject::Buffer(x)) = objects.last() else { unreachable!() };
-        x.as_ref()
+        block.pinned.borrow_mut().alloc(ObjKind::Buffer, self)
+    }
+}
+
+impl AllocObject for LispFinalizer {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        block.heap.borrow_mut().alloc(ObjKind::Finalizer, self)
+    }
+}
+
+impl AllocObject for LispBoolVec {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        block.heap.borrow_mut().alloc(ObjKind::BoolVec, self)
+    }
+}
+
+impl AllocObject for LispBigInt {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        block.heap.borrow_mut().alloc(ObjKind::BigInt, self)
+    }
+}
+
+impl AllocObject for CharTable {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        block.heap.borrow_mut().alloc(ObjKind::CharTable, self)
+    }
+}
+
+impl AllocObject for LispObarray {
+    type Output = Self;
+
+    fn alloc_obj<const C: bool>(self, block: &Block<C>) -> *const Self::Output {
+        block.heap.borrow_mut().alloc(ObjKind::Obarray, self)
     }
 }
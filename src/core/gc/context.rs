@@ -1,24 +1,28 @@
-use super::OwnedObject;
-use super::Trace;
-use crate::core::env::UninternedSymbolMap;
-use crate::core::object::{Gc, GcObj, IntoObject, WithLifetime};
+use super::safepoint::{self, ParkedThread, StoppedWorld};
+use super::{GcState, Heap, ObjCounts, Trace};
+use crate::core::env::{UninternedSymbolMap, INTERNED_SYMBOLS};
+use crate::core::object::{Gc, GcObj, IntoObject, LispFinalizer, TagType, WithLifetime};
 use std::cell::{Cell, RefCell};
 use std::fmt::Debug;
 use std::ops::Deref;
 use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
 
 /// A global store of all gc roots. This struct should be passed to the [Context]
 /// when it is created.
 #[derive(Default, Debug)]
 pub(crate) struct RootSet {
-    pub(super) roots: RefCell<Vec<*const dyn Trace>>,
+    pub(super) roots: RefCell<Vec<*mut dyn Trace>>,
 }
 
 /// A block of allocations. This type should be owned by [Context] and not used
 /// directly.
 #[derive(Default)]
 pub(crate) struct Block<const CONST: bool> {
-    pub(super) objects: RefCell<Vec<OwnedObject>>,
+    pub(in crate::core) heap: RefCell<Heap>,
+    /// Objects that must never be moved by the collector, such as buffers.
+    /// These are not collected.
+    pub(super) pinned: RefCell<Heap>,
     pub(in crate::core) uninterned_symbol_map: UninternedSymbolMap,
 }
 
@@ -28,52 +32,55 @@ pub(crate) struct Block<const CONST: bool> {
 pub(crate) struct Context<'rt> {
     pub(crate) block: Block<false>,
     root_set: &'rt RootSet,
-    prev_obj_count: usize,
+    /// Number of bytes that can be allocated before the next collection. This
+    /// is the value of `gc-cons-threshold`.
+    pub(crate) gc_threshold: usize,
+    /// Portion of the live heap that can be allocated before the next
+    /// collection, if that is larger than `gc_threshold`. This is the value of
+    /// `gc-cons-percentage`.
+    pub(crate) gc_percentage: f64,
+    gc_stats: GcStats,
+    /// Finalizers that were found unreachable and are waiting for their
+    /// functions to be run. These are kept alive by the collector.
+    pending_finalizers: Vec<Gc<&'static LispFinalizer>>,
+}
+
+/// Statistics about the garbage collector.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct GcStats {
+    /// Number of collections done so far.
+    pub(crate) gcs_done: usize,
+    /// Total time spent in the collector.
+    pub(crate) elapsed: Duration,
+    /// Objects that survived the last collection.
+    pub(crate) live: ObjCounts,
+    /// Bytes used by the objects that survived the last collection, including
+    /// the contents they own.
+    pub(crate) live_bytes: usize,
+    /// Objects that were freed by the last collection.
+    pub(crate) freed: ObjCounts,
+    /// Bytes reserved by the heap but not yet allocated.
+    pub(crate) free_bytes: usize,
+    /// Bytes used by the global block after it was last collected.
+    pub(crate) global_live_bytes: usize,
 }
 
 impl<'rt> Drop for Context<'rt> {
     fn drop(&mut self) {
+        self.pending_finalizers.clear();
         self.garbage_collect(true);
+        safepoint::unregister();
         assert!(
-            std::thread::panicking() || self.block.objects.borrow().is_empty(),
+            std::thread::panicking() || self.block.heap.borrow().is_empty(),
             "Error: Context was dropped while still holding data"
         );
     }
 }
 
-#[derive(Debug, Default, Eq)]
-pub(in crate::core) struct GcMark(Cell<bool>);
-
-impl Trace for GcMark {
-    fn trace(&self, _: &mut Vec<crate::core::object::RawObj>) {
-        self.0.set(true);
-    }
-}
-
-/// This trait represents a type that is managed by the Garbage collector and
-/// therefore has a markbit to preserve it.
-pub(in crate::core) trait GcManaged {
-    fn get_mark(&self) -> &GcMark;
-
-    fn mark(&self) {
-        self.get_mark().0.set(true);
-    }
-
-    fn unmark(&self) {
-        self.get_mark().0.set(false);
-    }
-
-    fn is_marked(&self) -> bool {
-        self.get_mark().0.get()
-    }
-}
-
-impl PartialEq for GcMark {
-    #[inline(always)]
-    fn eq(&self, _: &Self) -> bool {
-        true
-    }
-}
+/// This trait represents a type that is managed by the garbage collector and
+/// therefore lives in the heap of a [`Block`]. References to these types are
+/// updated when the object is moved by the collector.
+pub(crate) trait GcManaged {}
 
 thread_local! {
     static SINGLETON_CHECK: Cell<bool> = Cell::new(false);
@@ -95,10 +102,6 @@ impl Block<false> {
         Self::default()
     }
 
-    pub(crate) fn new_local_unchecked() -> Self {
-        Self::default()
-    }
-
     pub(crate) fn assert_unique() {
         SINGLETON_CHECK.with(|x| {
             assert!(!x.get(), "There was already and active context when this context was created");
@@ -123,20 +126,23 @@ impl<const CONST: bool> Block<CONST> {
     {
         obj.into_obj(self).into()
     }
-
-    pub(super) fn register(objects: &mut Vec<OwnedObject>, obj: OwnedObject) {
-        objects.push(obj);
-    }
 }
 
 impl<'ob, 'rt> Context<'rt> {
-    pub(crate) fn new(roots: &'rt RootSet) -> Self {
-        Context { block: Block::new_local(), root_set: roots, prev_obj_count: 0 }
-    }
+    const GC_THRESHOLD: usize = 800_000;
+    const GC_PERCENTAGE: f64 = 0.1;
 
-    pub(crate) fn from_block(block: Block<false>, roots: &'rt RootSet) -> Self {
-        Block::assert_unique();
-        Context { block, root_set: roots, prev_obj_count: 0 }
+    pub(crate) fn new(roots: &'rt RootSet) -> Self {
+        let block = Block::new_local();
+        safepoint::register();
+        Context {
+            block,
+            root_set: roots,
+            gc_threshold: Self::GC_THRESHOLD,
+            gc_percentage: Self::GC_PERCENTAGE,
+            gc_stats: GcStats::default(),
+            pending_finalizers: Vec::new(),
+        }
     }
 
     pub(crate) fn bind<T>(&'ob self, obj: T) -> <T as WithLifetime>::Out
@@ -150,68 +156,115 @@ impl<'ob, 'rt> Context<'rt> {
         self.root_set
     }
 
-    pub(crate) fn garbage_collect(&mut self, force: bool) {
-        let mut objects = self.block.objects.borrow_mut();
-        if cfg!(not(test))
-            && !force
-            && (objects.len() < 2000 || objects.len() < (self.prev_obj_count * 2))
-        {
-            return;
-        }
-        let gray_stack = &mut Vec::new();
-        for x in self.root_set.roots.borrow().iter() {
-            // SAFETY: The contact of root structs will ensure that it removes
-            // itself from this list before it drops.
-            unsafe {
-                (**x).trace(gray_stack);
-            }
-        }
-        while let Some(raw) = gray_stack.pop() {
-            let obj = unsafe { GcObj::from_raw(raw) };
-            if !obj.is_marked() {
-                obj.trace_mark(gray_stack);
-            }
-        }
+    pub(crate) fn gc_stats(&self) -> &GcStats {
+        &self.gc_stats
+    }
 
-        // let prev = objects.len();
-        objects.retain_mut(|x| {
-            let marked = x.is_marked();
-            if marked {
-                x.unmark();
+    /// Take the finalizers that were found unreachable by the collector. The
+    /// caller is responsible for running them.
+    pub(crate) fn take_pending_finalizers(&mut self) -> Vec<Gc<&'ob LispFinalizer>> {
+        let pending = std::mem::take(&mut self.pending_finalizers);
+        pending.into_iter().map(|x| unsafe { x.with_lifetime() }).collect()
+    }
+
+    /// Whether enough has been allocated since the last collection to start a
+    /// new one. Like Emacs, this is the larger of `gc_threshold` bytes and
+    /// `gc_percentage` of the heap that survived the last collection.
+    fn should_collect(&self) -> bool {
+        let live_bytes = self.gc_stats.live_bytes;
+        let allocated = self.block.heap.borrow().bytes().saturating_sub(live_bytes);
+        let threshold = (live_bytes as f64 * self.gc_percentage) as usize;
+        allocated >= self.gc_threshold.max(threshold)
+    }
+
+    fn parked_thread(&self) -> ParkedThread {
+        ParkedThread { roots: self.root_set, heap: std::ptr::addr_of!(self.block.heap) }
+    }
+
+    /// Run `func` without access to the heap. This should wrap anything that
+    /// blocks, like waiting on another thread or reading input, so that the
+    /// global block can be collected in the meantime.
+    pub(crate) fn park_while<T>(&mut self, func: impl FnOnce() -> T) -> T {
+        safepoint::park_while(self.parked_thread(), func)
+    }
+
+    /// Whether enough has been allocated in the global block since it was last
+    /// collected to collect it again.
+    pub(crate) fn should_collect_global(&self) -> bool {
+        let map = INTERNED_SYMBOLS.lock().unwrap();
+        let bytes = map.heap_bytes();
+        let live_bytes = self.gc_stats.global_live_bytes;
+        let threshold = (live_bytes as f64 * self.gc_percentage) as usize;
+        bytes.saturating_sub(live_bytes) >= self.gc_threshold.max(threshold)
+    }
+
+    /// Collect the global block, which holds the objects shared between
+    /// threads, such as function definitions. Every other thread is stopped at
+    /// a safepoint while this runs.
+    pub(crate) fn collect_global(&mut self) {
+        let Some(world) = StoppedWorld::new(self.parked_thread()) else { return };
+        let start = Instant::now();
+        let threads = world.parked().iter().copied().chain(std::iter::once(self.parked_thread()));
+        let mut map = INTERNED_SYMBOLS.lock().unwrap();
+        let live_bytes = map.garbage_collect(|state| {
+            for thread in threads {
+                // SAFETY: The threads are parked while the world is stopped,
+                // and we are the only ones accessing their roots and heaps.
+                unsafe {
+                    for root in (*thread.roots).roots.borrow().iter() {
+                        (**root).trace(state);
+                    }
+                    (*thread.heap).borrow().trace_objects(state);
+                }
             }
-            marked
         });
-        // let retained = prev - objects.len();
-        // println!("garbage collected: {retained}/{prev}");
-        self.prev_obj_count = objects.len();
+        self.gc_stats.global_live_bytes = live_bytes;
+        self.gc_stats.elapsed += start.elapsed();
     }
-}
 
-impl OwnedObject {
-    fn unmark(&self) {
-        match self {
-            OwnedObject::Float(x) => x.unmark(),
-            OwnedObject::Cons(x) => x.unmark(),
-            OwnedObject::Vec(x) => x.unmark(),
-            OwnedObject::HashTable(x) => x.unmark(),
-            OwnedObject::String(x) => x.unmark(),
-            OwnedObject::Symbol(x) => x.unmark(),
-            OwnedObject::ByteFn(x) => x.unmark(),
-            OwnedObject::Buffer(_) => todo!("unmark buffer"),
+    /// With the `gc_stress` feature, collect both the local and the global
+    /// block. This is called everywhere the collector is allowed to move
+    /// objects, so a missing root is found by the first collection instead of
+    /// whichever one happens to cross the threshold.
+    pub(crate) fn stress_collect(&mut self) {
+        if cfg!(feature = "gc_stress") {
+            self.garbage_collect(true);
+            self.collect_global();
         }
     }
 
-    fn is_marked(&self) -> bool {
-        match self {
-            OwnedObject::Float(x) => x.is_marked(),
-            OwnedObject::Cons(x) => x.is_marked(),
-            OwnedObject::Vec(x) => x.is_marked(),
-            OwnedObject::HashTable(x) => x.is_marked(),
-            OwnedObject::String(x) => x.is_marked(),
-            OwnedObject::Symbol(x) => x.is_marked(),
-            OwnedObject::ByteFn(x) => x.is_marked(),
-            OwnedObject::Buffer(_) => todo!("is_marked buffer"),
+    pub(crate) fn garbage_collect(&mut self, force: bool) {
+        safepoint::safepoint(self.parked_thread());
+        if !cfg!(feature = "gc_stress") && !force && !self.should_collect() {
+            return;
+        }
+        let start = Instant::now();
+        let from_space = std::mem::take(&mut *self.block.heap.borrow_mut());
+        let mut state = GcState::new(from_space);
+        for x in self.root_set.roots.borrow().iter() {
+            // SAFETY: The contact of root structs will ensure that it removes
+            // itself from this list before it drops. The collector is the only
+            // thing that can access the roots while it is running.
+            unsafe {
+                (**x).trace(&mut state);
+            }
         }
+        self.pending_finalizers.trace(&mut state);
+        state.scan();
+        for finalizer in state.resurrect_finalizers() {
+            let finalizer = unsafe { (*finalizer).with_lifetime() };
+            self.pending_finalizers.push(finalizer.tag());
+        }
+        let old_counts = state.old_counts();
+        let heap = state.finish();
+        let stats = &mut self.gc_stats;
+        stats.gcs_done += 1;
+        stats.live = heap.counts();
+        stats.live_bytes = heap.bytes();
+        stats.freed = old_counts.difference(&stats.live);
+        stats.free_bytes = heap.free_bytes();
+        stats.elapsed += start.elapsed();
+        *self.block.heap.borrow_mut() = heap;
     }
 }
 
@@ -284,6 +337,7 @@ macro_rules! rebind {
 
 #[cfg(test)]
 mod test {
+    use crate::core::gc::ObjKind;
     use crate::root;
 
     use super::*;
@@ -311,4 +365,58 @@ mod test {
         vec.push(cons);
         cx.garbage_collect(true);
     }
+
+    #[test]
+    fn objects_are_moved() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let _garbage = cx.add("garbage");
+        // negative floats are always allocated on the heap
+        let obj = list!["foo", -1.5, 1; cx];
+        let old = obj.into_raw();
+        root!(obj, cx);
+        cx.garbage_collect(true);
+        // 3 conses, a string and a float
+        assert_eq!(cx.block.heap.borrow().len(), 5);
+        let stats = cx.gc_stats();
+        assert_eq!(stats.live.get(ObjKind::Cons), 3);
+        assert_eq!(stats.live.get(ObjKind::String), 1);
+        assert_eq!(stats.live.get(ObjKind::Float), 1);
+        assert_eq!(stats.freed.get(ObjKind::String), 1);
+        assert_ne!(obj.bind(cx).into_raw(), old);
+        assert_eq!(obj.bind(cx), list!["foo", -1.5, 1; cx]);
+    }
+
+    #[test]
+    fn collect_on_every_allocation() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(list, crate::core::object::nil(), cx);
+        for i in 0..100_i64 {
+            let elem = match i % 4 {
+                0 => cx.add(-(i as f64)),
+                1 => cx.add(i.to_string()),
+                2 => cx.add(vec![cx.add(i)]),
+                _ => list![i; cx],
+            };
+            let new = cons!(elem, list.bind(cx); cx);
+            list.set(new);
+            cx.garbage_collect(true);
+        }
+        // one cons cell and one element for each iteration
+        assert_eq!(cx.block.heap.borrow().len(), 200);
+        assert_eq!(cx.gc_stats().live.total(), 200);
+        assert_eq!(cx.gc_stats().gcs_done, 100);
+        let list = list.bind(cx);
+        for (i, elem) in (0..100_i64).rev().zip(list.as_list().unwrap()) {
+            let elem = elem.unwrap();
+            let expect = match i % 4 {
+                0 => cx.add(-(i as f64)),
+                1 => cx.add(i.to_string()),
+                2 => cx.add(vec![cx.add(i)]),
+                _ => list![i; cx],
+            };
+            assert_eq!(elem, expect);
+        }
+    }
 }
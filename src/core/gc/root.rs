@@ -1,8 +1,5 @@
-use super::super::{
-    cons::Cons,
-    object::{GcObj, RawObj},
-};
-use super::{Block, Context, RootSet, Trace};
+use super::super::{cons::Cons, object::GcObj};
+use super::{Block, Context, GcState, RootSet, Trace};
 use crate::core::env::Symbol;
 use crate::core::object::{Gc, IntoObject, LispString, Object, Untag, WithLifetime};
 use crate::hashmap::{HashMap, HashSet};
@@ -95,8 +92,8 @@ impl<T: IntoRoot<U>, U> IntoRoot<Vec<U>> for Vec<T> {
 }
 
 impl<T> Trace for Gc<T> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        self.as_obj().trace_mark(stack);
+    fn trace(&mut self, state: &mut GcState) {
+        *self = self.forward(state);
     }
 }
 
@@ -491,12 +488,21 @@ impl<T> Rt<Option<T>> {
         }
     }
 
+    pub(crate) fn clear(&mut self) {
+        self.inner = None;
+    }
+
     // This is not really dead code, but the static analysis fails to find it
     #[allow(dead_code)]
     pub(crate) fn as_ref(&self) -> Option<&Rt<T>> {
         let option = self.inner.as_ref();
         option.map(|x| unsafe { &*(x as *const T).cast::<Rt<T>>() })
     }
+
+    pub(crate) fn as_mut(&mut self) -> Option<&mut Rt<T>> {
+        let option = self.inner.as_mut();
+        option.map(|x| unsafe { &mut *(x as *mut T).cast::<Rt<T>>() })
+    }
 }
 
 impl<T> Rt<Vec<T>> {
@@ -573,16 +579,6 @@ where
             .get(unsafe { &k.into_root() })
             .map(|x| unsafe { &*(x as *const V).cast::<Rt<V>>() })
     }
-
-    pub(crate) fn get_mut<Q: IntoRoot<K>>(&mut self, k: Q) -> Option<&mut Rt<V>> {
-        self.inner
-            .get_mut(unsafe { &k.into_root() })
-            .map(|x| unsafe { &mut *(x as *mut V).cast::<Rt<V>>() })
-    }
-
-    pub(crate) fn remove<Q: IntoRoot<K>>(&mut self, k: Q) {
-        self.inner.remove(unsafe { &k.into_root() });
-    }
 }
 
 impl<K, V> Deref for Rt<HashMap<K, V>> {
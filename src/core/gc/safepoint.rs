@@ -0,0 +1,137 @@
+//! Coordination between threads so that the global block can be collected.
+//!
+//! Every [`Context`](super::Context) is registered here for as long as it
+//! exists. Objects in the global block can be referenced from any thread, so
+//! before it is collected all other threads have to stop at a safepoint. A
+//! stopped thread is "parked" and publishes its roots and heap so that the
+//! collecting thread can update any references they hold. A thread that is
+//! about to block on something other than the collector (such as joining
+//! another thread) should park itself first, otherwise a collection could never
+//! start.
+use super::{Heap, RootSet};
+use std::cell::RefCell;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// The roots and heap of a thread that is stopped at a safepoint. These are
+/// only accessed by the collecting thread while the owning thread is parked.
+#[derive(Copy, Clone)]
+pub(super) struct ParkedThread {
+    pub(super) roots: *const RootSet,
+    pub(super) heap: *const RefCell<Heap>,
+}
+
+// SAFETY: The pointers are only dereferenced while the thread that owns them
+// is parked and can't access them.
+unsafe impl Send for ParkedThread {}
+
+impl ParkedThread {
+    fn same_thread(&self, other: &Self) -> bool {
+        std::ptr::eq(self.roots, other.roots)
+    }
+}
+
+struct Registry {
+    threads: usize,
+    stop_requested: bool,
+    parked: Vec<ParkedThread>,
+}
+
+static REGISTRY: Mutex<Registry> =
+    Mutex::new(Registry { threads: 0, stop_requested: false, parked: Vec::new() });
+static CHANGED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn wait(registry: MutexGuard<'static, Registry>) -> MutexGuard<'static, Registry> {
+    CHANGED.wait(registry).unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+pub(super) fn register() {
+    let mut registry = lock();
+    // Don't join in the middle of a collection
+    while registry.stop_requested {
+        registry = wait(registry);
+    }
+    registry.threads += 1;
+}
+
+pub(super) fn unregister() {
+    let mut registry = lock();
+    registry.threads -= 1;
+    CHANGED.notify_all();
+}
+
+/// Park the thread if another thread is waiting to collect the global block.
+pub(super) fn safepoint(thread: ParkedThread) {
+    let registry = lock();
+    if registry.stop_requested {
+        drop(park(registry, thread));
+    }
+}
+
+/// Park the thread and wait until the collection is over.
+fn park(
+    mut registry: MutexGuard<'static, Registry>,
+    thread: ParkedThread,
+) -> MutexGuard<'static, Registry> {
+    registry.parked.push(thread);
+    CHANGED.notify_all();
+    while registry.stop_requested {
+        registry = wait(registry);
+    }
+    registry.parked.retain(|x| !x.same_thread(&thread));
+    registry
+}
+
+/// Stay parked while running `func`, so that other threads can collect the
+/// global block in the meantime. `func` must not access the heap.
+pub(super) fn park_while<T>(thread: ParkedThread, func: impl FnOnce() -> T) -> T {
+    let mut registry = lock();
+    registry.parked.push(thread);
+    CHANGED.notify_all();
+    drop(registry);
+    let result = func();
+    let mut registry = lock();
+    while registry.stop_requested {
+        registry = wait(registry);
+    }
+    registry.parked.retain(|x| !x.same_thread(&thread));
+    result
+}
+
+/// All other threads are parked while this is alive.
+pub(super) struct StoppedWorld {
+    parked: Vec<ParkedThread>,
+}
+
+impl StoppedWorld {
+    /// Stop every other thread at a safepoint. If another thread is already
+    /// collecting, this thread is parked until it is done and `None` is
+    /// returned.
+    pub(super) fn new(thread: ParkedThread) -> Option<Self> {
+        let mut registry = lock();
+        if registry.stop_requested {
+            drop(park(registry, thread));
+            return None;
+        }
+        registry.stop_requested = true;
+        while registry.parked.len() + 1 < registry.threads {
+            registry = wait(registry);
+        }
+        Some(Self { parked: registry.parked.clone() })
+    }
+
+    pub(super) fn parked(&self) -> &[ParkedThread] {
+        &self.parked
+    }
+}
+
+impl Drop for StoppedWorld {
+    fn drop(&mut self) {
+        let mut registry = lock();
+        registry.stop_requested = false;
+        CHANGED.notify_all();
+    }
+}
@@ -1,70 +1,80 @@
-use super::super::object::RawObj;
+use super::{GcManaged, GcState};
 use crate::hashmap::{HashMap, HashSet};
+use std::hash::Hash;
 
+/// Trace all the objects referenced by a type. When an object is moved by the
+/// collector, any references to it are updated to point to the new location.
 pub(crate) trait Trace {
-    fn trace(&self, stack: &mut Vec<RawObj>);
+    fn trace(&mut self, state: &mut GcState);
 }
 
-impl<T: Trace> Trace for &T {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        (*self).trace(stack);
+impl<T: GcManaged> Trace for &T {
+    fn trace(&mut self, state: &mut GcState) {
+        let ptr: *const T = *self;
+        if let Some(new) = state.forward(ptr.cast()) {
+            *self = unsafe { &*new.cast::<T>() };
+        }
     }
 }
 
 impl<T: Trace, U: Trace> Trace for (T, U) {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        self.0.trace(stack);
-        self.1.trace(stack);
+    fn trace(&mut self, state: &mut GcState) {
+        self.0.trace(state);
+        self.1.trace(state);
     }
 }
 
 impl<T: Trace> Trace for [T] {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
+    fn trace(&mut self, state: &mut GcState) {
         for x in self {
-            x.trace(stack);
+            x.trace(state);
         }
     }
 }
 
 impl<T: Trace> Trace for Vec<T> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
+    fn trace(&mut self, state: &mut GcState) {
         for x in self {
-            x.trace(stack);
+            x.trace(state);
         }
     }
 }
 
 impl<T: Trace> Trace for std::collections::VecDeque<T> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
+    fn trace(&mut self, state: &mut GcState) {
         for x in self {
-            x.trace(stack);
+            x.trace(state);
         }
     }
 }
 
-impl<K: Trace, V: Trace> Trace for HashMap<K, V> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        for key in self.keys() {
-            key.trace(stack);
-        }
-        for value in self.values() {
-            value.trace(stack);
+// Keys are hashed by address, so the map needs to be rebuilt once they have
+// been moved.
+impl<K: Trace + Eq + Hash, V: Trace> Trace for HashMap<K, V> {
+    fn trace(&mut self, state: &mut GcState) {
+        let entries: Vec<_> = self.drain().collect();
+        for (mut key, mut value) in entries {
+            key.trace(state);
+            value.trace(state);
+            self.insert(key, value);
         }
     }
 }
 
-impl<T: Trace> Trace for HashSet<T> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        for x in self {
-            x.trace(stack);
+impl<T: Trace + Eq + Hash> Trace for HashSet<T> {
+    fn trace(&mut self, state: &mut GcState) {
+        let entries: Vec<_> = self.drain().collect();
+        for mut x in entries {
+            x.trace(state);
+            self.insert(x);
         }
     }
 }
 
 impl<T: Trace> Trace for Option<T> {
-    fn trace(&self, stack: &mut Vec<RawObj>) {
-        if let Some(x) = self.as_ref() {
-            x.trace(stack);
+    fn trace(&mut self, state: &mut GcState) {
+        if let Some(x) = self.as_mut() {
+            x.trace(state);
         }
     }
 }
@@ -77,7 +87,7 @@ mod test {
 
     struct Foo(u64);
     impl Trace for Foo {
-        fn trace(&self, _stack: &mut Vec<RawObj>) {
+        fn trace(&mut self, _state: &mut GcState) {
             assert!(self.0 == 7);
         }
     }
@@ -9,21 +9,33 @@
 //! aligned. All objects should be bound to a lifetime to ensure sound operation
 //! of the vm.
 
+mod bignum;
+mod boolvec;
 mod buffer;
+mod chartable;
 mod convert;
+mod equal;
+mod finalizer;
 mod float;
 mod func;
 mod hashtable;
+mod obarray;
 mod string;
 mod tagged;
 mod vector;
 
+pub(crate) use bignum::*;
 #[allow(unused_imports)]
+pub(crate) use boolvec::*;
 pub(crate) use buffer::*;
+pub(crate) use chartable::*;
 pub(crate) use convert::*;
+pub(crate) use equal::*;
+pub(crate) use finalizer::*;
 pub(crate) use float::*;
 pub(crate) use func::*;
 pub(crate) use hashtable::*;
+pub(crate) use obarray::*;
 pub(crate) use string::*;
 pub(crate) use tagged::*;
 pub(crate) use vector::*;
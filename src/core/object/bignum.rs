@@ -0,0 +1,83 @@
+use super::{CloneIn, Gc, IntoObject, Object};
+use crate::core::gc::{Block, GcManaged, GcState, Trace};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::fmt::{Debug, Display};
+
+/// The largest integer that is stored directly in an object. Integers are
+/// stored shifted past the tag byte, so they have 56 bits.
+pub(crate) const MOST_POSITIVE_FIXNUM: i64 = i64::MAX >> 8;
+/// The smallest integer that is stored directly in an object.
+pub(crate) const MOST_NEGATIVE_FIXNUM: i64 = i64::MIN >> 8;
+
+pub(crate) fn is_fixnum(x: i64) -> bool {
+    (MOST_NEGATIVE_FIXNUM..=MOST_POSITIVE_FIXNUM).contains(&x)
+}
+
+/// An integer that does not fit in a fixnum. Bignums are always normalized,
+/// so a `LispBigInt` never holds a value in the fixnum range. This means two
+/// integers are `eql` if they are both fixnums and `eq`, or both bignums with
+/// the same value.
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) struct LispBigInt(BigInt);
+
+impl LispBigInt {
+    pub(crate) fn get(&self) -> &BigInt {
+        &self.0
+    }
+}
+
+/// Integers outside of the fixnum range are allocated as bignums.
+impl IntoObject for BigInt {
+    type Out<'ob> = Object<'ob>;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        match self.to_i64() {
+            Some(x) if is_fixnum(x) => x.into(),
+            _ => LispBigInt(self).into_obj(block).into(),
+        }
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispBigInt {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        LispBigInt(self.0.clone()).into_obj(bk)
+    }
+}
+
+impl Trace for LispBigInt {
+    fn trace(&mut self, _state: &mut GcState) {}
+}
+
+impl GcManaged for LispBigInt {}
+
+impl Display for LispBigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Debug for LispBigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+
+    #[test]
+    fn test_normalize() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let small = cx.add(BigInt::from(MOST_POSITIVE_FIXNUM));
+        assert_eq!(small, MOST_POSITIVE_FIXNUM);
+        let big = cx.add(BigInt::from(MOST_POSITIVE_FIXNUM) + 1);
+        let Object::BigInt(x) = big.untag() else { unreachable!("expected bignum") };
+        assert_eq!(x.to_string(), "36028797018963968");
+        let negative = cx.add(BigInt::from(MOST_NEGATIVE_FIXNUM) - 1);
+        assert_eq!(negative.to_string(), "-36028797018963969");
+    }
+}
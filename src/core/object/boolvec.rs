@@ -0,0 +1,135 @@
+use super::{CloneIn, Gc, IntoObject};
+use crate::core::gc::{Block, GcManaged, GcState, Trace};
+use anyhow::{ensure, Result};
+use std::cell::Cell;
+use std::fmt::{Debug, Display, Write as _};
+
+/// A lisp bool-vector. This is a fixed length array of bits, stored packed
+/// with the first element in the lowest bit of the first byte.
+#[derive(PartialEq, Eq)]
+pub(crate) struct LispBoolVec {
+    len: usize,
+    bytes: Box<[Cell<u8>]>,
+}
+
+impl LispBoolVec {
+    pub(crate) fn new(len: usize, init: bool) -> Self {
+        let fill = if init { u8::MAX } else { 0 };
+        let bytes: Vec<_> = (0..len.div_ceil(8)).map(|_| Cell::new(fill)).collect();
+        let vec = Self { len, bytes: bytes.into_boxed_slice() };
+        vec.clear_padding();
+        vec
+    }
+
+    pub(crate) fn from_bits(bits: impl ExactSizeIterator<Item = bool>) -> Self {
+        let vec = Self::new(bits.len(), false);
+        for (idx, bit) in bits.enumerate() {
+            vec.bytes[idx / 8].set(vec.bytes[idx / 8].get() | (u8::from(bit) << (idx % 8)));
+        }
+        vec
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<bool> {
+        (idx < self.len).then(|| self.bytes[idx / 8].get() & (1 << (idx % 8)) != 0)
+    }
+
+    pub(crate) fn set(&self, idx: usize, value: bool) -> Result<()> {
+        let len = self.len;
+        ensure!(idx < len, "index {idx} is out of bounds. Length was {len}");
+        let byte = &self.bytes[idx / 8];
+        let mask = 1 << (idx % 8);
+        byte.set(if value { byte.get() | mask } else { byte.get() & !mask });
+        Ok(())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|idx| self.get(idx).unwrap())
+    }
+
+    /// The packed bytes of the vector. Bits past the end of the vector are
+    /// always zero.
+    pub(crate) fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.bytes.iter().map(Cell::get)
+    }
+
+    /// Replace the contents with the packed `bytes`, which must have the same
+    /// length as the vector.
+    pub(crate) fn set_bytes(&self, bytes: impl Iterator<Item = u8>) {
+        for (cell, byte) in self.bytes.iter().zip(bytes) {
+            cell.set(byte);
+        }
+        self.clear_padding();
+    }
+
+    pub(crate) fn count_ones(&self) -> usize {
+        self.bytes().map(|x| x.count_ones() as usize).sum()
+    }
+
+    fn clear_padding(&self) {
+        if let Some(last) = self.bytes.last() {
+            let used = self.len % 8;
+            if used != 0 {
+                last.set(last.get() & ((1 << used) - 1));
+            }
+        }
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispBoolVec {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        let vec = Self::new(self.len, false);
+        vec.set_bytes(self.bytes());
+        vec.into_obj(bk)
+    }
+}
+
+impl Trace for LispBoolVec {
+    fn trace(&mut self, _state: &mut GcState) {}
+}
+
+impl GcManaged for LispBoolVec {}
+
+// Printed the same way as Emacs, as the length followed by the packed bytes
+impl Display for LispBoolVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#&{}\"", self.len)?;
+        for byte in self.bytes() {
+            match byte {
+                b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                b' '..=b'~' => f.write_char(byte as char)?,
+                _ => write!(f, "\\{byte:o}")?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+impl Debug for LispBoolVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bool_vec() {
+        let vec = LispBoolVec::new(10, true);
+        assert_eq!(vec.count_ones(), 10);
+        assert_eq!(vec.to_string(), "#&10\"\\377\\3\"");
+        vec.set(1, false).unwrap();
+        assert_eq!(vec.get(0), Some(true));
+        assert_eq!(vec.get(1), Some(false));
+        assert_eq!(vec.get(10), None);
+        assert!(vec.set(10, true).is_err());
+        let bits = LispBoolVec::from_bits([true, false, true].into_iter());
+        assert_eq!(bits.to_string(), "#&3\"\\5\"");
+        assert_eq!(LispBoolVec::new(0, true).to_string(), "#&0\"\"");
+    }
+}
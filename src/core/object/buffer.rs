@@ -1,7 +1,7 @@
-use super::{Gc, GcObj, Object, RawObj, TagType, WithLifetime};
+use super::{Gc, GcObj, Object, TagType, WithLifetime};
 use crate::core::{
     error::{Type, TypeError},
-    gc::{AllocObject, Block, GcManaged, GcMark, Trace},
+    gc::{AllocObject, Block, GcManaged, GcState, Trace},
 };
 use anyhow::{bail, Result};
 use std::{
@@ -42,15 +42,43 @@ impl<'a> Buffer<'a> {
                 let Some(chr) = char::from_u32(u_32) else { bail!("{i} is an Invalid char") };
                 self.get_mut().text.insert_char(chr);
             }
-            Object::String(s) => self.get_mut().text.insert(s.try_into()?),
+            Object::String(s) => self.insert_str(s.try_into()?),
             x => bail!(TypeError::new(Type::String, x)),
         }
         Ok(())
     }
 
+    pub(crate) fn insert_str(&mut self, text: &str) {
+        self.get_mut().text.insert(text);
+    }
+
     pub(crate) fn delete(&mut self, beg: usize, end: usize) {
         self.get_mut().text.delete_range(beg, end);
     }
+
+    /// The position of point, counting chars from zero.
+    pub(crate) fn point(&self) -> usize {
+        self.get().text.cursor()
+    }
+
+    pub(crate) fn set_point(&mut self, pos: usize) {
+        self.get_mut().text.set_cursor(pos);
+    }
+
+    pub(crate) fn char_before_point(&self) -> Option<char> {
+        let text = &self.get().text;
+        text.char_before(text.cursor())
+    }
+
+    /// The character after char position `pos`, if there is one.
+    pub(crate) fn char_after(&self, pos: usize) -> Option<char> {
+        self.get().text.char_after(pos)
+    }
+
+    /// Kill the buffer, releasing its text. Returns the name it had.
+    pub(crate) fn kill(mut self) -> String {
+        self.data.take().expect("buffer should be live while it is locked").name
+    }
 }
 
 impl<'old, 'new> WithLifetime<'new> for Buffer<'old> {
@@ -91,6 +119,15 @@ impl LispBuffer {
         let buffer = self.text_buffer.lock().unwrap();
         Buffer::new(buffer)
     }
+
+    /// Whether this buffer has been killed. A buffer that is locked is in use
+    /// by some thread, so it is still live.
+    pub(in crate::core) fn is_killed(&self) -> bool {
+        match self.text_buffer.try_lock() {
+            Ok(data) => data.is_none(),
+            Err(_) => false,
+        }
+    }
 }
 
 impl PartialEq for LispBuffer {
@@ -113,16 +150,12 @@ impl Display for LispBuffer {
 }
 
 impl Trace for LispBuffer {
-    fn trace(&self, _v: &mut Vec<RawObj>) {
+    fn trace(&mut self, _state: &mut GcState) {
         // Implement once we hold gc data in the buffer
     }
 }
 
-impl GcManaged for LispBuffer {
-    fn get_mark(&self) -> &GcMark {
-        panic!("Buffer does not use GcMark")
-    }
-}
+impl GcManaged for LispBuffer {}
 
 impl<'old, 'new> LispBuffer {
     pub(in crate::core) fn clone_in<const C: bool>(
@@ -0,0 +1,313 @@
+use super::{nil, CloneIn, Gc, GcObj, IntoObject, Object, WithLifetime};
+use crate::core::gc::{Block, GcManaged, GcState, Trace};
+use anyhow::{ensure, Result};
+use std::cell::{Cell, RefCell};
+use std::fmt::{Debug, Display};
+
+/// The largest character code, the same as in Emacs.
+pub(crate) const MAX_CHAR: u32 = 0x3F_FFFF;
+
+/// A lisp char-table, which maps every character to a value.
+///
+/// The values are stored as a sorted list of disjoint character ranges, so a
+/// table only takes space for the ranges that have been set. Characters
+/// outside of every range have the value nil. Looking up a character that
+/// is nil falls back to the default value and then to the parent table.
+pub(crate) struct CharTable {
+    purpose: Cell<GcObj<'static>>,
+    parent: Cell<GcObj<'static>>,
+    default: Cell<GcObj<'static>>,
+    extras: Box<[Cell<GcObj<'static>>]>,
+    ranges: RefCell<Vec<CharRange>>,
+}
+
+/// The characters from `start` to `end` inclusive all have `value`.
+#[derive(Clone)]
+struct CharRange {
+    start: u32,
+    end: u32,
+    value: GcObj<'static>,
+}
+
+impl CharTable {
+    // SAFETY: Since this type does not have an object lifetime, it is only safe
+    // to create an owned version in context of the allocator.
+    pub(crate) unsafe fn new(purpose: GcObj, init: GcObj, extra_slots: usize) -> Self {
+        let extras = (0..extra_slots).map(|_| Cell::new(nil())).collect();
+        let table = Self {
+            purpose: Cell::new(purpose.with_lifetime()),
+            parent: Cell::new(nil()),
+            default: Cell::new(nil()),
+            extras,
+            ranges: RefCell::new(Vec::new()),
+        };
+        table.set_range(0, MAX_CHAR, init);
+        table
+    }
+
+    pub(crate) fn purpose(&self) -> GcObj<'_> {
+        unsafe { self.purpose.get().with_lifetime() }
+    }
+
+    pub(crate) fn parent(&self) -> GcObj<'_> {
+        unsafe { self.parent.get().with_lifetime() }
+    }
+
+    /// Set the parent, which must be nil or another char-table.
+    pub(crate) fn set_parent(&self, parent: GcObj) -> Result<()> {
+        let mut ancestor = parent;
+        while let Object::CharTable(table) = ancestor.untag() {
+            ensure!(!std::ptr::eq(table, self), "Attempt to make a chartable be its own parent");
+            ancestor = table.parent();
+        }
+        self.parent.set(unsafe { parent.with_lifetime() });
+        Ok(())
+    }
+
+    pub(crate) fn default_value(&self) -> GcObj<'_> {
+        unsafe { self.default.get().with_lifetime() }
+    }
+
+    pub(crate) fn set_default_value(&self, value: GcObj) {
+        self.default.set(unsafe { value.with_lifetime() });
+    }
+
+    pub(crate) fn extra_slot(&self, idx: usize) -> Option<GcObj<'_>> {
+        self.extras.get(idx).map(|x| unsafe { x.get().with_lifetime() })
+    }
+
+    pub(crate) fn set_extra_slot(&self, idx: usize, value: GcObj) -> Result<()> {
+        let len = self.extras.len();
+        let Some(slot) = self.extras.get(idx) else {
+            anyhow::bail!("index {idx} is out of bounds. Length was {len}")
+        };
+        slot.set(unsafe { value.with_lifetime() });
+        Ok(())
+    }
+
+    pub(crate) fn extra_slots(&self) -> usize {
+        self.extras.len()
+    }
+
+    /// The value stored for `chr` in this table, without using the default
+    /// value or the parent.
+    pub(crate) fn get_own(&self, chr: u32) -> GcObj<'_> {
+        let ranges = self.ranges.borrow();
+        let idx = ranges.partition_point(|x| x.end < chr);
+        match ranges.get(idx) {
+            Some(range) if range.start <= chr => unsafe { range.value.with_lifetime() },
+            _ => nil(),
+        }
+    }
+
+    /// The value for `chr`, falling back to the default value and then the
+    /// parent table when it is nil.
+    pub(crate) fn get(&self, chr: u32) -> GcObj<'_> {
+        let value = self.get_own(chr);
+        if !value.nil() {
+            return value;
+        }
+        let default = self.default_value();
+        if !default.nil() {
+            return default;
+        }
+        match self.parent().untag() {
+            Object::CharTable(parent) => parent.get(chr),
+            _ => nil(),
+        }
+    }
+
+    /// Set every character from `start` to `end` inclusive to `value`.
+    pub(crate) fn set_range(&self, start: u32, end: u32, value: GcObj) {
+        debug_assert!(start <= end && end <= MAX_CHAR);
+        let value = unsafe { value.with_lifetime() };
+        let mut ranges = self.ranges.borrow_mut();
+        // The ranges that overlap with the new one
+        let first = ranges.partition_point(|x| x.end < start);
+        let last = ranges.partition_point(|x| x.start <= end);
+        let mut replacement = Vec::with_capacity(3);
+        if let Some(before) = ranges.get(first).filter(|x| x.start < start) {
+            replacement.push(CharRange { end: start - 1, ..before.clone() });
+        }
+        if !value.nil() {
+            replacement.push(CharRange { start, end, value });
+        }
+        if let Some(after) = last.checked_sub(1).and_then(|i| ranges.get(i)).filter(|x| x.end > end)
+        {
+            replacement.push(CharRange { start: end + 1, ..after.clone() });
+        }
+        ranges.splice(first..last, replacement);
+        // Merge neighbours that have the same value
+        ranges.dedup_by(|next, prev| {
+            let adjacent = prev.end + 1 == next.start && prev.value.ptr_eq(next.value);
+            if adjacent {
+                prev.end = next.end;
+            }
+            adjacent
+        });
+    }
+
+    /// The ranges of characters that have a non-nil value in this table,
+    /// without using the default value or the parent.
+    pub(crate) fn own_ranges(&self) -> Vec<(u32, u32, GcObj<'_>)> {
+        let ranges = self.ranges.borrow();
+        ranges
+            .iter()
+            .map(|x| (x.start, x.end, unsafe { x.value.with_lifetime() }))
+            .collect()
+    }
+
+    /// The ranges of characters with a non-nil value, including the values
+    /// from the default value and the parent. Neighbouring ranges with the same
+    /// value are merged.
+    pub(crate) fn effective_ranges(&self) -> Vec<(u32, u32, GcObj<'_>)> {
+        let mut result = Vec::new();
+        let mut next = 0;
+        for (start, end, value) in self.own_ranges() {
+            if next < start {
+                self.inherited_ranges(next, start - 1, &mut result);
+            }
+            push_merged(&mut result, start, end, value);
+            next = end + 1;
+        }
+        if next <= MAX_CHAR {
+            self.inherited_ranges(next, MAX_CHAR, &mut result);
+        }
+        result
+    }
+
+    /// Push the ranges for the characters from `start` to `end` that are not
+    /// set in this table.
+    fn inherited_ranges<'a>(
+        &'a self,
+        start: u32,
+        end: u32,
+        result: &mut Vec<(u32, u32, GcObj<'a>)>,
+    ) {
+        let default = self.default_value();
+        if !default.nil() {
+            push_merged(result, start, end, default);
+        } else if let Object::CharTable(parent) = self.parent().untag() {
+            for (from, to, value) in parent.effective_ranges() {
+                let (from, to) = (from.max(start), to.min(end));
+                if from <= to {
+                    push_merged(result, from, to, value);
+                }
+            }
+        }
+    }
+}
+
+fn push_merged<'a>(
+    ranges: &mut Vec<(u32, u32, GcObj<'a>)>,
+    start: u32,
+    end: u32,
+    value: GcObj<'a>,
+) {
+    if let Some(last) = ranges.last_mut() {
+        if last.1 + 1 == start && last.2.ptr_eq(value) {
+            last.1 = end;
+            return;
+        }
+    }
+    ranges.push((start, end, value));
+}
+
+impl<'new> CloneIn<'new, &'new Self> for CharTable {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        let purpose = self.purpose().clone_in(bk);
+        let table = unsafe { Self::new(purpose, nil(), self.extras.len()) };
+        table.set_default_value(self.default_value().clone_in(bk));
+        table.parent.set(unsafe { self.parent().clone_in(bk).with_lifetime() });
+        for (idx, slot) in self.extras.iter().enumerate() {
+            let value = unsafe { slot.get().with_lifetime() }.clone_in(bk);
+            table.extras[idx].set(unsafe { value.with_lifetime() });
+        }
+        for (start, end, value) in self.own_ranges() {
+            table.set_range(start, end, value.clone_in(bk));
+        }
+        table.into_obj(bk)
+    }
+}
+
+impl Trace for CharTable {
+    fn trace(&mut self, state: &mut GcState) {
+        self.purpose.get_mut().trace(state);
+        self.parent.get_mut().trace(state);
+        self.default.get_mut().trace(state);
+        for slot in &mut self.extras {
+            slot.get_mut().trace(state);
+        }
+        for range in self.ranges.get_mut() {
+            range.value.trace(state);
+        }
+    }
+}
+
+impl GcManaged for CharTable {}
+
+impl PartialEq for CharTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.purpose() == other.purpose()
+            && self.parent() == other.parent()
+            && self.default_value() == other.default_value()
+            && self.extras == other.extras
+            && self.own_ranges() == other.own_ranges()
+    }
+}
+
+impl Eq for CharTable {}
+
+impl Display for CharTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<char-table {}>", self.purpose())
+    }
+}
+
+impl Debug for CharTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+
+    #[test]
+    fn test_set_range() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let table = unsafe { CharTable::new(nil(), nil(), 0) };
+        let one: GcObj = 1.into();
+        let two: GcObj = 2.into();
+        table.set_range(10, 20, one);
+        table.set_range(30, 40, one);
+        assert_eq!(table.own_ranges(), vec![(10, 20, one), (30, 40, one)]);
+        // Filling the gap merges the ranges
+        table.set_range(21, 29, one);
+        assert_eq!(table.own_ranges(), vec![(10, 40, one)]);
+        table.set_range(15, 16, two);
+        assert_eq!(table.own_ranges(), vec![(10, 14, one), (15, 16, two), (17, 40, one)]);
+        table.set_range(12, 35, nil());
+        assert_eq!(table.own_ranges(), vec![(10, 11, one), (36, 40, one)]);
+        assert_eq!(table.get(11), one);
+        assert_eq!(table.get(12), nil());
+        table.set_range(0, MAX_CHAR, two);
+        assert_eq!(table.own_ranges(), vec![(0, MAX_CHAR, two)]);
+
+        let parent = unsafe { CharTable::new(nil(), nil(), 0) }.into_obj(cx);
+        let child = unsafe { CharTable::new(nil(), nil(), 0) };
+        parent.untag().set_range(0, 100, one);
+        child.set_range(50, 60, two);
+        child.set_parent(parent.into()).unwrap();
+        assert_eq!(child.get(10), one);
+        assert_eq!(child.get(55), two);
+        assert_eq!(child.get(200), nil());
+        assert_eq!(child.effective_ranges(), vec![(0, 49, one), (50, 60, two), (61, 100, one)]);
+        child.set_default_value(two);
+        assert_eq!(child.get(10), two);
+        assert!(parent.untag().set_parent(parent.into()).is_err());
+    }
+}
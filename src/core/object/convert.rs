@@ -4,7 +4,7 @@
 
 use super::{
     super::error::{ArgError, Type, TypeError},
-    nil, qtrue, LispHashTable, LispString, LispVec,
+    nil, qtrue, CharTable, LispBoolVec, LispHashTable, LispObarray, LispString, LispVec,
 };
 use super::{Gc, Object};
 use super::{GcObj, LispFloat};
@@ -112,11 +112,14 @@ impl<'ob> From<bool> for GcObj<'ob> {
 }
 
 define_unbox!(Int, i64);
-define_unbox!(Float, &'ob LispFloat);
+define_unbox!(Float, LispFloat<'ob>);
 define_unbox!(HashTable, &'ob LispHashTable);
 define_unbox!(String, &'ob LispString);
 define_unbox!(Vec, &'ob LispVec);
 define_unbox!(Symbol, Symbol<'ob>);
+define_unbox!(BoolVec, &'ob LispBoolVec);
+define_unbox!(CharTable, &'ob CharTable);
+define_unbox!(Obarray, &'ob LispObarray);
 
 impl<'ob, T> From<Option<T>> for GcObj<'ob>
 where
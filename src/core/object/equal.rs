@@ -0,0 +1,187 @@
+//! Structural equality as used by `equal`.
+use super::{CharTable, GcObj, LispString, LispVec, Object};
+use std::fmt::Debug;
+
+/// Nesting depth at which `equal` gives up and treats the objects as
+/// circular. Cycles through the cdr of a list are found without reaching this
+/// limit.
+const MAX_DEPTH: usize = 200;
+
+/// Returned when the objects being compared are too deeply nested to be
+/// anything but circular. Contains the object where the comparison stopped.
+#[derive(Copy, Clone)]
+pub(crate) struct CircularList<'ob>(pub(crate) GcObj<'ob>);
+
+// The object can't be printed, since it is circular
+impl Debug for CircularList<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CircularList")
+    }
+}
+
+/// Compare two objects the way `equal` does. Numbers and strings are compared
+/// by value, and conses, vectors, records and byte-code functions are
+/// compared element by element. Everything else must be `eq`. When
+/// `properties` is set, the text properties of strings have to match as well.
+/// There is no marker object yet, so markers are not compared by position.
+pub(crate) fn internal_equal<'ob>(
+    o1: GcObj<'ob>,
+    o2: GcObj<'ob>,
+    properties: bool,
+) -> Result<bool, CircularList<'ob>> {
+    equal_at_depth(o1, o2, properties, 0)
+}
+
+fn equal_at_depth<'ob>(
+    o1: GcObj<'ob>,
+    o2: GcObj<'ob>,
+    properties: bool,
+    depth: usize,
+) -> Result<bool, CircularList<'ob>> {
+    if depth > MAX_DEPTH {
+        return Err(CircularList(o1));
+    }
+    if o1.ptr_eq(o2) {
+        return Ok(true);
+    }
+    let depth = depth + 1;
+    match (o1.untag(), o2.untag()) {
+        // Unlike `=`, NaNs with the same bits are equal and the signed zeros
+        // are not
+        (Object::Float(x), Object::Float(y)) => Ok(x.get().to_bits() == y.get().to_bits()),
+        (Object::String(x), Object::String(y)) => Ok(string_equal(x, y, properties)),
+        (Object::BigInt(x), Object::BigInt(y)) => Ok(x == y),
+        (Object::Cons(_), Object::Cons(_)) => list_equal(o1, o2, properties, depth),
+        (Object::Vec(x), Object::Vec(y)) => slice_equal(x, y, properties, depth),
+        (Object::Record(x), Object::Record(y)) => slice_equal(x, y, properties, depth),
+        (Object::BoolVec(x), Object::BoolVec(y)) => Ok(x == y),
+        (Object::CharTable(x), Object::CharTable(y)) => char_table_equal(x, y, properties, depth),
+        (Object::ByteFn(x), Object::ByteFn(y)) => {
+            for i in 0..4 {
+                let (Some(x), Some(y)) = (x.index(i), y.index(i)) else { unreachable!() };
+                if !equal_at_depth(x, y, properties, depth)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Compare two lists, iterating over the cdr so that long lists don't use up
+/// the depth limit. A cycle in the cdr of `o1` is found with Brent's
+/// algorithm.
+fn list_equal<'ob>(
+    o1: GcObj<'ob>,
+    o2: GcObj<'ob>,
+    properties: bool,
+    depth: usize,
+) -> Result<bool, CircularList<'ob>> {
+    let (mut x, mut y) = (o1, o2);
+    let mut tortoise = o1;
+    let (mut power, mut steps) = (1_usize, 0_usize);
+    loop {
+        let (Object::Cons(cons1), Object::Cons(cons2)) = (x.untag(), y.untag()) else {
+            return equal_at_depth(x, y, properties, depth);
+        };
+        if !equal_at_depth(cons1.car(), cons2.car(), properties, depth)? {
+            return Ok(false);
+        }
+        (x, y) = (cons1.cdr(), cons2.cdr());
+        if x.ptr_eq(y) {
+            return Ok(true);
+        }
+        if x.ptr_eq(tortoise) {
+            return Err(CircularList(o1));
+        }
+        steps += 1;
+        if steps == power {
+            tortoise = x;
+            power *= 2;
+            steps = 0;
+        }
+    }
+}
+
+fn slice_equal<'ob>(
+    x: &'ob LispVec,
+    y: &'ob LispVec,
+    properties: bool,
+    depth: usize,
+) -> Result<bool, CircularList<'ob>> {
+    if x.len() != y.len() {
+        return Ok(false);
+    }
+    for (x, y) in x.iter().zip(y.iter()) {
+        if !equal_at_depth(x.get(), y.get(), properties, depth)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn char_table_equal<'ob>(
+    x: &'ob CharTable,
+    y: &'ob CharTable,
+    properties: bool,
+    depth: usize,
+) -> Result<bool, CircularList<'ob>> {
+    let (x_ranges, y_ranges) = (x.own_ranges(), y.own_ranges());
+    if x.extra_slots() != y.extra_slots() || x_ranges.len() != y_ranges.len() {
+        return Ok(false);
+    }
+    let fields = [
+        (x.purpose(), y.purpose()),
+        (x.parent(), y.parent()),
+        (x.default_value(), y.default_value()),
+    ];
+    let extras = (0..x.extra_slots()).map(|i| (x.extra_slot(i).unwrap(), y.extra_slot(i).unwrap()));
+    for (x, y) in fields.into_iter().chain(extras) {
+        if !equal_at_depth(x, y, properties, depth)? {
+            return Ok(false);
+        }
+    }
+    for ((x_start, x_end, x), (y_start, y_end, y)) in x_ranges.into_iter().zip(y_ranges) {
+        if x_start != y_start || x_end != y_end || !equal_at_depth(x, y, properties, depth)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Strings are equal if they have the same characters. A unibyte and a
+/// multibyte string can only be equal if they are both ASCII, because any other
+/// byte means a different character in each representation.
+fn string_equal(x: &LispString, y: &LispString, _properties: bool) -> bool {
+    // Strings don't have text properties yet, so there is nothing more to
+    // compare for `equal-including-properties`
+    let (x_bytes, y_bytes): (&[u8], &[u8]) = (x, y);
+    x_bytes == y_bytes && (x.is_multibyte() == y.is_multibyte() || x_bytes.is_ascii())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+
+    #[test]
+    fn test_circular_list() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let list1 = list![1, 2, 3; cx];
+        let list2 = list![1, 2, 3; cx];
+        list1.as_cons().conses().last().unwrap().unwrap().set_cdr(list1).unwrap();
+        list2.as_cons().conses().last().unwrap().unwrap().set_cdr(list2).unwrap();
+        assert!(matches!(internal_equal(list1, list1, false), Ok(true)));
+        let result = internal_equal(list1, list2, false);
+        assert!(matches!(result, Err(CircularList(x)) if x.ptr_eq(list1)));
+
+        // Nested through the car
+        let nested1 = list![1; cx];
+        let nested2 = list![1; cx];
+        nested1.as_cons().set_car(nested1).unwrap();
+        nested2.as_cons().set_car(nested2).unwrap();
+        assert!(internal_equal(nested1, nested2, false).is_err());
+    }
+}
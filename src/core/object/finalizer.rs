@@ -0,0 +1,66 @@
+use super::{nil, CloneIn, Gc, GcObj, IntoObject, WithLifetime};
+use crate::core::gc::{Block, GcManaged, GcState, Trace};
+use std::cell::Cell;
+use std::fmt::{Debug, Display};
+
+/// An object created by `make-finalizer`. Once the collector finds that the
+/// finalizer is no longer reachable, its function is called with no
+/// arguments. The function is only called once.
+pub(crate) struct LispFinalizer {
+    function: Cell<GcObj<'static>>,
+}
+
+impl LispFinalizer {
+    // SAFETY: Since this type does not have an object lifetime, it is only safe
+    // to create an owned version in context of the allocator.
+    pub(crate) unsafe fn new(function: GcObj) -> Self {
+        Self { function: Cell::new(function.with_lifetime()) }
+    }
+
+    pub(crate) fn function(&self) -> GcObj<'_> {
+        unsafe { self.function.get().with_lifetime() }
+    }
+
+    /// Remove the function from the finalizer so that it will not be run
+    /// again.
+    pub(crate) fn take(&self) -> GcObj<'_> {
+        let function = self.function();
+        self.function.set(nil());
+        function
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispFinalizer {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        unsafe { Self::new(self.function().clone_in(bk)) }.into_obj(bk)
+    }
+}
+
+impl Trace for LispFinalizer {
+    fn trace(&mut self, state: &mut GcState) {
+        self.function.get_mut().trace(state);
+    }
+}
+
+impl GcManaged for LispFinalizer {}
+
+// Finalizers are only equal to themselves
+impl PartialEq for LispFinalizer {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for LispFinalizer {}
+
+impl Display for LispFinalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<finalizer>")
+    }
+}
+
+impl Debug for LispFinalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
@@ -1,22 +1,25 @@
-use crate::core::gc::{GcManaged, GcMark};
+use super::WithLifetime;
+use crate::core::gc::GcManaged;
 use std::fmt::{Debug, Display};
+use std::marker::PhantomData;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// A float that is allocated on the heap.
 #[derive(PartialEq)]
-pub(crate) struct LispFloat {
-    gc: GcMark,
+pub(crate) struct FloatCell {
     float: f64,
 }
 
-impl Eq for LispFloat {}
+impl Eq for FloatCell {}
 
-impl LispFloat {
+impl FloatCell {
     pub(in crate::core) fn new(float: f64) -> Self {
-        Self { gc: GcMark::default(), float }
+        Self { float }
     }
 }
 
-impl Deref for LispFloat {
+impl Deref for FloatCell {
     type Target = f64;
 
     fn deref(&self) -> &Self::Target {
@@ -24,25 +27,256 @@ impl Deref for LispFloat {
     }
 }
 
-impl GcManaged for LispFloat {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
+impl GcManaged for FloatCell {}
+
+/// A lisp float. Most floats are stored on the heap in a [`FloatCell`], but
+/// the common ones (0.0 and positive floats in the range [2^-8, 2^8) whose
+/// mantissa fits in 28 bits) are stored directly in the tagged pointer to
+/// avoid the allocation, as described in the Floats section of design.org.
+///
+/// A compact float also holds an id, which is taken from a counter every time
+/// one is created. That keeps two floats that were created separately from
+/// being `eq`, the same as two heap floats.
+#[derive(Copy, Clone)]
+pub(crate) struct LispFloat<'ob> {
+    // Either a pointer to a FloatCell or a compact float shifted left by one
+    // with the low bit set. A FloatCell is aligned, so its address never has
+    // the low bit set.
+    ptr: *const u8,
+    _data: PhantomData<&'ob FloatCell>,
+}
+
+const MANTISSA_BITS: u32 = 52;
+const MANTISSA_MASK: u64 = (1 << MANTISSA_BITS) - 1;
+const EXPONENT_BITS: u32 = 4;
+/// Biased exponent of 2^-8, the smallest compact exponent.
+const MIN_EXPONENT: u64 = 1023 - 8;
+/// The high bits of the mantissa that a compact float keeps. The rest have to
+/// be zero.
+const COMPACT_MANTISSA_BITS: u32 = 28;
+const DROPPED_BITS: u32 = MANTISSA_BITS - COMPACT_MANTISSA_BITS;
+const ID_BITS: u32 = 24;
+const ID_MASK: u64 = (1 << ID_BITS) - 1;
+
+/// The id of the next compact float. It wraps around after 2^24 floats, so
+/// two floats with the same value can end up `eq` after that many others were
+/// created. Emacs leaves it unspecified whether such floats are `eq`.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+impl<'ob> LispFloat<'ob> {
+    /// Number of bits needed to store a compact float.
+    pub(super) const COMPACT_BITS: u32 = EXPONENT_BITS + COMPACT_MANTISSA_BITS + ID_BITS;
+
+    pub(super) fn from_cell(cell: &'ob FloatCell) -> Self {
+        Self { ptr: std::ptr::from_ref(cell).cast(), _data: PhantomData }
+    }
+
+    pub(super) fn from_compact(compact: u64) -> Self {
+        Self { ptr: sptr::invalid(((compact << 1) | 1) as usize), _data: PhantomData }
+    }
+
+    /// Encode `float` in the compact form with a new id if it is in range. 0
+    /// is used for 0.0, so 2^-8 (whose compact value would also be 0) is left
+    /// on the heap.
+    pub(super) fn encode(float: f64) -> Option<u64> {
+        let value = Self::encode_value(float)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed) & ID_MASK;
+        Some((value << ID_BITS) | id)
+    }
+
+    fn encode_value(float: f64) -> Option<u64> {
+        let bits = float.to_bits();
+        if bits == 0 {
+            return Some(0);
+        }
+        // The sign bit is part of the exponent here, so negative floats are
+        // always out of range
+        let exponent = (bits >> MANTISSA_BITS).checked_sub(MIN_EXPONENT)?;
+        let mantissa = bits & MANTISSA_MASK;
+        if exponent >= 1 << EXPONENT_BITS || mantissa.trailing_zeros() < DROPPED_BITS {
+            return None;
+        }
+        let value = (exponent << COMPACT_MANTISSA_BITS) | (mantissa >> DROPPED_BITS);
+        (value != 0).then_some(value)
+    }
+
+    fn decode(compact: u64) -> f64 {
+        let value = compact >> ID_BITS;
+        if value == 0 {
+            return 0.0;
+        }
+        let exponent = (value >> COMPACT_MANTISSA_BITS) + MIN_EXPONENT;
+        let mantissa = (value << DROPPED_BITS) & MANTISSA_MASK;
+        f64::from_bits((exponent << MANTISSA_BITS) | mantissa)
+    }
+
+    /// The compact form of this float, or `None` if it lives on the heap.
+    pub(super) fn compact(self) -> Option<u64> {
+        let addr = self.ptr.addr() as u64;
+        (addr & 1 == 1).then_some(addr >> 1)
+    }
+
+    pub(super) fn cell(self) -> *const FloatCell {
+        debug_assert!(self.compact().is_none());
+        self.ptr.cast()
+    }
+
+    pub(crate) fn get(self) -> f64 {
+        match self.compact() {
+            Some(compact) => Self::decode(compact),
+            None => unsafe { **self.ptr.cast::<FloatCell>() },
+        }
+    }
+}
+
+impl<'new> WithLifetime<'new> for LispFloat<'_> {
+    type Out = LispFloat<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute(self)
+    }
+}
+
+impl PartialEq for LispFloat<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
     }
 }
 
-impl Display for LispFloat {
+impl Eq for LispFloat<'_> {}
+
+impl Display for LispFloat<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let float = self.float;
-        if float.fract() == 0.0_f64 {
-            write!(f, "{float:.1}")
-        } else {
-            write!(f, "{float}")
-        }
+        f.write_str(&float_to_string(self.get(), None))
     }
 }
 
-impl Debug for LispFloat {
+impl Debug for LispFloat<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{self}")
     }
 }
+
+/// The payload bits of a NaN, which are the mantissa without the quiet bit.
+pub(crate) const NAN_PAYLOAD_MASK: u64 = MANTISSA_MASK >> 1;
+
+/// Print `value` the way Emacs does. That is the shortest string that reads
+/// back as the same float, or the C printf conversion and precision given by
+/// `format`. Either way the result reads as a float. Infinities are printed as
+/// `1.0e+INF` and `-1.0e+INF`, and NaNs as `0.0e+NaN` with the payload in
+/// place of the 0.
+pub(crate) fn float_to_string(value: f64, format: Option<(char, Option<usize>)>) -> String {
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if value.is_nan() {
+        return format!("{sign}{}.0e+NaN", value.to_bits() & NAN_PAYLOAD_MASK);
+    }
+    if value.is_infinite() {
+        return format!("{sign}1.0e+INF");
+    }
+    let mut printed = match format {
+        Some((conversion, precision)) => format_float(value, conversion, precision),
+        None => shortest_float(value),
+    };
+    // "%.0f" is allowed to print an integer
+    if format != Some(('f', Some(0))) {
+        if !printed.contains(['.', 'e']) {
+            printed.push_str(".0");
+        } else if printed.ends_with('.') {
+            printed.push('0');
+        }
+    }
+    printed
+}
+
+/// The shortest `%g` conversion of `value` that reads back as the same float.
+fn shortest_float(value: f64) -> String {
+    // 15 digits are always enough for a normal float with fewer significant
+    // digits, and 17 are enough for any float.
+    let start = if value.abs() < f64::MIN_POSITIVE { 1 } else { 15 };
+    (start..17)
+        .map(|precision| format_float(value, 'g', Some(precision)))
+        .find(|printed| printed.parse() == Ok(value))
+        .unwrap_or_else(|| format_float(value, 'g', Some(17)))
+}
+
+/// Format `value` like the C printf conversion `%.Pe`, `%.Pf` or `%.Pg`.
+/// `precision` defaults to 6 as in C.
+pub(crate) fn format_float(value: f64, conversion: char, precision: Option<usize>) -> String {
+    let precision = precision.unwrap_or(6);
+    match conversion {
+        'e' => exponential(value, precision),
+        'g' => {
+            // %g drops trailing zeros
+            let printed = general(value, precision);
+            let (mantissa, exp) = printed.split_at(printed.find('e').unwrap_or(printed.len()));
+            if mantissa.contains('.') {
+                format!("{}{exp}", mantissa.trim_end_matches('0').trim_end_matches('.'))
+            } else {
+                printed
+            }
+        }
+        _ => format!("{value:.precision$}"),
+    }
+}
+
+/// Like [`format_float`] with the C `#` flag, so the result always has a
+/// decimal point and `%g` keeps its trailing zeros.
+pub(crate) fn format_float_alternate(
+    value: f64,
+    conversion: char,
+    precision: Option<usize>,
+) -> String {
+    let mut printed = match conversion {
+        'g' => general(value, precision.unwrap_or(6)),
+        _ => format_float(value, conversion, precision),
+    };
+    if !printed.contains('.') {
+        let end = printed.find('e').unwrap_or(printed.len());
+        printed.insert(end, '.');
+    }
+    printed
+}
+
+/// Format `value` like `%.Pg` in C but keep the trailing zeros. That uses
+/// `%e` if the exponent is less than -4 or at least the precision.
+fn general(value: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    let exp = exponential(value, precision - 1);
+    let exponent: i32 = exp.split_once('e').map_or(0, |x| x.1.parse().unwrap());
+    if exponent < -4 || exponent >= precision as i32 {
+        exp
+    } else {
+        format!("{value:.0$}", (precision as i32 - 1 - exponent) as usize)
+    }
+}
+
+/// Format `value` like `%.Pe` in C, which has a sign and at least two
+/// digits in the exponent.
+fn exponential(value: f64, precision: usize) -> String {
+    let printed = format!("{value:.precision$e}");
+    let (mantissa, exponent) = printed.split_once('e').expect("exponent format has an `e`");
+    let exponent: i32 = exponent.parse().expect("exponent is an integer");
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compact_encoding() {
+        for float in [0.0, 0.005_859_375, 0.5, 1.0, 1.5, 3.25, 100.0, 123.75, 255.5] {
+            let compact = LispFloat::encode(float).unwrap();
+            assert!(compact < 1 << LispFloat::COMPACT_BITS);
+            assert_eq!(LispFloat::decode(compact).to_bits(), float.to_bits());
+            assert_eq!(LispFloat::from_compact(compact).get().to_bits(), float.to_bits());
+            // every compact float gets its own id
+            assert_ne!(LispFloat::encode(float), Some(compact));
+        }
+        let floats = [-0.0, -1.0, 0.003_906_25, 0.1, 1.0 + 2_f64.powi(-30), 256.0, 1e10];
+        for float in floats.into_iter().chain([f64::NAN, f64::INFINITY]) {
+            assert_eq!(LispFloat::encode(float), None);
+        }
+    }
+}
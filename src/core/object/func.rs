@@ -6,7 +6,8 @@ use super::{
     display_slice, nil, CloneIn, IntoObject, LispString, LispVec,
 };
 use super::{GcObj, WithLifetime};
-use crate::core::gc::{GcManaged, GcMark, Rt};
+use crate::core::cons::Cons;
+use crate::core::gc::{GcManaged, Rt};
 use anyhow::{bail, ensure, Result};
 use fn_macros::Trace;
 use std::fmt::{self, Debug, Display};
@@ -16,13 +17,15 @@ use streaming_iterator::StreamingIterator;
 /// so this contains the byte-code representation of the function.
 #[derive(PartialEq, Eq, Trace)]
 pub(crate) struct ByteFn {
-    gc: GcMark,
     #[no_trace]
     pub(crate) args: FnArgs,
     #[no_trace]
     pub(crate) depth: usize,
     op_codes: &'static LispString,
     constants: &'static LispVec,
+    /// The argument list of a dynamically bound function. Its arguments are
+    /// bound as special variables instead of being passed on the stack.
+    arglist: Option<&'static Cons>,
 }
 
 define_unbox!(ByteFn, Func, &'ob ByteFn);
@@ -32,12 +35,13 @@ impl ByteFn {
         op_codes: &LispString,
         consts: &LispVec,
         args: FnArgs,
+        arglist: Option<&Cons>,
         depth: usize,
     ) -> Self {
         Self {
-            gc: GcMark::default(),
             constants: unsafe { consts.with_lifetime() },
             op_codes: unsafe { op_codes.with_lifetime() },
+            arglist: arglist.map(|x| unsafe { x.with_lifetime() }),
             args,
             depth,
         }
@@ -51,9 +55,17 @@ impl ByteFn {
         unsafe { std::mem::transmute::<&'static LispVec, &'a LispVec>(self.constants) }
     }
 
+    pub(crate) fn arglist<'a>(&'a self) -> Option<&'a Cons> {
+        self.arglist
+            .map(|x| unsafe { std::mem::transmute::<&'static Cons, &'a Cons>(x) })
+    }
+
     pub(crate) fn index(&self, index: usize) -> Option<GcObj> {
         match index {
-            0 => Some((self.args.into_arg_spec() as i64).into()),
+            0 => match self.arglist() {
+                Some(arglist) => Some(arglist.into()),
+                None => Some((self.args.into_arg_spec() as i64).into()),
+            },
             1 => Some(self.codes().into()),
             2 => Some(self.constants().into()),
             3 => Some(self.depth.into()),
@@ -69,6 +81,7 @@ impl<'new> CloneIn<'new, &'new Self> for ByteFn {
                 self.op_codes.clone_in(bk).untag(),
                 self.constants.clone_in(bk).untag(),
                 self.args,
+                self.arglist.map(|x| x.clone_in(bk).untag()),
                 self.depth,
             )
         };
@@ -76,21 +89,7 @@ impl<'new> CloneIn<'new, &'new Self> for ByteFn {
     }
 }
 
-impl Rt<&'static ByteFn> {
-    pub(crate) fn code(&self) -> &Rt<&'static LispString> {
-        unsafe { &*std::ptr::addr_of!(self.bind_unchecked().op_codes).cast() }
-    }
-
-    pub(crate) fn consts(&self) -> &Rt<&'static LispVec> {
-        unsafe { &*std::ptr::addr_of!(self.bind_unchecked().constants).cast() }
-    }
-}
-
-impl GcManaged for ByteFn {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
-    }
-}
+impl GcManaged for ByteFn {}
 
 impl Display for ByteFn {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -141,7 +140,7 @@ impl<'rt> StreamingIterator for ByteFnStreamIter<'rt> {
 }
 
 /// Argument requirments to a function.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub(crate) struct FnArgs {
     /// a &rest argument.
     pub(crate) rest: bool,
@@ -223,6 +222,7 @@ impl SubrFn {
                 args.push(nil());
             }
         }
+        cx.stress_collect();
         (self.subr)(args, env, cx)
     }
 }
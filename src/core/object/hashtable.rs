@@ -1,26 +1,281 @@
-use super::{CloneIn, Gc, GcObj, IntoObject, MutObjCell, ObjCell};
-use crate::core::gc::{Context, Rt};
+use super::{
+    internal_equal, CircularList, CloneIn, Function, Gc, GcObj, IntoObject, MutObjCell, ObjCell,
+    Object, WithLifetime,
+};
+use crate::core::env::{sym, Symbol};
+use crate::core::gc::Rt;
 use crate::{
-    core::gc::{GcManaged, GcMark, Trace},
-    hashmap::HashMap,
+    core::gc::{GcManaged, GcState, Trace},
+    hashmap::IndexMap,
 };
-use std::cell::{BorrowMutError, Ref, RefCell, RefMut};
-use std::collections::hash_map::Iter as HashIter;
+use indexmap::map::raw_entry_v1::RawEntryMut;
+use indexmap::map::{MutableKeys, RawEntryApiV1};
+use rustc_hash::FxHasher;
+use std::cell::{BorrowMutError, Cell, Ref, RefCell, RefMut};
 use std::fmt::{Debug, Display};
+use std::hash::{BuildHasher, Hash, Hasher};
 use streaming_iterator::StreamingIterator;
 
-pub(crate) type HashTable<'ob> = HashMap<GcObj<'ob>, GcObj<'ob>>;
-pub(crate) type HashTableView<'ob, T> = HashMap<GcObj<'ob>, T>;
-#[derive(Debug, Eq)]
+/// Hash tables keep their insertion order. This means they can be iterated by
+/// index, which stays valid when the table is moved and rehashed by the
+/// collector.
+pub(crate) type HashTable<'ob> = IndexMap<GcObj<'ob>, GcObj<'ob>>;
+pub(crate) type HashTableView<'ob, T> = IndexMap<GcObj<'ob>, T>;
+#[derive(Debug)]
 pub(crate) struct LispHashTable {
-    gc: GcMark,
     is_const: bool,
+    weakness: Cell<Option<Weakness>>,
+    test: Cell<HashTest<'static>>,
+    /// Set when the collector has moved the keys of a table with a custom test.
+    /// Their hashes have to be recomputed in Lisp before the table is used.
+    stale: Cell<bool>,
     inner: RefCell<HashTableView<'static, ObjCell>>,
 }
 
+/// How the keys of a hash table are compared and hashed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum HashTest<'ob> {
+    Eq,
+    Eql,
+    Equal,
+    /// A test created by `define-hash-table-test`. `test` and `hash` are Lisp
+    /// functions, so tables using this are only accessed through the lisp
+    /// functions in `fns.rs`.
+    Custom {
+        name: Symbol<'ob>,
+        test: Gc<Function<'ob>>,
+        hash: Gc<Function<'ob>>,
+    },
+}
+
+impl<'ob> HashTest<'ob> {
+    /// Hash `key` consistently with this test. Custom tests can only be hashed
+    /// by calling into Lisp, so they return `None`.
+    pub(crate) fn hash(self, key: GcObj) -> Option<u64> {
+        match self {
+            HashTest::Eq => Some(hash_eq(key)),
+            HashTest::Eql => Some(hash_eql(key)),
+            HashTest::Equal => Some(hash_equal(key)),
+            HashTest::Custom { .. } => None,
+        }
+    }
+
+    /// Whether `x` and `y` are the same key. Like `equal`, this fails for
+    /// `Equal` if the keys are circular.
+    pub(crate) fn matches<'a>(self, x: GcObj<'a>, y: GcObj<'a>) -> Result<bool, CircularList<'a>> {
+        match self {
+            HashTest::Eq => Ok(x.ptr_eq(y)),
+            HashTest::Eql => Ok(match (x.untag(), y.untag()) {
+                (Object::Float(x), Object::Float(y)) => x.get().to_bits() == y.get().to_bits(),
+                (Object::BigInt(x), Object::BigInt(y)) => x == y,
+                _ => x.ptr_eq(y),
+            }),
+            HashTest::Equal => internal_equal(x, y, false),
+            HashTest::Custom { .. } => unreachable!("custom hash tests are called from lisp"),
+        }
+    }
+
+    /// The name of the test, as passed to `make-hash-table`.
+    pub(crate) fn name(self) -> Symbol<'ob> {
+        match self {
+            HashTest::Eq => sym::EQ,
+            HashTest::Eql => sym::EQL,
+            HashTest::Equal => sym::EQUAL,
+            HashTest::Custom { name, .. } => name,
+        }
+    }
+
+    pub(crate) fn is_custom(self) -> bool {
+        matches!(self, HashTest::Custom { .. })
+    }
+
+    fn trace(&mut self, state: &mut GcState) {
+        if let HashTest::Custom { name, test, hash } = self {
+            name.trace(state);
+            test.trace(state);
+            hash.trace(state);
+        }
+    }
+}
+
+impl<'old, 'new> WithLifetime<'new> for HashTest<'old> {
+    type Out = HashTest<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<HashTest<'old>, HashTest<'new>>(self)
+    }
+}
+
+/// Hash an object by identity. This is the same hash that the table's hasher
+/// gives a `Gc`, so tables built directly from a [`HashTable`] are valid `eq`
+/// tables.
+pub(crate) fn hash_eq(obj: GcObj) -> u64 {
+    std::hash::BuildHasherDefault::<FxHasher>::default().hash_one(obj)
+}
+
+/// Like [`hash_eq`], but floats and bignums are hashed by value.
+pub(crate) fn hash_eql(obj: GcObj) -> u64 {
+    match obj.untag() {
+        Object::Float(x) => {
+            let mut hasher = FxHasher::default();
+            x.get().to_bits().hash(&mut hasher);
+            hasher.finish()
+        }
+        Object::BigInt(x) => {
+            let mut hasher = FxHasher::default();
+            x.hash(&mut hasher);
+            hasher.finish()
+        }
+        _ => hash_eq(obj),
+    }
+}
+
+/// Hash an object by its contents. Like Emacs, only the first few elements of
+/// lists and vectors, down to a limited depth, are included. This does not
+/// depend on the address of any object, so it stays the same when objects are
+/// moved by the collector.
+pub(crate) fn hash_equal(obj: GcObj) -> u64 {
+    let mut hasher = FxHasher::default();
+    hash_equal_into(obj, 0, &mut hasher);
+    hasher.finish()
+}
+
+const SXHASH_MAX_DEPTH: usize = 3;
+const SXHASH_MAX_LEN: usize = 7;
+
+fn hash_equal_into(obj: GcObj, depth: usize, hasher: &mut FxHasher) {
+    let obj = obj.untag();
+    std::mem::discriminant(&obj).hash(hasher);
+    if depth > SXHASH_MAX_DEPTH {
+        return;
+    }
+    match obj {
+        Object::Int(x) => x.hash(hasher),
+        Object::BigInt(x) => x.hash(hasher),
+        Object::Float(x) => {
+            // 0.0 and -0.0 are equal
+            let float = x.get();
+            let bits = if float == 0.0 { 0 } else { float.to_bits() };
+            bits.hash(hasher);
+        }
+        Object::String(x) => {
+            let bytes: &[u8] = x.as_ref();
+            bytes.hash(hasher);
+        }
+        Object::Symbol(x) => x.name().hash(hasher),
+        Object::Cons(x) => {
+            let mut cons = x;
+            for _ in 0..SXHASH_MAX_LEN {
+                hash_equal_into(cons.car(), depth + 1, hasher);
+                match cons.cdr().untag() {
+                    Object::Cons(next) => cons = next,
+                    _ => {
+                        hash_equal_into(cons.cdr(), depth + 1, hasher);
+                        break;
+                    }
+                }
+            }
+        }
+        Object::Vec(x) => {
+            x.len().hash(hasher);
+            for elem in x.iter().take(SXHASH_MAX_LEN) {
+                hash_equal_into(elem.get(), depth + 1, hasher);
+            }
+        }
+        Object::BoolVec(x) => {
+            x.len().hash(hasher);
+            x.bytes().for_each(|byte| byte.hash(hasher));
+        }
+        Object::Record(x) => {
+            x.len().hash(hasher);
+            for elem in x.iter().take(SXHASH_MAX_LEN) {
+                hash_equal_into(elem.get(), depth + 1, hasher);
+            }
+        }
+        // Everything else is only equal when it is the same object, and the
+        // address may change. These all hash the same for a given type.
+        _ => {}
+    }
+}
+
+/// Which parts of an entry in a weak hash table are not enough on their own to
+/// keep the entry alive. An entry is removed by the collector once the objects
+/// it depends on are only reachable through weak tables.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Weakness {
+    /// Entries are kept while the key is reachable.
+    Key,
+    /// Entries are kept while the value is reachable.
+    Value,
+    /// Entries are kept while either the key or the value is reachable.
+    KeyOrValue,
+    /// Entries are kept while both the key and the value are reachable.
+    KeyAndValue,
+}
+
+impl Weakness {
+    /// Parse the weakness argument of `make-hash-table`. `t` is the same as
+    /// `key-and-value`.
+    pub(crate) fn from_symbol(symbol: Symbol) -> Option<Self> {
+        match symbol {
+            sym::KEY => Some(Weakness::Key),
+            sym::VALUE => Some(Weakness::Value),
+            sym::KEY_OR_VALUE => Some(Weakness::KeyOrValue),
+            sym::KEY_AND_VALUE | sym::TRUE => Some(Weakness::KeyAndValue),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn symbol(self) -> Symbol<'static> {
+        match self {
+            Weakness::Key => sym::KEY,
+            Weakness::Value => sym::VALUE,
+            Weakness::KeyOrValue => sym::KEY_OR_VALUE,
+            Weakness::KeyAndValue => sym::KEY_AND_VALUE,
+        }
+    }
+
+    fn keep_entry(self, key_live: bool, value_live: bool) -> bool {
+        match self {
+            Weakness::Key => key_live,
+            Weakness::Value => value_live,
+            Weakness::KeyOrValue => key_live || value_live,
+            Weakness::KeyAndValue => key_live && value_live,
+        }
+    }
+}
+
+// Tables are equal if they use the same test and have equal values for the
+// same keys. A key that is too deeply nested to compare is treated as missing,
+// since this can't fail.
 impl PartialEq for LispHashTable {
     fn eq(&self, other: &Self) -> bool {
-        self.inner == other.inner
+        if self.test() != other.test() || self.count() != other.count() {
+            return false;
+        }
+        let lookup = |key: GcObj| {
+            let idx = match other.test().hash(key) {
+                Some(_) if !other.stale.get() => other.lookup(key).ok().and_then(|(_, idx)| idx),
+                _ => other.position_by(|k| k == key),
+            };
+            idx.map(|idx| other.value_at(idx))
+        };
+        self.borrow().iter().all(|(key, value)| lookup(*key) == Some(value.get()))
+    }
+}
+
+impl Eq for LispHashTable {}
+
+/// Insert an entry with a precomputed hash. The entry for `key` is replaced if
+/// it is already in the map.
+fn insert_hashed<V>(map: &mut HashTableView<'_, V>, hash: u64, key: GcObj, value: V) {
+    match map.raw_entry_mut_v1().from_hash(hash, |k| k.ptr_eq(key)) {
+        RawEntryMut::Occupied(mut entry) => {
+            entry.insert(value);
+        }
+        RawEntryMut::Vacant(entry) => {
+            entry.insert_hashed_nocheck(hash, unsafe { key.with_lifetime() }, value);
+        }
     }
 }
 
@@ -29,7 +284,184 @@ impl LispHashTable {
     // to create an owned version in context of the allocator.
     pub(in crate::core) unsafe fn new(vec: HashTable) -> Self {
         let cell = std::mem::transmute::<HashTable<'_>, HashTableView<'static, ObjCell>>(vec);
-        Self { gc: GcMark::default(), is_const: false, inner: RefCell::new(cell) }
+        Self {
+            is_const: false,
+            weakness: Cell::new(None),
+            test: Cell::new(HashTest::Eq),
+            stale: Cell::new(false),
+            inner: RefCell::new(cell),
+        }
+    }
+
+    pub(crate) fn weakness(&self) -> Option<Weakness> {
+        self.weakness.get()
+    }
+
+    pub(crate) fn set_weakness(&self, weakness: Option<Weakness>) {
+        self.weakness.set(weakness);
+    }
+
+    pub(crate) fn test(&self) -> HashTest<'_> {
+        unsafe { self.test.get().with_lifetime() }
+    }
+
+    /// Change the test of the table. Existing entries are rehashed, which
+    /// for a custom test is done the next time the table is used from Lisp.
+    pub(crate) fn set_test(&self, test: HashTest) {
+        self.test.set(unsafe { test.with_lifetime() });
+        if test.is_custom() {
+            self.stale.set(true);
+            return;
+        }
+        let mut map = self.inner.borrow_mut();
+        let entries: Vec<_> = map.drain(..).collect();
+        for (key, value) in entries {
+            insert_hashed(&mut map, test.hash(key).unwrap(), key, value);
+        }
+    }
+
+    /// Whether the hashes of a table with a custom test need to be
+    /// recomputed. The flag is cleared, so that it is set again if the
+    /// collector moves the keys while they are being rehashed.
+    pub(crate) fn take_stale(&self) -> bool {
+        self.stale.replace(false)
+    }
+
+    /// Replace the hashes of all entries, in order. This is used to rehash
+    /// tables with a custom test. If the table has changed size since the
+    /// hashes were computed it is left stale.
+    pub(crate) fn rehash(&self, hashes: &[u64]) -> Result<(), BorrowMutError> {
+        let mut map = self.inner.try_borrow_mut()?;
+        if map.len() == hashes.len() {
+            let entries: Vec<_> = map.drain(..).collect();
+            for ((key, value), hash) in entries.into_iter().zip(hashes) {
+                insert_hashed(&mut map, *hash, key, value);
+            }
+        } else {
+            self.stale.set(true);
+        }
+        Ok(())
+    }
+
+    /// A copy of the entries. The keys keep their hashes.
+    pub(crate) fn copy_entries(&self) -> HashTable<'_> {
+        let map = self.borrow();
+        // SAFETY: ObjCell is a transparent wrapper around a GcObj
+        let view: &HashTable = unsafe { &*std::ptr::from_ref(&*map).cast() };
+        view.clone()
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.inner.borrow().len()
+    }
+
+    /// Find the entry for `key` in a table with a builtin test. Returns the
+    /// hash of `key` and the index of its entry if there is one.
+    pub(crate) fn lookup<'a>(
+        &'a self,
+        key: GcObj<'a>,
+    ) -> Result<(u64, Option<usize>), CircularList<'a>> {
+        let test = self.test();
+        let hash = test.hash(key).expect("custom hash tests are called from lisp");
+        let mut error = None;
+        let idx = self.find(hash, |k| match test.matches(k, key) {
+            Ok(is_match) => is_match,
+            Err(e) => {
+                // stop at the first key that can't be compared
+                error = Some(e);
+                true
+            }
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok((hash, idx)),
+        }
+    }
+
+    /// Get the value for `key` in a table with a builtin test.
+    pub(crate) fn get<'a>(&'a self, key: GcObj<'a>) -> Result<Option<GcObj<'a>>, CircularList<'a>> {
+        let (_, idx) = self.lookup(key)?;
+        Ok(idx.map(|idx| self.value_at(idx)))
+    }
+
+    /// The value of the entry at `idx`.
+    pub(crate) fn value_at(&self, idx: usize) -> GcObj<'_> {
+        let map = self.borrow();
+        let (_, value) = map.get_index(idx).expect("index out of bounds");
+        unsafe { value.get().with_lifetime() }
+    }
+
+    /// The index of the entry with `hash` whose key satisfies `is_match`.
+    pub(crate) fn find<'a>(
+        &'a self,
+        hash: u64,
+        mut is_match: impl FnMut(GcObj<'a>) -> bool,
+    ) -> Option<usize> {
+        self.borrow().raw_entry_v1().index_from_hash(hash, |k| is_match(*k))
+    }
+
+    /// The keys that could be equal to a key with `hash`. This does not call
+    /// any Lisp code, so the keys can then be checked with a custom test.
+    pub(crate) fn candidates(&self, hash: u64) -> Vec<GcObj<'_>> {
+        let mut keys = Vec::new();
+        self.find(hash, |k| {
+            keys.push(k);
+            false
+        });
+        keys
+    }
+
+    /// The index of the first key that satisfies `pred`, without using the
+    /// hash.
+    pub(crate) fn position_by<'a>(&'a self, pred: impl FnMut(GcObj<'a>) -> bool) -> Option<usize> {
+        self.borrow().keys().copied().position(pred)
+    }
+
+    /// Set the value for `key` in a table with a builtin test. This fails if
+    /// `key` can't be compared to the other keys because it is circular.
+    pub(crate) fn insert(&self, key: GcObj, value: GcObj) -> anyhow::Result<()> {
+        let Ok((hash, idx)) = self.lookup(key) else {
+            anyhow::bail!("Circular list used as a hash table key")
+        };
+        match idx {
+            Some(idx) => self.set_index(idx, value),
+            None => self.insert_new(hash, key, value),
+        }
+    }
+
+    /// Set the value of the entry at `idx`.
+    pub(crate) fn set_index(&self, idx: usize, value: GcObj) -> anyhow::Result<()> {
+        // Don't take the mutable borrow flag, so this works while the table is
+        // being iterated
+        let map = self.try_borrow_shared_mut()?;
+        map.get_index(idx).expect("index out of bounds").1.set(value);
+        Ok(())
+    }
+
+    /// Insert a key that is known not to be in the table yet.
+    pub(crate) fn insert_new(&self, hash: u64, key: GcObj, value: GcObj) -> anyhow::Result<()> {
+        if self.is_const {
+            anyhow::bail!("Attempt to modify immutable hashtable");
+        }
+        insert_hashed(&mut *self.try_borrow_mut()?, hash, key, value);
+        Ok(())
+    }
+
+    /// Remove the entry at `idx`, keeping the order of the other entries.
+    pub(crate) fn remove_index(&self, idx: usize) -> anyhow::Result<()> {
+        if self.is_const {
+            anyhow::bail!("Attempt to modify immutable hashtable");
+        }
+        self.try_borrow_mut()?.shift_remove_index(idx);
+        Ok(())
+    }
+
+    pub(crate) fn clear(&self) -> anyhow::Result<()> {
+        if self.is_const {
+            anyhow::bail!("Attempt to modify immutable hashtable");
+        }
+        self.try_borrow_mut()?.clear();
+        Ok(())
     }
 
     pub(in crate::core) fn make_const(&mut self) {
@@ -78,72 +510,140 @@ impl LispHashTable {
         }
     }
 
-    pub(crate) fn iter<'rt, 'a>(
+    pub(crate) fn iter<'rt>(
         table: &'rt Rt<Gc<&'static LispHashTable>>,
         root: &'rt mut Rt<(GcObj<'static>, GcObj<'static>)>,
-        cx: &'a Context,
     ) -> HashTableStreamIter<'rt> {
-        // SAFETY: Since we know table is rooted, it is safe to change the inner
-        // lifetime to static and the outer one to match 'rt
-        let ref_cell = unsafe {
-            let cell = table.get(cx).borrow();
-            std::mem::transmute::<
-                Ref<'a, HashTableView<'a, ObjCell>>,
-                Ref<'rt, HashTableView<'static, ObjCell>>,
-            >(cell)
-        };
-        // SAFETY: We are creating two references to the hashtable. One is
-        // cell::Ref that will make sure the borrow flag forbids mutable borrows
-        // until we are done with iteration. The second is an iterator over the
-        // hashtable. You can't normally move the Ref while it borrowed (by the
-        // iterator), but we are hacking it here. I am not even sure if this is
-        // sound, and HashTables need a better abtraction.
-        let iter = unsafe {
-            let hashtable: &HashTableView<'static, ObjCell> = &ref_cell;
-            let hashtable: &'rt HashTableView<'static, ObjCell> = &*(hashtable as *const _);
-            hashtable.iter()
-        };
-        HashTableStreamIter { iter, _rf: ref_cell, item: Some(root) }
+        HashTableStreamIter { table, idx: 0, item: Some(root) }
     }
 }
 
 impl<'new> CloneIn<'new, &'new Self> for LispHashTable {
     fn clone_in<const C: bool>(&self, bk: &'new crate::core::gc::Block<C>) -> Gc<&'new Self> {
+        let test = match self.test() {
+            HashTest::Custom { name, test, hash } => HashTest::Custom {
+                name: name.clone_in(bk).untag(),
+                test: test.clone_in(bk),
+                hash: hash.clone_in(bk),
+            },
+            test => test,
+        };
+        // The entries are hashed before the table is allocated, because a
+        // table in a const block can't be borrowed mutably afterwards.
         let mut table = HashTable::default();
         let borrow = self.borrow();
         for (key, value) in &*borrow {
             let new_key = key.clone_in(bk);
             let new_value = value.get().clone_in(bk);
-            table.insert(new_key, new_value);
+            match test.hash(new_key) {
+                Some(hash) => insert_hashed(&mut table, hash, new_key, new_value),
+                None => {
+                    table.insert(new_key, new_value);
+                }
+            }
         }
-        table.into_obj(bk)
+        let table = table.into_obj(bk);
+        let new = table.untag();
+        new.set_weakness(self.weakness());
+        new.test.set(unsafe { test.with_lifetime() });
+        new.stale.set(test.is_custom());
+        table
     }
 }
 
-impl Trace for LispHashTable {
-    fn trace(&self, stack: &mut Vec<super::RawObj>) {
-        let table = self.borrow();
-        for (k, v) in &*table {
-            if k.is_markable() {
-                stack.push(k.into_raw());
+impl LispHashTable {
+    /// Update the entries after the collector has moved them. Entries for which
+    /// `keep` returns false are removed. `keep` is called before the entry is
+    /// updated.
+    fn retrace(&mut self, state: &mut GcState, keep: impl Fn(GcObj, GcObj, &GcState) -> bool) {
+        self.trace_test(state);
+        let test = self.test.get();
+        let table = self.inner.get_mut();
+        match test {
+            // Only the addresses of the keys change. Equal hashes do not depend
+            // on them, and custom tests are rehashed from Lisp later.
+            HashTest::Equal | HashTest::Custom { .. } => {
+                table.retain2(|key, value| {
+                    if keep(*key, value.get(), state) {
+                        key.trace(state);
+                        value.trace(state);
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if test.is_custom() {
+                    self.stale.set(true);
+                }
             }
-            if v.get().is_markable() {
-                stack.push(v.get().into_raw());
+            // Keys are hashed by address, so the table needs to be rebuilt
+            // once they have been moved.
+            HashTest::Eq | HashTest::Eql => {
+                let entries: Vec<_> = table.drain(..).collect();
+                for (mut key, mut value) in entries {
+                    if keep(key, value.get(), state) {
+                        key.trace(state);
+                        value.trace(state);
+                        insert_hashed(table, test.hash(key).unwrap(), key, value);
+                    }
+                }
             }
         }
-        self.mark();
     }
 }
 
-impl GcManaged for LispHashTable {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
+impl LispHashTable {
+    /// The functions of a custom test are always kept alive, even by weak
+    /// tables.
+    pub(in crate::core) fn trace_test(&mut self, state: &mut GcState) {
+        self.test.get_mut().trace(state);
+    }
+}
+
+impl Trace for LispHashTable {
+    fn trace(&mut self, state: &mut GcState) {
+        self.retrace(state, |_, _, _| true);
+    }
+}
+
+impl LispHashTable {
+    /// Keep alive the parts of entries that the weakness requires. Returns
+    /// true if any new objects were found reachable, which could keep more
+    /// entries alive.
+    pub(in crate::core) fn mark_weak(&self, state: &mut GcState) -> bool {
+        let Some(weakness) = self.weakness() else { return false };
+        let mut marked = false;
+        for (key, value) in self.inner.borrow().iter() {
+            let value = value.get();
+            let key_live = key.is_live(state);
+            let value_live = value.is_live(state);
+            if weakness.keep_entry(key_live, value_live) && !(key_live && value_live) {
+                key.forward(state);
+                value.forward(state);
+                marked = true;
+            }
+        }
+        marked
+    }
+
+    /// Remove the entries of a weak table that were not kept alive and update
+    /// the rest to their new locations.
+    pub(in crate::core) fn sweep_weak(&mut self, state: &mut GcState) {
+        let Some(weakness) = self.weakness() else { return };
+        self.retrace(state, |key, value, state| {
+            weakness.keep_entry(key.is_live(state), value.is_live(state))
+        });
     }
 }
 
+impl GcManaged for LispHashTable {}
+
+/// An iterator over a rooted hash table. This does not hold a borrow of the
+/// table, so the table can be modified (or moved by the collector) while it is
+/// being iterated.
 pub(crate) struct HashTableStreamIter<'rt> {
-    _rf: Ref<'rt, HashTableView<'static, ObjCell>>,
-    iter: HashIter<'rt, GcObj<'static>, ObjCell>,
+    table: &'rt Rt<Gc<&'static LispHashTable>>,
+    idx: usize,
     item: Option<&'rt mut Rt<(GcObj<'static>, GcObj<'static>)>>,
 }
 
@@ -151,11 +651,13 @@ impl<'rt> StreamingIterator for HashTableStreamIter<'rt> {
     type Item = (Rt<GcObj<'static>>, Rt<GcObj<'static>>);
 
     fn advance(&mut self) {
-        if let Some((k, v)) = self.iter.next() {
+        let table = unsafe { self.table.bind_unchecked().untag() };
+        if let Some((k, v)) = table.borrow().get_index(self.idx) {
             let item = self.item.as_mut().expect("item should never be None while iter is Some");
             let tuple: &mut (Rt<GcObj>, Rt<GcObj>) = item;
             tuple.0.set(*k);
             tuple.1.set(v.get());
+            self.idx += 1;
         } else {
             self.item = None;
         }
@@ -166,8 +668,20 @@ impl<'rt> StreamingIterator for HashTableStreamIter<'rt> {
     }
 }
 
+// Printed the same way as Emacs so that it can be read back in
 impl Display for LispHashTable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        write!(f, "#s(hash-table size {} test {}", self.count(), self.test().name())?;
+        if let Some(weakness) = self.weakness() {
+            write!(f, " weakness {}", weakness.symbol())?;
+        }
+        write!(f, " data (")?;
+        for (i, (key, value)) in self.borrow().iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{key} {}", value.get())?;
+        }
+        write!(f, "))")
     }
 }
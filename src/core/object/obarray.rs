@@ -0,0 +1,161 @@
+use super::{CloneIn, Gc, IntoObject, WithLifetime};
+use crate::core::env::{Symbol, SymbolCell, INTERNED_SYMBOLS};
+use crate::core::gc::{Block, Context, GcManaged, GcState, Trace};
+use crate::hashmap::HashMap;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display};
+
+/// A lisp obarray, which is a table of symbols indexed by name.
+///
+/// Symbols interned in a private obarray are allocated like uninterned
+/// symbols, so they are never visible in the global symbol map. The standard
+/// obarray (the default value of the `obarray` variable) has no table of its
+/// own and refers to the global symbol map instead.
+pub(crate) struct LispObarray {
+    symbols: Option<RefCell<HashMap<Box<str>, Symbol<'static>>>>,
+}
+
+impl LispObarray {
+    pub(crate) fn new() -> Self {
+        Self { symbols: Some(RefCell::new(HashMap::default())) }
+    }
+
+    /// An obarray that refers to the globally interned symbols.
+    pub(crate) fn standard() -> Self {
+        Self { symbols: None }
+    }
+
+    /// Return the symbol named `name`, creating it if it does not exist.
+    pub(crate) fn intern<'ob>(&self, name: &str, cx: &'ob Context) -> Symbol<'ob> {
+        let Some(symbols) = &self.symbols else {
+            return crate::core::env::intern(name, cx);
+        };
+        if let Some(sym) = symbols.borrow().get(name) {
+            return unsafe { sym.with_lifetime() };
+        }
+        let sym = SymbolCell::new_uninterned(name).into_obj(cx).untag();
+        symbols.borrow_mut().insert(name.into(), unsafe { sym.with_lifetime() });
+        sym
+    }
+
+    /// Return the symbol named `name`, if it has been interned.
+    pub(crate) fn get(&self, name: &str) -> Option<Symbol<'_>> {
+        match &self.symbols {
+            Some(symbols) => symbols.borrow().get(name).copied(),
+            None => {
+                let map = INTERNED_SYMBOLS.lock().unwrap();
+                map.get(name).map(|x| unsafe { x.with_lifetime() })
+            }
+        }
+    }
+
+    /// Remove `symbol` from the obarray. Returns false if the symbol was not
+    /// interned in this obarray.
+    pub(crate) fn remove(&self, symbol: Symbol) -> bool {
+        if self.get(symbol.name()) != Some(symbol) {
+            return false;
+        }
+        match &self.symbols {
+            Some(symbols) => symbols.borrow_mut().remove(symbol.name()).is_some(),
+            None => INTERNED_SYMBOLS.lock().unwrap().remove(symbol.name()),
+        }
+    }
+
+    /// All the symbols in the obarray.
+    pub(crate) fn symbols(&self) -> Vec<Symbol<'_>> {
+        match &self.symbols {
+            Some(symbols) => symbols.borrow().values().copied().collect(),
+            None => INTERNED_SYMBOLS.lock().unwrap().symbols(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.symbols {
+            Some(symbols) => symbols.borrow().len(),
+            None => INTERNED_SYMBOLS.lock().unwrap().len(),
+        }
+    }
+
+    /// Remove every symbol from a private obarray. The standard obarray can't
+    /// be cleared.
+    pub(crate) fn clear(&self) -> anyhow::Result<()> {
+        match &self.symbols {
+            Some(symbols) => {
+                symbols.borrow_mut().clear();
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Attempt to clear the standard obarray")),
+        }
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispObarray {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        let Some(symbols) = &self.symbols else { return Self::standard().into_obj(bk) };
+        let new = symbols
+            .borrow()
+            .iter()
+            .map(|(name, sym)| {
+                let sym = sym.clone_in(bk).untag();
+                (name.clone(), unsafe { sym.with_lifetime() })
+            })
+            .collect();
+        Self { symbols: Some(RefCell::new(new)) }.into_obj(bk)
+    }
+}
+
+impl Trace for LispObarray {
+    fn trace(&mut self, state: &mut GcState) {
+        if let Some(symbols) = &mut self.symbols {
+            for sym in symbols.get_mut().values_mut() {
+                sym.trace(state);
+            }
+        }
+    }
+}
+
+impl GcManaged for LispObarray {}
+
+// Obarrays are only equal to themselves
+impl PartialEq for LispObarray {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for LispObarray {}
+
+impl Display for LispObarray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<obarray n={}>", self.len())
+    }
+}
+
+impl Debug for LispObarray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_private_obarray() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let obarray = LispObarray::new();
+        let sym = obarray.intern("obarray-test-private", cx);
+        assert_eq!(obarray.intern("obarray-test-private", cx), sym);
+        assert_eq!(obarray.get("obarray-test-private"), Some(sym));
+        assert_eq!(obarray.len(), 1);
+        // not visible in the global symbol map
+        assert!(LispObarray::standard().get("obarray-test-private").is_none());
+        assert_ne!(crate::core::env::intern("obarray-test-private", cx), sym);
+        assert!(obarray.remove(sym));
+        assert!(!obarray.remove(sym));
+        assert!(obarray.get("obarray-test-private").is_none());
+    }
+}
@@ -1,17 +1,15 @@
 use super::{CloneIn, IntoObject};
-use crate::core::gc::{Block, GcManaged, GcMark};
+use crate::character::{byte8_to_char, char_to_byte8};
+use crate::core::gc::{Block, GcManaged};
 use anyhow::Result;
 use bstr::{BStr, BString, ByteSlice};
-use fn_macros::Trace;
 use std::{
-    fmt::{Debug, Display},
+    fmt::{Debug, Display, Write as _},
     ops::Deref,
 };
 
-#[derive(PartialEq, Eq, Trace)]
+#[derive(PartialEq, Eq)]
 pub(crate) struct LispString {
-    gc: GcMark,
-    #[no_trace]
     string: StrType,
 }
 
@@ -21,29 +19,88 @@ unsafe impl Sync for LispString {}
 enum StrType {
     String(String),
     BString(BString),
+    /// A multibyte string that contains raw bytes, which a `String` can't
+    /// hold. See [`RawMultibyte`].
+    RawMultibyte(BString),
 }
 
-impl LispString {
-    pub(crate) fn get_char_at(&self, idx: usize) -> Option<char> {
-        match &self.string {
-            StrType::String(s) => s.chars().nth(idx),
-            StrType::BString(s) => s.chars().nth(idx),
+/// The text of a multibyte string that contains raw bytes. It is stored in the
+/// encoding Emacs uses internally. Characters are UTF-8, and a raw byte is the
+/// overlong two-byte sequence that starts with 0xC0 or 0xC1, so it can't be
+/// confused with any character.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RawMultibyte(pub(in crate::core) Vec<u8>);
+
+impl RawMultibyte {
+    /// Encode the character codes `codes`. Every code has to be either a
+    /// Unicode scalar value or a raw-byte character.
+    pub(crate) fn from_codes(codes: impl IntoIterator<Item = u32>) -> Self {
+        let mut bytes = Vec::new();
+        for code in codes {
+            if let Some(byte) = char_to_byte8(code) {
+                bytes.extend_from_slice(&[0xC0 | ((byte >> 6) & 1), 0x80 | (byte & 0x3F)]);
+            } else {
+                let chr = char::from_u32(code).expect("code should be a character or raw byte");
+                bytes.extend_from_slice(chr.encode_utf8(&mut [0; 4]).as_bytes());
+            }
         }
+        Self(bytes)
     }
+}
 
-    pub(crate) fn len(&self) -> usize {
-        match &self.string {
-            StrType::String(s) => s.chars().count(),
-            StrType::BString(s) => s.chars().count(),
+/// An iterator over the character codes of a string.
+pub(crate) struct CharCodes<'a> {
+    bytes: &'a [u8],
+    raw: bool,
+}
+
+impl Iterator for CharCodes<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&first, rest) = self.bytes.split_first()?;
+        if self.raw && first & 0xFE == 0xC0 {
+            let byte = 0x80 | ((first & 1) << 6) | (rest[0] & 0x3F);
+            self.bytes = &rest[1..];
+            return Some(byte8_to_char(byte));
         }
+        let (chr, len) = bstr::decode_utf8(self.bytes);
+        self.bytes = &self.bytes[len..];
+        Some(chr.unwrap_or(char::REPLACEMENT_CHARACTER).into())
+    }
+}
+
+impl LispString {
+    pub(crate) fn get_char_at(&self, idx: usize) -> Option<u32> {
+        self.char_codes().nth(idx)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.char_codes().count()
+    }
+
+    /// The character codes of the string. Raw bytes in a multibyte string are
+    /// raw-byte characters.
+    pub(crate) fn char_codes(&self) -> CharCodes<'_> {
+        CharCodes { bytes: self, raw: matches!(self.string, StrType::RawMultibyte(_)) }
+    }
+
+    /// Multibyte strings hold characters, while unibyte strings hold raw
+    /// bytes.
+    pub(crate) fn is_multibyte(&self) -> bool {
+        matches!(self.string, StrType::String(_) | StrType::RawMultibyte(_))
     }
 
     pub(crate) unsafe fn from_string(value: String) -> Self {
-        Self { gc: GcMark::default(), string: StrType::String(value) }
+        Self { string: StrType::String(value) }
     }
 
     pub(crate) unsafe fn from_bstring(value: Vec<u8>) -> Self {
-        Self { gc: GcMark::default(), string: StrType::BString(BString::from(value)) }
+        Self { string: StrType::BString(BString::from(value)) }
+    }
+
+    pub(crate) unsafe fn from_raw_multibyte(value: RawMultibyte) -> Self {
+        Self { string: StrType::RawMultibyte(BString::from(value.0)) }
     }
 }
 
@@ -52,15 +109,12 @@ impl<'new> CloneIn<'new, &'new Self> for LispString {
         match &self.string {
             StrType::String(s) => s.clone().into_obj(bk),
             StrType::BString(s) => s.as_bytes().to_vec().into_obj(bk),
+            StrType::RawMultibyte(s) => RawMultibyte(s.as_bytes().to_vec()).into_obj(bk),
         }
     }
 }
 
-impl GcManaged for LispString {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
-    }
-}
+impl GcManaged for LispString {}
 
 impl Deref for LispString {
     type Target = BStr;
@@ -68,7 +122,7 @@ impl Deref for LispString {
     fn deref(&self) -> &Self::Target {
         match &self.string {
             StrType::String(s) => BStr::new(s),
-            StrType::BString(s) => s.as_ref(),
+            StrType::BString(s) | StrType::RawMultibyte(s) => s.as_ref(),
         }
     }
 }
@@ -81,6 +135,17 @@ impl Display for LispString {
                 let bytes: &[u8] = s.as_ref();
                 write!(f, "\"{bytes:?}\"")
             }
+            StrType::RawMultibyte(_) => {
+                f.write_char('"')?;
+                for code in self.char_codes() {
+                    match (char::from_u32(code), char_to_byte8(code)) {
+                        (Some(chr), _) => f.write_char(chr)?,
+                        (None, Some(byte)) => write!(f, "\\{byte:o}")?,
+                        (None, None) => unreachable!("strings only hold characters and raw bytes"),
+                    }
+                }
+                f.write_char('"')
+            }
         }
     }
 }
@@ -97,7 +162,7 @@ impl<'a> TryFrom<&'a LispString> for &'a str {
     fn try_from(value: &'a LispString) -> Result<Self, Self::Error> {
         match &value.string {
             StrType::String(s) => Ok(s),
-            StrType::BString(s) => Ok(s.try_into()?),
+            StrType::BString(s) | StrType::RawMultibyte(s) => Ok(s.try_into()?),
         }
     }
 }
@@ -9,10 +9,11 @@ use super::{
     LispBuffer,
 };
 use super::{
-    ByteFn, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record, RecordBuilder, SubrFn,
+    ByteFn, CharTable, FloatCell, HashTable, LispBigInt, LispBoolVec, LispFinalizer, LispFloat,
+    LispHashTable, LispObarray, LispString, LispVec, RawMultibyte, Record, RecordBuilder, SubrFn,
 };
 use crate::core::env::sym;
-use crate::core::gc::{GcManaged, Trace};
+use crate::core::gc::{GcManaged, GcState, Trace};
 use private::{Tag, TaggedPtr};
 use sptr::Strict;
 use std::fmt;
@@ -20,7 +21,7 @@ use std::marker::PhantomData;
 
 pub(crate) type GcObj<'ob> = Gc<Object<'ob>>;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct RawObj {
     ptr: *const u8,
 }
@@ -102,6 +103,43 @@ impl<T> Gc<T> {
     }
 }
 
+impl<T> Gc<T> {
+    /// Get the new location of this object if it was moved by the collector.
+    pub(in crate::core) fn forward(&self, state: &mut GcState) -> Self {
+        let (ptr, tag) = Self::new(self.ptr).untag_ptr();
+        match tag {
+            Tag::Int | Tag::CompactFloat | Tag::SubrFn => Self::new(self.ptr),
+            Tag::Symbol => {
+                let mut sym = unsafe { Symbol::from_offset_ptr(ptr) };
+                sym.trace(state);
+                Self::from_ptr(sym.as_ptr(), tag)
+            }
+            _ => match state.forward(ptr) {
+                Some(new) => Self::from_ptr(new, tag),
+                None => Self::new(self.ptr),
+            },
+        }
+    }
+
+    /// Whether this object has been found reachable by the collector so far.
+    /// Objects that are not part of the heap being collected are always live.
+    pub(in crate::core) fn is_live(&self, state: &GcState) -> bool {
+        let (ptr, tag) = Self::new(self.ptr).untag_ptr();
+        match tag {
+            Tag::Int | Tag::CompactFloat | Tag::SubrFn => true,
+            Tag::Symbol => {
+                let sym = unsafe { Symbol::from_offset_ptr(ptr) };
+                let cell: *const SymbolCell = sym.get();
+                state.is_live(cell.cast())
+            }
+            // Buffers are never moved or freed, but a killed buffer can no
+            // longer be used, so it does not keep weak entries alive.
+            Tag::Buffer => !unsafe { &*ptr.cast::<LispBuffer>() }.is_killed(),
+            _ => state.is_live(ptr),
+        }
+    }
+}
+
 impl<T: TaggedPtr> Gc<T> {
     pub(crate) fn untag(self) -> T {
         T::untag(self)
@@ -205,11 +243,16 @@ where
 }
 
 impl IntoObject for f64 {
-    type Out<'ob> = &'ob LispFloat;
+    type Out<'ob> = LispFloat<'ob>;
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
-        let ptr = self.alloc_obj(block);
-        unsafe { Self::Out::tag_ptr(ptr) }
+        match LispFloat::encode(self) {
+            Some(compact) => Gc::from_compact_float(compact),
+            None => {
+                let ptr = self.alloc_obj(block);
+                unsafe { Self::Out::tag_ptr(ptr) }
+            }
+        }
     }
 }
 
@@ -294,6 +337,17 @@ impl IntoObject for Vec<u8> {
     }
 }
 
+impl IntoObject for RawMultibyte {
+    type Out<'ob> = <String as IntoObject>::Out<'ob>;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        unsafe {
+            let ptr = LispString::from_raw_multibyte(self).alloc_obj(block);
+            <&LispString>::tag_ptr(ptr)
+        }
+    }
+}
+
 impl<'a> IntoObject for Vec<GcObj<'a>> {
     type Out<'ob> = &'ob LispVec;
 
@@ -316,6 +370,51 @@ impl<'a> IntoObject for RecordBuilder<'a> {
     }
 }
 
+impl IntoObject for LispFinalizer {
+    type Out<'ob> = &'ob LispFinalizer;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
+impl IntoObject for LispBoolVec {
+    type Out<'ob> = &'ob LispBoolVec;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
+impl IntoObject for LispBigInt {
+    type Out<'ob> = &'ob LispBigInt;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
+impl IntoObject for CharTable {
+    type Out<'ob> = &'ob CharTable;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
+impl IntoObject for LispObarray {
+    type Out<'ob> = &'ob LispObarray;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let ptr = self.alloc_obj(block);
+        unsafe { Self::Out::tag_ptr(ptr) }
+    }
+}
+
 impl<'a> IntoObject for HashTable<'a> {
     type Out<'ob> = &'ob LispHashTable;
 
@@ -343,6 +442,12 @@ mod private {
         SubrFn,
         ByteFn,
         Buffer,
+        Finalizer,
+        BoolVec,
+        CharTable,
+        BigInt,
+        Obarray,
+        CompactFloat,
     }
 
     pub(crate) trait TaggedPtr: Copy + for<'a> WithLifetime<'a> {
@@ -387,12 +492,17 @@ impl<'a> TaggedPtr for Object<'a> {
                 Tag::SubrFn => Object::SubrFn(&*ptr.cast()),
                 Tag::ByteFn => Object::ByteFn(<&ByteFn>::from_obj_ptr(ptr)),
                 Tag::Int => Object::Int(i64::from_obj_ptr(ptr)),
-                Tag::Float => Object::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::Float | Tag::CompactFloat => Object::Float(TaggedPtr::untag(Gc::new(val.ptr))),
                 Tag::String => Object::String(<&LispString>::from_obj_ptr(ptr)),
                 Tag::Vec => Object::Vec(<&LispVec>::from_obj_ptr(ptr)),
                 Tag::Record => Object::Record(<&Record>::from_obj_ptr(ptr)),
                 Tag::HashTable => Object::HashTable(<&LispHashTable>::from_obj_ptr(ptr)),
                 Tag::Buffer => Object::Buffer(<&LispBuffer>::from_obj_ptr(ptr)),
+                Tag::Finalizer => Object::Finalizer(<&LispFinalizer>::from_obj_ptr(ptr)),
+                Tag::BoolVec => Object::BoolVec(<&LispBoolVec>::from_obj_ptr(ptr)),
+                Tag::CharTable => Object::CharTable(<&CharTable>::from_obj_ptr(ptr)),
+                Tag::BigInt => Object::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
+                Tag::Obarray => Object::Obarray(<&LispObarray>::from_obj_ptr(ptr)),
             }
         }
     }
@@ -410,6 +520,11 @@ impl<'a> TaggedPtr for Object<'a> {
             Object::ByteFn(x) => TaggedPtr::tag(x).into(),
             Object::SubrFn(x) => TaggedPtr::tag(x).into(),
             Object::Buffer(x) => TaggedPtr::tag(x).into(),
+            Object::Finalizer(x) => TaggedPtr::tag(x).into(),
+            Object::BoolVec(x) => TaggedPtr::tag(x).into(),
+            Object::CharTable(x) => TaggedPtr::tag(x).into(),
+            Object::BigInt(x) => TaggedPtr::tag(x).into(),
+            Object::Obarray(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -484,7 +599,8 @@ impl<'a> TaggedPtr for Number<'a> {
         unsafe {
             match tag {
                 Tag::Int => Number::Int(i64::from_obj_ptr(ptr)),
-                Tag::Float => Number::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::Float | Tag::CompactFloat => Number::Float(TaggedPtr::untag(Gc::new(val.ptr))),
+                Tag::BigInt => Number::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
                 _ => unreachable!(),
             }
         }
@@ -494,6 +610,7 @@ impl<'a> TaggedPtr for Number<'a> {
         match self {
             Number::Int(x) => TaggedPtr::tag(x).into(),
             Number::Float(x) => TaggedPtr::tag(x).into(),
+            Number::BigInt(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -511,15 +628,35 @@ impl TaggedPtr for i64 {
     }
 }
 
-impl TaggedPtr for &LispFloat {
-    type Ptr = LispFloat;
+impl TaggedPtr for LispFloat<'_> {
+    type Ptr = FloatCell;
     const TAG: Tag = Tag::Float;
-    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
-        &*ptr.cast::<Self::Ptr>()
+
+    fn untag(val: Gc<Self>) -> Self {
+        let (ptr, tag) = val.untag_ptr();
+        match tag {
+            Tag::CompactFloat => LispFloat::from_compact(val.ptr.addr() as u64 >> 8),
+            _ => unsafe { LispFloat::from_cell(&*ptr.cast::<Self::Ptr>()) },
+        }
+    }
+
+    fn tag(self) -> Gc<Self> {
+        match self.compact() {
+            Some(compact) => Gc::from_compact_float(compact),
+            None => unsafe { Self::tag_ptr(self.get_ptr()) },
+        }
     }
 
     fn get_ptr(self) -> *const Self::Ptr {
-        self as *const Self::Ptr
+        self.cell()
+    }
+}
+
+impl<T> Gc<T> {
+    fn from_compact_float(compact: u64) -> Self {
+        debug_assert!(compact < 1 << LispFloat::COMPACT_BITS);
+        let addr = ((compact as usize) << 8) | Tag::CompactFloat as usize;
+        Self::new(sptr::invalid(addr))
     }
 }
 
@@ -636,6 +773,66 @@ impl TaggedPtr for &LispBuffer {
     }
 }
 
+impl TaggedPtr for &LispBoolVec {
+    type Ptr = LispBoolVec;
+    const TAG: Tag = Tag::BoolVec;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &CharTable {
+    type Ptr = CharTable;
+    const TAG: Tag = Tag::CharTable;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispBigInt {
+    type Ptr = LispBigInt;
+    const TAG: Tag = Tag::BigInt;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispObarray {
+    type Ptr = LispObarray;
+    const TAG: Tag = Tag::Obarray;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispFinalizer {
+    type Ptr = LispFinalizer;
+    const TAG: Tag = Tag::Finalizer;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 macro_rules! cast_gc {
     ($supertype:ty => $($subtype:ty),+ $(,)?) => {
         $(
@@ -647,7 +844,7 @@ macro_rules! cast_gc {
 
             impl<'ob> From<$subtype> for Gc<$supertype> {
                 fn from(x: $subtype) -> Self {
-                    unsafe { <$subtype>::tag_ptr(x.get_ptr()).into() }
+                    TaggedPtr::tag(x).into()
                 }
             }
         )+
@@ -663,9 +860,10 @@ macro_rules! cast_gc {
 #[repr(u8)]
 pub(crate) enum Number<'ob> {
     Int(i64) = Tag::Int as u8,
-    Float(&'ob LispFloat) = Tag::Float as u8,
+    Float(LispFloat<'ob>) = Tag::Float as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
 }
-cast_gc!(Number<'ob> => i64, &LispFloat);
+cast_gc!(Number<'ob> => i64, LispFloat<'ob>, &'ob LispBigInt);
 
 impl<'old, 'new> WithLifetime<'new> for Number<'old> {
     type Out = Number<'new>;
@@ -758,7 +956,7 @@ impl<'ob> Function<'ob> {
 /// tagged pointer type to take advantage of ergonomics of enums in Rust.
 pub(crate) enum Object<'ob> {
     Int(i64) = Tag::Int as u8,
-    Float(&'ob LispFloat) = Tag::Float as u8,
+    Float(LispFloat<'ob>) = Tag::Float as u8,
     Symbol(Symbol<'ob>) = Tag::Symbol as u8,
     Cons(&'ob Cons) = Tag::Cons as u8,
     Vec(&'ob LispVec) = Tag::Vec as u8,
@@ -768,8 +966,13 @@ pub(crate) enum Object<'ob> {
     ByteFn(&'ob ByteFn) = Tag::ByteFn as u8,
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
     Buffer(&'static LispBuffer) = Tag::Buffer as u8,
+    Finalizer(&'ob LispFinalizer) = Tag::Finalizer as u8,
+    BoolVec(&'ob LispBoolVec) = Tag::BoolVec as u8,
+    CharTable(&'ob CharTable) = Tag::CharTable as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
+    Obarray(&'ob LispObarray) = Tag::Obarray as u8,
 }
-cast_gc!(Object<'ob> => Number<'ob>, List<'ob>, Function<'ob>, i64, Symbol<'_>, &LispFloat, &'ob Cons, &'ob LispVec, &'ob Record, &'ob LispHashTable, &'ob LispString, &'ob ByteFn, &'ob SubrFn, &'ob LispBuffer);
+cast_gc!(Object<'ob> => Number<'ob>, List<'ob>, Function<'ob>, i64, Symbol<'_>, LispFloat<'ob>, &'ob Cons, &'ob LispVec, &'ob Record, &'ob LispHashTable, &'ob LispString, &'ob ByteFn, &'ob SubrFn, &'ob LispBuffer, &'ob LispFinalizer, &'ob LispBoolVec, &'ob CharTable, &'ob LispBigInt, &'ob LispObarray);
 
 impl Object<'_> {
     pub(crate) const NIL: Object<'static> = Object::Symbol(sym::NIL);
@@ -777,7 +980,7 @@ impl Object<'_> {
     /// Return the type of an object
     pub(crate) fn get_type(self) -> Type {
         match self {
-            Object::Int(_) => Type::Int,
+            Object::Int(_) | Object::BigInt(_) => Type::Int,
             Object::Float(_) => Type::Float,
             Object::Symbol(_) => Type::Symbol,
             Object::Cons(_) => Type::Cons,
@@ -787,6 +990,10 @@ impl Object<'_> {
             Object::String(_) => Type::String,
             Object::ByteFn(_) | Object::SubrFn(_) => Type::Func,
             Object::Buffer(_) => Type::Buffer,
+            Object::Finalizer(_) => Type::Finalizer,
+            Object::BoolVec(_) => Type::BoolVec,
+            Object::CharTable(_) => Type::CharTable,
+            Object::Obarray(_) => Type::Obarray,
         }
     }
 }
@@ -846,7 +1053,9 @@ impl<'ob> TryFrom<Gc<Object<'ob>>> for Gc<Number<'ob>> {
 
     fn try_from(value: Gc<Object<'ob>>) -> Result<Self, Self::Error> {
         match value.get_tag() {
-            Tag::Int | Tag::Float => unsafe { Ok(cast_gc(value)) },
+            Tag::Int | Tag::Float | Tag::CompactFloat | Tag::BigInt => unsafe {
+                Ok(cast_gc(value))
+            },
             _ => Err(TypeError::new(Type::Number, value)),
         }
     }
@@ -985,6 +1194,17 @@ impl<'ob> TryFrom<GcObj<'ob>> for Gc<&'ob LispVec> {
     }
 }
 
+impl<'ob> TryFrom<GcObj<'ob>> for Gc<&'ob CharTable> {
+    type Error = TypeError;
+
+    fn try_from(value: GcObj<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::CharTable => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::CharTable, value)),
+        }
+    }
+}
+
 impl<'ob> std::ops::Deref for Gc<&'ob Cons> {
     type Target = Cons;
 
@@ -1014,11 +1234,16 @@ where
             Object::Symbol(x) => x.clone_in(bk).into(),
             Object::ByteFn(x) => x.clone_in(bk).into(),
             Object::SubrFn(x) => x.into(),
-            Object::Float(x) => x.into_obj(bk).into(),
+            Object::Float(x) => x.get().into_obj(bk).into(),
             Object::Vec(x) => x.clone_in(bk).into(),
             Object::Record(x) => x.clone_in(bk).into(),
             Object::HashTable(x) => x.clone_in(bk).into(),
             Object::Buffer(x) => x.clone_in(bk).into(),
+            Object::Finalizer(x) => x.clone_in(bk).into(),
+            Object::BoolVec(x) => x.clone_in(bk).into(),
+            Object::CharTable(x) => x.clone_in(bk).into(),
+            Object::BigInt(x) => x.clone_in(bk).into(),
+            Object::Obarray(x) => x.clone_in(bk).into(),
         };
         let Ok(x) = Gc::<U>::try_from(obj) else { unreachable!() };
         x
@@ -1047,7 +1272,7 @@ impl<'ob> PartialEq<f64> for Gc<Object<'ob>> {
     fn eq(&self, other: &f64) -> bool {
         use float_cmp::ApproxEq;
         match self.untag() {
-            Object::Float(x) => x.approx_eq(*other, (f64::EPSILON, 2)),
+            Object::Float(x) => x.get().approx_eq(*other, (f64::EPSILON, 2)),
             _ => false,
         }
     }
@@ -1124,42 +1349,11 @@ impl fmt::Display for Object<'_> {
             Object::SubrFn(x) => D::fmt(x, f),
             Object::Float(x) => D::fmt(x, f),
             Object::Buffer(x) => D::fmt(x, f),
-        }
-    }
-}
-
-impl<'ob> Gc<Object<'ob>> {
-    pub(crate) fn is_markable(self) -> bool {
-        !matches!(self.untag(), Object::Int(_) | Object::SubrFn(_))
-    }
-
-    pub(crate) fn is_marked(self) -> bool {
-        match self.untag() {
-            Object::Int(_) | Object::SubrFn(_) => true,
-            Object::Float(x) => x.is_marked(),
-            Object::Cons(x) => x.is_marked(),
-            Object::Vec(x) => x.is_marked(),
-            Object::Record(x) => x.is_marked(),
-            Object::HashTable(x) => x.is_marked(),
-            Object::String(x) => x.is_marked(),
-            Object::ByteFn(x) => x.is_marked(),
-            Object::Symbol(x) => x.is_marked(),
-            Object::Buffer(x) => x.is_marked(),
-        }
-    }
-
-    pub(crate) fn trace_mark(self, stack: &mut Vec<RawObj>) {
-        match self.untag() {
-            Object::Int(_) | Object::SubrFn(_) => {}
-            Object::Float(x) => x.mark(),
-            Object::String(x) => x.mark(),
-            Object::Vec(vec) => vec.trace(stack),
-            Object::Record(x) => x.trace(stack),
-            Object::HashTable(x) => x.trace(stack),
-            Object::Cons(x) => x.trace(stack),
-            Object::Symbol(x) => x.trace(stack),
-            Object::ByteFn(x) => x.trace(stack),
-            Object::Buffer(x) => x.trace(stack),
+            Object::Finalizer(x) => D::fmt(x, f),
+            Object::BoolVec(x) => D::fmt(x, f),
+            Object::CharTable(x) => D::fmt(x, f),
+            Object::BigInt(x) => D::fmt(x, f),
+            Object::Obarray(x) => D::fmt(x, f),
         }
     }
 }
@@ -1,5 +1,5 @@
 use super::{display_slice, CloneIn, Gc, GcObj, IntoObject, WithLifetime};
-use crate::core::gc::{Block, GcManaged, GcMark, Trace};
+use crate::core::gc::{Block, GcManaged, GcState, Trace};
 use anyhow::{anyhow, Result};
 use std::{cell::Cell, fmt::Debug, fmt::Display, ops::Deref};
 
@@ -9,7 +9,6 @@ use std::{cell::Cell, fmt::Debug, fmt::Display, ops::Deref};
 /// into this slice.
 #[derive(Eq)]
 pub(crate) struct LispVec {
-    gc: GcMark,
     is_const: bool,
     inner: Box<[ObjCell]>,
 }
@@ -39,6 +38,12 @@ impl ObjCell {
     }
 }
 
+impl Trace for ObjCell {
+    fn trace(&mut self, state: &mut GcState) {
+        self.0.get_mut().trace(state);
+    }
+}
+
 impl Display for ObjCell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(&self.0.get(), f)
@@ -80,7 +85,7 @@ impl LispVec {
     // to use in context of the allocator.
     pub(in crate::core) unsafe fn new(vec: Vec<GcObj>) -> Self {
         let cell = std::mem::transmute::<Vec<GcObj>, Vec<ObjCell>>(vec);
-        Self { gc: GcMark::default(), is_const: false, inner: cell.into_boxed_slice() }
+        Self { is_const: false, inner: cell.into_boxed_slice() }
     }
 
     pub(in crate::core) fn make_const(&mut self) {
@@ -121,17 +126,11 @@ impl<'new> CloneIn<'new, &'new Self> for LispVec {
     }
 }
 
-impl GcManaged for LispVec {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
-    }
-}
+impl GcManaged for LispVec {}
 
 impl Trace for LispVec {
-    fn trace(&self, stack: &mut Vec<super::RawObj>) {
-        self.mark();
-        let unmarked = self.iter().map(ObjCell::get).filter(|x| x.is_markable()).map(Gc::into_raw);
-        stack.extend(unmarked);
+    fn trace(&mut self, state: &mut GcState) {
+        self.inner.trace(state);
     }
 }
 
@@ -169,15 +168,17 @@ impl<'new> CloneIn<'new, &'new Self> for Record {
     }
 }
 
-impl GcManaged for Record {
-    fn get_mark(&self) -> &GcMark {
-        &self.gc
-    }
-}
+impl GcManaged for Record {}
 
 impl Display for Record {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#s")?;
-        f.write_str(&display_slice(self))
+        write!(f, "#s(")?;
+        for (i, slot) in self.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", slot.get())?;
+        }
+        write!(f, ")")
     }
 }
@@ -1,15 +1,22 @@
+use crate::arith::NumberValue;
 use crate::core::{
     cons::Cons,
-    env::{sym, Env, Symbol, INTERNED_SYMBOLS},
+    env::{sym, Env, Symbol, TrappedWrite, INTERNED_SYMBOLS},
     error::{Type, TypeError},
     gc::{Context, IntoRoot, Rt},
-    object::{nil, Gc, GcObj, List, Number, Object, SubrFn},
+    object::{
+        nil, Function, Gc, GcObj, IntoObject, LispBoolVec, List, Number, Object, SubrFn, TagType,
+        MAX_CHAR,
+    },
 };
 use crate::hashmap::HashSet;
-use anyhow::{anyhow, Result};
+use crate::{root, rooted_iter};
+use anyhow::{anyhow, ensure, Result};
 use fn_macros::defun;
 use lazy_static::lazy_static;
+use num_bigint::BigInt;
 use std::sync::Mutex;
+use streaming_iterator::StreamingIterator;
 
 lazy_static! {
     pub(crate) static ref FEATURES: Mutex<HashSet<Symbol<'static>>> =
@@ -38,9 +45,61 @@ pub(crate) fn defalias<'ob>(
 }
 
 #[defun]
-pub(crate) fn set<'ob>(place: Symbol, newlet: GcObj<'ob>, env: &mut Rt<Env>) -> Result<GcObj<'ob>> {
-    env.set_var(place, newlet)?;
-    Ok(newlet)
+pub(crate) fn set<'ob>(
+    place: &Rt<Gc<Symbol>>,
+    newlet: &Rt<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    notify_variable_watchers(place, newlet, sym::SET, env, cx)?;
+    env.set_var(place.bind(cx).untag(), newlet.bind(cx), cx)?;
+    Ok(newlet.bind(cx))
+}
+
+/// Call the watchers of the variable SYMBOL before it is changed by
+/// OPERATION. If SYMBOL is an alias, the watchers are passed the variable it
+/// points to. NEWVAL is the value the variable is about to have. Buffer-local
+/// variables are not implemented yet, so the watchers are always passed nil
+/// for the buffer.
+pub(crate) fn notify_variable_watchers(
+    symbol: &Rt<Gc<Symbol>>,
+    newval: &Rt<GcObj>,
+    operation: Symbol<'static>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let base = symbol.bind(cx).untag().indirect_variable();
+    if base.trapped_write() != TrappedWrite::Trapped {
+        return Ok(());
+    }
+    let watchers = get(base, sym::WATCHERS, env, cx);
+    rooted_iter!(watchers, watchers, cx);
+    while let Some(watcher) = watchers.next() {
+        let watcher: &Rt<Gc<Function>> = watcher.try_into()?;
+        let base = symbol.bind(cx).untag().indirect_variable();
+        let args = vec![base.into(), newval.bind(cx), operation.into(), nil()];
+        root!(args, move(args), cx);
+        watcher.call(args, env, cx, None)?;
+    }
+    Ok(())
+}
+
+/// Remove the innermost COUNT dynamic bindings, notifying the watchers of
+/// each variable that is unbound.
+pub(crate) fn unbind(count: u16, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let mut result = Ok(());
+    for _ in 0..count {
+        let Some((symbol, value)) = env.last_binding(cx) else { panic!("Binding stack was empty") };
+        if symbol.trapped_write() == TrappedWrite::Trapped {
+            root!(symbol, move(symbol.tag()), cx);
+            root!(value, move(value.unwrap_or_default()), cx);
+            let notified = notify_variable_watchers(symbol, value, sym::UNLET, env, cx);
+            // the binding still has to be removed if a watcher fails
+            result = result.and(notified);
+        }
+        env.unbind(1, cx);
+    }
+    result
 }
 
 #[defun]
@@ -49,9 +108,28 @@ pub(crate) fn put<'ob>(
     propname: Symbol,
     value: GcObj<'ob>,
     env: &mut Rt<Env>,
-) -> GcObj<'ob> {
-    env.set_prop(symbol, propname, value);
-    value
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let mut plist = Vec::new();
+    let mut found = false;
+    let mut iter = env.plist(symbol, cx).as_list()?;
+    while let Some(prop) = iter.next() {
+        let prop = prop?;
+        let old_value = iter.next().unwrap_or(Ok(nil()))?;
+        plist.push(prop);
+        if prop == propname && !found {
+            plist.push(value);
+            found = true;
+        } else {
+            plist.push(old_value);
+        }
+    }
+    if !found {
+        plist.push(propname.into());
+        plist.push(value);
+    }
+    env.set_plist(symbol, crate::alloc::list(&plist, cx));
+    Ok(value)
 }
 
 #[defun]
@@ -61,13 +139,62 @@ pub(crate) fn get<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> GcObj<'ob> {
-    match env.props.get(symbol) {
-        Some(plist) => match plist.iter().find(|x| x.0 == propname) {
-            Some(element) => cx.bind(element.1.bind(cx)),
-            None => nil(),
-        },
-        None => nil(),
+    let Ok(mut iter) = env.plist(symbol, cx).as_list() else { return nil() };
+    while let (Some(Ok(prop)), Some(Ok(value))) = (iter.next(), iter.next()) {
+        if prop == propname {
+            return value;
+        }
+    }
+    nil()
+}
+
+#[defun]
+fn symbol_plist<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> GcObj<'ob> {
+    env.plist(symbol, cx)
+}
+
+/// Set the property list of SYMBOL to NEWPLIST. Other threads see a
+/// read-only copy of the list.
+#[defun]
+fn setplist<'ob>(symbol: Symbol, newplist: GcObj<'ob>, env: &mut Rt<Env>) -> GcObj<'ob> {
+    env.set_plist(symbol, newplist);
+    newplist
+}
+
+/// Return the value of property PROP of function F. If F is an alias for
+/// another function, the properties of the aliased function are also
+/// searched.
+#[defun]
+fn function_get<'ob>(
+    f: GcObj<'ob>,
+    prop: Symbol,
+    _autoload: Option<GcObj>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> GcObj<'ob> {
+    let mut func = f;
+    while let Object::Symbol(symbol) = func.untag() {
+        let value = get(symbol, prop, env, cx);
+        if !value.nil() {
+            return value;
+        }
+        match symbol.func(cx).map(Gc::untag) {
+            Some(Function::Symbol(alias)) if alias != symbol => func = alias.into(),
+            _ => break,
+        }
     }
+    nil()
+}
+
+#[defun]
+fn function_put<'ob>(
+    func: Symbol,
+    prop: Symbol,
+    value: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    put(func, prop, value, env, cx)
 }
 
 #[defun]
@@ -84,7 +211,7 @@ pub(crate) fn symbol_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Option<GcObj<'ob>> {
-    env.vars.get(symbol).map(|x| x.bind(cx))
+    env.var(symbol).map(|x| x.bind(cx))
 }
 
 #[defun]
@@ -110,18 +237,97 @@ pub(crate) fn fmakunbound(symbol: Symbol) -> Symbol {
 
 #[defun]
 pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+    env.var(symbol).is_some()
 }
 
 #[defun]
-pub(crate) fn makunbound<'ob>(symbol: Symbol<'ob>, env: &mut Rt<Env>) -> Symbol<'ob> {
-    env.vars.remove(symbol);
-    symbol
+pub(crate) fn makunbound<'ob>(
+    symbol: &Rt<Gc<Symbol>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Symbol<'ob>> {
+    let var = symbol.bind(cx).untag().indirect_variable();
+    if var.trapped_write() == TrappedWrite::NoWrite {
+        return Err(env.setting_constant(var, cx).into());
+    }
+    root!(unbound, nil(), cx);
+    notify_variable_watchers(symbol, unbound, sym::MAKUNBOUND, env, cx)?;
+    env.vars.remove(symbol.bind(cx).untag().indirect_variable());
+    Ok(symbol.bind(cx).untag())
 }
 
 #[defun]
 pub(crate) fn default_boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+    env.var(symbol).is_some()
+}
+
+/// Return the variable at the end of OBJECT's variable alias chain. If
+/// OBJECT is not a symbol, it is returned unchanged.
+#[defun]
+fn indirect_variable(object: GcObj) -> GcObj {
+    match object.untag() {
+        Object::Symbol(sym) => sym.indirect_variable().into(),
+        _ => object,
+    }
+}
+
+/// Cause WATCH-FUNCTION to be called when SYMBOL is about to be set. The
+/// function is called with the symbol, the new value, the operation (one of
+/// `set`, `let`, `unlet`, `makunbound` or `defvaralias`) and the buffer the
+/// change applies to.
+#[defun]
+fn add_variable_watcher<'ob>(
+    symbol: Symbol,
+    watch_function: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let symbol = symbol.indirect_variable();
+    if symbol.trapped_write() == TrappedWrite::Untrapped {
+        symbol.set_trapped_write(TrappedWrite::Trapped);
+    }
+    let watchers = get(symbol, sym::WATCHERS, env, cx);
+    for watcher in watchers.as_list()? {
+        if crate::fns::equal(watcher?, watch_function, env, cx)? {
+            return Ok(nil());
+        }
+    }
+    put(symbol, sym::WATCHERS, cons!(watch_function, watchers; cx), env, cx)?;
+    Ok(nil())
+}
+
+/// Undo the effect of `add-variable-watcher`.
+#[defun]
+fn remove_variable_watcher<'ob>(
+    symbol: Symbol,
+    watch_function: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let symbol = symbol.indirect_variable();
+    let mut remaining = Vec::new();
+    for watcher in get(symbol, sym::WATCHERS, env, cx).as_list()? {
+        let watcher = watcher?;
+        if !crate::fns::equal(watcher, watch_function, env, cx)? {
+            remaining.push(watcher);
+        }
+    }
+    if remaining.is_empty() && symbol.trapped_write() == TrappedWrite::Trapped {
+        symbol.set_trapped_write(TrappedWrite::Untrapped);
+    }
+    put(symbol, sym::WATCHERS, crate::alloc::list(&remaining, cx), env, cx)?;
+    Ok(nil())
+}
+
+/// Return the list of functions watching SYMBOL.
+#[defun]
+fn get_variable_watchers<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> GcObj<'ob> {
+    let symbol = symbol.indirect_variable();
+    if symbol.trapped_write() == TrappedWrite::Trapped {
+        get(symbol, sym::WATCHERS, env, cx)
+    } else {
+        nil()
+    }
 }
 
 #[defun]
@@ -161,7 +367,7 @@ pub(crate) fn stringp(object: GcObj) -> bool {
 
 #[defun]
 pub(crate) fn numberp(object: GcObj) -> bool {
-    matches!(object.untag(), Object::Int(_) | Object::Float(_))
+    matches!(object.untag(), Object::Int(_) | Object::Float(_) | Object::BigInt(_))
 }
 
 #[defun]
@@ -195,7 +401,7 @@ pub(crate) fn keywordp(object: GcObj) -> bool {
 
 #[defun]
 pub(crate) fn integerp(object: GcObj) -> bool {
-    matches!(object.untag(), Object::Int(_))
+    matches!(object.untag(), Object::Int(_) | Object::BigInt(_))
 }
 
 #[defun]
@@ -220,28 +426,33 @@ fn bufferp(_object: GcObj) -> bool {
 }
 
 #[defun]
-fn string_to_number<'ob>(string: &str, base: Option<i64>, cx: &'ob Context) -> Gc<Number<'ob>> {
+fn string_to_number(string: &str, base: Option<i64>) -> NumberValue {
     // TODO: Handle trailing characters, which should be ignored
     let base = base.unwrap_or(10);
     let string = string.trim();
     match i64::from_str_radix(string, base as u32) {
-        Ok(x) => x.into(),
-        Err(_) => match string.parse::<f64>() {
-            Ok(x) => cx.add_as(x),
-            Err(_) => 0.into(),
+        Ok(x) => NumberValue::Int(x),
+        Err(_) => match BigInt::parse_bytes(string.as_bytes(), base as u32) {
+            Some(x) => NumberValue::Big(x),
+            None => match string.parse::<f64>() {
+                Ok(x) => NumberValue::Float(x),
+                Err(_) => NumberValue::Int(0),
+            },
         },
     }
 }
 
 #[defun]
 pub(crate) fn defvar<'ob>(
-    symbol: Symbol,
-    initvalue: Option<GcObj<'ob>>,
-    _docstring: Option<&str>,
+    symbol: &Rt<Gc<Symbol>>,
+    initvalue: Option<&Rt<GcObj>>,
+    _docstring: Option<&Rt<GcObj>>,
     env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>> {
-    let value = initvalue.unwrap_or_default();
-    set(symbol, value, env)
+    let value = initvalue.map_or_else(nil, |x| x.bind(cx));
+    root!(value, cx);
+    set(symbol, value, env, cx)
 }
 
 #[defun]
@@ -263,15 +474,25 @@ fn subr_arity<'ob>(subr: &SubrFn, cx: &'ob Context) -> GcObj<'ob> {
     cons!(min, max; cx)
 }
 
+/// Shift VALUE left by COUNT bits, or right if COUNT is negative. Shifting
+/// right rounds towards negative infinity.
 #[defun]
-fn ash(value: i64, count: i64) -> i64 {
-    let shift = if count >= 0 { std::ops::Shl::shl } else { std::ops::Shr::shr };
-    let result = shift(value.abs(), count.abs());
-    if value >= 0 {
-        result
-    } else {
-        -result
+fn ash(value: Gc<Number>, count: i64) -> Result<NumberValue> {
+    let value = value.int_val()?;
+    if let NumberValue::Int(x) = value {
+        if count <= 0 {
+            return Ok(NumberValue::Int(x >> count.unsigned_abs().min(63)));
+        }
+        if count < 64 {
+            let shifted = x << count;
+            if shifted >> count == x {
+                return Ok(NumberValue::Int(shifted));
+            }
+        }
     }
+    let big = value.into_big();
+    let shift = usize::try_from(count.unsigned_abs())?;
+    Ok(NumberValue::Big(if count >= 0 { big << shift } else { big >> shift }))
 }
 
 #[defun]
@@ -297,6 +518,15 @@ pub(crate) fn aset<'ob>(array: GcObj<'ob>, idx: usize, newlet: GcObj<'ob>) -> Re
                 Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
             }
         }
+        Object::BoolVec(vec) => {
+            vec.set(idx, !newlet.nil())?;
+            Ok(newlet)
+        }
+        Object::CharTable(table) => {
+            ensure!(idx <= MAX_CHAR as usize, "Invalid character for a char-table: {idx}");
+            table.set_range(idx as u32, idx as u32, newlet);
+            Ok(newlet)
+        }
         x => Err(TypeError::new(Type::Sequence, x).into()),
     }
 }
@@ -319,7 +549,7 @@ pub(crate) fn aref(array: GcObj, idx: usize) -> Result<GcObj> {
             }
         },
         Object::String(string) => match string.get_char_at(idx) {
-            Some(x) => Ok((x as i64).into()),
+            Some(x) => Ok(i64::from(x).into()),
             None => {
                 let len = string.len();
                 Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
@@ -329,24 +559,173 @@ pub(crate) fn aref(array: GcObj, idx: usize) -> Result<GcObj> {
             Some(x) => Ok(x),
             None => Err(anyhow!("index {idx} is out of bounds")),
         },
+        Object::BoolVec(vec) => match vec.get(idx) {
+            Some(x) => Ok(x.into()),
+            None => {
+                let len = vec.len();
+                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
+            }
+        },
+        Object::CharTable(table) => {
+            ensure!(idx <= MAX_CHAR as usize, "Invalid character for a char-table: {idx}");
+            Ok(table.get(idx as u32))
+        }
         x => Err(TypeError::new(Type::Sequence, x).into()),
     }
 }
 
+#[defun]
+fn bool_vector_p(object: GcObj) -> bool {
+    matches!(object.untag(), Object::BoolVec(_))
+}
+
+/// Return the number of elements of A that are t.
+#[defun]
+fn bool_vector_count_population(a: &LispBoolVec) -> usize {
+    a.count_ones()
+}
+
+/// Return the number of consecutive elements of A equal to B, starting at
+/// index I.
+#[defun]
+fn bool_vector_count_consecutive(a: &LispBoolVec, b: GcObj, i: usize) -> Result<usize> {
+    let len = a.len();
+    ensure!(i <= len, "index {i} is out of bounds. Length was {len}");
+    let b = !b.nil();
+    Ok(a.iter().skip(i).take_while(|x| *x == b).count())
+}
+
+/// Combine the bool-vectors A and B byte by byte with `op`. If C is given, the
+/// result is stored in C and C is returned if it changed, otherwise nil. When
+/// C is not given, the result is a new bool-vector.
+fn bool_vector_binop<'ob>(
+    a: &LispBoolVec,
+    b: &LispBoolVec,
+    c: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+    op: impl Fn(u8, u8) -> u8,
+) -> Result<GcObj<'ob>> {
+    let len = a.len();
+    ensure!(b.len() == len, "Bool-vectors have different lengths: {len} and {}", b.len());
+    let bytes = a.bytes().zip(b.bytes()).map(|(a, b)| op(a, b));
+    match c {
+        Some(c) => {
+            ensure!(c.len() == len, "Bool-vectors have different lengths: {len} and {}", c.len());
+            let old: Vec<u8> = c.bytes().collect();
+            c.set_bytes(bytes);
+            let changed = c.bytes().ne(old);
+            Ok(if changed { c.into_obj(cx).into() } else { nil() })
+        }
+        None => {
+            let vec = LispBoolVec::new(len, false);
+            vec.set_bytes(bytes);
+            Ok(vec.into_obj(cx).into())
+        }
+    }
+}
+
+/// Return the union of A and B, stored in C if it is given.
+#[defun]
+fn bool_vector_union<'ob>(
+    a: &LispBoolVec,
+    b: &LispBoolVec,
+    c: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    bool_vector_binop(a, b, c, cx, |a, b| a | b)
+}
+
+/// Return the intersection of A and B, stored in C if it is given.
+#[defun]
+fn bool_vector_intersection<'ob>(
+    a: &LispBoolVec,
+    b: &LispBoolVec,
+    c: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    bool_vector_binop(a, b, c, cx, |a, b| a & b)
+}
+
+/// Return the exclusive or of A and B, stored in C if it is given.
+#[defun]
+fn bool_vector_exclusive_or<'ob>(
+    a: &LispBoolVec,
+    b: &LispBoolVec,
+    c: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    bool_vector_binop(a, b, c, cx, |a, b| a ^ b)
+}
+
+/// Return the elements of A that are not in B, stored in C if it is given.
+#[defun]
+fn bool_vector_set_difference<'ob>(
+    a: &LispBoolVec,
+    b: &LispBoolVec,
+    c: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    bool_vector_binop(a, b, c, cx, |a, b| a & !b)
+}
+
+/// Return the complement of A, stored in B if it is given.
+#[defun]
+fn bool_vector_not<'ob>(
+    a: &LispBoolVec,
+    b: Option<&'ob LispBoolVec>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let bytes = a.bytes().map(|x| !x);
+    match b {
+        Some(b) => {
+            let len = a.len();
+            ensure!(b.len() == len, "Bool-vectors have different lengths: {len} and {}", b.len());
+            b.set_bytes(bytes);
+            Ok(b.into_obj(cx).into())
+        }
+        None => {
+            let vec = LispBoolVec::new(a.len(), false);
+            vec.set_bytes(bytes);
+            Ok(vec.into_obj(cx).into())
+        }
+    }
+}
+
+/// Return t if every element that is t in A is also t in B.
+#[defun]
+fn bool_vector_subsetp(a: &LispBoolVec, b: &LispBoolVec) -> Result<bool> {
+    let len = a.len();
+    ensure!(b.len() == len, "Bool-vectors have different lengths: {len} and {}", b.len());
+    Ok(a.bytes().zip(b.bytes()).all(|(a, b)| a & !b == 0))
+}
+
 #[defun]
 fn type_of(object: GcObj) -> GcObj {
     match object.untag() {
-        Object::Int(_) => sym::INTEGER.into(),
+        Object::Int(_) | Object::BigInt(_) => sym::INTEGER.into(),
         Object::Float(_) => sym::FLOAT.into(),
         Object::Symbol(_) => sym::SYMBOL.into(),
         Object::Cons(_) => sym::CONS.into(),
         Object::Vec(_) => sym::VECTOR.into(),
-        Object::Record(x) => x.get(0).expect("record was missing type").get(),
+        Object::Record(x) => {
+            let type_ = x.get(0).expect("record was missing type").get();
+            // A record whose type slot is itself a record is an instance of a
+            // class (e.g. `cl-defstruct` or EIEIO), and the name of the class
+            // is stored in slot 1 of the class record.
+            match type_.untag() {
+                Object::Record(class) if class.len() > 1 => class[1].get(),
+                _ => type_,
+            }
+        }
         Object::ByteFn(_) => sym::COMPILED_FUNCTION.into(),
         Object::HashTable(_) => sym::HASH_TABLE.into(),
         Object::String(_) => sym::STRING.into(),
         Object::SubrFn(_) => sym::SUBR.into(),
         Object::Buffer(_) => sym::BUFFER.into(),
+        Object::Finalizer(_) => sym::FINALIZER.into(),
+        Object::BoolVec(_) => sym::BOOL_VECTOR.into(),
+        Object::CharTable(_) => sym::CHAR_TABLE.into(),
+        Object::Obarray(_) => sym::OBARRAY.into(),
     }
 }
 
@@ -422,18 +801,150 @@ pub(crate) fn cons<'ob>(car: GcObj, cdr: GcObj, cx: &'ob Context) -> GcObj<'ob>
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::core::object::qtrue;
 
     #[test]
     fn test_ash() {
-        assert_eq!(ash(4, 1), 8);
-        assert_eq!(ash(4, -1), 2);
-        assert_eq!(ash(-8, -1), -4);
-        assert_eq!(ash(256, -8), 1);
-        assert_eq!(ash(-8, 1), -16);
+        let ash = |x: i64, y| ash(x.into(), y).unwrap();
+        assert_eq!(ash(4, 1), NumberValue::Int(8));
+        assert_eq!(ash(4, -1), NumberValue::Int(2));
+        assert_eq!(ash(-8, -1), NumberValue::Int(-4));
+        assert_eq!(ash(256, -8), NumberValue::Int(1));
+        assert_eq!(ash(-8, 1), NumberValue::Int(-16));
+        assert_eq!(ash(-1, -3), NumberValue::Int(-1));
+        let big = BigInt::from(1) << 70_usize;
+        assert_eq!(ash(1, 70), NumberValue::Big(big));
+    }
+
+    #[test]
+    fn test_bool_vector() {
+        let roots = &crate::core::gc::RootSet::default();
+        let cx = &Context::new(roots);
+        let bits = |x: &[bool]| LispBoolVec::from_bits(x.iter().copied()).into_obj(cx).untag();
+        let a = bits(&[true, true, false, false, true]);
+        let b = bits(&[true, false, true, false, true]);
+        assert_eq!(bool_vector_count_population(a), 3);
+        assert_eq!(bool_vector_count_consecutive(a, qtrue(), 0).unwrap(), 2);
+        assert_eq!(bool_vector_count_consecutive(a, nil(), 2).unwrap(), 2);
+        assert_eq!(bool_vector_count_consecutive(a, nil(), 5).unwrap(), 0);
+        let union = bool_vector_union(a, b, None, cx).unwrap();
+        assert_eq!(union.untag(), Object::BoolVec(bits(&[true, true, true, false, true])));
+        let xor = bool_vector_exclusive_or(a, b, None, cx).unwrap();
+        assert_eq!(xor.untag(), Object::BoolVec(bits(&[false, true, true, false, false])));
+        let difference = bool_vector_set_difference(a, b, None, cx).unwrap();
+        assert_eq!(difference.untag(), Object::BoolVec(bits(&[false, true, false, false, false])));
+        let not = bool_vector_not(a, None, cx).unwrap();
+        assert_eq!(not.untag(), Object::BoolVec(bits(&[false, false, true, true, false])));
+
+        // The result is only returned when the destination changes
+        let c = bits(&[false; 5]);
+        assert_eq!(
+            bool_vector_intersection(a, b, Some(c), cx).unwrap().untag(),
+            Object::BoolVec(c)
+        );
+        assert_eq!(bool_vector_intersection(a, b, Some(c), cx).unwrap(), nil());
+        assert!(bool_vector_subsetp(c, a).unwrap());
+        assert!(!bool_vector_subsetp(b, a).unwrap());
+        assert!(bool_vector_union(a, bits(&[true]), None, cx).is_err());
+
+        aset(a.into(), 2, qtrue()).unwrap();
+        assert_eq!(aref(a.into(), 2).unwrap(), qtrue());
+        assert!(aref(a.into(), 5).is_err());
+    }
+
+    #[test]
+    fn test_record_type_of() {
+        let roots = &crate::core::gc::RootSet::default();
+        let cx = &Context::new(roots);
+        let read = |x| crate::reader::read(x, cx).unwrap().0;
+        let record = read("#s(foo 1 2)");
+        assert_eq!(type_of(record), read("foo"));
+        // records whose type is a class record use the class name
+        let instance = read("#s(#s(my-class foo nil) 1 2)");
+        assert_eq!(type_of(instance), read("foo"));
+        let copy = crate::fns::copy_sequence(instance, cx).unwrap();
+        assert!(recordp(copy));
+        assert!(!copy.ptr_eq(instance));
+        assert_eq!(type_of(copy), read("foo"));
+    }
+
+    #[test]
+    fn test_plist() {
+        use crate::core::env::intern;
+        use crate::root;
+        let roots = &crate::core::gc::RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let symbol = intern("test-plist-symbol", cx);
+        let prop = intern("test-plist-prop", cx);
+        assert_eq!(get(symbol, prop, env, cx), nil());
+        put(symbol, prop, 1.into(), env, cx).unwrap();
+        put(symbol, sym::FUNCTION, 2.into(), env, cx).unwrap();
+        put(symbol, prop, 3.into(), env, cx).unwrap();
+        assert_eq!(get(symbol, prop, env, cx), 3);
+        assert_eq!(symbol_plist(symbol, env, cx), list![prop, 3, sym::FUNCTION, 2; cx]);
+        // other threads see the same plist
+        let seen = std::thread::spawn(|| {
+            let roots = &crate::core::gc::RootSet::default();
+            let cx = &mut Context::new(roots);
+            root!(env, Env::default(), cx);
+            let symbol = intern("test-plist-symbol", cx);
+            get(symbol, intern("test-plist-prop", cx), env, cx) == 3
+        });
+        assert!(seen.join().unwrap());
+        setplist(symbol, list![sym::FUNCTION, 4; cx], env);
+        assert_eq!(get(symbol, prop, env, cx), nil());
+        assert_eq!(get(symbol, sym::FUNCTION, env, cx), 4);
+
+        // the thread that set a property can modify the value in place
+        put(symbol, prop, list![1; cx], env, cx).unwrap();
+        let value = get(symbol, prop, env, cx);
+        value.as_cons().set_cdr(list![2; cx]).unwrap();
+        assert_eq!(get(symbol, prop, env, cx), list![1, 2; cx]);
+
+        // function-get follows aliases
+        let alias = intern("test-plist-alias", cx);
+        defalias(alias, symbol.into(), None).unwrap();
+        assert_eq!(function_get(alias.into(), sym::FUNCTION, None, env, cx), 4);
+        function_put(alias, sym::FUNCTION, 5.into(), env, cx).unwrap();
+        assert_eq!(function_get(alias.into(), sym::FUNCTION, None, env, cx), 5);
+    }
+
+    #[test]
+    fn test_fset_collects_old_definition() {
+        use crate::core::gc::RootSet;
+        use crate::fns::make_hash_table;
+        use crate::root;
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let symbol = crate::core::env::intern("test-fset-collects-old-definition", cx);
+        fset(symbol, cons!(1; cx)).unwrap();
+        // Only a weak table refers to the old definition
+        let table = make_hash_table(&[sym::KW_WEAKNESS.into(), sym::KEY.into()], env, cx).unwrap();
+        let Object::HashTable(inner) = table.untag() else { unreachable!() };
+        let old = symbol_function(symbol, cx);
+        inner.insert(old, 1.into()).unwrap();
+        fset(symbol, cons!(2; cx)).unwrap();
+        let new = symbol_function(symbol, cx);
+        root!(inner, cx);
+        root!(new, cx);
+        cx.collect_global();
+
+        assert!(inner.bind(cx).borrow().is_empty());
+        let symbol = crate::core::env::intern("test-fset-collects-old-definition", cx);
+        let current = symbol_function(symbol, cx);
+        assert_eq!(current, cons!(2; cx));
+        // references into the global block are updated
+        assert!(new.bind(cx).ptr_eq(current));
     }
 }
 
 defsym!(MANY);
+defsym!(WATCHERS);
+defsym!(UNLET);
+defsym!(VARIABLE_DOCUMENTATION);
 defsym!(INTEGER);
 defsym!(SYMBOL);
 defsym!(COMPILED_FUNCTION);
@@ -441,3 +952,7 @@ defsym!(HASH_TABLE);
 defsym!(BUFFER);
 defsym!(STRING);
 defsym!(SUBR);
+defsym!(FINALIZER);
+defsym!(CHAR_TABLE);
+defvar!(MOST_POSITIVE_FIXNUM, crate::core::object::MOST_POSITIVE_FIXNUM);
+defvar!(MOST_NEGATIVE_FIXNUM, crate::core::object::MOST_NEGATIVE_FIXNUM);
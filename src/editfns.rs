@@ -1,15 +1,19 @@
 use crate::core::{
-    env::Env,
-    gc::Rt,
-    object::{GcObj, Object},
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{format_float, format_float_alternate, GcObj, Object},
 };
-use anyhow::{bail, ensure, Result};
+use crate::print::{print_to_string, PrintSettings};
+use anyhow::{anyhow, bail, ensure, Result};
 use fn_macros::defun;
-use std::{fmt::Write as _, io::Write};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+use std::fmt::{Display, LowerHex, Octal, UpperHex};
+use std::io::Write;
 
 #[defun]
-fn message(format_string: &str, args: &[GcObj]) -> Result<String> {
-    let message = format(format_string, args)?;
+fn message(format_string: &str, args: &[GcObj], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let message = format_message(format_string, args, env, cx)?;
     println!("MESSAGE: {message}");
     std::io::stdout().flush()?;
     Ok(message)
@@ -18,57 +22,290 @@ fn message(format_string: &str, args: &[GcObj]) -> Result<String> {
 defvar!(MESSAGE_NAME);
 defvar!(MESSAGE_TYPE, "new message");
 
+/// Format a string out of a format-string and arguments. Each `%` directive
+/// has the form `%[FIELD$][FLAGS][WIDTH][.PRECISION]CONVERSION`, where FIELD
+/// picks the argument to use by number, counting from 1.
 #[defun]
-fn format(string: &str, objects: &[GcObj]) -> Result<String> {
+fn format(string: &str, objects: &[GcObj], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    styled_format(string, objects, None, env, cx)
+}
+
+/// Like `format`, but the grave accents and apostrophes of the format string
+/// are translated according to `text-quoting-style`.
+#[defun]
+fn format_message(string: &str, objects: &[GcObj], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    styled_format(string, objects, Some(QuotingStyle::from_env(env, cx)), env, cx)
+}
+
+defvar!(TEXT_QUOTING_STYLE);
+defsym!(CURVE);
+defsym!(STRAIGHT);
+defsym!(GRAVE);
+
+/// How `format-message` translates the quotes in its format string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuotingStyle {
+    /// ‘like this’, which is what nil means
+    Curve,
+    /// 'like this'
+    Straight,
+    /// left as is
+    Grave,
+}
+
+impl QuotingStyle {
+    fn from_env(env: &Rt<Env>, cx: &Context) -> Self {
+        match env.vars.get(sym::TEXT_QUOTING_STYLE).map(|x| x.bind(cx).untag()) {
+            Some(Object::Symbol(sym::STRAIGHT)) => Self::Straight,
+            Some(Object::Symbol(sym::GRAVE)) => Self::Grave,
+            _ => Self::Curve,
+        }
+    }
+
+    fn translate(self, c: char) -> char {
+        match (self, c) {
+            (Self::Curve, '`') => '‘',
+            (Self::Curve, '\'') => '’',
+            (Self::Straight, '`') => '\'',
+            _ => c,
+        }
+    }
+}
+
+fn styled_format(
+    string: &str,
+    objects: &[GcObj],
+    quoting: Option<QuotingStyle>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    // Strings don't have text properties yet, so there are none to copy from
+    // the format string or the arguments
     let mut result = String::new();
-    let mut arguments = objects.iter();
+    let push_literal = |result: &mut String, text: &str| match quoting {
+        Some(style) => result.extend(text.chars().map(|c| style.translate(c))),
+        None => result.push_str(text),
+    };
+    let mut next_arg = 0;
     let mut remaining = string;
+    while let Some(start) = remaining.find('%') {
+        push_literal(&mut result, &remaining[..start]);
+        let (spec, rest) = FormatSpec::parse(&remaining[start + 1..])?;
+        remaining = rest;
+        // "%%" inserts a single "%" in the output
+        if spec.conversion == '%' {
+            result.push('%');
+            continue;
+        }
+        let index = spec.field.unwrap_or(next_arg);
+        let Some(arg) = objects.get(index) else { bail!("Not enough arguments for format string") };
+        next_arg = index + 1;
+        spec.format(*arg, &mut result, env, cx)?;
+    }
+    push_literal(&mut result, remaining);
+    Ok(result)
+}
 
-    let mut escaped = false;
-    let mut is_format_char = |c: char| {
-        if escaped {
-            escaped = false;
-            false
-        } else if c == '\\' {
-            escaped = true;
-            false
-        } else {
-            c == '%'
+const TYPE_MISMATCH: &str = "Format specifier doesn’t match argument type";
+
+/// A parsed `%` directive of a format string.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct FormatSpec {
+    /// Index of the argument, if given explicitly
+    field: Option<usize>,
+    /// `-`: pad on the right
+    minus: bool,
+    /// `+`: always print the sign of numbers
+    plus: bool,
+    /// ` `: print a space in place of a plus sign
+    space: bool,
+    /// `0`: pad numbers with zeros
+    zero: bool,
+    /// `#`: use the alternate form of the conversion
+    sharp: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl FormatSpec {
+    /// Parse the directive following a `%`, and return it along with the rest
+    /// of the format string.
+    fn parse(spec: &str) -> Result<(Self, &str)> {
+        let mut this = Self::default();
+        let mut rest = spec;
+        if let (Some(field), after) = parse_number(rest)? {
+            if let Some(after) = after.strip_prefix('$') {
+                ensure!(field > 0, "Invalid format field number 0");
+                this.field = Some(field - 1);
+                rest = after;
+            }
         }
-    };
-    while let Some(start) = remaining.find(&mut is_format_char) {
-        result.push_str(&remaining[..start]);
-        let Some(specifier) = remaining.as_bytes().get(start + 1) else {
+        loop {
+            match rest.chars().next() {
+                Some('-') => this.minus = true,
+                Some('+') => this.plus = true,
+                Some(' ') => this.space = true,
+                Some('0') => this.zero = true,
+                Some('#') => this.sharp = true,
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+        let (width, after) = parse_number(rest)?;
+        this.width = width.unwrap_or(0);
+        rest = after;
+        if let Some(after) = rest.strip_prefix('.') {
+            let (precision, after) = parse_number(after)?;
+            this.precision = Some(precision.unwrap_or(0));
+            rest = after;
+        }
+        let mut chars = rest.chars();
+        let Some(conversion) = chars.next() else {
             bail!("Format string ends in middle of format specifier")
         };
-        // "%%" inserts a single "%" in the output
-        if *specifier == b'%' {
-            result.push('%');
+        this.conversion = conversion;
+        Ok((this, chars.as_str()))
+    }
+
+    fn format(&self, arg: GcObj, out: &mut String, env: &Rt<Env>, cx: &Context) -> Result<()> {
+        match self.conversion {
+            's' | 'S' => {
+                let settings = PrintSettings::from_env(env, cx, self.conversion == 'S');
+                let printed = print_to_string(arg, &settings);
+                match self.precision {
+                    Some(precision) => {
+                        let truncated: String = printed.chars().take(precision).collect();
+                        self.pad(out, "", &truncated, false);
+                    }
+                    None => self.pad(out, "", &printed, false),
+                }
+            }
+            'c' => {
+                let Object::Int(code) = arg.untag() else { bail!(TYPE_MISMATCH) };
+                let chr = u32::try_from(code)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow!("Invalid character: {code}"))?;
+                self.pad(out, "", chr.encode_utf8(&mut [0; 4]), false);
+            }
+            'd' | 'o' | 'x' | 'X' => self.format_integer(arg, out)?,
+            'e' | 'f' | 'g' => self.format_float(arg, out)?,
+            conversion => bail!("Invalid format operation %{conversion}"),
+        }
+        Ok(())
+    }
+
+    fn format_integer(&self, arg: GcObj, out: &mut String) -> Result<()> {
+        let conversion = self.conversion;
+        let (negative, mut digits) = match arg.untag() {
+            Object::Int(int) => (int < 0, radix_digits(int.unsigned_abs(), conversion)),
+            Object::BigInt(int) => {
+                (int.get().is_negative(), radix_digits(int.get().magnitude(), conversion))
+            }
+            // floats are truncated towards zero
+            Object::Float(float) => {
+                let Some(int) = BigInt::from_f64(float.get().trunc()) else {
+                    bail!("Not an integer: {float}")
+                };
+                (int.is_negative(), radix_digits(int.magnitude(), conversion))
+            }
+            _ => bail!(TYPE_MISMATCH),
+        };
+        if let Some(precision) = self.precision {
+            let len = digits.len();
+            if len < precision {
+                digits.insert_str(0, &"0".repeat(precision - len));
+            }
+        }
+        let radix_prefix = match conversion {
+            'o' if self.sharp && !digits.starts_with('0') => "0",
+            'x' if self.sharp && digits != "0" => "0x",
+            'X' if self.sharp && digits != "0" => "0X",
+            _ => "",
+        };
+        let prefix = format!("{}{radix_prefix}", self.sign(negative));
+        // a precision takes the place of zero padding
+        self.pad(out, &prefix, &digits, self.precision.is_none());
+        Ok(())
+    }
+
+    fn format_float(&self, arg: GcObj, out: &mut String) -> Result<()> {
+        let value = match arg.untag() {
+            Object::Int(int) => int as f64,
+            Object::BigInt(int) => int.get().to_f64().unwrap_or(f64::NAN),
+            Object::Float(float) => float.get(),
+            _ => bail!(TYPE_MISMATCH),
+        };
+        let prefix = self.sign(value.is_sign_negative());
+        if value.is_nan() {
+            self.pad(out, prefix, "nan", false);
+        } else if value.is_infinite() {
+            self.pad(out, prefix, "inf", false);
         } else {
-            // TODO: currently handles all format types the same. Need to check the modifier characters.
-            let Some(val) = arguments.next() else {
-                bail!("Not enough arguments for format string")
+            let printed = if self.sharp {
+                format_float_alternate(value.abs(), self.conversion, self.precision)
+            } else {
+                format_float(value.abs(), self.conversion, self.precision)
             };
-            match val.untag() {
-                Object::String(s) => result.push_str(s.try_into().unwrap()),
-                obj => write!(result, "{obj}")?,
-            }
+            self.pad(out, prefix, &printed, true);
+        }
+        Ok(())
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Write `prefix` and `body` padded to the field width. If `zero_fill` is
+    /// set, the `0` flag pads with zeros between the prefix and the body.
+    fn pad(&self, out: &mut String, prefix: &str, body: &str, zero_fill: bool) {
+        let len = prefix.chars().count() + body.chars().count();
+        let padding = self.width.saturating_sub(len);
+        if self.minus {
+            out.push_str(prefix);
+            out.push_str(body);
+            out.push_str(&" ".repeat(padding));
+        } else if self.zero && zero_fill {
+            out.push_str(prefix);
+            out.push_str(&"0".repeat(padding));
+            out.push_str(body);
+        } else {
+            out.push_str(&" ".repeat(padding));
+            out.push_str(prefix);
+            out.push_str(body);
         }
-        remaining = &remaining[start + 2..];
     }
-    result.push_str(remaining);
-    ensure!(arguments.next().is_none(), "Too many arguments for format string");
-    Ok(result)
 }
 
-#[defun]
-fn format_message(string: &str, objects: &[GcObj]) -> Result<String> {
-    let formatted = format(string, objects)?;
-    // TODO: implement support for `text-quoting-style`.
-    Ok(formatted
-        .chars()
-        .map(|c| if matches!(c, '`' | '\'') { '"' } else { c })
-        .collect())
+/// Parse the decimal number at the start of `string`, if any, and return it
+/// along with the rest of the string.
+fn parse_number(string: &str) -> Result<(Option<usize>, &str)> {
+    let end = string.find(|c: char| !c.is_ascii_digit()).unwrap_or(string.len());
+    if end == 0 {
+        return Ok((None, string));
+    }
+    let Ok(number) = string[..end].parse() else { bail!("Format width or precision too large") };
+    Ok((Some(number), &string[end..]))
+}
+
+/// The digits of a non-negative integer in the radix of `conversion`.
+fn radix_digits<T: Display + Octal + LowerHex + UpperHex>(int: T, conversion: char) -> String {
+    match conversion {
+        'o' => format!("{int:o}"),
+        'x' => format!("{int:x}"),
+        'X' => format!("{int:X}"),
+        _ => int.to_string(),
+    }
 }
 
 #[defun]
@@ -101,6 +338,11 @@ mod test {
 
     #[test]
     fn test_format() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let cx = &*cx;
+        let format = |string, objects: &[GcObj]| format(string, objects, env, cx);
         assert_eq!(&format("%s", &[1.into()]).unwrap(), "1");
         assert_eq!(&format("foo-%s", &[2.into()]).unwrap(), "foo-2");
         assert_eq!(&format("%%", &[]).unwrap(), "%");
@@ -110,9 +352,90 @@ mod test {
         assert_eq!(&format("%s", &[sym]).unwrap(), "function");
 
         assert!(&format("%s", &[]).is_err());
-        assert!(&format("%s", &[1.into(), 2.into()]).is_err());
+        // extra arguments are ignored
+        assert_eq!(&format("%s", &[1.into(), 2.into()]).unwrap(), "1");
 
         assert!(format("`%s' %s%s%s", &[0.into(), 1.into(), 2.into(), 3.into()]).is_ok());
+
+        let string = cx.add("a\"b");
+        assert_eq!(&format("%s %S", &[string, string]).unwrap(), "a\"b \"a\\\"b\"");
+        assert_eq!(
+            &format("%.2s|%5s|%-5s|", &[string, string, string]).unwrap(),
+            "a\"|  a\"b|a\"b  |"
+        );
+        assert_eq!(&format("%2$s %1$s %s", &[1.into(), 2.into()]).unwrap(), "2 1 2");
+
+        assert_eq!(
+            &format("%d %d %d", &[42.into(), (-7).into(), cx.add(-2.7)]).unwrap(),
+            "42 -7 -2"
+        );
+        assert_eq!(
+            &format("%5d|%-5d|%05d", &[(-42).into(), 42.into(), (-42).into()]).unwrap(),
+            "  -42|42   |-0042"
+        );
+        assert_eq!(&format("%+d % d %.3d", &[5.into(), 5.into(), 5.into()]).unwrap(), "+5  5 005");
+        assert_eq!(
+            &format("%o %x %X %x", &[8.into(), 255.into(), 255.into(), (-255).into()]).unwrap(),
+            "10 ff FF -ff"
+        );
+        assert_eq!(
+            &format("%#o %#x %#X %#08x", &[8.into(), 255.into(), 255.into(), 255.into()]).unwrap(),
+            "010 0xff 0XFF 0x0000ff"
+        );
+        let big = cx.add(BigInt::from(1u8) << 70);
+        assert_eq!(
+            &format("%d %x", &[big, big]).unwrap(),
+            "1180591620717411303424 400000000000000000"
+        );
+        assert_eq!(&format("%c%3c", &[97.into(), 955.into()]).unwrap(), "a  λ");
+
+        assert_eq!(
+            &format("%f %e %g", &[cx.add(1.5), cx.add(1.5), cx.add(1.5)]).unwrap(),
+            "1.500000 1.500000e+00 1.5"
+        );
+        assert_eq!(
+            &format("%.2f %.1e %g %g", &[cx.add(1.23456), 1234.into(), cx.add(1e-5), cx.add(1e20)])
+                .unwrap(),
+            "1.23 1.2e+03 1e-05 1e+20"
+        );
+        assert_eq!(
+            &format("%+08.2f|%-8.1f|% f", &[cx.add(-1.23456), cx.add(2.5), 1.into()]).unwrap(),
+            "-0001.23|2.5     | 1.000000"
+        );
+        assert_eq!(
+            &format("%#g %#.0f %#.0e", &[cx.add(1.5), 2.into(), 3.into()]).unwrap(),
+            "1.50000 2. 3.e+00"
+        );
+        assert_eq!(
+            &format(
+                "%f %5f %f",
+                &[cx.add(f64::INFINITY), cx.add(f64::NEG_INFINITY), cx.add(f64::NAN)]
+            )
+            .unwrap(),
+            "inf  -inf nan"
+        );
+
+        assert!(format("%d", &[cx.add("1")]).is_err());
+        assert!(format("%f", &[sym]).is_err());
+        assert!(format("%c", &[cx.add(1.0)]).is_err());
+        assert!(format("%d", &[cx.add(f64::NAN)]).is_err());
+        assert!(format("%q", &[1.into()]).is_err());
+        assert!(format("%5", &[1.into()]).is_err());
+    }
+
+    #[test]
+    fn test_format_message() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let args = [cx.add("`x'")];
+        assert_eq!(&format_message("`%s' can't", &args, env, cx).unwrap(), "‘`x'’ can’t");
+        let straight: GcObj = sym::STRAIGHT.into();
+        env.vars.insert(sym::TEXT_QUOTING_STYLE, straight);
+        assert_eq!(&format_message("`%s' can't", &args, env, cx).unwrap(), "'`x'' can't");
+        let grave: GcObj = sym::GRAVE.into();
+        env.vars.insert(sym::TEXT_QUOTING_STYLE, grave);
+        assert_eq!(&format_message("`%s' can't", &args, env, cx).unwrap(), "``x'' can't");
     }
 
     #[test]
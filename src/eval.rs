@@ -1,4 +1,4 @@
-use crate::core::env::{sym, Env, Symbol};
+use crate::core::env::{sym, Env, Symbol, TrappedWrite, INTERNED_SYMBOLS};
 use crate::core::error::{EvalError, Type, TypeError};
 use crate::core::gc::Rt;
 use crate::core::object::{nil, LispString, Object};
@@ -49,7 +49,7 @@ pub(crate) fn funcall<'ob>(
 }
 
 #[defun]
-fn run_hooks<'ob>(
+pub(crate) fn run_hooks<'ob>(
     hooks: &[Rt<GcObj>],
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
@@ -57,7 +57,7 @@ fn run_hooks<'ob>(
     for hook in hooks {
         match hook.get(cx) {
             Object::Symbol(sym) => {
-                if let Some(val) = env.vars.get(sym) {
+                if let Some(val) = env.var(sym) {
                     let val = val.bind(cx);
                     match val.untag() {
                         Object::Cons(hook_list) => {
@@ -108,7 +108,7 @@ pub(crate) fn autoload_do_load<'ob>(
                 "autoload arguments are not yet implemented"
             );
             root!(file, cx);
-            crate::lread::load(file, None, None, cx, env)?;
+            crate::lread::load(file, None, None, None, None, cx, env)?;
             match funname {
                 Some(func) => match func.get(cx).func(cx) {
                     Some(x) => Ok(x.into()),
@@ -206,23 +206,146 @@ fn special_variable_p(symbol: Symbol) -> bool {
 
 #[defun]
 fn set_default_toplevel_value<'ob>(
-    symbol: Symbol,
-    value: GcObj,
-    env: &'ob mut Rt<Env>,
+    symbol: &Rt<Gc<Symbol>>,
+    value: &Rt<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>> {
-    env.set_var(symbol, value)?;
+    crate::data::set(symbol, value, env, cx)?;
     Ok(nil())
 }
 
 #[defun]
 fn set_default<'ob>(
-    symbol: Symbol,
-    value: GcObj<'ob>,
-    env: &'ob mut Rt<Env>,
+    symbol: &Rt<Gc<Symbol>>,
+    value: &Rt<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>> {
     // TODO: implement buffer local variables
-    env.set_var(symbol, value)?;
-    Ok(value)
+    crate::data::set(symbol, value, env, cx)
+}
+
+/// Make NEW-ALIAS a variable alias for BASE-VARIABLE, so that both names
+/// refer to the same value. If NEW-ALIAS is bound and BASE-VARIABLE is not,
+/// BASE-VARIABLE is given the value of NEW-ALIAS.
+#[defun]
+pub(crate) fn defvaralias<'ob>(
+    new_alias: &Rt<Gc<Symbol>>,
+    base_variable: &Rt<Gc<Symbol>>,
+    docstring: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Symbol<'ob>> {
+    let alias = new_alias.bind(cx).untag();
+    if alias.trapped_write() == TrappedWrite::NoWrite {
+        bail!("Cannot make a constant an alias: {alias}");
+    }
+    crate::data::notify_variable_watchers(
+        new_alias,
+        base_variable.use_as(),
+        sym::DEFVARALIAS,
+        env,
+        cx,
+    )?;
+    let alias = new_alias.bind(cx).untag();
+    let base = base_variable.bind(cx).untag();
+    INTERNED_SYMBOLS.lock().unwrap().set_var_alias(alias, base)?;
+    if let Some(value) = env.vars.get(alias).map(|x| x.bind(cx)) {
+        let base = base.indirect_variable();
+        if env.vars.get(base).is_none() {
+            env.vars.insert(base, value);
+        }
+        env.vars.remove(alias);
+    }
+    alias.make_special();
+    base.make_special();
+    let doc = docstring.map_or_else(nil, |x| x.bind(cx));
+    crate::data::put(alias, sym::VARIABLE_DOCUMENTATION, doc, env, cx)?;
+    Ok(base_variable.bind(cx).untag())
+}
+
+/// Return true if a `condition-case` handler for CONDITIONS catches an error
+/// signaled with ERROR-SYMBOL. CONDITIONS is either a single condition or a
+/// list of them, and matches if any condition is `t` or is one of the
+/// `error-conditions` of the error. An error with no conditions is treated as
+/// a plain `error`.
+pub(crate) fn handles_error(
+    conditions: GcObj,
+    error_symbol: GcObj,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let error_conditions = match error_symbol.untag() {
+        Object::Symbol(symbol) => crate::data::get(symbol, sym::ERROR_CONDITIONS, env, cx),
+        _ => nil(),
+    };
+    let matches = |condition: GcObj| -> Result<bool> {
+        if condition == sym::TRUE {
+            return Ok(true);
+        }
+        if error_conditions.nil() {
+            return Ok(condition == error_symbol || condition == sym::ERROR);
+        }
+        for x in error_conditions.as_list()? {
+            if x? == condition {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    match conditions.untag() {
+        Object::Symbol(_) => matches(conditions),
+        Object::Cons(cons) => {
+            for condition in cons.elements() {
+                // `debug` only controls whether the debugger is entered
+                let condition = condition?;
+                if condition != sym::DEBUG && matches(condition)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => bail!("Invalid condition handler: {conditions}"),
+    }
+}
+
+/// Define NAME as an error with MESSAGE, inheriting the conditions of PARENT.
+fn define_error(name: Symbol, message: &str, parent: Symbol, env: &mut Rt<Env>, cx: &Context) {
+    let parent_conditions = crate::data::get(parent, sym::ERROR_CONDITIONS, env, cx);
+    let conditions = cons!(name, parent_conditions; cx);
+    crate::data::put(name, sym::ERROR_CONDITIONS, conditions, env, cx).unwrap();
+    crate::data::put(name, sym::ERROR_MESSAGE, cx.add(message), env, cx).unwrap();
+}
+
+/// Set the `error-conditions` and `error-message` properties of the errors
+/// signaled by the runtime.
+pub(crate) fn init_errors(env: &mut Rt<Env>, cx: &Context) {
+    let conditions = list![sym::ERROR; cx];
+    crate::data::put(sym::ERROR, sym::ERROR_CONDITIONS, conditions, env, cx).unwrap();
+    crate::data::put(sym::ERROR, sym::ERROR_MESSAGE, cx.add("error"), env, cx).unwrap();
+    let errors = [
+        (sym::QUIT, "Quit", sym::NIL),
+        (sym::USER_ERROR, "", sym::ERROR),
+        (sym::ARGS_OUT_OF_RANGE, "Args out of range", sym::ERROR),
+        (sym::ARITH_ERROR, "Arithmetic error", sym::ERROR),
+        (sym::OVERFLOW_ERROR, "Arithmetic overflow error", sym::ARITH_ERROR),
+        (sym::CIRCULAR_LIST, "List contains a loop", sym::ERROR),
+        (sym::END_OF_FILE, "End of file during parsing", sym::ERROR),
+        (sym::INVALID_FUNCTION, "Invalid function", sym::ERROR),
+        (sym::INVALID_READ_SYNTAX, "Invalid read syntax", sym::ERROR),
+        (sym::NO_CATCH, "No catch for tag", sym::ERROR),
+        (sym::SETTING_CONSTANT, "Attempt to set a constant symbol", sym::ERROR),
+        (sym::VOID_FUNCTION, "Symbol's function definition is void", sym::ERROR),
+        (sym::VOID_VARIABLE, "Symbol's value as variable is void", sym::ERROR),
+        (sym::WRONG_NUMBER_OF_ARGUMENTS, "Wrong number of arguments", sym::ERROR),
+        (sym::WRONG_TYPE_ARGUMENT, "Wrong type argument", sym::ERROR),
+        (sym::FILE_ERROR, "File error", sym::ERROR),
+        (sym::FILE_MISSING, "No such file or directory", sym::FILE_ERROR),
+    ];
+    for (name, message, parent) in errors {
+        define_error(name, message, parent, env, cx);
+    }
 }
 
 defsym!(FUNCTION);
@@ -255,5 +378,23 @@ defsym!(CATCH);
 defsym!(THROW);
 defsym!(ERROR);
 defsym!(DEBUG);
+defsym!(ERROR_CONDITIONS);
+defsym!(ERROR_MESSAGE);
+defsym!(QUIT);
+defsym!(USER_ERROR);
+defsym!(ARGS_OUT_OF_RANGE);
+defsym!(ARITH_ERROR);
+defsym!(OVERFLOW_ERROR);
+defsym!(END_OF_FILE);
+defsym!(INVALID_FUNCTION);
+defsym!(INVALID_READ_SYNTAX);
+defsym!(NO_CATCH);
+defsym!(SETTING_CONSTANT);
+defsym!(VOID_FUNCTION);
+defsym!(VOID_VARIABLE);
+defsym!(WRONG_NUMBER_OF_ARGUMENTS);
+defsym!(WRONG_TYPE_ARGUMENT);
+defsym!(FILE_ERROR);
+defsym!(FILE_MISSING);
 
 defvar!(DEBUG_ON_ERROR, false);
@@ -1,22 +1,25 @@
 use crate::{
-    arith::NumberValue,
+    arith::{arith_error, NumberValue},
     core::{
-        gc::Context,
+        env::Env,
+        gc::{Context, Rt},
         object::{Gc, Number},
     },
 };
+use anyhow::Result;
 use fn_macros::defun;
+use num_traits::ToPrimitive;
 
 #[defun]
-fn floor(arg: Gc<Number>, divisor: Option<Gc<Number>>) -> i64 {
+fn floor(arg: Gc<Number>, divisor: Option<Gc<Number>>, env: &mut Rt<Env>) -> Result<NumberValue> {
     let num = match divisor {
-        Some(div) => arg.val() / div.val(),
+        Some(div) => arg.val().checked_div(div.val()).ok_or_else(|| arith_error(env))?,
         None => arg.val(),
     };
-    match num {
-        NumberValue::Int(i) => i,
-        NumberValue::Float(f) => f.floor() as i64,
-    }
+    Ok(match num {
+        NumberValue::Float(f) => NumberValue::from_integral_float(f.floor()),
+        integer => integer,
+    })
 }
 
 #[defun]
@@ -24,5 +27,6 @@ fn float<'ob>(arg: Gc<Number<'ob>>, cx: &'ob Context) -> Gc<Number<'ob>> {
     match arg.untag() {
         Number::Int(i) => cx.add_as(i as f64),
         Number::Float(_) => arg,
+        Number::BigInt(x) => cx.add_as(x.get().to_f64().unwrap_or(f64::NAN)),
     }
 }
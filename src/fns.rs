@@ -2,18 +2,18 @@ use crate::{
     core::{
         cons::Cons,
         env::{sym, Env, Symbol},
-        error::{Type, TypeError},
+        error::{EvalError, Type, TypeError},
         gc::{Context, IntoRoot, Rt},
         object::{
-            nil, Function, Gc, GcObj, HashTable, IntoObject, LispHashTable, LispString, LispVec,
-            List, ObjCell, Object,
+            hash_eq, hash_eql, hash_equal, internal_equal, nil, CircularList, Function, Gc, GcObj,
+            HashTable, HashTest, IntoObject, LispHashTable, LispString, LispVec, List, ObjCell,
+            Object, RawMultibyte, RecordBuilder, Weakness,
         },
     },
     data::aref,
 };
 use crate::{root, rooted_iter};
 use anyhow::{bail, ensure, Result};
-use bstr::ByteSlice;
 use fn_macros::defun;
 use streaming_iterator::StreamingIterator;
 
@@ -37,24 +37,49 @@ pub(crate) fn eq(obj1: GcObj, obj2: GcObj) -> bool {
 }
 
 #[defun]
-pub(crate) fn equal<'ob>(obj1: GcObj<'ob>, obj2: GcObj<'ob>) -> bool {
-    obj1 == obj2
+pub(crate) fn equal<'ob>(
+    obj1: GcObj<'ob>,
+    obj2: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<bool> {
+    internal_equal(obj1, obj2, false).or_else(|e| signal_circular_list(e, env, cx))
 }
 
 #[defun]
 pub(crate) fn eql<'ob>(obj1: GcObj<'ob>, obj2: GcObj<'ob>) -> bool {
     match (obj1.untag(), obj2.untag()) {
-        (Object::Float(f1), Object::Float(f2)) => f1.to_bits() == f2.to_bits(),
+        (Object::Float(f1), Object::Float(f2)) => f1.get().to_bits() == f2.get().to_bits(),
+        (Object::BigInt(x), Object::BigInt(y)) => x == y,
         _ => obj1.ptr_eq(obj2),
     }
 }
 
+/// Like `equal`, but the text properties of strings have to match as well.
+/// Strings can't have text properties yet, so for now this is the same as
+/// `equal`.
 #[defun]
-fn equal_including_properties<'ob>(o1: GcObj<'ob>, o2: GcObj<'ob>) -> bool {
-    // TODO: implement text properties
-    equal(o1, o2)
+fn equal_including_properties<'ob>(
+    o1: GcObj<'ob>,
+    o2: GcObj<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<bool> {
+    internal_equal(o1, o2, true).or_else(|e| signal_circular_list(e, env, cx))
+}
+
+/// Signal `circular-list` for a comparison that could not finish.
+pub(crate) fn signal_circular_list<T>(
+    err: CircularList,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<T> {
+    let data = list![err.0; cx];
+    Err(EvalError::signal(sym::CIRCULAR_LIST.into(), data, env).into())
 }
 
+defsym!(CIRCULAR_LIST);
+
 #[defun]
 fn plist_get<'ob>(plist: Gc<List<'ob>>, prop: GcObj<'ob>) -> Result<GcObj<'ob>> {
     // TODO: this function should never fail. Need to implement safe iterator
@@ -68,9 +93,17 @@ fn plist_get<'ob>(plist: Gc<List<'ob>>, prop: GcObj<'ob>) -> Result<GcObj<'ob>>
     Ok(nil())
 }
 
+/// Return the tail of PLIST starting at PROP, or nil if PROP is not one of its
+/// properties.
 #[defun]
-pub(crate) fn prin1_to_string(object: GcObj, _noescape: Option<GcObj>) -> String {
-    format!("{object}")
+fn plist_member<'ob>(plist: Gc<List<'ob>>, prop: GcObj<'ob>) -> Result<GcObj<'ob>> {
+    for cons in plist.conses().step_by(2) {
+        let cons = cons?;
+        if eq(cons.car(), prop) {
+            return Ok(cons.into());
+        }
+    }
+    Ok(nil())
 }
 
 #[defun]
@@ -147,7 +180,7 @@ fn maphash(
     cx: &mut Context,
 ) -> Result<bool> {
     root!(cell, (nil(), nil()), cx);
-    let mut iter = LispHashTable::iter(table, cell, cx);
+    let mut iter = LispHashTable::iter(table, cell);
 
     root!(call_arg, Vec::new(), cx);
     while let Some((key, val)) = iter.next() {
@@ -249,11 +282,13 @@ pub(crate) fn assoc<'ob>(
     key: GcObj<'ob>,
     alist: Gc<List<'ob>>,
     testfn: Option<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
     ensure!(testfn.is_none(), "test functions for assoc not yet supported");
     for elem in alist.elements() {
         if let Object::Cons(cons) = elem?.untag() {
-            if equal(key, cons.car()) {
+            if equal(key, cons.car(), env, cx)? {
                 return Ok(cons.into());
             }
         }
@@ -261,8 +296,6 @@ pub(crate) fn assoc<'ob>(
     Ok(nil())
 }
 
-type EqFunc = for<'ob> fn(GcObj<'ob>, GcObj<'ob>) -> bool;
-
 #[defun]
 fn copy_alist<'ob>(alist: Gc<List<'ob>>, cx: &'ob Context) -> Result<GcObj<'ob>> {
     match alist.untag() {
@@ -292,13 +325,13 @@ fn copy_alist_elem<'ob>(elem: GcObj<'ob>, cx: &'ob Context) -> GcObj<'ob> {
 fn delete_from_list<'ob>(
     elt: GcObj<'ob>,
     list: Gc<List<'ob>>,
-    eq_fn: EqFunc,
+    mut eq_fn: impl FnMut(GcObj<'ob>, GcObj<'ob>) -> Result<bool>,
 ) -> Result<GcObj<'ob>> {
     let mut head = list.into();
     let mut prev: Option<&'ob Cons> = None;
     for tail in list.conses() {
         let tail = tail?;
-        if eq_fn(tail.car(), elt) {
+        if eq_fn(tail.car(), elt)? {
             if let Some(prev_tail) = &mut prev {
                 prev_tail.set_cdr(tail.cdr())?;
             } else {
@@ -312,50 +345,52 @@ fn delete_from_list<'ob>(
 }
 
 #[defun]
-pub(crate) fn delete<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>) -> Result<GcObj<'ob>> {
-    delete_from_list(elt, list, equal)
+pub(crate) fn delete<'ob>(
+    elt: GcObj<'ob>,
+    list: Gc<List<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    delete_from_list(elt, list, |x, y| equal(x, y, env, cx))
 }
 
 #[defun]
 pub(crate) fn delq<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>) -> Result<GcObj<'ob>> {
-    delete_from_list(elt, list, eq)
+    delete_from_list(elt, list, |x, y| Ok(eq(x, y)))
 }
 
-fn member_of_list<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>, eq_fn: EqFunc) -> Result<GcObj<'ob>> {
-    let val = list.conses().find(|x| match x {
-        Ok(obj) => eq_fn(obj.car(), elt),
-        Err(_) => true,
-    });
-    match val {
-        Some(Ok(elem)) => Ok(elem.into()),
-        Some(Err(e)) => Err(e),
-        None => Ok(nil()),
+fn member_of_list<'ob>(
+    elt: GcObj<'ob>,
+    list: Gc<List<'ob>>,
+    mut eq_fn: impl FnMut(GcObj<'ob>, GcObj<'ob>) -> Result<bool>,
+) -> Result<GcObj<'ob>> {
+    for tail in list.conses() {
+        let tail = tail?;
+        if eq_fn(tail.car(), elt)? {
+            return Ok(tail.into());
+        }
     }
+    Ok(nil())
 }
 
 #[defun]
 pub(crate) fn memq<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>) -> Result<GcObj<'ob>> {
-    member_of_list(elt, list, eq)
+    member_of_list(elt, list, |x, y| Ok(eq(x, y)))
 }
 
 #[defun]
 pub(crate) fn memql<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>) -> Result<GcObj<'ob>> {
-    member_of_list(elt, list, eql)
+    member_of_list(elt, list, |x, y| Ok(eql(x, y)))
 }
 
 #[defun]
-pub(crate) fn member<'ob>(elt: GcObj<'ob>, list: Gc<List<'ob>>) -> Result<GcObj<'ob>> {
-    member_of_list(elt, list, equal)
-}
-
-#[defun]
-pub(crate) fn defvaralias<'ob>(
-    new_alias: Symbol<'ob>,
-    _base_variable: Symbol,
-    _docstring: Option<&str>,
-) -> Symbol<'ob> {
-    // TODO: implement
-    new_alias
+pub(crate) fn member<'ob>(
+    elt: GcObj<'ob>,
+    list: Gc<List<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    member_of_list(elt, list, |x, y| equal(x, y, env, cx))
 }
 
 #[defun]
@@ -383,7 +418,7 @@ fn require<'ob>(
     };
     let file = file.into_obj(cx);
     root!(file, cx);
-    match crate::lread::load(file, None, None, cx, env) {
+    match crate::lread::load(file, None, None, None, None, cx, env) {
         Ok(_) => Ok(feature.get(cx)),
         Err(e) => match noerror {
             Some(()) => Ok(sym::NIL),
@@ -411,8 +446,8 @@ pub(crate) fn vconcat<'ob>(sequences: &[GcObj], cx: &'ob Context) -> Result<Gc<&
         match elt.untag() {
             // TODO: need to correctly handle unibyte strings (no unicode codepoints)
             Object::String(string) => {
-                for chr in string.chars() {
-                    concated.push((chr as i64).into());
+                for code in string.char_codes() {
+                    concated.push(i64::from(code).into());
                 }
             }
             Object::Cons(cons) => {
@@ -438,6 +473,7 @@ pub(crate) fn length(sequence: GcObj) -> Result<i64> {
         Object::Cons(x) => x.elements().len(),
         Object::Vec(x) => x.len(),
         Object::String(x) => x.len(),
+        Object::BoolVec(x) => x.len(),
         Object::NIL => 0,
         obj => bail!(TypeError::new(Type::Sequence, obj)),
     };
@@ -455,6 +491,30 @@ pub(crate) fn safe_length(sequence: GcObj) -> i64 {
     size.try_into().expect("conversion from usize to isize should never fail")
 }
 
+/// Return the length of OBJECT if it is a proper list, otherwise nil. A
+/// circular or dotted list is not a proper list.
+#[defun]
+fn proper_list_p(object: GcObj) -> GcObj {
+    let mut len = 0;
+    let mut slow = object;
+    let mut fast = object;
+    loop {
+        match fast.untag() {
+            Object::NIL => return len.into(),
+            Object::Cons(cons) => fast = cons.cdr(),
+            _ => return nil(),
+        }
+        len += 1;
+        if len % 2 == 0 {
+            let Object::Cons(cons) = slow.untag() else { unreachable!() };
+            slow = cons.cdr();
+            if slow.ptr_eq(fast) {
+                return nil();
+            }
+        }
+    }
+}
+
 #[defun]
 pub(crate) fn nth(n: usize, list: Gc<List>) -> Result<GcObj> {
     list.elements().nth(n).unwrap_or_else(|| Ok(nil()))
@@ -482,25 +542,112 @@ pub(crate) fn elt(sequence: GcObj, n: usize) -> Result<GcObj> {
 }
 
 defsym!(KW_TEST);
+defsym!(KW_SIZE);
+defsym!(KW_REHASH_SIZE);
+defsym!(KW_REHASH_THRESHOLD);
+defsym!(KW_PURECOPY);
+defsym!(KW_WEAKNESS);
+defsym!(KEY);
+defsym!(VALUE);
+defsym!(KEY_OR_VALUE);
+defsym!(KEY_AND_VALUE);
 
 #[defun]
 pub(crate) fn make_hash_table<'ob>(
     keyword_args: &[GcObj<'ob>],
+    env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
-    let kw_test_pos = keyword_args.iter().step_by(2).position(|&x| x == sym::KW_TEST);
-    if let Some(i) = kw_test_pos {
-        let Some(val) = keyword_args.get((i * 2) + 1) else {
-            bail!("Missing keyword value for :test")
-        };
-        if *val != sym::EQ && *val != sym::EQUAL {
-            // TODO: we are currently only using `equal', but eq should be okay
-            bail!("only `eq' and `equal' keywords support for make-hash-table :test. Found {val}");
+    let mut test = HashTest::Eql;
+    let mut weakness = None;
+    let mut size = 0;
+    for pair in keyword_args.chunks(2) {
+        let &[keyword, val] = pair else { bail!("Missing keyword value for {}", pair[0]) };
+        match keyword.untag() {
+            Object::Symbol(sym::KW_TEST) => {
+                let name: Symbol = val.try_into()?;
+                test = hash_table_test_named(name, env, cx)?;
+            }
+            Object::Symbol(sym::KW_WEAKNESS) => {
+                weakness = match val.untag() {
+                    Object::NIL => None,
+                    Object::Symbol(s) => match Weakness::from_symbol(s) {
+                        Some(weakness) => Some(weakness),
+                        None => bail!("Invalid hash table weakness: {val}"),
+                    },
+                    _ => bail!("Invalid hash table weakness: {val}"),
+                };
+            }
+            Object::Symbol(sym::KW_SIZE) => {
+                size = match val.untag() {
+                    Object::NIL => 0,
+                    Object::Int(x) if x >= 0 => x as usize,
+                    _ => bail!("Invalid hash table size: {val}"),
+                };
+            }
+            // Tables grow on their own, so these only need to be valid
+            Object::Symbol(sym::KW_REHASH_SIZE | sym::KW_REHASH_THRESHOLD) => {
+                ensure!(
+                    matches!(val.untag(), Object::NIL | Object::Int(_) | Object::Float(_)),
+                    "Invalid hash table {keyword}: {val}"
+                );
+            }
+            // There is no pure storage to copy the table to
+            Object::Symbol(sym::KW_PURECOPY) => {}
+            _ => bail!("Invalid argument list: {keyword}"),
         }
     }
-    // TODO, the rest of the keywords need to be supported here
-    let map = HashTable::with_hasher(std::hash::BuildHasherDefault::default());
-    Ok(cx.add(map))
+    let map = HashTable::with_capacity_and_hasher(size, std::hash::BuildHasherDefault::default());
+    let table = map.into_obj(cx);
+    table.untag().set_weakness(weakness);
+    table.untag().set_test(test);
+    Ok(table.into())
+}
+
+fn hash_table_test_named<'ob>(
+    name: Symbol<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<HashTest<'ob>> {
+    match name {
+        sym::EQ => Ok(HashTest::Eq),
+        sym::EQL => Ok(HashTest::Eql),
+        sym::EQUAL => Ok(HashTest::Equal),
+        _ => {
+            let definition = crate::data::get(name, sym::HASH_TABLE_TEST, env, cx);
+            let Object::Cons(cons) = definition.untag() else {
+                bail!("Invalid hash table test: {name}")
+            };
+            let test = cons.car().try_into()?;
+            let hash = cons.cdr().as_cons().car().try_into()?;
+            Ok(HashTest::Custom { name, test, hash })
+        }
+    }
+}
+
+#[defun]
+fn define_hash_table_test<'ob>(
+    name: Symbol,
+    test: Gc<Function>,
+    hash: Gc<Function>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let definition = list![test, hash; cx];
+    crate::data::put(name, sym::HASH_TABLE_TEST, definition, env, cx)
+}
+
+#[defun]
+fn hash_table_test(table: &LispHashTable) -> Symbol<'_> {
+    table.test().name()
+}
+
+#[defun]
+fn hash_table_weakness(table: &LispHashTable) -> GcObj<'_> {
+    match table.weakness() {
+        Some(weakness) => weakness.symbol().into(),
+        None => nil(),
+    }
 }
 
 #[defun]
@@ -508,45 +655,166 @@ pub(crate) fn hash_table_p(obj: GcObj) -> bool {
     matches!(obj.untag(), Object::HashTable(_))
 }
 
+#[defun]
+fn hash_table_count(table: &LispHashTable) -> usize {
+    table.count()
+}
+
+/// Find the entry for `key` in `table`. Returns the hash of `key` and the index
+/// of its entry if there is one. Tables with a custom test call back into Lisp
+/// to hash and compare the keys.
+fn hash_table_lookup(
+    key: &Rt<GcObj>,
+    table: &Rt<Gc<&'static LispHashTable>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<(u64, Option<usize>)> {
+    let test = table.bind(cx).untag().test();
+    let HashTest::Custom { test, hash, .. } = test else {
+        let table = table.bind(cx).untag();
+        return table.lookup(key.bind(cx)).or_else(|e| signal_circular_list(e, env, cx));
+    };
+    root!(test, cx);
+    root!(hash, cx);
+    if table.bind(cx).untag().take_stale() {
+        // The keys have been moved since they were hashed
+        let keys: Vec<_> = table.bind(cx).untag().borrow().keys().copied().collect();
+        root!(keys, cx);
+        let mut hashes = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            hashes.push(call_hash_function(hash, key, env, cx)?);
+        }
+        table.bind(cx).untag().rehash(&hashes)?;
+    }
+    let key_hash = call_hash_function(hash, key, env, cx)?;
+    let candidates = table.bind(cx).untag().candidates(key_hash);
+    root!(candidates, cx);
+    root!(args, Vec::new(), cx);
+    for candidate in candidates.iter() {
+        args.push(key);
+        args.push(candidate);
+        let is_match = !test.call(args, env, cx, None)?.nil();
+        args.clear();
+        if is_match {
+            let found = candidate.bind(cx);
+            let idx = table.bind(cx).untag().position_by(|k| k.ptr_eq(found));
+            return Ok((key_hash, idx));
+        }
+    }
+    Ok((key_hash, None))
+}
+
+fn call_hash_function(
+    function: &Rt<Gc<Function>>,
+    key: &Rt<GcObj>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<u64> {
+    root!(args, Vec::new(), cx);
+    args.push(key);
+    let hash = function.call(args, env, cx, None)?;
+    match hash.untag() {
+        Object::Int(x) => Ok(x as u64),
+        _ => Err(TypeError::new(Type::Int, hash).into()),
+    }
+}
+
 #[defun]
 pub(crate) fn puthash<'ob>(
-    key: GcObj<'ob>,
-    value: GcObj<'ob>,
-    table: &'ob LispHashTable,
+    key: &Rt<GcObj>,
+    value: &Rt<GcObj>,
+    table: &Rt<Gc<&'static LispHashTable>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>> {
-    // Don't attempt to take the mutable borrow flag if we can avoid it
-    let hashtable = table.try_borrow_shared_mut()?;
-    if let Some(val) = hashtable.get(&key) {
-        val.set(value);
-    } else {
-        // If the key is not already inserted, we will have to attempt a mutable
-        // borrow
-        drop(hashtable);
-        table.try_borrow_mut()?.insert(key, value);
+    let (hash, idx) = hash_table_lookup(key, table, env, cx)?;
+    let value = value.bind(cx);
+    let table = table.bind(cx).untag();
+    match idx {
+        Some(idx) => table.set_index(idx, value)?,
+        None => table.insert_new(hash, key.bind(cx), value)?,
     }
     Ok(value)
 }
 
 #[defun]
 pub(crate) fn gethash<'ob>(
-    key: GcObj<'ob>,
-    table: &'ob LispHashTable,
-    dflt: Option<GcObj<'ob>>,
-    cx: &'ob Context,
-) -> Option<GcObj<'ob>> {
-    match table.borrow().get(&key) {
-        Some(x) => Some(cx.bind(x.get())),
-        None => dflt,
+    key: &Rt<GcObj>,
+    table: &Rt<Gc<&'static LispHashTable>>,
+    dflt: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    match hash_table_lookup(key, table, env, cx)? {
+        (_, Some(idx)) => Ok(table.bind(cx).untag().value_at(idx)),
+        (_, None) => Ok(dflt.map_or_else(nil, |x| x.bind(cx))),
     }
 }
 
 #[defun]
-fn copy_sequence<'ob>(arg: GcObj<'ob>, cx: &'ob Context) -> Result<GcObj<'ob>> {
+fn remhash(
+    key: &Rt<GcObj>,
+    table: &Rt<Gc<&'static LispHashTable>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    if let (_, Some(idx)) = hash_table_lookup(key, table, env, cx)? {
+        table.bind(cx).untag().remove_index(idx)?;
+    }
+    Ok(false)
+}
+
+#[defun]
+fn clrhash(table: &LispHashTable) -> Result<&LispHashTable> {
+    table.clear()?;
+    Ok(table)
+}
+
+#[defun]
+fn copy_hash_table<'ob>(table: &'ob LispHashTable, cx: &'ob Context) -> Gc<&'ob LispHashTable> {
+    let copy = table.copy_entries().into_obj(cx);
+    copy.untag().set_weakness(table.weakness());
+    copy.untag().set_test(table.test());
+    copy
+}
+
+#[defun]
+fn hash_table_keys<'ob>(table: &'ob LispHashTable, cx: &'ob Context) -> GcObj<'ob> {
+    let keys: Vec<_> = table.borrow().keys().copied().collect();
+    slice_into_list(&keys, None, cx)
+}
+
+#[defun]
+fn sxhash_eq(obj: GcObj) -> i64 {
+    fixnum_hash(hash_eq(obj))
+}
+
+#[defun]
+fn sxhash_eql(obj: GcObj) -> i64 {
+    fixnum_hash(hash_eql(obj))
+}
+
+#[defun]
+fn sxhash_equal(obj: GcObj) -> i64 {
+    fixnum_hash(hash_equal(obj))
+}
+
+/// Fit a hash in the range of a fixnum.
+fn fixnum_hash(hash: u64) -> i64 {
+    (hash as i64) >> 8
+}
+
+#[defun]
+pub(crate) fn copy_sequence<'ob>(arg: GcObj<'ob>, cx: &'ob Context) -> Result<GcObj<'ob>> {
     match arg.untag() {
         Object::Vec(x) => {
             let copy: Vec<_> = x.iter().map(ObjCell::get).collect();
             Ok(cx.add(copy))
         }
+        Object::Record(x) => {
+            let copy: Vec<_> = x.iter().map(ObjCell::get).collect();
+            Ok(cx.add(RecordBuilder(copy)))
+        }
         Object::Cons(x) => {
             // TODO: remove this temp vector
             let mut elements = Vec::new();
@@ -564,6 +832,7 @@ fn copy_sequence<'ob>(arg: GcObj<'ob>, cx: &'ob Context) -> Result<GcObj<'ob>> {
             let string: Result<&str, _> = x.try_into();
             match string {
                 Ok(s) => Ok(cx.add(s)),
+                Err(_) if x.is_multibyte() => Ok(cx.add(RawMultibyte::from_codes(x.char_codes()))),
                 Err(_) => Ok(cx.add(x.to_vec())),
             }
         }
@@ -602,10 +871,200 @@ fn disable_debug() -> bool {
 
 #[cfg(test)]
 mod test {
-    use crate::core::{gc::RootSet, object::qtrue};
+    use crate::core::{error::ErrorType, gc::RootSet, object::qtrue};
 
     use super::*;
 
+    #[test]
+    fn test_eq_floats() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        // compact floats and heap floats keep the same identity
+        for value in [1.5, 0.0, -1.5, 0.1] {
+            let float = cx.add(value);
+            let other = cx.add(value);
+            assert!(eq(float, float));
+            assert!(!eq(float, other));
+            assert!(eql(float, other));
+        }
+        assert!(!eql(cx.add(0.0), cx.add(-0.0)));
+    }
+
+    #[test]
+    fn test_equal() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        // Each case is two objects and whether they are `equal`
+        let cases: &[(&str, &str, bool)] = &[
+            ("1", "1", true),
+            ("1", "1.0", false),
+            ("1.5", "1.5", true),
+            ("0.0", "-0.0", false),
+            ("-0.0", "-0.0", true),
+            ("foo", "foo", true),
+            ("foo", "bar", false),
+            ("\"foo\"", "\"foo\"", true),
+            ("\"foo\"", "\"Foo\"", false),
+            ("\"\"", "nil", false),
+            ("(1 2 (3 \"4\"))", "(1 2 (3 \"4\"))", true),
+            ("(1 2 . 3)", "(1 2 . 3)", true),
+            ("(1 2 . 3)", "(1 2 3)", false),
+            ("(1 2)", "(1 2 3)", false),
+            ("[1 (2) \"3\"]", "[1 (2) \"3\"]", true),
+            ("[1 2]", "[1 2 3]", false),
+            ("[1 2]", "(1 2)", false),
+            ("#s(foo 1 [2])", "#s(foo 1 [2])", true),
+            ("#s(foo 1)", "[foo 1]", false),
+            ("#s(hash-table data (a 1))", "#s(hash-table data (a 1))", false),
+        ];
+        let mut objects = Vec::new();
+        for &(lhs, rhs, expect) in cases {
+            let x = crate::reader::read(lhs, cx).unwrap().0;
+            let y = crate::reader::read(rhs, cx).unwrap().0;
+            assert_eq!(internal_equal(x, y, false).ok(), Some(expect), "(equal {lhs} {rhs})");
+            objects.push(x);
+            objects.push(y);
+        }
+        let nan = cx.add(f64::NAN);
+        assert!(internal_equal(nan, cx.add(f64::NAN), false).unwrap());
+        assert!(!internal_equal(nan, cx.add(-f64::NAN), false).unwrap());
+        let multibyte = cx.add("abc");
+        assert!(internal_equal(multibyte, cx.add(b"abc".to_vec()), false).unwrap());
+        let multibyte = cx.add("\u{e9}");
+        assert!(!internal_equal(multibyte, cx.add("\u{e9}".as_bytes().to_vec()), false).unwrap());
+        objects.extend([nan, multibyte]);
+
+        // equal is an equivalence relation that agrees with sxhash-equal
+        for &x in &objects {
+            assert!(internal_equal(x, x, false).unwrap(), "{x} is not equal to itself");
+            assert!(internal_equal(x, x, true).unwrap());
+            for &y in &objects {
+                let equal = internal_equal(x, y, false).unwrap();
+                assert_eq!(equal, internal_equal(y, x, false).unwrap(), "{x} {y}");
+                assert_eq!(equal, internal_equal(x, y, true).unwrap(), "{x} {y}");
+                if equal {
+                    assert_eq!(sxhash_equal(x), sxhash_equal(y), "{x} {y}");
+                    for &z in &objects {
+                        let transitive = internal_equal(y, z, false).unwrap();
+                        assert_eq!(transitive, internal_equal(x, z, false).unwrap());
+                    }
+                }
+            }
+        }
+
+        let list1 = list![1, 2; cx];
+        let list2 = list![1, 2; cx];
+        list1.as_cons().cdr().as_cons().set_cdr(list1).unwrap();
+        list2.as_cons().cdr().as_cons().set_cdr(list2).unwrap();
+        assert!(equal(list1, list1, env, cx).unwrap());
+        let err = equal(list1, list2, env, cx).unwrap_err();
+        let Ok(EvalError { error: ErrorType::Signal(id), .. }) = err.downcast::<EvalError>() else {
+            panic!("expected a signal")
+        };
+        let (symbol, data) = env.get_exception(id).unwrap();
+        assert_eq!(*symbol, sym::CIRCULAR_LIST);
+        assert!(data.bind(cx).as_cons().car().ptr_eq(list1));
+    }
+
+    #[test]
+    fn test_equal_table_circular_key() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let list1 = list![1, 2; cx];
+        let list2 = list![1, 2; cx];
+        list1.as_cons().cdr().as_cons().set_cdr(list1).unwrap();
+        list2.as_cons().cdr().as_cons().set_cdr(list2).unwrap();
+        let table = HashTable::default().into_obj(cx);
+        table.untag().set_test(HashTest::Equal);
+        let value = cx.add(1);
+        root!(list1, cx);
+        root!(list2, cx);
+        root!(table, cx);
+        root!(value, cx);
+        puthash(list1, value, table, env, cx).unwrap();
+        assert_eq!(gethash(list1, table, None, env, cx).unwrap(), 1);
+        // the lists hash the same, but comparing them never finishes
+        let err = gethash(list2, table, None, env, cx).unwrap_err();
+        let Ok(EvalError { error: ErrorType::Signal(id), .. }) = err.downcast::<EvalError>() else {
+            panic!("expected a signal")
+        };
+        let (symbol, _) = env.get_exception(id).unwrap();
+        assert_eq!(*symbol, sym::CIRCULAR_LIST);
+        assert!(puthash(list2, value, table, env, cx).is_err());
+        assert!(remhash(list2, table, env, cx).is_err());
+        assert_eq!(table.bind(cx).untag().count(), 1);
+    }
+
+    /// The shape of an object for the property tests. The same shape can be
+    /// built more than once to get objects that are `equal` but not `eq`.
+    #[derive(Debug, Clone)]
+    enum Shape {
+        Int(i64),
+        Float(f64),
+        String(String),
+        Symbol(&'static str),
+        List(Vec<Shape>),
+        Vector(Vec<Shape>),
+    }
+
+    impl Shape {
+        fn build<'ob>(&self, cx: &'ob Context) -> GcObj<'ob> {
+            match self {
+                Shape::Int(x) => cx.add(*x),
+                Shape::Float(x) => cx.add(*x),
+                Shape::String(x) => cx.add(x.as_str()),
+                Shape::Symbol(x) => crate::core::env::intern(x, cx).into(),
+                Shape::List(elems) => {
+                    let elems: Vec<_> = elems.iter().map(|x| x.build(cx)).collect();
+                    crate::alloc::list(&elems, cx)
+                }
+                Shape::Vector(elems) => {
+                    cx.add(elems.iter().map(|x| x.build(cx)).collect::<Vec<_>>())
+                }
+            }
+        }
+    }
+
+    fn shape() -> impl proptest::strategy::Strategy<Value = Shape> {
+        use proptest::prelude::*;
+        // Mostly small values, so that unrelated objects are often equal
+        let leaf = prop_oneof![
+            (-2_i64..3).prop_map(Shape::Int),
+            any::<i32>().prop_map(|x| Shape::Int(x.into())),
+            prop::sample::select(vec![0.0, -0.0, 1.5, f64::NAN]).prop_map(Shape::Float),
+            any::<f64>().prop_map(Shape::Float),
+            "[ab\u{e9}]{0,2}".prop_map(Shape::String),
+            prop::sample::select(vec!["a", "b", "nil"]).prop_map(Shape::Symbol),
+        ];
+        leaf.prop_recursive(4, 24, 3, |elem| {
+            prop_oneof![
+                prop::collection::vec(elem.clone(), 0..3).prop_map(Shape::List),
+                prop::collection::vec(elem, 0..3).prop_map(Shape::Vector),
+            ]
+        })
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_equal_properties(x in shape(), y in shape()) {
+            let roots = &RootSet::default();
+            let cx = &Context::new(roots);
+            let (a, b, copy) = (x.build(cx), y.build(cx), x.build(cx));
+            // reflexive, including for a separate copy
+            proptest::prop_assert!(internal_equal(a, a, false).unwrap());
+            proptest::prop_assert!(internal_equal(a, copy, false).unwrap());
+            proptest::prop_assert_eq!(sxhash_equal(a), sxhash_equal(copy));
+            // symmetric and consistent with sxhash-equal
+            let equal = internal_equal(a, b, false).unwrap();
+            proptest::prop_assert_eq!(equal, internal_equal(b, a, false).unwrap());
+            if equal {
+                proptest::prop_assert_eq!(sxhash_equal(a), sxhash_equal(b));
+            }
+        }
+    }
+
     #[test]
     fn test_delq() {
         let roots = &RootSet::default();
@@ -631,6 +1090,24 @@ mod test {
         assert_eq!(res.untag().car(), 2);
     }
 
+    #[test]
+    fn test_proper_list_p() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(proper_list_p(list![1, 2, 3; cx]), 3);
+        assert_eq!(proper_list_p(nil()), 0);
+        assert_eq!(proper_list_p(cons!(1, 2; cx)), nil());
+        let circular = list![1, 2, 3; cx];
+        let List::Cons(tail) = nthcdr(2, circular.try_into().unwrap()).unwrap().untag() else {
+            unreachable!()
+        };
+        tail.set_cdr(circular).unwrap();
+        assert_eq!(proper_list_p(circular), nil());
+        let plist = list![1, 2, 3, 4; cx];
+        assert_eq!(plist_member(plist.try_into().unwrap(), 3.into()).unwrap(), list![3, 4; cx]);
+        assert_eq!(plist_member(plist.try_into().unwrap(), 2.into()).unwrap(), nil());
+    }
+
     #[test]
     fn test_reverse() {
         let roots = &RootSet::default();
@@ -721,6 +1198,164 @@ mod test {
         maphash(func, table, env, cx).unwrap();
     }
 
+    #[test]
+    fn test_hash_table_after_gc() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let table = make_hash_table(&[], env, cx).unwrap();
+        let key = cx.add("key");
+        let Object::HashTable(inner) = table.untag() else { unreachable!() };
+        inner.insert(key, cx.add(-1.5)).unwrap();
+        inner.insert(cx.add(2), cx.add("value")).unwrap();
+        root!(table, cx);
+        root!(key, cx);
+        // keys are hashed by address, so the table has to be rebuilt after the
+        // objects are moved
+        cx.garbage_collect(true);
+        let Object::HashTable(inner) = table.bind(cx).untag() else { unreachable!() };
+        assert_eq!(inner.get(key.bind(cx)).unwrap(), Some(cx.add(-1.5)));
+        assert_eq!(inner.get(cx.add(2)).unwrap(), Some(cx.add("value")));
+        assert_eq!(inner.get(cx.add("key")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_weak_hash_table() {
+        fn weak_table<'ob>(
+            weakness: Symbol,
+            env: &Rt<Env>,
+            cx: &'ob Context,
+        ) -> (&'ob LispHashTable, GcObj<'ob>) {
+            let args = [sym::KW_WEAKNESS.into(), weakness.into()];
+            let table = make_hash_table(&args, env, cx).unwrap();
+            let Object::HashTable(inner) = table.untag() else { unreachable!() };
+            let live = cx.add("live");
+            let dead = cx.add("dead");
+            // The value refers to the key, but that should not keep the key
+            // alive in a key weak table.
+            inner.insert(dead, cons!(dead; cx)).unwrap();
+            inner.insert(live, cx.add("value")).unwrap();
+            inner.insert(cx.add("value"), live).unwrap();
+            inner.insert(1.into(), 2.into()).unwrap();
+            (inner, live)
+        }
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let count = |table: &Rt<&LispHashTable>, cx: &Context| table.bind(cx).count();
+
+        let (table, live) = weak_table(sym::KEY, env, cx);
+        root!(table, cx);
+        root!(live, cx);
+        cx.garbage_collect(true);
+        assert_eq!(count(table, cx), 2);
+        assert!(table.bind(cx).get(live.bind(cx)).unwrap().is_some());
+
+        let (table, _live) = weak_table(sym::VALUE, env, cx);
+        root!(table, cx);
+        root!(_live, cx);
+        cx.garbage_collect(true);
+        assert_eq!(count(table, cx), 2);
+
+        let (table, _live) = weak_table(sym::KEY_OR_VALUE, env, cx);
+        root!(table, cx);
+        root!(_live, cx);
+        cx.garbage_collect(true);
+        assert_eq!(count(table, cx), 3);
+
+        let (table, _live) = weak_table(sym::KEY_AND_VALUE, env, cx);
+        root!(table, cx);
+        root!(_live, cx);
+        cx.garbage_collect(true);
+        assert_eq!(count(table, cx), 1);
+        assert_eq!(hash_table_weakness(table.bind(cx)), sym::KEY_AND_VALUE);
+    }
+
+    #[test]
+    fn test_weak_hash_table_buffers() {
+        use crate::buffer::{get_buffer_create, kill_buffer};
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let args = [sym::KW_WEAKNESS.into(), sym::KEY.into()];
+        let table = make_hash_table(&args, env, cx).unwrap();
+        let Object::HashTable(table) = table.untag() else { unreachable!() };
+        let live = get_buffer_create(cx.add("weak-table-live"), nil(), cx).unwrap();
+        let killed = get_buffer_create(cx.add("weak-table-killed"), nil(), cx).unwrap();
+        table.insert(live, 1.into()).unwrap();
+        table.insert(killed, 2.into()).unwrap();
+        root!(table, cx);
+        root!(live, cx);
+        assert!(kill_buffer(Some(killed), env, cx).unwrap());
+        // Buffers are never freed, but a killed buffer is unreachable as far
+        // as weak tables are concerned.
+        cx.garbage_collect(true);
+        assert_eq!(table.bind(cx).count(), 1);
+        assert_eq!(table.bind(cx).get(live.bind(cx)).unwrap(), Some(1.into()));
+    }
+
+    #[test]
+    fn test_hash_table_tests() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let table = |test: Symbol, cx| {
+            let table = make_hash_table(&[sym::KW_TEST.into(), test.into()], env, cx).unwrap();
+            let Object::HashTable(table) = table.untag() else { unreachable!() };
+            table.insert(cx.add("key"), 1.into()).unwrap();
+            table.insert(cx.add(-1.5), 2.into()).unwrap();
+            table.insert(list![1, "two"; cx], 3.into()).unwrap();
+            table
+        };
+        let eq = table(sym::EQ, cx);
+        assert_eq!(eq.get(cx.add("key")).unwrap(), None);
+        assert_eq!(eq.get(cx.add(-1.5)).unwrap(), None);
+        assert_eq!(hash_table_test(eq), sym::EQ);
+
+        let eql = table(sym::EQL, cx);
+        assert_eq!(eql.get(cx.add("key")).unwrap(), None);
+        assert_eq!(eql.get(cx.add(-1.5)).unwrap(), Some(2.into()));
+        assert_eq!(eql.get(list![1, "two"; cx]).unwrap(), None);
+
+        let equal = table(sym::EQUAL, cx);
+        assert_eq!(equal.get(cx.add("key")).unwrap(), Some(1.into()));
+        assert_eq!(equal.get(cx.add(-1.5)).unwrap(), Some(2.into()));
+        assert_eq!(equal.get(list![1, "two"; cx]).unwrap(), Some(3.into()));
+        assert_eq!(sxhash_equal(cx.add("key")), sxhash_equal(cx.add("key")));
+        assert_eq!(sxhash_eql(cx.add(-1.5)), sxhash_eql(cx.add(-1.5)));
+
+        let copy = copy_hash_table(equal, cx);
+        let (_, idx) = equal.lookup(cx.add("key")).unwrap();
+        equal.remove_index(idx.unwrap()).unwrap();
+        assert_eq!(hash_table_count(equal), 2);
+        assert_eq!(hash_table_count(copy.untag()), 3);
+        assert_eq!(copy.untag().get(cx.add("key")).unwrap(), Some(1.into()));
+        clrhash(equal).unwrap();
+        assert_eq!(hash_table_count(equal), 0);
+    }
+
+    #[test]
+    fn test_custom_hash_table_test() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let form = "(progn
+                      (define-hash-table-test 'same-length
+                        #'(lambda (x y) (= (length x) (length y)))
+                        #'(lambda (x) (sxhash-equal (length x))))
+                      (let ((table (make-hash-table :test 'same-length :size 10)))
+                        (puthash \"foo\" 1 table)
+                        (puthash \"bar\" 2 table)
+                        (puthash \"quux\" 3 table)
+                        (remhash \"abcd\" table)
+                        (list (hash-table-test table) (hash-table-count table)
+                              (gethash \"baz\" table) (hash-table-keys table))))";
+        let obj = crate::reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let result = crate::interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(result.to_string(), "(same-length 1 2 (\"foo\"))");
+    }
+
     #[test]
     fn test_copy_alist() {
         let roots = &RootSet::default();
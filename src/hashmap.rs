@@ -1,5 +1,8 @@
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
+use rustc_hash::FxHasher;
+use std::hash::BuildHasherDefault;
 
 pub(crate) type HashMap<K, V> = FxHashMap<K, V>;
 pub(crate) type HashSet<K> = FxHashSet<K>;
+pub(crate) type IndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<FxHasher>>;
@@ -3,7 +3,7 @@ use crate::core::{
     env::{sym, Env, Symbol},
     error::{ArgError, ErrorType, EvalError, EvalResult, Type, TypeError},
     gc::{Context, Rt},
-    object::{nil, qtrue, Function, Gc, GcObj, List, Object},
+    object::{nil, qtrue, Function, Gc, GcObj, List, Object, TagType},
 };
 use crate::{root, rooted_iter};
 use anyhow::Context as _;
@@ -24,7 +24,7 @@ pub(crate) fn eval<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<GcObj<'ob>, anyhow::Error> {
-    cx.garbage_collect(false);
+    crate::alloc::maybe_garbage_collect(env, cx);
     root!(vars, Vec::new(), cx);
     let mut interpreter = Interpreter { vars, env };
     interpreter.eval_form(form, cx).map_err(Into::into)
@@ -32,11 +32,18 @@ pub(crate) fn eval<'ob>(
 
 impl Interpreter<'_> {
     fn eval_form<'ob>(&mut self, rt: &Rt<GcObj>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        cx.stress_collect();
         match rt.get(cx) {
             Object::Symbol(sym) => self.var_ref(sym, cx),
             Object::Cons(_) => {
                 let x = rt.try_into().unwrap();
-                self.eval_sexp(x, cx)
+                match self.eval_sexp(x, cx) {
+                    Ok(value) => Ok(rebind!(value, cx)),
+                    Err(e) => {
+                        let form = rt.bind(cx);
+                        Err(e.add_location(|| crate::lread::source_position(form)))
+                    }
+                }
             }
             _ => Ok(rt.bind(cx)),
         }
@@ -64,7 +71,8 @@ impl Interpreter<'_> {
                 sym::PROG1 => self.eval_progx(forms, 1, cx),
                 sym::PROG2 => self.eval_progx(forms, 2, cx),
                 sym::SETQ => self.setq(forms, cx),
-                sym::DEFVAR | sym::DEFCONST => self.defvar(forms, cx),
+                sym::DEFVAR => self.defvar(forms, false, cx),
+                sym::DEFCONST => self.defvar(forms, true, cx),
                 sym::FUNCTION => self.eval_function(forms.bind(cx), cx),
                 sym::INTERACTIVE => Ok(nil()), // TODO: implement
                 sym::CATCH => self.catch(forms, cx),
@@ -121,11 +129,16 @@ impl Interpreter<'_> {
         }
     }
 
-    fn defvar<'ob>(&mut self, obj: &Rt<GcObj>, cx: &'ob mut Context) -> EvalResult<'ob> {
+    fn defvar<'ob>(
+        &mut self,
+        obj: &Rt<GcObj>,
+        constant: bool,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
         rooted_iter!(forms, obj, cx);
         // (defvar x ...)                 // (defvar)
         let Some(sym) = forms.next() else { bail_err!(ArgError::new(1, 0, "defvar")) };
-        let name: Symbol = sym.bind(cx).try_into()?;
+        let name: Gc<Symbol> = sym.bind(cx).try_into()?;
         root!(name, cx);
         let value = match forms.next() {
             // (defvar x y)
@@ -133,7 +146,14 @@ impl Interpreter<'_> {
             // (defvar x)
             None => nil(),
         };
-        self.env.defvar(name.bind(cx), value)?;
+        root!(value, cx);
+        crate::data::notify_variable_watchers(name, value, sym::SET, self.env, cx)?;
+        let (name, value) = (name.bind(cx).untag(), value.bind(cx));
+        if constant {
+            self.env.defconst(name, value, cx)?;
+        } else {
+            self.env.defvar(name, value, cx)?;
+        }
         Ok(value)
     }
 
@@ -316,11 +336,12 @@ impl Interpreter<'_> {
         while let Some((var, val)) = Self::pairs(&mut forms, cx) {
             match (var.untag(), val) {
                 (Object::Symbol(var), Some(val)) => {
-                    root!(var, cx);
+                    root!(var, move(var.tag()), cx);
                     root!(val, cx);
                     let val = rebind!(self.eval_form(val, cx)?);
-                    self.var_set(var.bind(cx), val, cx)?;
-                    last_value.set(val);
+                    root!(val, cx);
+                    self.var_set(var, val, cx)?;
+                    last_value.set(val.bind(cx));
                 }
                 (_, Some(_)) => bail_err!(TypeError::new(Type::Symbol, var)),
                 (_, None) => bail_err!(ArgError::new(arg_cnt, arg_cnt + 1, "setq")),
@@ -350,7 +371,7 @@ impl Interpreter<'_> {
             let mut iter = self.vars.iter().rev();
             match iter.find_map(|cons| (cons.car(cx) == sym).then(|| cons.cdr(cx))) {
                 Some(value) => Ok(value),
-                None => match self.env.vars.get(sym) {
+                None => match self.env.var(sym) {
                     Some(v) => Ok(v.bind(cx)),
                     None => Err(error!("Void variable: {sym}")),
                 },
@@ -358,14 +379,24 @@ impl Interpreter<'_> {
         }
     }
 
-    fn var_set(&mut self, name: Symbol, new_value: GcObj, cx: &Context) -> AnyResult<()> {
+    fn var_set(
+        &mut self,
+        name: &Rt<Gc<Symbol>>,
+        new_value: &Rt<GcObj>,
+        cx: &mut Context,
+    ) -> Result<(), EvalError> {
+        let sym = name.bind(cx).untag();
         let mut iter = self.vars.iter().rev();
-        match iter.find(|cons| (cons.car(cx) == name)) {
+        match iter.find(|cons| (cons.car(cx) == sym)) {
             Some(value) => {
-                value.bind(cx).set_cdr(new_value).expect("variables should never be immutable");
+                let value = value.bind(cx);
+                value.set_cdr(new_value.bind(cx)).expect("variables should never be immutable");
                 Ok(())
             }
-            None => self.env.set_var(name, new_value),
+            None => {
+                crate::data::notify_variable_watchers(name, new_value, sym::SET, self.env, cx)?;
+                Ok(self.env.set_var(name.bind(cx).untag(), new_value.bind(cx), cx)?)
+            }
         }
     }
 
@@ -395,8 +426,9 @@ impl Interpreter<'_> {
         let obj = rebind!(self.implicit_progn(iter, cx)?);
         // Remove old bindings
         self.vars.truncate(prev_len);
-        self.env.unbind(varbind_count, cx);
-        Ok(obj)
+        root!(obj, cx);
+        crate::data::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
     }
 
     fn let_bind_serial(&mut self, form: &Rt<GcObj>, cx: &mut Context) -> Result<u16, EvalError> {
@@ -408,13 +440,17 @@ impl Interpreter<'_> {
                 Object::Cons(_) => {
                     let cons = binding.as_cons();
                     let val = rebind!(self.let_bind_value(cons, cx)?);
-                    let var: Symbol =
+                    root!(val, cx);
+                    let var: Gc<Symbol> =
                         cons.get(cx).car().try_into().context("let variable must be a symbol")?;
-                    varbind_count += self.create_let_binding(var, val, cx);
+                    root!(var, cx);
+                    varbind_count += self.create_let_binding(var, val, cx)?;
                 }
                 // (let (x))
                 Object::Symbol(sym) => {
-                    varbind_count += self.create_let_binding(sym, nil(), cx);
+                    root!(sym, move(sym.tag()), cx);
+                    root!(val, nil(), cx);
+                    varbind_count += self.create_let_binding(sym, val, cx)?;
                 }
                 // (let (1))
                 x => bail_err!(TypeError::new(Type::Cons, x)),
@@ -432,34 +468,45 @@ impl Interpreter<'_> {
                 Object::Cons(_) => {
                     let cons = binding.as_cons();
                     let var = rebind!(self.let_bind_value(cons, cx)?);
-                    let sym: Symbol =
+                    let sym: Gc<Symbol> =
                         cons.get(cx).car().try_into().context("let variable must be a symbol")?;
                     let_bindings.push((sym, var));
                 }
                 // (let (x))
                 Object::Symbol(sym) => {
-                    let_bindings.push((sym, nil()));
+                    let_bindings.push((sym.tag(), nil()));
                 }
                 // (let (1))
                 x => bail_err!(TypeError::new(Type::Cons, x)),
             }
         }
         let mut sum = 0;
-        for (var, val) in let_bindings.bind_ref(cx) {
-            sum += self.create_let_binding(*var, *val, cx);
+        for i in 0..let_bindings.len() {
+            let (var, val) = &*let_bindings[i];
+            sum += self.create_let_binding(var, val, cx)?;
         }
         Ok(sum)
     }
 
-    fn create_let_binding(&mut self, var: Symbol, val: GcObj, cx: &Context) -> u16 {
-        if var.is_special() {
-            self.env.varbind(var, val, cx);
+    fn create_let_binding(
+        &mut self,
+        var: &Rt<Gc<Symbol>>,
+        val: &Rt<GcObj>,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let sym = var.bind(cx).untag();
+        if sym.is_const() {
+            return Err(self.env.setting_constant(sym, cx));
+        }
+        if sym.is_special() {
+            crate::data::notify_variable_watchers(var, val, sym::LET, self.env, cx)?;
+            self.env.varbind(var.bind(cx).untag(), val.bind(cx), cx)?;
             // return 1 if the variable is bound
-            1
+            Ok(1)
         } else {
-            let cons = cons!(var, val; cx).as_cons();
+            let cons = cons!(sym, val.bind(cx); cx).as_cons();
             self.vars.push(cons);
-            0
+            Ok(0)
         }
     }
 
@@ -523,36 +570,27 @@ impl Interpreter<'_> {
         if matches!(err.error, ErrorType::Throw(_)) {
             return Err(err);
         }
+        let error = if let ErrorType::Signal(id) = err.error {
+            let Some((sym, data)) = self.env.get_exception(id) else {
+                unreachable!("Exception not found")
+            };
+            cons!(sym, data; cx)
+        } else {
+            // TODO: Need to remove the anyhow branch once
+            // full errors are implemented
+            cons!(sym::ERROR, format!("{err}"); cx)
+        };
+        root!(error, cx);
         while let Some(handler) = forms.next() {
             match handler.get(cx) {
                 Object::Cons(cons) => {
                     // Check that conditions match
-                    let condition = cons.car();
-                    match condition.untag() {
-                        Object::Symbol(sym::ERROR) => {}
-                        Object::Cons(cons) => {
-                            for x in cons.elements() {
-                                let x = x?;
-                                // TODO: Handle different error symbols
-                                if x != sym::DEBUG && x != sym::ERROR {
-                                    bail_err!("non-error conditions {x} not yet supported")
-                                }
-                            }
-                        }
-                        _ => bail_err!("Invalid condition handler: {condition}"),
+                    let error_symbol = error.bind(cx).as_cons().car();
+                    if !crate::eval::handles_error(cons.car(), error_symbol, self.env, cx)? {
+                        continue;
                     }
                     // Call handlers with error
-                    let error = if let ErrorType::Signal(id) = err.error {
-                        let Some((sym, data)) = self.env.get_exception(id) else {
-                            unreachable!("Exception not found")
-                        };
-                        cons!(sym, data; cx)
-                    } else {
-                        // TODO: Need to remove the anyhow branch once
-                        // full errors are implemented
-                        cons!(sym::ERROR, format!("{err}"); cx)
-                    };
-                    let binding = list!(var, error; cx).as_cons();
+                    let binding = cons!(var, error.bind(cx); cx).as_cons();
                     self.vars.push(binding);
                     let list: Gc<List> = match cons.cdr().try_into() {
                         Ok(x) => x,
@@ -628,7 +666,7 @@ fn call_closure<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> EvalResult<'ob> {
-    cx.garbage_collect(false);
+    crate::alloc::maybe_garbage_collect(env, cx);
     let closure: &Cons = closure.get(cx);
     match closure.car().untag() {
         Object::Symbol(sym::CLOSURE) => {
@@ -722,7 +760,9 @@ fn bind_args<'a>(
     Ok(())
 }
 
-fn parse_arg_list(bindings: GcObj) -> AnyResult<(Vec<Symbol>, Vec<Symbol>, Option<Symbol>)> {
+pub(crate) fn parse_arg_list(
+    bindings: GcObj,
+) -> AnyResult<(Vec<Symbol>, Vec<Symbol>, Option<Symbol>)> {
     let mut required = Vec::new();
     let mut optional = Vec::new();
     let mut rest = None;
@@ -773,6 +813,26 @@ mod test {
         assert!(eval(obj, None, env, cx).is_err());
     }
 
+    #[test]
+    fn gc_stress() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let expect = list![20, 19, 28.5, 0; cx];
+        root!(expect, cx);
+        // Garbage is collected on every closure call during tests, so all live
+        // objects are moved on each iteration of the loop.
+        check_interpreter(
+            "(let ((i 0) (acc nil) (table (make-hash-table :test 'eq)) (key \"key\"))
+               (while (< i 20)
+                 (setq acc (funcall #'(lambda (x a) (cons (list x (* x 1.5) (vector x \"str\")) a)) i acc))
+                 (puthash key (car (car acc)) table)
+                 (setq i (1+ i)))
+               (list (length acc) (gethash key table) (car (cdr (car acc))) (car (car (nreverse acc)))))",
+            expect,
+            cx,
+        );
+    }
+
     #[test]
     fn basic() {
         let roots = &RootSet::default();
@@ -986,11 +1046,128 @@ mod test {
         check_interpreter("(condition-case nil (if) (error 7 9 11))", 11, cx);
         check_interpreter("(condition-case nil (if) (error . 7))", false, cx);
         check_interpreter("(condition-case nil (if) ((debug error) 7))", 7, cx);
+        root!(env, Env::default(), cx);
+        crate::eval::init_errors(env, cx);
+        check_interpreter(
+            "(condition-case nil (signal 'wrong-type-argument nil) (arith-error 1) (wrong-type-argument 2))",
+            2,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case nil (signal 'overflow-error nil) (arith-error 3))",
+            3,
+            cx,
+        );
+        check_interpreter("(condition-case nil (signal 'overflow-error nil) (t 4))", 4, cx);
+        check_interpreter(
+            "(condition-case err (signal 'overflow-error '(1)) (error (equal err '(overflow-error 1))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case nil (condition-case nil (signal 'void-variable nil) (arith-error 1)) (error 5))",
+            5,
+            cx,
+        );
+        check_interpreter(
+            "(progn (put 'test-condition-case-child 'error-conditions '(test-condition-case-child test-condition-case-parent error))
+                    (condition-case nil (signal 'test-condition-case-child nil) ((arith-error test-condition-case-parent) 6)))",
+            6,
+            cx,
+        );
+        check_error("(condition-case nil (signal 'void-variable nil) (arith-error 1))", cx);
         check_error("(condition-case nil (if))", cx);
         check_error("(condition-case nil (if) nil)", cx);
         check_error("(condition-case nil (if) 5 (error 7))", cx);
     }
 
+    #[test]
+    fn test_variable_alias() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defvar alias-test-base 1) (defvaralias 'alias-test-new 'alias-test-base) (setq alias-test-new 2) alias-test-base)",
+            2,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar alias-test-base 1) (let ((alias-test-new 3)) alias-test-base))",
+            3,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar alias-test-base 4) (symbol-value 'alias-test-new))",
+            4,
+            cx,
+        );
+        check_interpreter("(eq (indirect-variable 'alias-test-new) 'alias-test-base)", true, cx);
+        check_interpreter(
+            "(condition-case nil (defvaralias 'alias-test-base 'alias-test-new) (error 5))",
+            5,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_variable_watchers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defvar watch-test-log nil)
+                    (defvar watch-test-var 1)
+                    (add-variable-watcher 'watch-test-var #'(lambda (sym val op where) (setq watch-test-log (cons (list op val) watch-test-log))))
+                    (setq watch-test-var 2)
+                    (let ((watch-test-var 3)) nil)
+                    (makunbound 'watch-test-var)
+                    (equal watch-test-log '((makunbound nil) (unlet 2) (let 3) (set 2))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar watch-test-log nil)
+                    (add-variable-watcher 'watch-test-alias #'(lambda (sym val op where) (setq watch-test-log (list sym val op))))
+                    (defvaralias 'watch-test-alias 'watch-test-var)
+                    (equal watch-test-log '(watch-test-alias watch-test-var defvaralias)))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar watch-test-log nil)
+                    (defvar watch-test-base 1)
+                    (defvaralias 'watch-test-other 'watch-test-base)
+                    (add-variable-watcher 'watch-test-other #'(lambda (sym val op where) (setq watch-test-log (list sym val op))))
+                    (setq watch-test-other 5)
+                    (equal watch-test-log '(watch-test-base 5 set)))",
+            true,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_constant_variables() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        crate::eval::init_errors(env, cx);
+        check_interpreter(
+            "(progn (defconst const-test-var 1) (condition-case nil (setq const-test-var 2) (setting-constant const-test-var)))",
+            1,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case nil (let ((const-test-var 2)) 3) (setting-constant 4))",
+            4,
+            cx,
+        );
+        check_interpreter("(condition-case nil (setq :test-key 1) (setting-constant 5))", 5, cx);
+        check_interpreter("(condition-case nil (set :test-key 1) (setting-constant 6))", 6, cx);
+        check_interpreter(
+            "(condition-case nil (let ((:test-key 1)) 2) (setting-constant 7))",
+            7,
+            cx,
+        );
+    }
+
     #[test]
     fn test_throw_catch() {
         let roots = &RootSet::default();
@@ -1,16 +1,22 @@
+use crate::core::cons::Cons;
 use crate::core::env::Symbol;
 use crate::core::env::{sym, Env};
 use crate::core::error::{Type, TypeError};
 use crate::core::gc::Context;
 use crate::core::gc::Rt;
-use crate::core::object::{nil, Gc, GcObj, LispString, Object, WithLifetime};
-use crate::reader;
+use crate::core::object::{
+    nil, Function, Gc, GcObj, IntoObject, LispBuffer, LispObarray, LispString, Object, WithLifetime,
+};
+use crate::reader::{self, CharSource, ObjectTokens, ReadSettings};
 use crate::{interpreter, root};
 use anyhow::{anyhow, Context as _};
 use anyhow::{bail, ensure, Result};
 use fn_macros::defun;
+use std::borrow::Cow;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 fn check_lower_bounds(idx: Option<i64>, len: usize) -> Result<usize> {
     let len = len as i64;
@@ -39,13 +45,15 @@ pub(crate) fn read_from_string<'ob>(
     string: &str,
     start: Option<i64>,
     end: Option<i64>,
+    env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
     let len = string.len();
     let start = check_lower_bounds(start, len)?;
     let end = check_upper_bounds(end, len)?;
 
-    let (obj, new_pos) = match reader::read(&string[start..end], cx) {
+    let settings = ReadSettings::from_env(env, cx);
+    let (obj, new_pos) = match reader::read_with(&string[start..end], settings, None, cx) {
         Ok((obj, pos)) => (obj, pos),
         Err(mut e) => {
             e.update_pos(start);
@@ -55,10 +63,282 @@ pub(crate) fn read_from_string<'ob>(
     Ok(cons!(obj, new_pos as i64; cx))
 }
 
+/// Read one Lisp expression from STREAM. STREAM can be a buffer, where
+/// reading starts at point and moves it past the expression, a string, a
+/// function, or t for standard input. If STREAM is nil the value of
+/// `standard-input` is used.
+#[defun]
+pub(crate) fn read<'ob>(
+    stream: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    let mut stream = stream.map_or_else(nil, |x| x.bind(cx));
+    if stream.nil() {
+        stream = env.vars.get(sym::STANDARD_INPUT).map_or_else(nil, |x| x.bind(cx));
+    }
+    match stream.untag() {
+        Object::String(string) => {
+            let settings = ReadSettings::from_env(env, cx);
+            Ok(reader::read_with(string.try_into()?, settings, None, cx)?.0)
+        }
+        Object::Buffer(buffer) => {
+            let Some(pos) = env.with_buffer(buffer, |b| b.map(|b| b.point())) else {
+                bail!("Selecting deleted buffer")
+            };
+            let settings = ReadSettings::from_env(env, cx);
+            let mut source = StreamSource::new(BufferStream { buffer, env, pos });
+            let obj = reader::read_from_source(&mut source, settings, cx);
+            let BufferStream { buffer, env, pos } = source.finish()?;
+            env.with_buffer(buffer, |b| {
+                if let Some(b) = b {
+                    b.set_point(pos);
+                }
+            });
+            Ok(obj?)
+        }
+        _ if stream.nil() || stream == sym::TRUE => {
+            let extended_syntax = ReadSettings::from_env(env, cx).extended_syntax;
+            let mut source = StreamSource::new(StdinStream { line: Vec::new().into_iter(), cx });
+            let tokens = ObjectTokens::new(&mut source, extended_syntax);
+            source.finish()?;
+            Ok(tokens.read(ReadSettings::from_env(env, cx), cx)?)
+        }
+        _ => {
+            let function: Gc<Function> = stream.try_into()?;
+            root!(function, cx);
+            let extended_syntax = ReadSettings::from_env(env, cx).extended_syntax;
+            let mut source = StreamSource::new(FunctionStream { function, env, cx });
+            let tokens = ObjectTokens::new(&mut source, extended_syntax);
+            source.finish()?;
+            Ok(tokens.read(ReadSettings::from_env(env, cx), cx)?)
+        }
+    }
+}
+
+/// A stream that `read` pulls characters from one at a time.
+trait Stream {
+    /// Return the next character, or `None` at the end of the stream.
+    fn next_char(&mut self) -> Result<Option<char>>;
+
+    /// Give back `chr`, which was only pulled to find the end of an atom.
+    fn unread(&mut self, chr: char) -> Result<()>;
+}
+
+/// The characters of a [`Stream`], for the reader. The characters pulled so
+/// far are kept, because the text of tokens is taken from them.
+struct StreamSource<S> {
+    stream: S,
+    text: String,
+    peeked: Option<char>,
+    done: bool,
+    error: Option<anyhow::Error>,
+}
+
+impl<S: Stream> StreamSource<S> {
+    fn new(stream: S) -> Self {
+        Self { stream, text: String::new(), peeked: None, done: false, error: None }
+    }
+
+    /// Give back the character that was pulled past the end of the object and
+    /// return the stream. Fails if the stream failed while it was read.
+    fn finish(mut self) -> Result<S> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if let Some(chr) = self.peeked {
+            self.stream.unread(chr)?;
+        }
+        Ok(self.stream)
+    }
+}
+
+impl<S: Stream> CharSource<'static> for StreamSource<S> {
+    fn peek(&mut self) -> Option<(usize, char)> {
+        if self.peeked.is_none() && !self.done {
+            match self.stream.next_char() {
+                Ok(Some(chr)) => self.peeked = Some(chr),
+                Ok(None) => self.done = true,
+                Err(err) => {
+                    self.error = Some(err);
+                    self.done = true;
+                }
+            }
+        }
+        self.peeked.map(|chr| (self.text.len(), chr))
+    }
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        let next = self.peek();
+        if let Some((_, chr)) = next {
+            self.text.push(chr);
+            self.peeked = None;
+        }
+        next
+    }
+
+    fn pos(&mut self) -> usize {
+        self.text.len()
+    }
+
+    fn text(&self, start: usize, end: usize) -> Cow<'static, str> {
+        Cow::Owned(self.text[start..end].to_owned())
+    }
+}
+
+/// A buffer stream, which is read one character at a time from `pos`.
+struct BufferStream<'a> {
+    buffer: &'a LispBuffer,
+    env: &'a mut Rt<Env>,
+    pos: usize,
+}
+
+impl Stream for BufferStream<'_> {
+    fn next_char(&mut self) -> Result<Option<char>> {
+        let pos = self.pos;
+        let chr = self.env.with_buffer(self.buffer, |b| b.and_then(|b| b.char_after(pos)));
+        if chr.is_some() {
+            self.pos += 1;
+        }
+        Ok(chr)
+    }
+
+    fn unread(&mut self, _: char) -> Result<()> {
+        self.pos -= 1;
+        Ok(())
+    }
+}
+
+/// A function stream. It is called with no arguments to get the next
+/// character, or nil at the end, and with a character to put it back.
+struct FunctionStream<'a, 'rt> {
+    function: &'a Rt<Gc<Function<'static>>>,
+    env: &'a mut Rt<Env>,
+    cx: &'a mut Context<'rt>,
+}
+
+impl Stream for FunctionStream<'_, '_> {
+    fn next_char(&mut self) -> Result<Option<char>> {
+        let cx = &mut *self.cx;
+        root!(args, Vec::new(), cx);
+        let chr = self.function.call(args, self.env, cx, None)?;
+        match chr.untag() {
+            _ if chr.nil() => Ok(None),
+            Object::Int(i) => match u32::try_from(i).ok().and_then(char::from_u32) {
+                Some(chr) => Ok(Some(chr)),
+                None => bail!("Invalid character: {i}"),
+            },
+            x => Err(TypeError::new(Type::Int, x).into()),
+        }
+    }
+
+    fn unread(&mut self, chr: char) -> Result<()> {
+        let cx = &mut *self.cx;
+        root!(args, Vec::new(), cx);
+        args.push(cx.add(i64::from(u32::from(chr))));
+        self.function.call(args, self.env, cx, None)?;
+        Ok(())
+    }
+}
+
+/// Standard input, which is read a line at a time. Whatever is left on the
+/// line after the object is discarded.
+struct StdinStream<'a, 'rt> {
+    line: std::vec::IntoIter<char>,
+    cx: &'a mut Context<'rt>,
+}
+
+impl Stream for StdinStream<'_, '_> {
+    fn next_char(&mut self) -> Result<Option<char>> {
+        if let Some(chr) = self.line.next() {
+            return Ok(Some(chr));
+        }
+        let mut line = String::new();
+        self.cx.park_while(|| std::io::stdin().read_line(&mut line))?;
+        self.line = line.chars().collect::<Vec<_>>().into_iter();
+        Ok(self.line.next())
+    }
+
+    fn unread(&mut self, _: char) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Where a cons was read from.
+struct SourcePosition {
+    file: u32,
+    line: u32,
+    column: u32,
+}
+
+/// The positions of the lists read by `load`, indexed by the id stored in
+/// each cons. Id zero means the position is unknown.
+struct SourcePositions {
+    files: Vec<String>,
+    positions: Vec<SourcePosition>,
+}
+
+static SOURCE_POSITIONS: Mutex<SourcePositions> =
+    Mutex::new(SourcePositions { files: Vec::new(), positions: Vec::new() });
+
+impl SourcePositions {
+    fn file_id(&mut self, file: &str) -> u32 {
+        let idx = match self.files.iter().rposition(|x| x == file) {
+            Some(idx) => idx,
+            None => {
+                self.files.push(file.to_owned());
+                self.files.len() - 1
+            }
+        };
+        idx as u32
+    }
+
+    fn record(&mut self, cons: &Cons, file: u32, line: usize, column: usize) {
+        let Ok(id) = u32::try_from(self.positions.len() + 1) else { return };
+        let (line, column) = (line as u32, column as u32);
+        self.positions.push(SourcePosition { file, line, column });
+        cons.set_source(id);
+    }
+}
+
+/// The position `form` was read from as `FILE:LINE:COLUMN`, if it is known.
+/// Positions are only recorded by `load` when `load-record-positions` is
+/// non-nil.
+pub(crate) fn source_position(form: GcObj) -> Option<String> {
+    let Object::Cons(cons) = form.untag() else { return None };
+    let id = cons.source().checked_sub(1)?;
+    let table = SOURCE_POSITIONS.lock().unwrap();
+    let SourcePosition { file, line, column } = table.positions.get(id as usize)?;
+    let file = &table.files[*file as usize];
+    Some(format!("{file}:{line}:{column}"))
+}
+
+/// The offset of the start of each line in `contents`.
+fn line_starts(contents: &str) -> Vec<usize> {
+    let ends = contents.match_indices('\n').map(|(idx, _)| idx + 1);
+    std::iter::once(0).chain(ends).collect()
+}
+
+/// The line (counting from 1) and column (counting from 0) of the byte at
+/// `offset` in `contents`.
+fn line_column(contents: &str, offset: usize, line_starts: &[usize]) -> (usize, usize) {
+    let line = line_starts.partition_point(|&start| start <= offset);
+    let start = line_starts[line - 1];
+    (line, contents[start..offset].chars().count())
+}
+
 pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<bool> {
     let mut pos = 0;
+    let mut lines = None;
     loop {
-        let (obj, new_pos) = match reader::read(&contents[pos..], cx) {
+        let settings = ReadSettings::from_env(env, cx);
+        let file_name = settings.load_file_name;
+        let record = matches!(file_name.untag(), Object::String(_))
+            && env.vars.get(sym::LOAD_RECORD_POSITIONS).is_some_and(|x| !x.bind(cx).nil());
+        let mut positions = Vec::new();
+        let read =
+            reader::read_with(&contents[pos..], settings, record.then_some(&mut positions), cx);
+        let (obj, new_pos) = match read {
             Ok((obj, pos)) => (obj, pos),
             Err(reader::Error::EmptyStream) => return Ok(true),
             Err(mut e) => {
@@ -66,6 +346,15 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
                 bail!(e);
             }
         };
+        if !positions.is_empty() {
+            let lines = lines.get_or_insert_with(|| line_starts(contents));
+            let mut table = SOURCE_POSITIONS.lock().unwrap();
+            let file = table.file_id(file_name.try_into()?);
+            for (form, offset) in positions {
+                let (line, column) = line_column(contents, pos + offset, lines);
+                table.record(form.as_cons(), file, line, column);
+            }
+        }
         if crate::debug::debug_enabled() {
             let content = &contents[pos..(new_pos + pos)];
             println!("-----READ START-----\n {content}");
@@ -78,79 +367,142 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
     }
 }
 
-fn file_in_path(file: &str, path: &str) -> Option<PathBuf> {
-    let path = Path::new(path).join(file);
-    if path.exists() {
-        Some(path)
-    } else {
-        let with_ext = path.with_extension("el");
-        with_ext.exists().then_some(with_ext)
-    }
-}
-
-fn find_file_in_load_path(file: &str, cx: &Context, env: &Rt<Env>) -> Result<PathBuf> {
-    let load_path = env.vars.get(sym::LOAD_PATH).unwrap();
-    let paths = load_path.bind(cx).as_list().context("`load-path' was not a list")?;
-    let mut final_file = None;
-    for path in paths {
-        match path?.untag() {
-            Object::String(path) => {
-                if let Some(x) = file_in_path(file, path.try_into()?) {
-                    final_file = Some(x);
-                    break;
-                }
-            }
+/// Collect the strings in the list stored in variable `var`.
+fn string_list_var(var: Symbol, cx: &Context, env: &Rt<Env>) -> Result<Vec<String>> {
+    let Some(value) = env.vars.get(var) else { return Ok(Vec::new()) };
+    let list = value.bind(cx).as_list().with_context(|| format!("`{var}' was not a list"))?;
+    let mut strings = Vec::new();
+    for elem in list {
+        match elem?.untag() {
+            Object::String(string) => strings.push(<&str>::try_from(string)?.to_owned()),
             x => {
                 return Err(TypeError::new(Type::String, x))
-                    .context("Found non-string in `load-path'");
+                    .with_context(|| format!("Found non-string in `{var}'"));
             }
         }
     }
-    final_file.ok_or_else(|| anyhow!("Unable to find file `{file}' in load-path"))
+    Ok(strings)
+}
+
+/// Try `file` with each of `suffixes` in `dir`. The first suffix that names
+/// an existing file wins, unless `prefer_newer` is set, in which case the most
+/// recently modified one is used.
+fn file_in_path(
+    dir: &Path,
+    file: &str,
+    suffixes: &[String],
+    prefer_newer: bool,
+) -> Option<PathBuf> {
+    let mut found: Option<(PathBuf, Option<SystemTime>)> = None;
+    for suffix in suffixes {
+        let path = dir.join(format!("{file}{suffix}"));
+        if !path.is_file() {
+            continue;
+        }
+        if !prefer_newer {
+            return Some(path);
+        }
+        let modified = path.metadata().and_then(|x| x.modified()).ok();
+        if found.as_ref().is_none_or(|(_, time)| modified > *time) {
+            found = Some((path, modified));
+        }
+    }
+    found.map(|(path, _)| path)
+}
+
+/// Find the file to load for `file`. Relative names are looked up in the
+/// current directory and then in each directory of `load-path`.
+fn find_file(file: &str, suffixes: &[String], cx: &Context, env: &Rt<Env>) -> Result<PathBuf> {
+    let prefer_newer = env.vars.get(sym::LOAD_PREFER_NEWER).is_some_and(|x| !x.bind(cx).nil());
+    if Path::new(file).is_absolute() {
+        return file_in_path(Path::new(""), file, suffixes, prefer_newer)
+            .ok_or_else(|| anyhow!("Cannot open load file: {file}"));
+    }
+    let load_path = string_list_var(sym::LOAD_PATH, cx, env)?;
+    let mut dirs = std::iter::once("").chain(load_path.iter().map(String::as_str));
+    dirs.find_map(|dir| file_in_path(Path::new(dir), file, suffixes, prefer_newer))
+        .ok_or_else(|| anyhow!("Unable to find file `{file}' in load-path"))
+}
+
+/// The suffixes to try when loading `file`. These are the elements of
+/// `load-suffixes` followed by the bare name, unless `must_suffix` is set.
+/// A name that already ends in `.el` or `.elc` is always tried as is.
+fn load_suffixes(
+    file: &str,
+    nosuffix: bool,
+    must_suffix: bool,
+    cx: &Context,
+    env: &Rt<Env>,
+) -> Result<Vec<String>> {
+    if nosuffix {
+        return Ok(vec![String::new()]);
+    }
+    let mut suffixes = string_list_var(sym::LOAD_SUFFIXES, cx, env)?;
+    let has_suffix = Path::new(file).extension().is_some_and(|x| x == "el" || x == "elc");
+    if !must_suffix || has_suffix {
+        suffixes.push(String::new());
+    }
+    Ok(suffixes)
+}
+
+/// If `file` is a byte-compiled file whose source has been modified since it
+/// was compiled, return the source file.
+fn newer_source(file: &Path) -> Option<PathBuf> {
+    if file.extension()? != "elc" {
+        return None;
+    }
+    let source = file.with_extension("el");
+    let modified = |path: &Path| path.metadata().and_then(|x| x.modified()).ok();
+    (modified(&source)? > modified(file)?).then_some(source)
 }
 
+/// Execute a file of Lisp code named FILE. The suffixes in `load-suffixes`
+/// are tried first, so a byte-compiled `.elc` file is used in preference to
+/// the `.el` source. If NOSUFFIX is non-nil, only FILE itself is tried. If
+/// MUST-SUFFIX is non-nil, FILE is never tried without a suffix.
 #[defun]
 pub(crate) fn load(
     file: &Rt<Gc<&LispString>>,
     noerror: Option<()>,
     nomessage: Option<()>,
+    nosuffix: Option<()>,
+    must_suffix: Option<()>,
     cx: &mut Context,
     env: &mut Rt<Env>,
 ) -> Result<bool> {
     let noerror = noerror.is_some();
     let nomessage = nomessage.is_some();
     let file: &str = file.get(cx).try_into()?;
-    let final_file = if Path::new(file).exists() {
-        PathBuf::from(file)
-    } else {
-        match find_file_in_load_path(file, cx, env) {
-            Ok(x) => x,
-            Err(e) => {
-                return match noerror {
-                    true => Ok(false),
-                    false => Err(e),
-                };
-            }
+    let suffixes = load_suffixes(file, nosuffix.is_some(), must_suffix.is_some(), cx, env)?;
+    let final_file = match find_file(file, &suffixes, cx, env) {
+        Ok(x) => x,
+        Err(e) => {
+            return match noerror {
+                true => Ok(false),
+                false => Err(e),
+            };
         }
     };
 
     if !nomessage {
-        println!("Loading {file}...");
+        match newer_source(&final_file) {
+            Some(source) => println!(
+                "Loading {file}... (source file `{}' is newer, using older file)",
+                source.display()
+            ),
+            None => println!("Loading {file}..."),
+        }
     }
     let new_load_file = cx.add(final_file.to_string_lossy().to_string());
-    let prev_load_file = match env.vars.get_mut(sym::LOAD_FILE_NAME) {
-        Some(val) => {
-            let prev = val.bind(cx);
-            val.set(new_load_file);
-            prev
-        }
-        None => nil(),
-    };
+    let prev_load_file = env.vars.get(sym::LOAD_FILE_NAME).map_or_else(nil, |x| x.bind(cx));
     root!(prev_load_file, cx);
-    let result = match fs::read_to_string(&final_file)
+    env.vars.insert(sym::LOAD_FILE_NAME, new_load_file);
+    let result = match fs::read(&final_file)
         .with_context(|| format!("Couldn't open file {:?}", final_file.as_os_str()))
     {
-        Ok(content) => load_internal(&content, cx, env),
+        // compiled files can contain bytes that are not valid UTF-8, these are
+        // replaced instead of failing the whole load
+        Ok(content) => load_internal(&String::from_utf8_lossy(&content), cx, env),
         Err(e) => match noerror {
             true => Ok(false),
             false => Err(e),
@@ -160,39 +512,138 @@ pub(crate) fn load(
     result
 }
 
+/// Return the obarray to use for OBARRAY, which defaults to the value of the
+/// variable `obarray`. Vectors can be used as obarrays, for compatibility
+/// with code that creates them with `make-vector`. Their symbols are kept in
+/// an obarray stored in the first slot.
+fn get_obarray<'ob>(
+    obarray: Option<GcObj<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispObarray> {
+    let obarray = match obarray {
+        Some(obarray) if !obarray.nil() => obarray,
+        _ => match env.vars.get(sym::OBARRAY) {
+            Some(obarray) => obarray.bind(cx),
+            None => cx.add(LispObarray::standard()),
+        },
+    };
+    match obarray.untag() {
+        Object::Obarray(obarray) => Ok(obarray),
+        Object::Vec(vec) if !vec.is_empty() => {
+            if let Object::Obarray(inner) = vec[0].get().untag() {
+                return Ok(inner);
+            }
+            let inner = LispObarray::new().into_obj(cx);
+            vec.try_mut()?[0].set(inner.into());
+            Ok(inner.untag())
+        }
+        _ => Err(TypeError::new(Type::Obarray, obarray).into()),
+    }
+}
+
 #[defun]
-pub(crate) fn intern<'ob>(string: &str, cx: &'ob Context) -> Symbol<'ob> {
-    crate::core::env::intern(string, cx)
+pub(crate) fn intern<'ob>(
+    string: &str,
+    obarray: Option<GcObj<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    Ok(get_obarray(obarray, env, cx)?.intern(string, cx))
 }
 
 #[defun]
-pub(crate) fn intern_soft(string: GcObj, obarray: Option<()>) -> Result<Symbol> {
-    ensure!(obarray.is_none(), "intern-soft obarray not implemented");
-    match string.untag() {
-        Object::Symbol(sym) => {
-            if sym.interned() {
-                Ok(sym)
-            } else {
-                Ok(sym::NIL)
-            }
-        }
-        Object::String(string) => {
-            let map = crate::core::env::INTERNED_SYMBOLS.lock().unwrap();
-            match map.get(string.try_into()?) {
-                Some(sym) => Ok(unsafe { sym.with_lifetime() }),
-                None => Ok(sym::NIL),
-            }
-        }
+pub(crate) fn intern_soft<'ob>(
+    name: GcObj<'ob>,
+    obarray: Option<GcObj<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    let obarray = get_obarray(obarray, env, cx)?;
+    match name.untag() {
+        Object::Symbol(sym) => match obarray.get(sym.name()) {
+            Some(found) if found == sym => Ok(sym),
+            _ => Ok(sym::NIL),
+        },
+        Object::String(string) => match obarray.get(string.try_into()?) {
+            Some(sym) => Ok(unsafe { sym.with_lifetime() }),
+            None => Ok(sym::NIL),
+        },
         x => Err(TypeError::new(Type::String, x).into()),
     }
 }
 
+/// Remove the symbol NAME from OBARRAY. NAME can be a symbol or the name of
+/// one. Return t if a symbol was removed.
+#[defun]
+fn unintern<'ob>(
+    name: GcObj<'ob>,
+    obarray: Option<GcObj<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<bool> {
+    let obarray = get_obarray(obarray, env, cx)?;
+    let sym = match name.untag() {
+        Object::Symbol(sym) => sym,
+        Object::String(string) => match obarray.get(string.try_into()?) {
+            Some(sym) => unsafe { sym.with_lifetime() },
+            None => return Ok(false),
+        },
+        x => bail!(TypeError::new(Type::String, x)),
+    };
+    Ok(obarray.remove(sym))
+}
+
+/// Call FUNCTION on every symbol in OBARRAY.
+#[defun]
+fn mapatoms(
+    function: &Rt<Gc<Function>>,
+    obarray: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let obarray = get_obarray(obarray.map(|x| x.bind(cx)), env, cx)?;
+    let symbols: Vec<GcObj> = obarray.symbols().into_iter().map(Into::into).collect();
+    root!(symbols, cx);
+    root!(args, Vec::new(), cx);
+    for symbol in symbols.iter() {
+        args.push(symbol);
+        function.call(args, env, cx, None)?;
+        args.clear();
+    }
+    Ok(false)
+}
+
+/// Return a new obarray. SIZE is ignored, because obarrays grow as needed.
+#[defun]
+fn obarray_make(size: Option<usize>) -> LispObarray {
+    let _ = size;
+    LispObarray::new()
+}
+
+#[defun]
+fn obarrayp(object: GcObj) -> bool {
+    matches!(object.untag(), Object::Obarray(_))
+}
+
+/// Remove every symbol from OBARRAY.
+#[defun]
+fn obarray_clear(obarray: &LispObarray) -> Result<bool> {
+    obarray.clear()?;
+    Ok(false)
+}
+
 defvar!(LEXICAL_BINDING, true);
 defvar!(CURRENT_LOAD_LIST);
 defvar!(LOAD_HISTORY);
 defvar!(LOAD_PATH, list!["lisp"]);
 defvar!(LOAD_FILE_NAME);
+defvar!(LOAD_SUFFIXES, list![".elc", ".el"]);
+defvar!(LOAD_PREFER_NEWER);
+defvar!(LOAD_RECORD_POSITIONS, true);
+defvar!(STANDARD_INPUT, true);
 defvar!(BYTE_BOOLEAN_VARS);
+defvar!(OBARRAY, crate::core::object::LispObarray::standard());
 
 #[cfg(test)]
 mod test {
@@ -214,4 +665,147 @@ mod test {
         let val = interpreter::eval(obj, None, env, cx).unwrap();
         assert_eq!(val, 4.5);
     }
+
+    #[test]
+    fn test_load_elc() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let dir = std::env::temp_dir().join(format!("rune-lread-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("lread-test.el");
+        let compiled = dir.join("lread-test.elc");
+        fs::write(&source, "(setq lread-test-loaded 'source)").unwrap();
+        // compiled files are not always valid UTF-8
+        let elc = b";ELC\x17\0\0\0\n#@5 doc\n\
+                   (defalias 'lread-test-inc #[257 \"\\211T\\207\" [] 2 (#$ . 8)])\n\
+                   ;; \xff\xfe\n\
+                   (defalias 'lread-test-dyn #[(lread-test-x) \"\\010T\\207\" [lread-test-x] 1])\n\
+                   (setq lread-test-loaded (lread-test-dyn (lread-test-inc 1)) lread-test-file #$)\n";
+        fs::write(&compiled, elc).unwrap();
+
+        let form = format!(
+            "(progn (setq load-path (list {:?}) load-suffixes (list \".elc\" \".el\"))
+               (load \"lread-test\" nil t)
+               (list lread-test-loaded lread-test-file (boundp 'lread-test-x)))",
+            dir.to_str().unwrap()
+        );
+        let obj = reader::read(&form, cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        root!(val, cx);
+        let expect = list![3, compiled.to_str().unwrap(), false; cx];
+        assert_eq!(val.bind(cx), expect);
+
+        // a stale .elc is only skipped when `load-prefer-newer' is set
+        let newer = SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(newer)
+            .unwrap();
+        let suffixes = [".elc".to_owned(), ".el".to_owned()];
+        assert_eq!(file_in_path(&dir, "lread-test", &suffixes, false), Some(compiled.clone()));
+        assert_eq!(file_in_path(&dir, "lread-test", &suffixes, true), Some(source.clone()));
+        assert_eq!(newer_source(&compiled), Some(source));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_source_positions() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        env.vars.insert(sym::LOAD_FILE_NAME, cx.add("lread-positions.el"));
+        env.vars.insert(sym::LOAD_RECORD_POSITIONS, cx.add(true));
+        let contents =
+            "(setq lread-pos-fn\n  #'(lambda (x)\n      (car x)))\n(funcall lread-pos-fn 1)";
+        let err = load_internal(contents, cx, env).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("at lread-positions.el:3:6"), "{message}");
+
+        assert_eq!(line_column("ab\ncd\u{e9}f", 7, &line_starts("ab\ncd\u{e9}f")), (2, 3));
+        assert_eq!(source_position(cx.add(1)), None);
+    }
+
+    #[test]
+    fn test_read_stream() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let string = cx.add("(a b) c");
+        root!(string, cx);
+        assert_eq!(read(Some(string), env, cx).unwrap().to_string(), "(a b)");
+
+        // a buffer is read from point, which is moved past the object
+        let name = cx.add("test_read_stream");
+        let buffer = crate::buffer::get_buffer_create(name, nil(), cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        crate::editfns::insert(&[cx.add("x (a b) c")], env).unwrap();
+        env.current_buffer.as_mut().unwrap().set_point(1);
+        let buffer = crate::buffer::get_buffer_create(name, nil(), cx).unwrap();
+        root!(buffer, cx);
+        assert_eq!(read(Some(buffer), env, cx).unwrap().to_string(), "(a b)");
+        assert_eq!(env.current_buffer.as_ref().unwrap().point(), 7);
+        assert_eq!(read(Some(buffer), env, cx).unwrap().to_string(), "c");
+        assert_eq!(env.current_buffer.as_ref().unwrap().point(), 9);
+        assert!(read(Some(buffer), env, cx).is_err());
+
+        // parens in comments, strings and characters do not end the object
+        let text = cx.add(" ; (\n(\"a)\" ?\\) ?)) x");
+        crate::editfns::insert(&[text], env).unwrap();
+        env.current_buffer.as_mut().unwrap().set_point(9);
+        let obj = read(Some(buffer), env, cx).unwrap().to_string();
+        assert_eq!(obj, r#"("a)" 41 41)"#);
+        assert_eq!(env.current_buffer.as_ref().unwrap().point(), 27);
+
+        // a function stream is not read past a closing paren, and gets back
+        // the character after an atom. It has the same syntax as a string.
+        let form = r#"
+(let* ((chars nil) (unread nil)
+       (stream #'(lambda (&optional c)
+                   (if c (setq unread (cons c unread))
+                     (prog1 (car chars) (setq chars (cdr chars)))))))
+  (list (progn (setq chars '(?\( ?x ?\s ?1 ?\) ?\s ?y))
+               (list (read stream) unread chars))
+        (progn (setq chars '(?a ?b ?\s ?c) unread nil)
+               (list (read stream) unread chars))
+        (progn (setq chars '(?# ?@ ?2 ?\s ?x ?# ?r ?\" ?a ?\\ ?\" ?b ?\" ?\s ?y)
+                     unread nil)
+               (let ((rune-extended-syntax t))
+                 (list (read stream) unread chars)))))"#;
+        let obj = reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        root!(val, cx);
+        let expect = r#"(((x 1) nil (32 121)) (ab (32) (99)) ("a\\\"b" nil (32 121)))"#;
+        let expect = reader::read(expect, cx).unwrap().0;
+        assert_eq!(val.bind(cx), expect);
+    }
+
+    #[test]
+    fn test_obarray() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        let form = r#"
+(let ((ob (obarray-make)) (vec (make-vector 3 0)) (symbols nil))
+  (intern "lread-test-private" ob)
+  (mapatoms #'(lambda (s) (setq symbols (cons s symbols))) ob)
+  (list (obarrayp ob)
+        (intern-soft "lread-test-private")
+        (eq (intern-soft "lread-test-private" ob) (car symbols))
+        (length symbols)
+        (unintern "lread-test-private" ob)
+        (intern-soft "lread-test-private" ob)
+        (eq (intern "lread-test-vector" vec) (intern "lread-test-vector" vec))
+        (intern-soft "lread-test-vector")))"#;
+        let obj = reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        root!(val, cx);
+        let expect = reader::read("(t nil t 1 t nil t nil)", cx).unwrap().0;
+        assert_eq!(val.bind(cx), expect);
+    }
 }
@@ -49,7 +49,9 @@ mod alloc;
 mod arith;
 mod buffer;
 mod bytecode;
+mod casefiddle;
 mod character;
+mod chartab;
 mod data;
 mod editfns;
 mod emacs;
@@ -86,7 +88,7 @@ fn repl(env: &mut Rt<Env>, cx: &mut Context) {
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
-        stdin.read_line(&mut buffer).unwrap();
+        cx.park_while(|| stdin.read_line(&mut buffer)).unwrap();
         if buffer.trim() == "exit" {
             return;
         }
@@ -114,11 +116,18 @@ fn repl(env: &mut Rt<Env>, cx: &mut Context) {
     }
 }
 
-fn load(env: &mut Rt<Env>, cx: &mut Context) {
+/// Initialize the variables, errors and buffers that the elisp code expects
+/// to exist before anything is loaded.
+fn init(env: &mut Rt<Env>, cx: &mut Context) {
     crate::core::env::init_variables(cx, env);
+    eval::init_errors(env, cx);
     crate::data::defalias(intern("not", cx), (crate::core::env::sym::NULL).into(), None)
         .expect("null should be defined");
     buffer::get_buffer_create(cx.add("*scratch*"), core::object::nil(), cx).unwrap();
+}
+
+fn load(env: &mut Rt<Env>, cx: &mut Context) {
+    init(env, cx);
 
     let buffer = String::from(r#"(load "lisp/bootstrap.el")"#);
 
@@ -180,3 +189,33 @@ fn main() {
         gui::launch();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    // Loading the elisp takes too long when collecting at every step
+    #[cfg_attr(feature = "gc_stress", ignore)]
+    fn test_cl_defstruct_include() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, Env::default(), cx);
+        init(env, cx);
+        // loadup loads cl-preloaded, which defines the records used by
+        // cl-defstruct. This is the start of the bootstrap, without the byte
+        // compiler.
+        let bootstrap = r#"(progn (load "lisp/loadup.el") (load "stubs"))"#;
+        lread::load_internal(bootstrap, cx, env).unwrap();
+        let form = "(progn (cl-defstruct foo a)
+                           (cl-defstruct (bar (:include foo)) b)
+                           (let ((x (make-bar :a 3 :b 4)))
+                             (list (bar-a x) (bar-b x) (foo-p x) (bar-p (make-foo)))))";
+        let (obj, _) = reader::read(form, cx).unwrap();
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        let val = rebind!(val, cx);
+        let expect = list![3, 4, true, false; cx];
+        assert_eq!(val, expect);
+    }
+}
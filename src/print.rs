@@ -1,12 +1,803 @@
-use crate::core::object::GcObj;
+//! The Lisp printer.
+use crate::character::char_to_byte8;
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env, Symbol},
+    gc::{Context, Rt},
+    object::{
+        float_to_string, nil, Function, Gc, GcObj, LispFloat, LispHashTable, LispString, LispVec,
+        Object, RawObj,
+    },
+};
+use crate::hashmap::HashMap;
+use crate::root;
+use anyhow::{anyhow, bail, Result};
 use fn_macros::defun;
+use std::collections::hash_map::Entry;
+use std::fmt::{self, Write as _};
+use std::io::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+/// How objects are printed, from the `print-*` variables.
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct PrintSettings {
+    /// Print strings and symbols so they can be read back, like `prin1`.
+    /// Otherwise they are printed as plain text, like `princ`.
+    pub(crate) escape: bool,
+    /// How many elements of a list or vector to print.
+    pub(crate) length: Option<usize>,
+    /// How deeply nested lists and vectors are printed.
+    pub(crate) level: Option<usize>,
+    pub(crate) escape_newlines: bool,
+    pub(crate) escape_control_characters: bool,
+    /// Print `(quote x)` as `'x`, and the same for the other reader macros.
+    pub(crate) quoted: bool,
+    /// Label shared structure with `#N=` and `#N#`.
+    pub(crate) circle: bool,
+    /// The conversion and precision from `float-output-format`.
+    pub(crate) float_format: Option<(char, Option<usize>)>,
+}
+
+impl Default for PrintSettings {
+    fn default() -> Self {
+        Self {
+            escape: true,
+            length: None,
+            level: None,
+            escape_newlines: false,
+            escape_control_characters: false,
+            quoted: true,
+            circle: false,
+            float_format: None,
+        }
+    }
+}
+
+impl PrintSettings {
+    /// The settings given by the printer variables in `env`.
+    pub(crate) fn from_env(env: &Rt<Env>, cx: &Context, escape: bool) -> Self {
+        let var = |sym| env.vars.get(sym).map(|x| x.bind(cx));
+        let flag = |sym, default| var(sym).map_or(default, |x| !x.nil());
+        let limit = |sym| match var(sym)?.untag() {
+            Object::Int(x) => usize::try_from(x).ok(),
+            _ => None,
+        };
+        let float_format = match var(sym::FLOAT_OUTPUT_FORMAT).map(Gc::untag) {
+            Some(Object::String(format)) => {
+                <&str>::try_from(format).ok().and_then(parse_float_format)
+            }
+            _ => None,
+        };
+        Self {
+            escape,
+            length: limit(sym::PRINT_LENGTH),
+            level: limit(sym::PRINT_LEVEL),
+            escape_newlines: flag(sym::PRINT_ESCAPE_NEWLINES, false),
+            escape_control_characters: flag(sym::PRINT_ESCAPE_CONTROL_CHARACTERS, false),
+            quoted: flag(sym::PRINT_QUOTED, true),
+            circle: flag(sym::PRINT_CIRCLE, false),
+            float_format,
+        }
+    }
+}
+
+/// Parse a `float-output-format` of the form `%.Pc`, where the precision `.P`
+/// is optional and the conversion `c` is `e`, `f` or `g`.
+fn parse_float_format(format: &str) -> Option<(char, Option<usize>)> {
+    let spec = format.strip_prefix('%')?;
+    let conversion = spec.chars().last().filter(|x| matches!(x, 'e' | 'f' | 'g'))?;
+    let precision = match &spec[..spec.len() - 1] {
+        "" => None,
+        precision => Some(precision.strip_prefix('.')?.parse().ok()?),
+    };
+    Some((conversion, precision))
+}
+
+/// Print `obj` to a string using `settings`.
+pub(crate) fn print_to_string(obj: GcObj, settings: &PrintSettings) -> String {
+    let mut printer = Printer {
+        settings,
+        out: String::new(),
+        labels: HashMap::default(),
+        next_label: 0,
+        parents: Vec::new(),
+    };
+    if settings.circle {
+        let mut seen = HashMap::default();
+        find_shared(obj, false, &mut seen);
+        printer.labels = seen.into_iter().filter(|x| x.1).map(|(obj, _)| (obj, 0)).collect();
+    }
+    printer.print(obj).expect("writing to a string can't fail");
+    printer.out
+}
+
+/// Mark the objects reachable from `obj` that are seen more than once. Only
+/// the objects that `print-circle` labels are recorded. Strings are only
+/// labeled when they are `nested` in another object.
+fn find_shared(mut obj: GcObj, mut nested: bool, seen: &mut HashMap<RawObj, bool>) {
+    loop {
+        match obj.untag() {
+            Object::Cons(_) | Object::Vec(_) | Object::Record(_) | Object::HashTable(_) => {}
+            Object::String(_) if nested => {}
+            _ => return,
+        }
+        match seen.entry(obj.into_raw()) {
+            Entry::Occupied(mut shared) => {
+                shared.insert(true);
+                return;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(false);
+            }
+        }
+        match obj.untag() {
+            Object::Cons(cons) => {
+                find_shared(cons.car(), true, seen);
+                obj = cons.cdr();
+                nested = true;
+            }
+            Object::Vec(vec) => return vec.iter().for_each(|x| find_shared(x.get(), true, seen)),
+            Object::Record(rec) => {
+                return rec.iter().for_each(|x| find_shared(x.get(), true, seen))
+            }
+            Object::HashTable(table) => {
+                for (key, value) in table.borrow().iter() {
+                    find_shared(*key, true, seen);
+                    find_shared(value.get(), true, seen);
+                }
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+struct Printer<'a> {
+    settings: &'a PrintSettings,
+    out: String,
+    /// The label of each object that `print-circle` found more than once.
+    /// Zero means the label has not been printed yet.
+    labels: HashMap<RawObj, u32>,
+    next_label: u32,
+    /// The lists and vectors that contain the object being printed.
+    parents: Vec<RawObj>,
+}
+
+impl Printer<'_> {
+    fn print(&mut self, obj: GcObj) -> fmt::Result {
+        let container = matches!(
+            obj.untag(),
+            Object::Cons(_) | Object::Vec(_) | Object::Record(_) | Object::HashTable(_)
+        );
+        if self.settings.circle {
+            if let Some(label) = self.labels.get_mut(&obj.into_raw()) {
+                if *label != 0 {
+                    return write!(self.out, "#{label}#");
+                }
+                self.next_label += 1;
+                *label = self.next_label;
+                write!(self.out, "#{}=", self.next_label)?;
+            }
+        } else if container {
+            // Without labels, a cycle is printed as the depth of the object
+            // it refers back to.
+            let raw = obj.into_raw();
+            if let Some(depth) = self.parents.iter().position(|x| *x == raw) {
+                return write!(self.out, "#{depth}");
+            }
+        }
+        if !container {
+            return match obj.untag() {
+                Object::Float(x) => self.print_float(x),
+                Object::Symbol(x) => self.print_symbol(x),
+                Object::String(x) => self.print_string(x),
+                _ => write!(self.out, "{obj}"),
+            };
+        }
+        if self.settings.level.is_some_and(|level| self.parents.len() >= level) {
+            return self.out.write_str("...");
+        }
+        self.parents.push(obj.into_raw());
+        let result = match obj.untag() {
+            Object::Cons(x) => self.print_list(x),
+            Object::Vec(x) => self.print_elements("[", x, "]"),
+            Object::Record(x) => self.print_elements("#s(", x, ")"),
+            Object::HashTable(x) => self.print_hash_table(x),
+            _ => unreachable!("not a container: {obj}"),
+        };
+        self.parents.pop();
+        result
+    }
+
+    fn print_list(&mut self, list: &Cons) -> fmt::Result {
+        if let Some((prefix, quoted)) = self.quote_prefix(list) {
+            self.out.write_str(prefix)?;
+            return self.print(quoted);
+        }
+        self.out.write_char('(')?;
+        // Use Brent's algorithm to find a cycle in the tail of the list. The
+        // cycle is printed as the index of the element it loops back to.
+        let (mut tortoise, mut tortoise_idx, mut steps, mut power) = (list, 0, 2, 2);
+        let mut cons = list;
+        let mut idx = 0;
+        loop {
+            if self.settings.length.is_some_and(|len| idx >= len) {
+                self.out.write_str("...")?;
+                break;
+            }
+            self.print(cons.car())?;
+            idx += 1;
+            let cdr = cons.cdr();
+            match cdr.untag() {
+                Object::NIL => break,
+                Object::Cons(next) if !self.labels.contains_key(&cdr.into_raw()) => {
+                    if std::ptr::eq(next, tortoise) {
+                        write!(self.out, " . #{tortoise_idx}")?;
+                        break;
+                    }
+                    steps -= 1;
+                    if steps == 0 {
+                        power *= 2;
+                        steps = power;
+                        tortoise = next;
+                        tortoise_idx = idx;
+                    }
+                    self.out.write_char(' ')?;
+                    cons = next;
+                }
+                _ => {
+                    self.out.write_str(" . ")?;
+                    self.print(cdr)?;
+                    break;
+                }
+            }
+        }
+        self.out.write_char(')')
+    }
+
+    /// If `print-quoted` is set and `list` is a form like `(quote x)`, return
+    /// the reader macro to print it with and the quoted object.
+    fn quote_prefix<'ob>(&self, list: &'ob Cons) -> Option<(&'static str, GcObj<'ob>)> {
+        if !self.settings.quoted {
+            return None;
+        }
+        let Object::Cons(rest) = list.cdr().untag() else { return None };
+        if !rest.cdr().nil() || self.labels.contains_key(&list.cdr().into_raw()) {
+            return None;
+        }
+        let prefix = match list.car().untag() {
+            Object::Symbol(sym::QUOTE) => "'",
+            Object::Symbol(sym::FUNCTION) => "#'",
+            Object::Symbol(sym::BACKQUOTE) => "`",
+            Object::Symbol(sym::UNQUOTE) => ",",
+            Object::Symbol(sym::SPLICE) => ",@",
+            _ => return None,
+        };
+        Some((prefix, rest.car()))
+    }
+
+    fn print_elements(&mut self, open: &str, vec: &LispVec, close: &str) -> fmt::Result {
+        self.out.write_str(open)?;
+        for (i, elem) in vec.iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(' ')?;
+            }
+            if self.settings.length.is_some_and(|len| i >= len) {
+                self.out.write_str("...")?;
+                break;
+            }
+            self.print(elem.get())?;
+        }
+        self.out.write_str(close)
+    }
+
+    fn print_hash_table(&mut self, table: &LispHashTable) -> fmt::Result {
+        write!(self.out, "#s(hash-table size {} test {}", table.count(), table.test().name())?;
+        if let Some(weakness) = table.weakness() {
+            write!(self.out, " weakness {}", weakness.symbol())?;
+        }
+        self.out.write_str(" data (")?;
+        for (i, (key, value)) in table.borrow().iter().enumerate() {
+            if i != 0 {
+                self.out.write_char(' ')?;
+            }
+            if self.settings.length.is_some_and(|len| i >= len) {
+                self.out.write_str("...")?;
+                break;
+            }
+            self.print(*key)?;
+            self.out.write_char(' ')?;
+            self.print(value.get())?;
+        }
+        self.out.write_str("))")
+    }
+
+    fn print_float(&mut self, float: LispFloat) -> fmt::Result {
+        self.out.write_str(&float_to_string(float.get(), self.settings.float_format))
+    }
+
+    fn print_symbol(&mut self, symbol: Symbol) -> fmt::Result {
+        let name = symbol.name();
+        if !self.settings.escape {
+            return self.out.write_str(name);
+        }
+        if name.is_empty() {
+            return self.out.write_str("##");
+        }
+        // The first character is escaped if the name could be read as
+        // something other than a symbol.
+        let mut escape_next =
+            name == "." || name.starts_with('?') || crate::reader::is_number(name);
+        for chr in name.chars() {
+            let special = matches!(
+                chr,
+                '"' | '\\' | '\'' | ';' | '#' | '(' | ')' | ',' | '`' | '[' | ']' | '\u{A0}'
+            );
+            if escape_next || special || chr <= ' ' {
+                self.out.write_char('\\')?;
+            }
+            escape_next = false;
+            self.out.write_char(chr)?;
+        }
+        Ok(())
+    }
+
+    fn print_string(&mut self, string: &LispString) -> fmt::Result {
+        let bytes: &[u8] = string;
+        // Raw bytes in a multibyte string are printed like the raw bytes of a
+        // unibyte string
+        let raw_byte_char = |code| match char::from_u32(code) {
+            Some(chr) => chr,
+            None => char::from(char_to_byte8(code).expect("code should be a raw byte")),
+        };
+        if !self.settings.escape {
+            match <&str>::try_from(string) {
+                Ok(text) => self.out.push_str(text),
+                Err(_) if string.is_multibyte() => {
+                    self.out.extend(string.char_codes().map(raw_byte_char));
+                }
+                Err(_) => self.out.extend(bytes.iter().map(|x| char::from(*x))),
+            }
+            return Ok(());
+        }
+        self.out.write_char('"')?;
+        if string.is_multibyte() {
+            let mut codes = string.char_codes().peekable();
+            while let Some(code) = codes.next() {
+                let next = codes.peek().copied().and_then(char::from_u32);
+                match (char::from_u32(code), char_to_byte8(code)) {
+                    (Some(chr), _) => self.print_string_char(chr, next)?,
+                    (None, Some(byte)) => self.print_octal(byte, next)?,
+                    (None, None) => unreachable!("strings only hold characters and raw bytes"),
+                }
+            }
+        } else {
+            let mut bytes = bytes.iter().map(|x| char::from(*x)).peekable();
+            while let Some(chr) = bytes.next() {
+                if chr.is_ascii() {
+                    self.print_string_char(chr, bytes.peek().copied())?;
+                } else {
+                    // a raw byte
+                    self.print_octal(chr as u8, None)?;
+                }
+            }
+        }
+        self.out.write_char('"')
+    }
+
+    fn print_string_char(&mut self, chr: char, next: Option<char>) -> fmt::Result {
+        match chr {
+            '"' | '\\' => write!(self.out, "\\{chr}"),
+            '\n' if self.settings.escape_newlines => self.out.write_str("\\n"),
+            '\x0C' if self.settings.escape_newlines => self.out.write_str("\\f"),
+            '\0'..='\x1F' | '\x7F' if self.settings.escape_control_characters => {
+                self.print_octal(chr as u8, next)
+            }
+            _ => self.out.write_char(chr),
+        }
+    }
+
+    /// Print `byte` as an octal escape. All three digits are needed if the
+    /// next character is an octal digit, so it isn't read as part of the
+    /// escape.
+    fn print_octal(&mut self, byte: u8, next: Option<char>) -> fmt::Result {
+        if byte > 0o77 || next.is_some_and(|x| matches!(x, '0'..='7')) {
+            write!(self.out, "\\{byte:03o}")
+        } else {
+            write!(self.out, "\\{byte:o}")
+        }
+    }
+}
+
+/// Whether the last character written to standard output was a newline.
+static STDOUT_AT_LINE_START: AtomicBool = AtomicBool::new(true);
+
+/// The stream given by PRINTCHARFUN, or `standard-output` if it is nil.
+fn output_stream<'ob>(
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> GcObj<'ob> {
+    match printcharfun.map(|x| x.bind(cx)) {
+        Some(stream) if !stream.nil() => stream,
+        _ => env.vars.get(sym::STANDARD_OUTPUT).map_or_else(nil, |x| x.bind(cx)),
+    }
+}
+
+/// Output `text` to PRINTCHARFUN. A buffer gets the text inserted at point,
+/// a function is called with each character, and t (or nil) means standard
+/// output.
+fn write_to_stream(
+    text: &str,
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let stream = output_stream(printcharfun, env, cx);
+    match stream.untag() {
+        Object::Buffer(buffer) => env
+            .with_buffer(buffer, |b| b.map(|b| b.insert_str(text)))
+            .ok_or_else(|| anyhow!("Selecting deleted buffer")),
+        _ if stream.nil() || stream == sym::TRUE => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(text.as_bytes())?;
+            stdout.flush()?;
+            if let Some(last) = text.chars().last() {
+                STDOUT_AT_LINE_START.store(last == '\n', Ordering::Relaxed);
+            }
+            Ok(())
+        }
+        _ => {
+            let function: Gc<Function> = stream.try_into()?;
+            root!(function, cx);
+            root!(args, Vec::new(), cx);
+            for chr in text.chars() {
+                args.push(cx.add(i64::from(u32::from(chr))));
+                function.call(args, env, cx, None)?;
+                args.clear();
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Output the printed representation of OBJECT to PRINTCHARFUN, so that it
+/// can be read back by `read`. PRINTCHARFUN defaults to `standard-output`.
 #[defun]
-fn error_message_string(obj: GcObj) -> String {
-    // TODO: implement
-    format!("Error: {obj}")
+fn prin1<'ob>(
+    object: &Rt<GcObj>,
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    let text = print_to_string(object.bind(cx), &PrintSettings::from_env(env, cx, true));
+    write_to_stream(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output the printed representation of OBJECT to PRINTCHARFUN without
+/// quoting, so strings are printed as their contents.
+#[defun]
+fn princ<'ob>(
+    object: &Rt<GcObj>,
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    let text = print_to_string(object.bind(cx), &PrintSettings::from_env(env, cx, false));
+    write_to_stream(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output OBJECT to PRINTCHARFUN like `prin1`, but with a newline before and
+/// after it.
+#[defun]
+fn print<'ob>(
+    object: &Rt<GcObj>,
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<GcObj<'ob>> {
+    let text = print_to_string(object.bind(cx), &PrintSettings::from_env(env, cx, true));
+    write_to_stream(&format!("\n{text}\n"), printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output a newline to PRINTCHARFUN. If ENSURE is non-nil, only output it if
+/// the stream is not already at the start of a line. Return t if a newline
+/// was output.
+#[defun]
+fn terpri(
+    printcharfun: Option<&Rt<GcObj>>,
+    ensure: Option<()>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    if ensure.is_some() {
+        let stream = output_stream(printcharfun, env, cx);
+        let at_line_start = match stream.untag() {
+            Object::Buffer(buffer) => env.with_buffer(buffer, |b| {
+                b.is_none_or(|b| matches!(b.char_before_point(), None | Some('\n')))
+            }),
+            _ if stream.nil() || stream == sym::TRUE => {
+                STDOUT_AT_LINE_START.load(Ordering::Relaxed)
+            }
+            _ => bail!("Unsupported function argument: {stream}"),
+        };
+        if at_line_start {
+            return Ok(false);
+        }
+    }
+    write_to_stream("\n", printcharfun, env, cx)?;
+    Ok(true)
+}
+
+/// Output CHARACTER to PRINTCHARFUN.
+#[defun]
+fn write_char(
+    character: &Rt<GcObj>,
+    printcharfun: Option<&Rt<GcObj>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<i64> {
+    let character: i64 = character.bind(cx).try_into()?;
+    let Some(chr) = u32::try_from(character).ok().and_then(char::from_u32) else {
+        bail!("Invalid character: {character}")
+    };
+    write_to_stream(chr.encode_utf8(&mut [0; 4]), printcharfun, env, cx)?;
+    Ok(character)
+}
+
+/// Return a string with the printed representation of OBJECT. If NOESCAPE is
+/// non-nil, print it like `princ` instead of `prin1`.
+#[defun]
+pub(crate) fn prin1_to_string(
+    object: GcObj,
+    noescape: Option<()>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    print_to_string(object, &PrintSettings::from_env(env, cx, noescape.is_none()))
+}
+
+/// Convert an error value (ERROR-SYMBOL . DATA) to an error message. The
+/// message comes from the `error-message` property of ERROR-SYMBOL and is
+/// followed by the elements of DATA.
+#[defun]
+fn error_message_string(obj: GcObj, env: &Rt<Env>, cx: &Context) -> String {
+    let Object::Cons(cons) = obj.untag() else { return format!("peculiar error: {obj}") };
+    let error_symbol = cons.car();
+    let (message, mut data, file_error) = match error_symbol.untag() {
+        Object::Symbol(sym::ERROR) => match cons.cdr().untag() {
+            // (error "message" . data)
+            Object::Cons(data) => (data.car(), data.cdr(), false),
+            _ => (nil(), nil(), false),
+        },
+        Object::Symbol(symbol) => {
+            let message = crate::data::get(symbol, sym::ERROR_MESSAGE, env, cx);
+            let conditions = crate::data::get(symbol, sym::ERROR_CONDITIONS, env, cx);
+            let file_error = conditions
+                .as_list()
+                .is_ok_and(|mut x| x.any(|x| x.is_ok_and(|x| x == sym::FILE_ERROR)));
+            (message, cons.cdr(), file_error)
+        }
+        _ => (nil(), cons.cdr(), false),
+    };
+    let mut message = message;
+    // file errors are made by concatenating the data
+    if let (true, Object::Cons(cons)) = (file_error, data.untag()) {
+        message = cons.car();
+        data = cons.cdr();
+    }
+    let princ = PrintSettings::from_env(env, cx, false);
+    let prin1 = PrintSettings::from_env(env, cx, true);
+    let mut out = String::new();
+    let mut sep = Some(": ");
+    match message.untag() {
+        Object::String(s) if s.is_empty() => sep = None,
+        Object::String(_) => out.push_str(&print_to_string(message, &princ)),
+        _ => out.push_str("peculiar error"),
+    }
+    let raw = file_error || error_symbol == sym::END_OF_FILE || error_symbol == sym::USER_ERROR;
+    while let Object::Cons(cons) = data.untag() {
+        if let Some(sep) = sep {
+            out.push_str(sep);
+        }
+        sep = Some(", ");
+        out.push_str(&print_to_string(cons.car(), if raw { &princ } else { &prin1 }));
+        data = cons.cdr();
+    }
+    out
 }
 
 defvar!(PRINT_LENGTH);
 defvar!(PRINT_LEVEL);
 defvar_bool!(PRINT_ESCAPE_NEWLINES, false);
+defvar_bool!(PRINT_ESCAPE_CONTROL_CHARACTERS, false);
+defvar_bool!(PRINT_QUOTED, true);
+defvar_bool!(PRINT_CIRCLE, false);
+defvar!(FLOAT_OUTPUT_FORMAT);
+defvar!(STANDARD_OUTPUT, true);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_print() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let prin1 = PrintSettings::default();
+        let princ = PrintSettings { escape: false, ..PrintSettings::default() };
+        let print = |text, settings| {
+            let obj = crate::reader::read(text, cx).unwrap().0;
+            print_to_string(obj, settings)
+        };
+        assert_eq!(
+            print(r#"(a\ b \1 \?c d?e \#f "g\"\\")"#, &prin1),
+            r#"(a\ b \1 \?c d?e \#f "g\"\\")"#
+        );
+        assert_eq!(print(r#"(a\ b "c\"")"#, &princ), r#"(a b c")"#);
+        assert_eq!(print_to_string(crate::core::env::intern("", cx).into(), &prin1), "##");
+        assert_eq!(print("(quote a)", &prin1), "'a");
+        assert_eq!(print("(b #'c `(,d ,@e) (quote f g))", &prin1), "(b #'c `(,d ,@e) (quote f g))");
+        let unquoted = PrintSettings { quoted: false, ..PrintSettings::default() };
+        assert_eq!(print("'a", &unquoted), "(quote a)");
+
+        let escaped = PrintSettings {
+            escape_newlines: true,
+            escape_control_characters: true,
+            ..PrintSettings::default()
+        };
+        assert_eq!(print("\"a\nb\\^A1\\^Bx\"", &escaped), r#""a\nb\0011\2x""#);
+        assert_eq!(print("\"a\nb\"", &prin1), "\"a\nb\"");
+        assert_eq!(print(r#""\377a""#, &prin1), r#""\377a""#);
+        assert_eq!(print(r#""\377é\200""#, &prin1), r#""\377é\200""#);
+
+        let limited = PrintSettings { length: Some(2), level: Some(2), ..PrintSettings::default() };
+        assert_eq!(print("(1 2 3)", &limited), "(1 2 ...)");
+        assert_eq!(print("[1 (2 (3)) 4]", &limited), "[1 (2 ...) ...]");
+        assert_eq!(print("(1 . 2)", &limited), "(1 . 2)");
+
+        let circle = PrintSettings { circle: true, ..PrintSettings::default() };
+        assert_eq!(print("#1=(a . #1#)", &prin1), "(a . #0)");
+        assert_eq!(print("#1=(a . #1#)", &circle), "#1=(a . #1#)");
+        assert_eq!(print("#1=(#1#)", &prin1), "(#0)");
+        assert_eq!(print("#1=(1 2 3 . #1#)", &prin1), "(1 2 3 1 2 . #2)");
+        assert_eq!(print("(#1=(x) #1# #2=[y] #2#)", &prin1), "((x) (x) [y] [y])");
+        assert_eq!(print("(#1=(x) #1# #2=[y] #2#)", &circle), "(#1=(x) #1# #2=[y] #2#)");
+        assert_eq!(print("(a #1=\"s\" . #1#)", &circle), "(a #1=\"s\" . #1#)");
+    }
+
+    #[test]
+    fn test_print_float() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let print = |value: f64, format: Option<&str>| {
+            let float_format = format.and_then(parse_float_format);
+            print_to_string(
+                cx.add(value),
+                &PrintSettings { float_format, ..PrintSettings::default() },
+            )
+        };
+        assert_eq!(print(1.5, None), "1.5");
+        assert_eq!(print(f64::INFINITY, None), "1.0e+INF");
+        assert_eq!(print(f64::NEG_INFINITY, Some("%.3f")), "-1.0e+INF");
+        assert_eq!(print(f64::NAN, None), "0.0e+NaN");
+        assert_eq!(print(1.0, Some("%.3f")), "1.000");
+        assert_eq!(print(2.0, Some("%.0f")), "2");
+        assert_eq!(print(100_000.0, Some("%g")), "100000.0");
+        assert_eq!(print(1500.0, Some("%e")), "1.500000e+03");
+        assert_eq!(print(0.000_123_4, Some("%.2g")), "0.00012");
+        assert_eq!(print(1_234_567.0, Some("%.2g")), "1.2e+06");
+        assert_eq!(print(0.5, Some("%.0e")), "5e-01");
+        assert_eq!(print(1.25, Some("%x")), "1.25");
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let prin1 = PrintSettings::default();
+        for (value, printed) in [
+            (0.0, "0.0"),
+            (-0.0, "-0.0"),
+            (1.0, "1.0"),
+            (-1.5, "-1.5"),
+            (0.1, "0.1"),
+            (1.0 / 3.0, "0.3333333333333333"),
+            (100.0, "100.0"),
+            (1e3, "1000.0"),
+            (123_456_789.125, "123456789.125"),
+            (1e20, "1e+20"),
+            (1e21, "1e+21"),
+            (1.5e-5, "1.5e-05"),
+            (0.0001, "0.0001"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            (5e-324, "5e-324"),
+            (f64::INFINITY, "1.0e+INF"),
+            (f64::NEG_INFINITY, "-1.0e+INF"),
+            (f64::NAN, "0.0e+NaN"),
+            (-f64::NAN, "-0.0e+NaN"),
+            (f64::from_bits(f64::NAN.to_bits() | 0x2A), "42.0e+NaN"),
+        ] {
+            assert_eq!(print_to_string(cx.add(value), &prin1), printed);
+        }
+
+        // every float should read back as the same bits
+        let mut state: u64 = 0x853c_49e6_748f_ea9b;
+        let specials = [0.0, -0.0, f64::INFINITY, f64::NAN, f64::EPSILON, 1e15, 1e16, 1e17];
+        let random = std::iter::repeat_with(|| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            // signaling NaNs can't be read, just like in Emacs
+            let value = f64::from_bits(state);
+            if value.is_nan() {
+                f64::from_bits(state | f64::NAN.to_bits())
+            } else {
+                value
+            }
+        });
+        for value in specials.into_iter().chain(random.take(20_000)) {
+            let printed = print_to_string(cx.add(value), &prin1);
+            let read = crate::reader::read(&printed, cx).unwrap().0;
+            let Object::Float(float) = read.untag() else { panic!("{printed} is not a float") };
+            assert_eq!(float.get().to_bits(), value.to_bits(), "{printed}");
+        }
+    }
+
+    #[test]
+    fn test_print_streams() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        crate::root!(env, Env::default(), cx);
+        let form = r#"
+(let ((out nil))
+  (prin1 '(a "b") #'(lambda (c) (setq out (cons c out))))
+  (print 1 #'(lambda (c) (setq out (cons c out))))
+  (princ "c" #'(lambda (c) (setq out (cons c out))))
+  (write-char ?d #'(lambda (c) (setq out (cons c out))))
+  (terpri #'(lambda (c) (setq out (cons c out))))
+  (nreverse out))"#;
+        let obj = crate::reader::read(form, cx).unwrap().0;
+        crate::root!(obj, cx);
+        let val = crate::interpreter::eval(obj, None, env, cx).unwrap();
+        let printed: String = val
+            .as_list()
+            .unwrap()
+            .map(|x| char::from_u32(i64::try_from(x.unwrap()).unwrap() as u32).unwrap())
+            .collect();
+        assert_eq!(printed, "(a \"b\")\n1\ncd\n");
+
+        let name = cx.add("test_print_streams");
+        let buffer = crate::buffer::get_buffer_create(name, nil(), cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        env.vars.insert(sym::STANDARD_OUTPUT, buffer);
+        let form = "(list (prin1 'a) (terpri nil t) (terpri nil t) (prin1-to-string \"b\" t))";
+        let obj = crate::reader::read(form, cx).unwrap().0;
+        crate::root!(obj, cx);
+        let val = crate::interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val.to_string(), "(a t nil \"b\")");
+        assert_eq!(*env.current_buffer.as_ref().unwrap(), *"a\n");
+    }
+
+    #[test]
+    fn test_error_message_string() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        crate::root!(env, Env::default(), cx);
+        crate::eval::init_errors(env, cx);
+        let message = |x| error_message_string(crate::reader::read(x, cx).unwrap().0, env, cx);
+        assert_eq!(message("(error \"foo bar\")"), "foo bar");
+        assert_eq!(message("(error \"foo\" 1 \"bar\")"), "foo: 1, \"bar\"");
+        assert_eq!(
+            message("(wrong-type-argument integerp \"a\")"),
+            "Wrong type argument: integerp, \"a\""
+        );
+        assert_eq!(message("(user-error \"in buffer\")"), "in buffer");
+        assert_eq!(message("(file-missing \"Opening\" \"foo.el\")"), "Opening: foo.el");
+        assert_eq!(message("(test-error-message-unknown 1)"), "peculiar error: 1");
+    }
+}
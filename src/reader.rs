@@ -1,18 +1,29 @@
 //! Lisp reader that reads an object from a string.
+use crate::character::{
+    byte8_to_char, char_from_name, char_to_byte8, ctrlify, CHAR_ALT, CHAR_CTL, CHAR_HYPER,
+    CHAR_META, CHAR_MODIFIER_MASK, CHAR_SHIFT, CHAR_SUPER,
+};
 use crate::core::{
-    env::{intern, sym, Symbol},
-    gc::Context,
-    object::GcObj,
+    env::{intern, sym, Env, Symbol, SymbolCell},
+    gc::{Context, Rt},
+    object::{
+        is_fixnum, nil, GcObj, HashTable, HashTest, IntoObject, Object, RawMultibyte, RawObj,
+        RecordBuilder, Weakness, MAX_CHAR, NAN_PAYLOAD_MASK,
+    },
 };
 use crate::fns;
+use crate::hashmap::{HashMap, HashSet};
+use num_bigint::BigInt;
+use std::borrow::Cow;
 use std::fmt::Display;
+use std::marker::PhantomData;
 use std::str;
 use std::{fmt, iter::Peekable, str::CharIndices};
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// Errors that can occur during reading a sexp from a string
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Debug, Clone)]
 pub(crate) enum Error {
     MissingCloseParen(usize),
     MissingCloseBracket(usize),
@@ -24,6 +35,12 @@ pub(crate) enum Error {
     UnexpectedChar(char, usize),
     UnknownMacroCharacter(char, usize),
     ParseInt(u8, usize),
+    InvalidRecord(usize),
+    InvalidEscape(usize),
+    InvalidModifier(usize),
+    UnknownCharName(String, usize),
+    InvalidLabel(usize),
+    InvalidByteCode(usize),
     EmptyStream,
 }
 
@@ -45,6 +62,12 @@ impl Display for Error {
             Error::UnknownMacroCharacter(chr, i) => {
                 write!(f, "Unkown reader macro character {chr}: at {i}")
             }
+            Error::InvalidRecord(i) => write!(f, "Invalid #s syntax: at {i}"),
+            Error::InvalidEscape(i) => write!(f, "Invalid escape character syntax: at {i}"),
+            Error::InvalidModifier(i) => write!(f, "Invalid modifier in string: at {i}"),
+            Error::UnknownCharName(name, i) => write!(f, "Unknown character name {name}: at {i}"),
+            Error::InvalidLabel(i) => write!(f, "Invalid #N= or #N# syntax: at {i}"),
+            Error::InvalidByteCode(i) => write!(f, "Invalid byte-code object: at {i}"),
         }
     }
 }
@@ -63,6 +86,12 @@ impl Error {
             | Error::ExtraItemInCdr(x)
             | Error::UnexpectedChar(_, x)
             | Error::ParseInt(_, x)
+            | Error::InvalidRecord(x)
+            | Error::InvalidEscape(x)
+            | Error::InvalidModifier(x)
+            | Error::UnknownCharName(_, x)
+            | Error::InvalidLabel(x)
+            | Error::InvalidByteCode(x)
             | Error::UnknownMacroCharacter(_, x) => *x,
             Error::EmptyStream => 0,
         }
@@ -79,6 +108,12 @@ impl Error {
             | Error::ExtraCloseBracket(i)
             | Error::MissingQuotedItem(i)
             | Error::UnknownMacroCharacter(_, i)
+            | Error::InvalidRecord(i)
+            | Error::InvalidEscape(i)
+            | Error::InvalidModifier(i)
+            | Error::UnknownCharName(_, i)
+            | Error::InvalidLabel(i)
+            | Error::InvalidByteCode(i)
             | Error::ParseInt(_, i) => Some(i),
             Error::EmptyStream => None,
         }
@@ -91,7 +126,7 @@ impl Error {
     }
 }
 
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Debug, Clone)]
 enum Token<'a> {
     OpenParen(usize),
     CloseParen(usize),
@@ -101,13 +136,60 @@ enum Token<'a> {
     Backquote(usize),
     Unquote(usize),
     Splice(usize),
-    Sharp(usize),
-    QuestionMark(usize, char),
-    Ident(&'a str),
-    String(&'a str),
+    Sharp(usize, Sharp<'a>),
+    QuestionMark(usize, u32),
+    Ident(usize, Cow<'a, str>),
+    /// The contents of a string literal, and where they start.
+    String(usize, Cow<'a, str>),
     Error(Error),
 }
 
+/// The syntax that starts with a `#`.
+#[derive(PartialEq, Debug, Clone)]
+enum Sharp<'a> {
+    /// `#'`, followed by a function.
+    Function,
+    /// `#s`, followed by the list of a record or hash table.
+    Record,
+    /// `#b`, `#o`, `#x` or `#NNr`, followed by the digits of an integer.
+    Radix(u8),
+    /// `#N=`, followed by the labeled object.
+    Label(u32),
+    /// `#N#`
+    LabelRef(u32),
+    /// `#[`, which starts a byte-code function.
+    ByteCode,
+    /// `#$`
+    LoadFileName,
+    /// `##` or `#_NAME`, a symbol that is never read as a number.
+    Symbol(Cow<'a, str>),
+    /// `#:NAME`
+    Uninterned(Cow<'a, str>),
+    /// `#r"..."`
+    RawString(Cow<'a, str>),
+}
+
+impl Token<'_> {
+    /// The position of the token in the source it was read from.
+    fn pos(&self) -> usize {
+        match self {
+            Token::OpenParen(x)
+            | Token::CloseParen(x)
+            | Token::OpenBracket(x)
+            | Token::CloseBracket(x)
+            | Token::Quote(x)
+            | Token::Backquote(x)
+            | Token::Unquote(x)
+            | Token::Splice(x)
+            | Token::Sharp(x, _)
+            | Token::QuestionMark(x, _)
+            | Token::Ident(x, _)
+            | Token::String(x, _) => *x,
+            Token::Error(e) => e.position(),
+        }
+    }
+}
+
 impl<'a> Display for Token<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -119,64 +201,128 @@ impl<'a> Display for Token<'a> {
             Token::Backquote(_) => write!(f, "`"),
             Token::Unquote(_) => write!(f, ","),
             Token::Splice(_) => write!(f, ",@"),
-            Token::Sharp(_) => write!(f, "#"),
-            Token::QuestionMark(_, chr) => write!(f, "?{chr}"),
-            Token::Ident(x) => write!(f, "{x}"),
-            Token::String(x) => write!(f, "\"{x}\""),
+            Token::Sharp(..) => write!(f, "#"),
+            Token::QuestionMark(_, code) => match char::from_u32(*code) {
+                Some(chr) => write!(f, "?{chr}"),
+                None => write!(f, "?{code}"),
+            },
+            Token::Ident(_, x) => write!(f, "{x}"),
+            Token::String(_, x) => write!(f, "\"{x}\""),
             Token::Error(_) => write!(f, "error"),
         }
     }
 }
 
+/// Where a [`Tokenizer`] gets its characters. Positions are byte offsets from
+/// the start of the source.
+pub(crate) trait CharSource<'a> {
+    /// Return the next character and its position without consuming it.
+    fn peek(&mut self) -> Option<(usize, char)>;
+
+    /// Consume the next character and return it with its position.
+    fn next(&mut self) -> Option<(usize, char)>;
+
+    /// Return the position of the next character. At the end of the source
+    /// this is the length of everything that was read.
+    fn pos(&mut self) -> usize;
+
+    /// The text between `start` and `end`, which have already been read.
+    fn text(&self, start: usize, end: usize) -> Cow<'a, str>;
+
+    /// Consume the next character if `func` returns true for it.
+    fn next_if(&mut self, func: impl FnOnce(&(usize, char)) -> bool) -> Option<(usize, char)> {
+        let next = self.peek()?;
+        if func(&next) {
+            self.next()
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T: CharSource<'a>> CharSource<'a> for &mut T {
+    fn peek(&mut self) -> Option<(usize, char)> {
+        (**self).peek()
+    }
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        (**self).next()
+    }
+
+    fn pos(&mut self) -> usize {
+        (**self).pos()
+    }
+
+    fn text(&self, start: usize, end: usize) -> Cow<'a, str> {
+        (**self).text(start, end)
+    }
+}
+
+/// A [`CharSource`] over a string slice. The text of tokens is borrowed from
+/// the slice.
 #[derive(Clone)]
-struct Tokenizer<'a> {
+struct StrSource<'a> {
     slice: &'a str,
     iter: Peekable<CharIndices<'a>>,
 }
 
-impl<'a> Tokenizer<'a> {
+impl<'a> StrSource<'a> {
     fn new(slice: &'a str) -> Self {
         Self { slice, iter: slice.char_indices().peekable() }
     }
+}
 
-    /// Given a [`Token`] calculate it's position relative to this `Tokenizer`.
-    fn relative_pos(&self, token: Token<'a>) -> usize {
-        match token {
-            Token::OpenParen(x)
-            | Token::CloseParen(x)
-            | Token::OpenBracket(x)
-            | Token::CloseBracket(x)
-            | Token::Quote(x)
-            | Token::Backquote(x)
-            | Token::Unquote(x)
-            | Token::Splice(x)
-            | Token::Sharp(x)
-            | Token::QuestionMark(x, _) => x,
-            Token::Ident(slice) | Token::String(slice) => {
-                let beg = self.slice.as_ptr() as usize;
-                let end = slice.as_ptr() as usize;
-                end - beg
-            }
-            Token::Error(e) => e.position(),
-        }
+impl<'a> CharSource<'a> for StrSource<'a> {
+    fn peek(&mut self) -> Option<(usize, char)> {
+        self.iter.peek().copied()
     }
 
-    /// Return the current position of the Tokenizer. This is the index of the
-    /// next character.
-    fn cur_pos(&mut self) -> usize {
+    fn next(&mut self) -> Option<(usize, char)> {
+        self.iter.next()
+    }
+
+    fn pos(&mut self) -> usize {
         match self.iter.peek() {
             Some((idx, _)) => *idx,
             None => self.slice.len(),
         }
     }
 
+    fn text(&self, start: usize, end: usize) -> Cow<'a, str> {
+        Cow::Borrowed(&self.slice[start..end])
+    }
+}
+
+#[derive(Clone)]
+struct Tokenizer<'a, S> {
+    source: S,
+    /// Whether rune's extensions to the syntax, like `#r"..."`, can be read.
+    extended_syntax: bool,
+    /// Tokens borrow their text from the source for `'a`.
+    _text: PhantomData<&'a str>,
+}
+
+impl<'a> Tokenizer<'a, StrSource<'a>> {
+    fn from_str(slice: &'a str) -> Self {
+        Self::new(StrSource::new(slice))
+    }
+}
+
+impl<'a, S: CharSource<'a>> Tokenizer<'a, S> {
+    fn new(source: S) -> Self {
+        Self { source, extended_syntax: false, _text: PhantomData }
+    }
+
+    /// Return the current position of the Tokenizer. This is the index of the
+    /// next character.
+    fn cur_pos(&mut self) -> usize {
+        self.source.pos()
+    }
+
     /// Skip characters until the closure returns true.
     fn skip_till(&mut self, mut func: impl FnMut(char) -> bool) -> usize {
-        while self.iter.next_if(|x| !func(x.1)).is_some() {}
-        match self.iter.peek() {
-            Some((idx, _)) => *idx,
-            None => self.slice.len(),
-        }
+        while self.source.next_if(|x| !func(x.1)).is_some() {}
+        self.source.pos()
     }
 
     /// Skip whitespace and comments until the next valid read character.
@@ -202,60 +348,248 @@ impl<'a> Tokenizer<'a> {
 
     fn get_string(&mut self, open_delim_pos: usize) -> Token<'a> {
         let mut skip = false;
-        let idx_chr = self.iter.find(|(_, chr)| !escaped(&mut skip, *chr) && *chr == '"');
-        match idx_chr {
-            Some((end, '"')) => Token::String(&self.slice[(open_delim_pos + 1)..end]),
-            _ => Token::Error(Error::MissingStringDel(open_delim_pos)),
+        while let Some((end, chr)) = self.source.next() {
+            if !escaped(&mut skip, chr) && chr == '"' {
+                let start = open_delim_pos + 1;
+                return Token::String(start, self.source.text(start, end));
+            }
         }
+        Token::Error(Error::MissingStringDel(open_delim_pos))
     }
 
     fn get_symbol(&mut self, beg: usize, chr: char) -> Token<'a> {
         let mut skip = chr == '\\';
         let end = self.skip_till(|c| !escaped(&mut skip, c) && !symbol_char(c));
-        Token::Ident(&self.slice[beg..end])
+        Token::Ident(beg, self.source.text(beg, end))
     }
 
     /// After having found a `,`, see if the next token is a `@` or not.
     fn get_macro_char(&mut self, idx: usize) -> Token<'a> {
-        match self.iter.next_if(|(_, chr)| *chr == '@') {
+        match self.source.next_if(|(_, chr)| *chr == '@') {
             Some(_) => Token::Splice(idx),
             None => Token::Unquote(idx),
         }
     }
 
-    fn read_quoted_char(&mut self, idx: usize) -> Token<'a> {
-        match self.iter.next() {
-            Some((start, item)) => {
-                if item == '\\' {
-                    // TODO implement keycode parsing
-                    self.get_symbol(start, item);
-                    Token::QuestionMark(start, '\0')
-                } else {
-                    match self.iter.peek() {
-                        // ?aa
-                        Some((i, chr)) if symbol_char(*chr) && *chr != '?' => {
-                            Token::Error(Error::UnexpectedChar(*chr, *i))
+    /// Read the syntax after a `#`, which is at `idx`.
+    fn get_sharp(&mut self, idx: usize) -> Token<'a> {
+        let sharp = match self.read_char() {
+            Some('\'') => Sharp::Function,
+            Some('s') => Sharp::Record,
+            Some('b') => Sharp::Radix(2),
+            Some('o') => Sharp::Radix(8),
+            Some('x') => Sharp::Radix(16),
+            // rune has no shorthands, so this is just a symbol that is never
+            // read as a number
+            Some('_') => Sharp::Symbol(self.read_symbol_name()),
+            Some('#') => Sharp::Symbol(Cow::Borrowed("")),
+            Some('[') => Sharp::ByteCode,
+            Some('$') => Sharp::LoadFileName,
+            Some('r') if self.extended_syntax => {
+                let Some((start, _)) = self.source.next_if(|x| x.1 == '"') else {
+                    return Token::Error(Error::UnknownMacroCharacter('r', idx));
+                };
+                match self.get_string(start) {
+                    Token::String(_, raw) => Sharp::RawString(raw),
+                    error => return error,
+                }
+            }
+            Some(':') => Sharp::Uninterned(self.read_symbol_name()),
+            Some(chr) if chr.is_ascii_digit() => {
+                let Some(num) = self.read_decimal(chr) else {
+                    return Token::Error(Error::InvalidLabel(idx));
+                };
+                match self.read_char() {
+                    Some('=') => Sharp::Label(num),
+                    Some('#') => Sharp::LabelRef(num),
+                    Some('r') => match u8::try_from(num) {
+                        Ok(radix @ 2..=36) => Sharp::Radix(radix),
+                        _ => {
+                            let radix = num.try_into().unwrap_or(u8::MAX);
+                            return Token::Error(Error::ParseInt(radix, idx));
                         }
-                        // ?a
-                        _ => Token::QuestionMark(idx, item),
-                    }
+                    },
+                    Some(chr) => return Token::Error(Error::UnknownMacroCharacter(chr, idx)),
+                    None => return Token::Error(Error::MissingQuotedItem(idx)),
+                }
+            }
+            Some(chr) => return Token::Error(Error::UnknownMacroCharacter(chr, idx)),
+            None => return Token::Error(Error::MissingQuotedItem(idx)),
+        };
+        Token::Sharp(idx, sharp)
+    }
+
+    fn read_quoted_char(&mut self, idx: usize) -> Token<'a> {
+        let code = match self.source.next() {
+            Some((start, '\\')) => match self.read_char_escape(start, false) {
+                // raw bytes are just integers in a character literal
+                Ok(code) => char_to_byte8(code).map_or(code, u32::from),
+                Err(e) => return Token::Error(e),
+            },
+            Some((_, chr)) => chr.into(),
+            None => return Token::Error(Error::MissingQuotedItem(idx)),
+        };
+        match self.source.peek() {
+            // ?aa
+            Some((i, chr)) if symbol_char(chr) && chr != '?' => {
+                Token::Error(Error::UnexpectedChar(chr, i))
+            }
+            // ?a
+            _ => Token::QuestionMark(idx, code),
+        }
+    }
+
+    /// Read the escape sequence of a character literal, or of a string literal
+    /// if `in_string` is set. `pos` is the position of the backslash.
+    fn read_char_escape(&mut self, pos: usize, in_string: bool) -> Result<u32> {
+        let Some((_, chr)) = self.source.next() else { return Err(Error::MissingQuotedItem(pos)) };
+        let code = match chr {
+            'a' => 7,
+            'b' => 8,
+            'd' => 127,
+            'e' => 27,
+            'f' => 12,
+            'n' => 10,
+            'r' => 13,
+            't' => 9,
+            'v' => 11,
+            // \s is a space, unless it is the super modifier in a character
+            's' if !in_string && self.source.next_if(|x| x.1 == '-').is_some() => {
+                CHAR_SUPER | self.read_modified_char(pos, in_string)?
+            }
+            's' => 32,
+            '^' => ctrlify(self.read_modified_char(pos, in_string)?),
+            'C' | 'M' | 'S' | 'H' | 'A' => {
+                if self.source.next_if(|x| x.1 == '-').is_none() {
+                    return Err(Error::InvalidEscape(pos));
+                }
+                let code = self.read_modified_char(pos, in_string)?;
+                match chr {
+                    'C' => ctrlify(code),
+                    'M' => code | CHAR_META,
+                    'S' => code | CHAR_SHIFT,
+                    'H' => code | CHAR_HYPER,
+                    _ => code | CHAR_ALT,
+                }
+            }
+            // a hex escape of at most two digits is a raw byte
+            'x' => match self.read_escape_digits(16, usize::MAX) {
+                Some((code, len)) if len > 0 && code <= MAX_CHAR => match u8::try_from(code) {
+                    Ok(byte) if len < 3 && byte >= 0x80 => byte8_to_char(byte),
+                    _ => code,
+                },
+                _ => return Err(Error::InvalidEscape(pos)),
+            },
+            'u' | 'U' => {
+                let expected = if chr == 'u' { 4 } else { 8 };
+                match self.read_escape_digits(16, expected) {
+                    Some((code, len)) if len == expected && code <= 0x10_FFFF => code,
+                    _ => return Err(Error::InvalidEscape(pos)),
+                }
+            }
+            'N' => self.read_char_name(pos)?,
+            '0'..='7' => {
+                let digit = chr.to_digit(8).unwrap();
+                let (rest, len) = self.read_escape_digits(8, 2).unwrap();
+                let code = digit * 8_u32.pow(len as u32) + rest;
+                match u8::try_from(code) {
+                    Ok(byte) if byte >= 0x80 => byte8_to_char(byte),
+                    _ => code,
                 }
             }
-            None => Token::Error(Error::MissingQuotedItem(idx)),
+            other => other.into(),
+        };
+        Ok(code)
+    }
+
+    /// Read the character after a modifier prefix like `\C-`, which can
+    /// itself be an escape sequence.
+    fn read_modified_char(&mut self, pos: usize, in_string: bool) -> Result<u32> {
+        match self.source.next() {
+            Some((i, '\\')) => self.read_char_escape(i, in_string),
+            Some((_, chr)) => Ok(chr.into()),
+            None => Err(Error::MissingQuotedItem(pos)),
+        }
+    }
+
+    /// Read up to `max_len` digits in `radix`. Return the value and the number
+    /// of digits read, or `None` if the value overflows.
+    fn read_escape_digits(&mut self, radix: u32, max_len: usize) -> Option<(u32, usize)> {
+        let mut value: u32 = 0;
+        let mut len = 0;
+        while len < max_len {
+            let Some((_, chr)) = self.source.next_if(|x| x.1.is_digit(radix)) else { break };
+            value = value.checked_mul(radix)?.checked_add(chr.to_digit(radix)?)?;
+            len += 1;
+        }
+        Some((value, len))
+    }
+
+    /// Read the `{NAME}` part of a `\N{NAME}` escape.
+    fn read_char_name(&mut self, pos: usize) -> Result<u32> {
+        if self.source.next_if(|x| x.1 == '{').is_none() {
+            return Err(Error::InvalidEscape(pos));
         }
+        let start = self.cur_pos();
+        let end = self.skip_till(|chr| chr == '}');
+        if self.source.next().is_none() {
+            return Err(Error::InvalidEscape(pos));
+        }
+        let name = self.source.text(start, end);
+        char_from_name(&name).ok_or_else(|| Error::UnknownCharName(name.into_owned(), pos))
     }
 
     fn read_char(&mut self) -> Option<char> {
-        self.iter.next().map(|x| x.1)
+        self.source.next().map(|x| x.1)
+    }
+
+    /// Read the rest of a decimal number that starts with `first`. Return
+    /// `None` if the value overflows.
+    fn read_decimal(&mut self, first: char) -> Option<u32> {
+        let mut value = first.to_digit(10)?;
+        while let Some((_, chr)) = self.source.next_if(|x| x.1.is_ascii_digit()) {
+            value = value.checked_mul(10)?.checked_add(chr.to_digit(10)?)?;
+        }
+        Some(value)
+    }
+
+    /// Read a symbol name that starts at the current position. Unlike
+    /// [`Tokenizer::next`] this does not skip whitespace, so the name can be
+    /// empty.
+    fn read_symbol_name(&mut self) -> Cow<'a, str> {
+        let beg = self.cur_pos();
+        let mut skip = false;
+        let end = self.skip_till(|c| !escaped(&mut skip, c) && !symbol_char(c));
+        self.source.text(beg, end)
+    }
+
+    /// Skip a `#@NUMBER` comment, which ignores the NUMBER bytes following it.
+    /// The character that ends the number is counted as the first skipped
+    /// byte. `#@00` skips the rest of the input.
+    fn skip_lazy_string(&mut self) {
+        let mut count: usize = 0;
+        let mut digits = 0;
+        while let Some((_, chr)) = self.source.next_if(|x| x.1.is_ascii_digit()) {
+            let digit = chr.to_digit(10).unwrap() as usize;
+            count = count.saturating_mul(10).saturating_add(digit);
+            digits += 1;
+        }
+        if count == 0 && digits == 2 {
+            while self.source.next().is_some() {}
+            return;
+        }
+        let Some((start, _)) = self.source.next() else { return };
+        let end = start + count;
+        while self.cur_pos() < end && self.source.next().is_some() {}
     }
 }
 
-impl<'a> Iterator for Tokenizer<'a> {
+impl<'a, S: CharSource<'a>> Iterator for Tokenizer<'a, S> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.skip_till_char();
-        let (idx, chr) = self.iter.next()?;
+        let (idx, chr) = self.source.next()?;
         let token = match chr {
             '(' => Token::OpenParen(idx),
             ')' => Token::CloseParen(idx),
@@ -264,7 +598,11 @@ impl<'a> Iterator for Tokenizer<'a> {
             '\'' => Token::Quote(idx),
             ',' => self.get_macro_char(idx),
             '`' => Token::Backquote(idx),
-            '#' => Token::Sharp(idx),
+            '#' if self.source.next_if(|x| x.1 == '@').is_some() => {
+                self.skip_lazy_string();
+                return self.next();
+            }
+            '#' => self.get_sharp(idx),
             '?' => self.read_quoted_char(idx),
             '"' => self.get_string(idx),
             other if symbol_char(other) => self.get_symbol(idx, other),
@@ -274,7 +612,8 @@ impl<'a> Iterator for Tokenizer<'a> {
     }
 }
 
-fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+/// Remove the escapes from the name of a symbol.
+fn symbol_name(symbol: &str) -> Cow<'_, str> {
     let mut escaped = false;
     let is_not_escape = |c: &char| {
         if escaped {
@@ -288,48 +627,162 @@ fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
         }
     };
     if symbol.contains('\\') {
-        let escaped_slice: String = symbol.chars().filter(is_not_escape).collect();
-        intern(escaped_slice.as_str(), cx)
+        Cow::Owned(symbol.chars().filter(is_not_escape).collect())
     } else {
-        intern(symbol, cx)
+        Cow::Borrowed(symbol)
     }
 }
 
+fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+    intern(&symbol_name(symbol), cx)
+}
+
 /// Parse a symbol from a string. This will either by a true symbol or a number
 /// literal.
 fn parse_symbol<'a>(slice: &str, cx: &'a Context) -> GcObj<'a> {
-    match slice.parse::<i64>() {
-        Ok(num) => cx.add(num),
-        Err(_) => match slice.parse::<f64>() {
-            Ok(num) => cx.add(num),
-            Err(_) => cx.add(intern_symbol(slice, cx)),
+    // an integer can end with a `.`
+    let integer = slice.strip_suffix('.').unwrap_or(slice);
+    match integer.parse::<i64>() {
+        Ok(num) if is_fixnum(num) => cx.add(num),
+        _ => match parse_integer(integer, 10) {
+            Some(num) => cx.add(num),
+            None => match parse_float(slice) {
+                Some(num) => cx.add(num),
+                None => cx.add(intern_symbol(slice, cx)),
+            },
         },
     }
 }
 
-/// process escape characters in the string slice and return the resulting
-/// string.
-fn unescape_string(string: &str) -> String {
-    let mut escaped = false;
-    let unescape = |c: char| {
-        if escaped {
-            escaped = false;
-            // TODO: Handle unicode, hex, and octal escapes
-            match c {
-                'n' => Some('\n'),
-                't' => Some('\t'),
-                'r' => Some('\r'),
-                '\n' | ' ' => None,
-                _ => Some(c),
+/// True if `name` reads as a number, so a symbol with that name has to be
+/// escaped when it is printed.
+pub(crate) fn is_number(name: &str) -> bool {
+    let integer = name.strip_suffix('.').unwrap_or(name);
+    parse_integer(integer, 10).is_some() || parse_float(name).is_some()
+}
+
+/// Parse a float with the Emacs syntax. There have to be digits after the
+/// decimal point, or an exponent, so `1.` is an integer but `.5` and `1e3`
+/// are floats. An exponent of `+INF` or `+NaN` makes an infinity or a NaN,
+/// with the integer part of a NaN as its payload.
+fn parse_float(slice: &str) -> Option<f64> {
+    let unsigned = slice.strip_prefix(['-', '+']).unwrap_or(slice);
+    let lead_digits = unsigned.bytes().take_while(u8::is_ascii_digit).count();
+    let mut rest = &unsigned[lead_digits..];
+    let mut trail_digits = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        trail_digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        rest = &fraction[trail_digits..];
+    }
+    let value = if rest.is_empty() {
+        if trail_digits == 0 {
+            return None;
+        }
+        unsigned.parse().ok()?
+    } else {
+        let exponent = rest.strip_prefix(['e', 'E'])?;
+        if lead_digits == 0 && trail_digits == 0 {
+            return None;
+        }
+        match exponent {
+            "+INF" => f64::INFINITY,
+            "+NaN" => {
+                let payload = unsigned[..lead_digits].parse::<u64>().unwrap_or(u64::MAX);
+                f64::from_bits(f64::NAN.to_bits() | (payload & NAN_PAYLOAD_MASK))
             }
-        } else if c == '\\' {
-            escaped = true;
-            None
+            _ => {
+                let digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+                if digits.is_empty() || !digits.bytes().all(|x| x.is_ascii_digit()) {
+                    return None;
+                }
+                unsigned.parse().ok()?
+            }
+        }
+    };
+    Some(if slice.starts_with('-') { -value } else { value })
+}
+
+/// Parse an integer of any size. Unlike [`BigInt::parse_bytes`], this does not
+/// allow underscores between the digits.
+fn parse_integer(slice: &str, radix: u32) -> Option<BigInt> {
+    let digits = slice.strip_prefix(['-', '+']).unwrap_or(slice);
+    if digits.is_empty() || !digits.chars().all(|x| x.is_digit(radix)) {
+        return None;
+    }
+    BigInt::parse_bytes(slice.as_bytes(), radix)
+}
+
+/// The contents of a string literal after processing the escapes.
+enum StringLiteral {
+    Multibyte(String),
+    Unibyte(Vec<u8>),
+    RawMultibyte(RawMultibyte),
+}
+
+/// Process the escapes in the string literal `string`. Following Emacs, the
+/// string is unibyte if it holds only ASCII and raw bytes (from hex or octal
+/// escapes). Any other non-ASCII character, whether it is written literally or
+/// with a `\u`, `\U` or `\N` escape, makes the string multibyte, and then the
+/// raw bytes become raw-byte characters.
+fn unescape_string(string: &str) -> Result<StringLiteral> {
+    let mut chars = Tokenizer::from_str(string);
+    let mut codes = Vec::new();
+    let mut raw_bytes = false;
+    let mut multibyte = false;
+    while let Some((pos, chr)) = chars.source.next() {
+        if chr != '\\' {
+            multibyte |= !chr.is_ascii();
+            codes.push(u32::from(chr));
+            continue;
+        }
+        // backslash-newline and backslash-space are ignored
+        if chars.source.next_if(|x| matches!(x.1, '\n' | ' ')).is_some() {
+            continue;
+        }
+        let code = chars.read_char_escape(pos, true)?;
+        let mut modifiers = code & CHAR_MODIFIER_MASK;
+        let mut code = code & !CHAR_MODIFIER_MASK;
+        if char_to_byte8(code).is_some() {
+            raw_bytes = true;
+        } else if code >= 0x80 {
+            if char::from_u32(code).is_none() {
+                return Err(Error::InvalidEscape(pos));
+            }
+            multibyte = true;
         } else {
-            Some(c)
+            // \C-SPC is NUL in a string
+            if modifiers == CHAR_CTL && code == u32::from(' ') {
+                code = 0;
+                modifiers = 0;
+            }
+            let byte = code as u8;
+            if modifiers & CHAR_SHIFT != 0 && byte.is_ascii_alphabetic() {
+                code = byte.to_ascii_uppercase().into();
+                modifiers &= !CHAR_SHIFT;
+            }
+            // meta sets the high bit of the byte
+            if modifiers & CHAR_META != 0 {
+                code = byte8_to_char(code as u8 | 0x80);
+                modifiers &= !CHAR_META;
+                raw_bytes = true;
+            }
+        }
+        if modifiers != 0 {
+            return Err(Error::InvalidModifier(pos));
         }
+        codes.push(code);
+    }
+    let literal = match (multibyte, raw_bytes) {
+        (true, true) => StringLiteral::RawMultibyte(RawMultibyte::from_codes(codes)),
+        // only ASCII and raw bytes are left
+        (false, true) => StringLiteral::Unibyte(
+            codes.into_iter().map(|x| char_to_byte8(x).unwrap_or(x as u8)).collect(),
+        ),
+        (_, false) => StringLiteral::Multibyte(
+            codes.into_iter().map(|x| char::from_u32(x).unwrap()).collect(),
+        ),
     };
-    string.chars().filter_map(unescape).collect()
+    Ok(literal)
 }
 
 /// Return true if `chr` is a valid symbol character.
@@ -350,14 +803,20 @@ fn escaped(escaped: &mut bool, chr: char) -> bool {
 }
 
 /// State of the reader.
-struct Reader<'a, 'ob> {
-    /// The iterator over the tokens in the current slice.
-    tokens: Tokenizer<'a>,
+struct Reader<'ob, T> {
+    /// The tokens of the object being read.
+    tokens: T,
     /// New objects are allocated in the context.
     cx: &'ob Context<'ob>,
+    /// Objects defined with `#N=` in the current read.
+    labels: HashMap<u32, GcObj<'ob>>,
+    settings: ReadSettings<'ob>,
+    /// The lists read so far and their start positions, if positions are
+    /// being recorded.
+    positions: Option<Vec<(GcObj<'ob>, usize)>>,
 }
 
-impl<'a, 'ob> Reader<'a, 'ob> {
+impl<'a, 'ob, T: Iterator<Item = Token<'a>>> Reader<'ob, T> {
     /// Read the cdr of a literal list.
     /// ```lisp
     /// '(1 2 3 . 45)
@@ -370,7 +829,7 @@ impl<'a, 'ob> Reader<'a, 'ob> {
                 let obj = self.read_sexp(sexp);
                 match self.tokens.next() {
                     Some(Token::CloseParen(_)) => obj.map(Some),
-                    Some(token) => Err(Error::ExtraItemInCdr(self.tokens.relative_pos(token))),
+                    Some(token) => Err(Error::ExtraItemInCdr(token.pos())),
                     None => Err(Error::MissingCloseParen(delim)),
                 }
             }
@@ -382,13 +841,17 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         let mut objects = Vec::new();
         while let Some(token) = self.tokens.next() {
             match token {
-                Token::CloseParen(_) => return Ok(fns::slice_into_list(&objects, None, self.cx)),
-                Token::Ident(".") => {
+                Token::CloseParen(_) => {
+                    let list = fns::slice_into_list(&objects, None, self.cx);
+                    return Ok(self.record_position(list, delim));
+                }
+                Token::Ident(_, ref x) if x == "." => {
                     let cdr = self.read_cdr(delim)?;
                     if cdr.is_none() {
                         objects.push(parse_symbol(".", self.cx));
                     }
-                    return Ok(fns::slice_into_list(&objects, cdr, self.cx));
+                    let list = fns::slice_into_list(&objects, cdr, self.cx);
+                    return Ok(self.record_position(list, delim));
                 }
                 tok => objects.push(self.read_sexp(tok)?),
             }
@@ -396,58 +859,213 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         Err(Error::MissingCloseParen(delim))
     }
 
+    /// Note that the list `obj` started at `pos`.
+    fn record_position(&mut self, obj: GcObj<'ob>, pos: usize) -> GcObj<'ob> {
+        if let Some(positions) = &mut self.positions {
+            if matches!(obj.untag(), Object::Cons(_)) {
+                positions.push((obj, pos));
+            }
+        }
+        obj
+    }
+
     fn read_vec(&mut self, delim: usize) -> Result<GcObj<'ob>> {
+        let objects = self.read_vec_elements(delim)?;
+        Ok(self.cx.add(objects))
+    }
+
+    fn read_vec_elements(&mut self, delim: usize) -> Result<Vec<GcObj<'ob>>> {
         let mut objects = Vec::new();
         while let Some(token) = self.tokens.next() {
             match token {
-                Token::CloseBracket(_) => return Ok(self.cx.add(objects)),
+                Token::CloseBracket(_) => return Ok(objects),
                 tok => objects.push(self.read_sexp(tok)?),
             }
         }
         Err(Error::MissingCloseBracket(delim))
     }
 
+    /// Read a byte-code function. The argument list is an integer for lexical
+    /// functions and a list of symbols for dynamically bound ones.
+    /// ```lisp
+    /// #[257 "\300\1!\207" [foo] 3 "docstring"]
+    /// #[(x) "\301\10!\207" [x foo] 2]
+    /// ```
+    fn read_byte_code(&mut self, pos: usize) -> Result<GcObj<'ob>> {
+        let slots = self.read_vec_elements(pos + 1)?;
+        let [args, code, consts, depth, rest @ ..] = slots.as_slice() else {
+            return Err(Error::InvalidByteCode(pos));
+        };
+        let (Object::String(code), Object::Vec(consts), Object::Int(depth)) =
+            (code.untag(), consts.untag(), depth.untag())
+        else {
+            return Err(Error::InvalidByteCode(pos));
+        };
+        let Ok(depth) = usize::try_from(depth) else {
+            return Err(Error::InvalidByteCode(pos));
+        };
+        let doc = rest.first().copied();
+        let interactive = rest.get(1).copied();
+        let elements = rest.get(2..).unwrap_or_default();
+        match crate::alloc::make_byte_code(
+            *args,
+            code,
+            consts,
+            depth,
+            doc,
+            interactive,
+            elements,
+            self.cx,
+        ) {
+            Ok(func) => Ok(func.into()),
+            Err(_) => Err(Error::InvalidByteCode(pos)),
+        }
+    }
+
     /// Quote an item using `symbol`.
     fn quote_item(&mut self, pos: usize, symbol: Symbol) -> Result<GcObj<'ob>> {
         let obj: GcObj = match self.tokens.next() {
             Some(token) => self.read_sexp(token)?,
             None => return Err(Error::MissingQuotedItem(pos)),
         };
-        Ok(list!(symbol, obj; self.cx))
+        let list = list!(symbol, obj; self.cx);
+        Ok(self.record_position(list, pos))
     }
 
     /// Read number with specificed radix
     fn read_radix(&mut self, pos: usize, radix: u8) -> Result<GcObj<'ob>> {
         match self.tokens.next() {
-            Some(Token::Ident(ident)) => match usize::from_str_radix(ident, radix.into()) {
-                Ok(x) => Ok(self.cx.add(x as i64)),
-                Err(_) => Err(Error::ParseInt(radix, pos)),
+            Some(Token::Ident(_, ident)) => match parse_integer(&ident, radix.into()) {
+                Some(x) => Ok(self.cx.add(x)),
+                None => Err(Error::ParseInt(radix, pos)),
             },
             _ => Err(Error::ParseInt(radix, pos)),
         }
     }
 
-    /// read a sharp quoted character. This could be used for reader macro's in
-    /// the future, but right now it just handles the special cases from elisp.
-    fn read_sharp(&mut self, pos: usize) -> Result<GcObj<'ob>> {
-        match self.tokens.read_char() {
-            Some('\'') => match self.tokens.next() {
+    /// Read a record or a hash table.
+    /// ```lisp
+    /// #s(foo 1 2)
+    /// #s(hash-table size 1 test equal data (key value))
+    /// ```
+    fn read_record(&mut self, pos: usize) -> Result<GcObj<'ob>> {
+        let slots = match self.tokens.next() {
+            Some(Token::OpenParen(i)) => self.read_list(i)?,
+            Some(_) => return Err(Error::InvalidRecord(pos)),
+            None => return Err(Error::MissingQuotedItem(pos)),
+        };
+        let slots = list_to_vec(slots, pos)?;
+        match slots.as_slice() {
+            [obj, plist @ ..] if *obj == sym::HASH_TABLE => self.read_hash_table(pos, plist),
+            [_, ..] => Ok(self.cx.add(RecordBuilder(slots))),
+            [] => Err(Error::InvalidRecord(pos)),
+        }
+    }
+
+    /// Build a hash table from the property list of its printed form. Only
+    /// the builtin tests can be read, because custom tests are defined at
+    /// runtime.
+    fn read_hash_table(&mut self, pos: usize, plist: &[GcObj<'ob>]) -> Result<GcObj<'ob>> {
+        let mut test = HashTest::Eql;
+        let mut weakness = None;
+        let mut data = Vec::new();
+        for pair in plist.chunks(2) {
+            let &[prop, val] = pair else { return Err(Error::InvalidRecord(pos)) };
+            match (prop.untag(), val.untag()) {
+                (Object::Symbol(sym::TEST), Object::Symbol(sym::EQ)) => test = HashTest::Eq,
+                (Object::Symbol(sym::TEST), Object::Symbol(sym::EQL)) => test = HashTest::Eql,
+                (Object::Symbol(sym::TEST), Object::Symbol(sym::EQUAL)) => test = HashTest::Equal,
+                (Object::Symbol(sym::TEST), _) => return Err(Error::InvalidRecord(pos)),
+                (Object::Symbol(sym::WEAKNESS), Object::NIL) => weakness = None,
+                (Object::Symbol(sym::WEAKNESS), Object::Symbol(s)) => {
+                    weakness = Some(Weakness::from_symbol(s).ok_or(Error::InvalidRecord(pos))?);
+                }
+                (Object::Symbol(sym::DATA), _) => data = list_to_vec(val, pos)?,
+                // size, rehash-size and the like don't affect the contents
+                _ => {}
+            }
+        }
+        if data.len() % 2 != 0 {
+            return Err(Error::InvalidRecord(pos));
+        }
+        let table = HashTable::default().into_obj(self.cx);
+        let table = table.untag();
+        table.set_test(test);
+        table.set_weakness(weakness);
+        for pair in data.chunks(2) {
+            // a new table is not borrowed, so this only fails for circular keys
+            table.insert(pair[0], pair[1]).map_err(|_| Error::InvalidRecord(pos))?;
+        }
+        Ok(table.into())
+    }
+
+    /// Read the object for the syntax `sharp` that starts with a `#`.
+    fn read_sharp(&mut self, pos: usize, sharp: Sharp<'a>) -> Result<GcObj<'ob>> {
+        match sharp {
+            Sharp::Function => match self.tokens.next() {
                 Some(Token::OpenParen(i)) => {
                     let list = self.read_list(i)?;
-                    Ok(list!(sym::FUNCTION, list; self.cx))
+                    let quoted = list!(sym::FUNCTION, list; self.cx);
+                    Ok(self.record_position(quoted, pos))
                 }
                 Some(token) => {
                     let obj = self.read_sexp(token)?;
-                    Ok(list!(sym::FUNCTION, obj; self.cx))
+                    let quoted = list!(sym::FUNCTION, obj; self.cx);
+                    Ok(self.record_position(quoted, pos))
                 }
                 None => Err(Error::MissingQuotedItem(pos)),
             },
-            Some('b') => self.read_radix(pos, 2),
-            Some('o') => self.read_radix(pos, 8),
-            Some('x') => self.read_radix(pos, 16),
-            Some(chr) => Err(Error::UnknownMacroCharacter(chr, pos)),
-            None => Err(Error::MissingQuotedItem(pos)),
+            Sharp::Record => self.read_record(pos),
+            Sharp::Radix(radix) => self.read_radix(pos, radix),
+            Sharp::Label(label) => self.read_label(pos, label),
+            Sharp::LabelRef(label) => {
+                self.labels.get(&label).copied().ok_or(Error::InvalidLabel(pos))
+            }
+            Sharp::ByteCode => self.read_byte_code(pos),
+            Sharp::LoadFileName => Ok(self.settings.load_file_name),
+            Sharp::Symbol(name) => Ok(self.cx.add(intern_symbol(&name, self.cx))),
+            Sharp::Uninterned(name) => {
+                let symbol = SymbolCell::new_uninterned(&symbol_name(&name)).into_obj(self.cx);
+                Ok(symbol.into())
+            }
+            Sharp::RawString(raw) => Ok(self.read_raw_string(&raw)),
+        }
+    }
+
+    /// Read a raw string, which has no escape sequences. It is meant for
+    /// regular expressions, so `(`, `)`, `|`, `{` and `}` are operators unless
+    /// escaped, as in most regex syntaxes. The resulting string uses the Emacs
+    /// syntax, where those characters have to be escaped to be operators.
+    /// ```lisp
+    /// #r"\`(foo|bar)\.el\'"  ; "\\`\\(foo\\|bar\\)\\.el\\'"
+    /// ```
+    fn read_raw_string(&self, raw: &str) -> GcObj<'ob> {
+        self.cx.add(crate::search::swap_regex_escapes(raw))
+    }
+
+    /// Read an object labeled with `#N=`. References to the label inside the
+    /// object are first read as a placeholder cons. If the object is a cons
+    /// the placeholder becomes the object, otherwise every reference to the
+    /// placeholder is replaced with the object.
+    fn read_label(&mut self, pos: usize, label: u32) -> Result<GcObj<'ob>> {
+        let placeholder = cons!(false, false; self.cx);
+        self.labels.insert(label, placeholder);
+        let obj = match self.tokens.next() {
+            Some(token) => self.read_sexp(token)?,
+            None => return Err(Error::MissingQuotedItem(pos)),
+        };
+        if obj.ptr_eq(placeholder) {
+            return Err(Error::InvalidLabel(pos));
         }
+        if let Object::Cons(cons) = obj.untag() {
+            let new = placeholder.as_cons();
+            new.set_car(cons.car()).expect("placeholder should be mutable");
+            new.set_cdr(cons.cdr()).expect("placeholder should be mutable");
+            return Ok(placeholder);
+        }
+        substitute_object(obj, placeholder, obj, &mut HashSet::default());
+        self.labels.insert(label, obj);
+        Ok(obj)
     }
 
     fn read_sexp(&mut self, token: Token<'a>) -> Result<GcObj<'ob>> {
@@ -460,22 +1078,212 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             Token::Unquote(i) => self.quote_item(i, sym::UNQUOTE),
             Token::Splice(i) => self.quote_item(i, sym::SPLICE),
             Token::Backquote(i) => self.quote_item(i, sym::BACKQUOTE),
-            Token::Sharp(i) => self.read_sharp(i),
-            Token::QuestionMark(_, c) => Ok((c as i64).into()),
-            Token::Ident(x) => Ok(parse_symbol(x, self.cx)),
-            Token::String(x) => Ok(self.cx.add(unescape_string(x))),
+            Token::Sharp(i, sharp) => self.read_sharp(i, sharp),
+            Token::QuestionMark(_, c) => Ok(i64::from(c).into()),
+            Token::Ident(_, x) => Ok(parse_symbol(&x, self.cx)),
+            Token::String(offset, x) => match unescape_string(&x) {
+                Ok(StringLiteral::Multibyte(string)) => Ok(self.cx.add(string)),
+                Ok(StringLiteral::Unibyte(bytes)) => Ok(self.cx.add(bytes)),
+                Ok(StringLiteral::RawMultibyte(string)) => Ok(self.cx.add(string)),
+                Err(mut e) => {
+                    e.update_pos(offset);
+                    Err(e)
+                }
+            },
             Token::Error(e) => Err(e),
         }
     }
 }
 
+/// Replace every reference to `placeholder` in `tree` with `value`. The
+/// objects in `tree` were all just read, so they are mutable.
+fn substitute_object(tree: GcObj, placeholder: GcObj, value: GcObj, seen: &mut HashSet<RawObj>) {
+    let mut obj = tree;
+    loop {
+        if !seen.insert(obj.into_raw()) {
+            return;
+        }
+        let slots = match obj.untag() {
+            Object::Cons(cons) => {
+                let car = cons.car();
+                if car.ptr_eq(placeholder) {
+                    let _ = cons.set_car(value);
+                } else {
+                    substitute_object(car, placeholder, value, seen);
+                }
+                let cdr = cons.cdr();
+                if cdr.ptr_eq(placeholder) {
+                    let _ = cons.set_cdr(value);
+                    return;
+                }
+                obj = cdr;
+                continue;
+            }
+            Object::Vec(vec) => vec.try_mut(),
+            Object::Record(record) => record.try_mut(),
+            Object::HashTable(table) => {
+                for idx in 0..table.count() {
+                    let val = table.value_at(idx);
+                    if val.ptr_eq(placeholder) {
+                        let _ = table.set_index(idx, value);
+                    } else {
+                        substitute_object(val, placeholder, value, seen);
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        for slot in slots.into_iter().flatten() {
+            let elem = slot.get();
+            if elem.ptr_eq(placeholder) {
+                slot.set(value);
+            } else {
+                substitute_object(elem, placeholder, value, seen);
+            }
+        }
+        return;
+    }
+}
+
+/// Collect the elements of a proper list read at `pos`.
+fn list_to_vec(list: GcObj, pos: usize) -> Result<Vec<GcObj>> {
+    let elements = list.as_list().map_err(|_| Error::InvalidRecord(pos))?;
+    elements.collect::<anyhow::Result<_>>().map_err(|_| Error::InvalidRecord(pos))
+}
+
+defsym!(TEST);
+defsym!(WEAKNESS);
+defsym!(DATA);
+defvar!(RUNE_EXTENDED_SYNTAX);
+
+/// Settings for a read that come from the Lisp environment.
+#[derive(Default)]
+pub(crate) struct ReadSettings<'ob> {
+    /// The value of `#$`, which is the name of the file being loaded.
+    pub(crate) load_file_name: GcObj<'ob>,
+    /// Whether rune's extensions to the syntax, like `#r"..."`, can be read.
+    pub(crate) extended_syntax: bool,
+}
+
+impl<'ob> ReadSettings<'ob> {
+    /// The settings given by `load-file-name` and `rune-extended-syntax`.
+    pub(crate) fn from_env(env: &Rt<Env>, cx: &'ob Context) -> Self {
+        let value = |var| env.vars.get(var).map_or_else(nil, |x| x.bind(cx));
+        Self {
+            load_file_name: value(sym::LOAD_FILE_NAME),
+            extended_syntax: !value(sym::RUNE_EXTENDED_SYNTAX).nil(),
+        }
+    }
+}
+
 /// read a lisp object from `slice`. Return the object and index of next
 /// remaining character in the slice.
 pub(crate) fn read<'ob>(slice: &str, cx: &'ob Context) -> Result<(GcObj<'ob>, usize)> {
-    let mut reader = Reader { tokens: Tokenizer::new(slice), cx };
-    match reader.tokens.next() {
-        Some(t) => reader.read_sexp(t).map(|x| (x, reader.tokens.cur_pos())),
+    read_with(slice, ReadSettings::default(), None, cx)
+}
+
+/// Like [`read`], but using `settings`. If `positions` is given, every list
+/// that is read is pushed onto it along with the offset in `slice` where it
+/// starts.
+pub(crate) fn read_with<'ob>(
+    slice: &str,
+    settings: ReadSettings<'ob>,
+    positions: Option<&mut Vec<(GcObj<'ob>, usize)>>,
+    cx: &'ob Context,
+) -> Result<(GcObj<'ob>, usize)> {
+    let mut tokens = Tokenizer::from_str(slice);
+    tokens.extended_syntax = settings.extended_syntax;
+    let obj = read_tokens(&mut tokens, settings, positions, cx)?;
+    Ok((obj, tokens.cur_pos()))
+}
+
+/// Read an object from `source`. Only the characters of the object are
+/// consumed, along with the one after it if the object is an atom.
+pub(crate) fn read_from_source<'a, 'ob>(
+    source: impl CharSource<'a>,
+    settings: ReadSettings<'ob>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let mut tokens = Tokenizer::new(source);
+    tokens.extended_syntax = settings.extended_syntax;
+    read_tokens(tokens, settings, None, cx)
+}
+
+fn read_tokens<'a, 'ob>(
+    tokens: impl Iterator<Item = Token<'a>>,
+    settings: ReadSettings<'ob>,
+    positions: Option<&mut Vec<(GcObj<'ob>, usize)>>,
+    cx: &'ob Context,
+) -> Result<GcObj<'ob>> {
+    let mut reader = Reader {
+        tokens,
+        cx,
+        labels: HashMap::default(),
+        settings,
+        positions: positions.as_ref().map(|_| Vec::new()),
+    };
+    let result = match reader.tokens.next() {
+        Some(t) => reader.read_sexp(t),
         None => Err(Error::EmptyStream),
+    };
+    if let (Some(positions), Some(read)) = (positions, reader.positions) {
+        positions.extend(read);
+    }
+    result
+}
+
+/// The tokens of one object, taken from a [`CharSource`] before the object is
+/// read.
+pub(crate) struct ObjectTokens<'a>(Vec<Token<'a>>);
+
+impl<'a> ObjectTokens<'a> {
+    /// Take the tokens of the next object from `source`. This needs no
+    /// [`Context`], so it works with sources that have to run Lisp code to get
+    /// their characters. Only the nesting of the tokens is followed here, any
+    /// errors are found when the tokens are read.
+    pub(crate) fn new(source: impl CharSource<'a>, extended_syntax: bool) -> Self {
+        let mut tokenizer = Tokenizer::new(source);
+        tokenizer.extended_syntax = extended_syntax;
+        let mut tokens = Vec::new();
+        let mut depth = 0_usize;
+        for token in tokenizer {
+            let complete = match token {
+                Token::OpenParen(_) | Token::OpenBracket(_) | Token::Sharp(_, Sharp::ByteCode) => {
+                    depth += 1;
+                    false
+                }
+                Token::CloseParen(_) | Token::CloseBracket(_) => {
+                    depth = depth.saturating_sub(1);
+                    depth == 0
+                }
+                // these are followed by the object they apply to
+                Token::Quote(_)
+                | Token::Backquote(_)
+                | Token::Unquote(_)
+                | Token::Splice(_)
+                | Token::Sharp(
+                    _,
+                    Sharp::Function | Sharp::Record | Sharp::Radix(_) | Sharp::Label(_),
+                ) => false,
+                Token::Error(_) => true,
+                _ => depth == 0,
+            };
+            tokens.push(token);
+            if complete {
+                break;
+            }
+        }
+        Self(tokens)
+    }
+
+    /// Read the object made of these tokens.
+    pub(crate) fn read<'ob>(
+        self,
+        settings: ReadSettings<'ob>,
+        cx: &'ob Context,
+    ) -> Result<GcObj<'ob>> {
+        read_tokens(self.0.into_iter(), settings, None, cx)
     }
 }
 
@@ -487,13 +1295,14 @@ mod test {
 
     #[test]
     fn tokens() {
-        let mut iter = Tokenizer::new("1 foo (\"bar\" . 1.3)");
-        assert_eq!(iter.next(), Some(Token::Ident("1")));
-        assert_eq!(iter.next(), Some(Token::Ident("foo")));
+        let ident = |pos, name| Some(Token::Ident(pos, Cow::Borrowed(name)));
+        let mut iter = Tokenizer::from_str("1 foo (\"bar\" . 1.3)");
+        assert_eq!(iter.next(), ident(0, "1"));
+        assert_eq!(iter.next(), ident(2, "foo"));
         assert_eq!(iter.next(), Some(Token::OpenParen(6)));
-        assert_eq!(iter.next(), Some(Token::String("bar")));
-        assert_eq!(iter.next(), Some(Token::Ident(".")));
-        assert_eq!(iter.next(), Some(Token::Ident("1.3")));
+        assert_eq!(iter.next(), Some(Token::String(8, Cow::Borrowed("bar"))));
+        assert_eq!(iter.next(), ident(13, "."));
+        assert_eq!(iter.next(), ident(15, "1.3"));
         assert_eq!(iter.next(), Some(Token::CloseParen(18)));
         assert_eq!(iter.next(), None);
     }
@@ -527,6 +1336,56 @@ mod test {
         check_reader!(0xdead_beef_i64, "#xDeAdBeEf", cx);
     }
 
+    #[test]
+    fn test_read_float() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let float_bits = |text| match read(text, cx).unwrap().0.untag() {
+            Object::Float(x) => Some(x.get().to_bits()),
+            _ => None,
+        };
+        for (text, value) in [
+            ("1e3", 1000.0),
+            ("1.5E-2", 0.015),
+            ("5.e1", 50.0),
+            (".5", 0.5),
+            ("-.5", -0.5),
+            ("+.5e1", 5.0),
+            ("-0.0", -0.0),
+            ("1.0e+INF", f64::INFINITY),
+            ("-1.0e+INF", f64::NEG_INFINITY),
+            ("0.0e+NaN", f64::NAN),
+            ("-0.0e+NaN", -f64::NAN),
+        ] {
+            assert_eq!(float_bits(text), Some(value.to_bits()), "{text}");
+        }
+        let nan = float_bits("7.0e+NaN").unwrap();
+        assert_eq!(nan & NAN_PAYLOAD_MASK, 7);
+        assert!(f64::from_bits(nan).is_nan());
+
+        check_reader!(1, "1.", cx);
+        check_reader!(-12, "-12.", cx);
+        for symbol in ["inf", "nan", "infinity", "1e", ".e3", "1.0e-INF", "1e+", "1.5.3", "1e3x"] {
+            check_reader!(intern(symbol, cx), symbol, cx);
+        }
+    }
+
+    #[test]
+    fn test_read_bignum() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let big = BigInt::from(1) << 100_usize;
+        check_reader!(big.clone(), "1267650600228229401496703205376", cx);
+        check_reader!(-big.clone(), "-1267650600228229401496703205376", cx);
+        check_reader!(big, "#x10000000000000000000000000", cx);
+        // Integers that fit in an i64 but not in a fixnum
+        check_reader!(BigInt::from(i64::MAX), "9223372036854775807", cx);
+        check_reader!(crate::core::object::MOST_POSITIVE_FIXNUM, "36028797018963967", cx);
+        let obj = read("123456789012345678901234567890", cx).unwrap().0;
+        assert_eq!(obj.to_string(), "123456789012345678901234567890");
+        assert!(read("#x1_0", cx).is_err());
+    }
+
     #[test]
     #[allow(clippy::non_ascii_literal)]
     fn test_read_char() {
@@ -541,6 +1400,72 @@ mod test {
         assert_error("?", Error::MissingQuotedItem(0), cx);
     }
 
+    #[test]
+    #[allow(clippy::non_ascii_literal)]
+    fn test_read_char_escape() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        check_reader!(10, "?\\n", cx);
+        check_reader!(7, "?\\a", cx);
+        check_reader!(127, "?\\d", cx);
+        check_reader!(27, "?\\e", cx);
+        check_reader!(32, "?\\s", cx);
+        check_reader!(40, "?\\(", cx);
+        check_reader!(92, "?\\\\", cx);
+        check_reader!(list!(32, 10; cx), "(?\\s ?\\n)", cx);
+        // hex, octal and unicode
+        check_reader!(65, "?\\x41", cx);
+        check_reader!(0x3F_FF7F, "?\\x3fff7f", cx);
+        check_reader!(255, "?\\xff", cx);
+        check_reader!(255, "?\\377", cx);
+        check_reader!(65, "?\\101", cx);
+        check_reader!(0, "?\\0", cx);
+        check_reader!(233, "?\\u00e9", cx);
+        check_reader!(0x1F600, "?\\U0001F600", cx);
+        check_reader!(233, "?é", cx);
+        check_reader!(233, "?\\N{LATIN SMALL LETTER E WITH ACUTE}", cx);
+        check_reader!(233, "?\\N{latin small letter e\nwith acute}", cx);
+        check_reader!(233, "?\\N{U+E9}", cx);
+        check_reader!(0x4E00, "?\\N{CJK UNIFIED IDEOGRAPH-4E00}", cx);
+        check_reader!(0x3B1, "?\\N{GREEK SMALL LETTER ALPHA}", cx);
+        check_reader!(0x2603, "?\\N{snowman}", cx);
+        check_reader!(0xAC00, "?\\N{HANGUL SYLLABLE GA}", cx);
+        check_reader!(0x1F600, "?\\N{GRINNING FACE}", cx);
+        // control characters
+        check_reader!(24, "?\\C-x", cx);
+        check_reader!(24, "?\\^x", cx);
+        check_reader!(13, "?\\^M", cx);
+        check_reader!(0, "?\\^@", cx);
+        check_reader!(127, "?\\^?", cx);
+        check_reader!(127, "?\\C-?", cx);
+        check_reader!(0x400_0025, "?\\C-%", cx);
+        check_reader!(0x400_0020, "?\\C-\\s", cx);
+        // modifiers
+        check_reader!(0x800_0061, "?\\M-a", cx);
+        check_reader!(0x800_0001, "?\\C-\\M-a", cx);
+        check_reader!(0x800_0001, "?\\M-\\C-a", cx);
+        check_reader!(0x80_0061, "?\\s-a", cx);
+        check_reader!(0x200_0061, "?\\S-a", cx);
+        check_reader!(0x100_0061, "?\\H-a", cx);
+        check_reader!(0x40_0061, "?\\A-a", cx);
+        check_reader!(0x880_000A, "?\\M-\\s-\\n", cx);
+        // errors
+        assert_error("?\\x", Error::InvalidEscape(1), cx);
+        assert_error("?\\x400000", Error::InvalidEscape(1), cx);
+        assert_error("?\\u12", Error::InvalidEscape(1), cx);
+        assert_error("?\\U00110000", Error::InvalidEscape(1), cx);
+        assert_error("?\\Ca", Error::InvalidEscape(1), cx);
+        assert_error("?\\M", Error::InvalidEscape(1), cx);
+        assert_error(
+            "?\\N{NOT A CHARACTER}",
+            Error::UnknownCharName("NOT A CHARACTER".into(), 1),
+            cx,
+        );
+        assert_error("?\\N{LATIN", Error::InvalidEscape(1), cx);
+        assert_error("?\\C-", Error::MissingQuotedItem(1), cx);
+        assert_error("?\\C-ab", Error::UnexpectedChar('b', 5), cx);
+    }
+
     #[test]
     fn read_bool() {
         let roots = &RootSet::default();
@@ -582,6 +1507,55 @@ baz""#,
         );
     }
 
+    #[test]
+    #[allow(clippy::non_ascii_literal)]
+    fn test_read_string_escape() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        check_reader!("\x07\x08\x7f\x1b\x0c\x0b \"", r#""\a\b\d\e\f\v\s\"""#, cx);
+        check_reader!("AAA", r#""\x41\101\u0041""#, cx);
+        check_reader!("A!", r#""\x41\ !""#, cx);
+        check_reader!("\x01\x18\x7f\0", r#""\C-a\^x\^?\C-\s""#, cx);
+        check_reader!("A", r#""\S-a""#, cx);
+        // \s is always a space in a string, never the super modifier
+        check_reader!(" -a", r#""\s-a""#, cx);
+        // multibyte characters
+        check_reader!("é", r#""é""#, cx);
+        check_reader!("éé", r#""\u00e9\N{LATIN SMALL LETTER E WITH ACUTE}""#, cx);
+        check_reader!("é", r#""\x0e9""#, cx);
+        check_reader!("😀", r#""\U0001F600""#, cx);
+        // raw bytes make the string unibyte
+        check_reader!(vec![0xe9_u8], r#""\xe9""#, cx);
+        check_reader!(vec![b'a', 0xff], r#""a\377""#, cx);
+        check_reader!(vec![0xe1_u8], r#""\M-a""#, cx);
+        let obj = read(r#""\xff""#, cx).unwrap().0;
+        let Object::String(string) = obj.untag() else { panic!("expected string") };
+        assert!(!string.is_multibyte());
+        let obj = read(r#""\u00e9""#, cx).unwrap().0;
+        let Object::String(string) = obj.untag() else { panic!("expected string") };
+        assert!(string.is_multibyte());
+        // other non-ASCII characters make the string multibyte, and the raw
+        // bytes become raw-byte characters
+        let raw_ff = byte8_to_char(0xff);
+        check_reader!(RawMultibyte::from_codes([raw_ff, 0xe9]), r#""\xffé""#, cx);
+        check_reader!(RawMultibyte::from_codes([0xe9, raw_ff]), r#""é\xff""#, cx);
+        check_reader!(RawMultibyte::from_codes([0xe9, raw_ff]), r#""\N{U+E9}\xff""#, cx);
+        check_reader!(RawMultibyte::from_codes([raw_ff, 0xe9]), r#""\377\u00e9""#, cx);
+        check_reader!(RawMultibyte::from_codes([0xe1 + 0x3F_FF00, 0x3b1]), r#""\M-aα""#, cx);
+        let obj = read(r#""a\xff\N{U+E9}""#, cx).unwrap().0;
+        let Object::String(string) = obj.untag() else { panic!("expected string") };
+        assert!(string.is_multibyte());
+        assert_eq!(string.len(), 3);
+        assert_eq!(string.char_codes().collect::<Vec<_>>(), [0x61, raw_ff, 0xe9]);
+        assert_eq!(obj.to_string(), r#""a\377é""#);
+        // errors
+        assert_error(r#" "ab\x""#, Error::InvalidEscape(4), cx);
+        assert_error(r#""\uD800""#, Error::InvalidEscape(1), cx);
+        assert_error(r#""\C-%""#, Error::InvalidModifier(1), cx);
+        assert_error(r#""\H-a""#, Error::InvalidModifier(1), cx);
+        assert_error(r#""\M-é""#, Error::InvalidModifier(1), cx);
+    }
+
     #[test]
     fn test_read_cons() {
         let roots = &RootSet::default();
@@ -624,6 +1598,175 @@ baz""#,
         assert_error("#a", Error::UnknownMacroCharacter('a', 0), cx);
     }
 
+    #[test]
+    fn read_sharp_symbols() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        check_reader!(intern("", cx), "##", cx);
+        check_reader!(intern("1", cx), "#_1", cx);
+        check_reader!(intern("foo bar", cx), "#_foo\\ bar", cx);
+        let obj = read("(#:foo foo)", cx).unwrap().0;
+        let Object::Symbol(uninterned) = obj.as_cons().car().untag() else { panic!() };
+        assert_eq!(uninterned.name(), "foo");
+        assert!(!uninterned.interned());
+        assert_ne!(uninterned, intern("foo", cx));
+        let obj = read("#:", cx).unwrap().0;
+        let Object::Symbol(empty) = obj.untag() else { panic!() };
+        assert_eq!(empty.name(), "");
+        assert_ne!(empty, intern("", cx));
+    }
+
+    #[test]
+    fn read_labels() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        // shared structure
+        let obj = read("(#1=(a) #1# #2=\"str\" #2#)", cx).unwrap().0;
+        let elems = list_to_vec(obj, 0).unwrap();
+        assert_eq!(elems[0], list!(intern("a", cx); cx));
+        assert!(elems[0].ptr_eq(elems[1]));
+        assert!(elems[2].ptr_eq(elems[3]));
+        // circular list
+        let obj = read("#1=(a b . #1#)", cx).unwrap().0;
+        let cdr = obj.as_cons().cdr();
+        assert!(cdr.as_cons().cdr().ptr_eq(obj));
+        // circular car
+        let obj = read("#1=(#1#)", cx).unwrap().0;
+        assert!(obj.as_cons().car().ptr_eq(obj));
+        // circular vector and record
+        let obj = read("#1=[a (#1#) #1#]", cx).unwrap().0;
+        let Object::Vec(vec) = obj.untag() else { panic!("expected vector") };
+        assert!(vec[2].get().ptr_eq(obj));
+        assert!(vec[1].get().as_cons().car().ptr_eq(obj));
+        let obj = read("#3=#s(foo #3#)", cx).unwrap().0;
+        let Object::Record(record) = obj.untag() else { panic!("expected record") };
+        assert!(record[1].get().ptr_eq(obj));
+        // radix syntax
+        check_reader!(255, "#16rff", cx);
+        check_reader!(35, "#36rz", cx);
+        assert_error("#1r0", Error::ParseInt(1, 0), cx);
+        assert_error("#1#", Error::InvalidLabel(0), cx);
+        assert_error("(#1=a #2#)", Error::InvalidLabel(6), cx);
+        assert_error("#1=#1#", Error::InvalidLabel(0), cx);
+        assert_error("#1=", Error::MissingQuotedItem(0), cx);
+        assert_error("#1a", Error::UnknownMacroCharacter('a', 0), cx);
+    }
+
+    #[test]
+    fn lazy_comments() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        check_reader!(1, "#@4 foo1", cx);
+        check_reader!(2, "#@5\n(a)\n2", cx);
+        check_reader!(list!(1, 2; cx), "(1 #@3 xx 2)", cx);
+        assert_error("#@00 (1 2)", Error::EmptyStream, cx);
+        assert_error("#@10 a", Error::EmptyStream, cx);
+    }
+
+    #[test]
+    fn read_byte_code() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let obj = read("#[513 \"\\300\\1T\\207\" [foo] 3 \"doc\"]", cx).unwrap().0;
+        let Object::ByteFn(func) = obj.untag() else { panic!("expected byte-code function") };
+        assert_eq!(func.args.into_arg_spec(), 513);
+        assert_eq!(func.depth, 3);
+        let code: &[u8] = func.codes();
+        assert_eq!(code, b"\xc0\x01T\x87");
+        assert_eq!(func.constants()[0].get(), intern("foo", cx));
+        assert_error("#[257 \"\" []]", Error::InvalidByteCode(0), cx);
+        let obj = read("#[(x &optional y) \"\\10\\207\" [x y] 1]", cx).unwrap().0;
+        let Object::ByteFn(func) = obj.untag() else { panic!("expected byte-code function") };
+        assert_eq!(func.args.required, 1);
+        assert_eq!(func.args.optional, 1);
+        assert_eq!(
+            func.index(0).unwrap(),
+            list!(intern("x", cx), sym::AND_OPTIONAL, intern("y", cx); cx)
+        );
+        assert_error("#[(1) \"\" [] 0]", Error::InvalidByteCode(0), cx);
+        assert_error("#[257 \"\" [] 0", Error::MissingCloseBracket(1), cx);
+        check_reader!(false, "#$", cx);
+        let file = cx.add("foo.elc");
+        let settings = ReadSettings { load_file_name: file, extended_syntax: false };
+        assert_eq!(read_with("(#$ . 10)", settings, None, cx).unwrap().0, cons!(file, 10; cx));
+    }
+
+    #[test]
+    fn test_read_raw_string() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let read_raw = |text| {
+            let settings = ReadSettings { load_file_name: nil(), extended_syntax: true };
+            read_with(text, settings, None, cx).map(|x| x.0)
+        };
+        let expect = cx.add(r"\`\\\(\(a\|b\|c\)\(d\|e\))\'");
+        assert_eq!(read_raw(r#"#r"\`\\((a|b|c)(d|e)\)\'""#).unwrap(), expect);
+        assert_eq!(read_raw(r#"#r"a\"b""#).unwrap(), cx.add(r#"a\"b"#));
+        assert_eq!(read_raw("#r\"\\d{2}\" x").unwrap(), cx.add(r"\d\{2\}"));
+        assert_eq!(read_raw("#ra").unwrap_err(), Error::UnknownMacroCharacter('r', 0));
+        assert_eq!(read_raw("#r\"a").unwrap_err(), Error::MissingStringDel(2));
+        assert_error("#r\"a\"", Error::UnknownMacroCharacter('r', 0), cx);
+    }
+
+    #[test]
+    fn read_positions() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let mut positions = Vec::new();
+        let text = "(a\n (b 'c) #'d [(e)])";
+        let obj = read_with(text, ReadSettings::default(), Some(&mut positions), cx);
+        let obj = obj.unwrap().0;
+        let offsets: Vec<_> = positions.iter().map(|x| x.1).collect();
+        assert_eq!(offsets, vec![7, 4, 11, 16, 0]);
+        assert!(positions[4].0.ptr_eq(obj));
+        let second = obj.as_cons().cdr().as_cons().car();
+        assert!(positions[1].0.ptr_eq(second));
+    }
+
+    #[test]
+    fn test_read_record() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let foo = intern("foo", cx);
+        check_reader!(
+            RecordBuilder(vec![foo.into(), 1.into(), cx.add("bar")]),
+            "#s(foo 1 \"bar\")",
+            cx
+        );
+        let obj = read("#s(foo (1 2) #s(bar))", cx).unwrap().0;
+        assert_eq!(obj.to_string(), "#s(foo (1 2) #s(bar))");
+        assert_eq!(read(&obj.to_string(), cx).unwrap().0, obj);
+        assert_error("#s()", Error::InvalidRecord(0), cx);
+        assert_error("#s(foo . 1)", Error::InvalidRecord(0), cx);
+        assert_error("#s[foo]", Error::InvalidRecord(0), cx);
+    }
+
+    #[test]
+    fn test_read_hash_table() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let text = "#s(hash-table size 2 test equal rehash-size 1.5 data (\"key\" 1 (1 2) -3.5))";
+        let obj = read(text, cx).unwrap().0;
+        let Object::HashTable(table) = obj.untag() else { panic!("expected hash table") };
+        assert_eq!(table.test(), HashTest::Equal);
+        assert_eq!(table.get(cx.add("key")).unwrap(), Some(1.into()));
+        assert_eq!(table.get(list![1, 2; cx]).unwrap(), Some(cx.add(-3.5)));
+
+        let printed = obj.to_string();
+        assert_eq!(printed, "#s(hash-table size 2 test equal data (\"key\" 1 (1 2) -3.5))");
+        assert_eq!(read(&printed, cx).unwrap().0, obj);
+
+        let obj = read("#s(hash-table weakness key data (a b))", cx).unwrap().0;
+        let Object::HashTable(table) = obj.untag() else { panic!("expected hash table") };
+        assert_eq!(table.test(), HashTest::Eql);
+        assert_eq!(table.weakness(), Some(Weakness::Key));
+        assert_eq!(read(&obj.to_string(), cx).unwrap().0, obj);
+
+        assert_error("#s(hash-table data (a))", Error::InvalidRecord(0), cx);
+        assert_error("#s(hash-table test foo)", Error::InvalidRecord(0), cx);
+        assert_error("#s(hash-table test)", Error::InvalidRecord(0), cx);
+    }
+
     #[test]
     fn test_read_vec() {
         let roots = &RootSet::default();
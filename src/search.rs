@@ -15,7 +15,7 @@ fn string_match<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<GcObj<'ob>> {
-    let re = Regex::new(&lisp_regex_to_rust(regexp))?;
+    let re = Regex::new(&swap_regex_escapes(regexp))?;
 
     let start = start.unwrap_or(0) as usize;
     if let Some(matches) = re.captures_iter(&string[start..]).next() {
@@ -34,17 +34,28 @@ fn string_match<'ob>(
     }
 }
 
-// Invert the escaping of parens. i.e. \( => ( and ( => \(
-fn lisp_regex_to_rust(regexp: &str) -> String {
+/// Invert the escaping of the characters that are operators in Rust regexps
+/// but literals in lisp regexps, and the other way around. i.e. `\(` => `(` and
+/// `(` => `\(`. This converts a lisp regexp to Rust syntax, and also converts
+/// a raw string (`#r"..."`) to a lisp regexp.
+pub(crate) fn swap_regex_escapes(regexp: &str) -> String {
+    let swapped = |chr| matches!(chr, '(' | ')' | '|' | '{' | '}');
     let mut norm_regex = String::new();
-    let mut chars = regexp.chars().peekable();
+    let mut chars = regexp.chars();
     while let Some(ch) = chars.next() {
         match ch {
-            '(' => norm_regex.push_str("\\("),
-            ')' => norm_regex.push_str("\\)"),
-            '\\' if matches!(chars.peek(), Some('(' | ')')) => {
-                norm_regex.push(chars.next().unwrap());
+            c if swapped(c) => {
+                norm_regex.push('\\');
+                norm_regex.push(c);
             }
+            '\\' => match chars.next() {
+                Some(c) if swapped(c) => norm_regex.push(c),
+                Some(c) => {
+                    norm_regex.push('\\');
+                    norm_regex.push(c);
+                }
+                None => norm_regex.push('\\'),
+            },
             c => norm_regex.push(c),
         }
     }
@@ -93,9 +104,13 @@ mod test {
 
     #[test]
     fn lisp_regex() {
-        assert_eq!(lisp_regex_to_rust("foo"), "foo");
-        assert_eq!(lisp_regex_to_rust("\\foo"), "\\foo");
-        assert_eq!(lisp_regex_to_rust("\\(foo\\)"), "(foo)");
-        assert_eq!(lisp_regex_to_rust("(foo)"), "\\(foo\\)");
+        assert_eq!(swap_regex_escapes("foo"), "foo");
+        assert_eq!(swap_regex_escapes("\\foo"), "\\foo");
+        assert_eq!(swap_regex_escapes("\\(foo\\)"), "(foo)");
+        assert_eq!(swap_regex_escapes("(foo)"), "\\(foo\\)");
+        assert_eq!(swap_regex_escapes("a\\|b|c"), "a|b\\|c");
+        assert_eq!(swap_regex_escapes("a\\{2\\}{"), "a{2}\\{");
+        // an escaped backslash is not an escape for the next character
+        assert_eq!(swap_regex_escapes("\\\\("), "\\\\\\(");
     }
 }
@@ -1,33 +1,49 @@
 use crate::{
     core::{
-        env::Env,
-        gc::{Block, Context, RootSet},
-        object::{CloneIn, GcObj},
+        env::{Env, INTERNED_SYMBOLS},
+        gc::{Context, RootSet, Rt},
+        object::GcObj,
     },
     root,
 };
 use fn_macros::defun;
+use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
 
 #[defun]
-fn go(obj: GcObj) -> bool {
-    go_internal(obj);
+fn go(obj: &Rt<GcObj>, cx: &mut Context) -> bool {
+    go_internal(obj, cx);
     false
 }
 
-fn go_internal(obj: GcObj) -> JoinHandle<()> {
-    let block = Block::new_local_unchecked();
-    let sexp = obj.clone_in(&block);
-    let raw = sexp.into_raw();
+/// A rooted object that is handed to a new thread.
+struct SharedObj(*const Rt<GcObj<'static>>);
+
+// SAFETY: The sending thread stays parked until the new thread has rooted the
+// object, so the root can't be accessed by both threads at once.
+unsafe impl Send for SharedObj {}
+
+fn go_internal(obj: &Rt<GcObj>, cx: &mut Context) -> JoinHandle<()> {
+    // The object is copied into the global block so that both threads can
+    // reference it. It stays rooted here until the new thread has rooted it,
+    // in case the global block is collected in the meantime.
+    let shared = INTERNED_SYMBOLS.lock().unwrap().share(obj.bind(cx), cx);
+    root!(shared, cx);
+    let handoff = SharedObj(std::ptr::addr_of!(*shared));
+    let (started, wait_for_start) = mpsc::channel();
     crate::debug::enable_debug();
-    thread::spawn(move || {
+    let handle = thread::spawn(move || {
         let roots = &RootSet::default();
-        let cx = &mut Context::from_block(block, roots);
+        let cx = &mut Context::new(roots);
         root!(env, Env::default(), cx);
-        let obj = unsafe { GcObj::from_raw(raw) };
+        let handoff = handoff;
+        let obj = unsafe { (*handoff.0).bind(cx) };
         root!(obj, cx);
+        started.send(()).unwrap();
         _ = crate::interpreter::eval(obj, None, env, cx);
-    })
+    });
+    cx.park_while(|| wait_for_start.recv().unwrap());
+    handle
 }
 
 #[cfg(test)]
@@ -39,20 +55,28 @@ mod tests {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
         let obj = cx.add("test string");
-        go_internal(obj).join().unwrap();
+        root!(obj, cx);
+        let thread = go_internal(obj, cx);
+        cx.park_while(|| thread.join().unwrap());
     }
 
     #[test]
     fn test_go_eval() {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
-        let threads = [
-            go_internal(crate::reader::read("(if nil 1 2 3)", cx).unwrap().0),
-            go_internal(crate::reader::read("(progn (defvar foo 1) foo)", cx).unwrap().0),
-            go_internal(crate::reader::read("(progn (defvar foo 1) (makunbound 'foo) (let ((fn #'(lambda () (defvar foo 3))) (foo 7)) (funcall fn)) foo)", cx).unwrap().0),
+        let forms = [
+            "(if nil 1 2 3)",
+            "(progn (defvar foo 1) foo)",
+            "(progn (defvar foo 1) (makunbound 'foo) (let ((fn #'(lambda () (defvar foo 3))) (foo 7)) (funcall fn)) foo)",
         ];
+        let mut threads = Vec::new();
+        for form in forms {
+            let obj = crate::reader::read(form, cx).unwrap().0;
+            root!(obj, cx);
+            threads.push(go_internal(obj, cx));
+        }
         for thread in threads {
-            thread.join().unwrap();
+            cx.park_while(|| thread.join().unwrap());
         }
     }
 
@@ -62,6 +86,8 @@ mod tests {
         println!("hello main thread");
         let cx = &mut Context::new(roots);
         let obj = crate::reader::read("(message \"hello from thread\")", cx).unwrap().0;
-        go_internal(obj).join().unwrap();
+        root!(obj, cx);
+        let thread = go_internal(obj, cx);
+        cx.park_while(|| thread.join().unwrap());
     }
 }